@@ -1,30 +1,101 @@
 /// Copyright (C) 2023 Muqiu Han
-use crate::r#const::project_info;
+use crate::r#const::{project_info, LIT_DIR_ENV};
 use structopt::StructOpt;
 
 #[derive(StructOpt, Debug)]
 #[structopt(name = project_info::NAME, about = project_info::DESCRIPTION)]
-pub enum CommandLineParser {
+pub struct CommandLineParser {
+    /// Set the path to the repository's lit directory (or to a `gitdir:` file)
+    #[structopt(long, env = LIT_DIR_ENV)]
+    pub lit_dir: Option<String>,
+
+    #[structopt(subcommand)]
+    pub command: Command,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
     /// Create an empty lit repository or reinitialize an existing one
     Init {
         /// Force initialization
         #[structopt(short)]
         force: bool,
 
+        /// Create a bare repository, without a worktree
+        #[structopt(long)]
+        bare: bool,
+
         /// The repository path, defaults to the current directory (.)
         #[structopt(default_value = ".")]
         path: String,
+
+        /// Enable a repository extension, as `name` or `name=value`
+        #[structopt(long = "extension", number_of_values = 1)]
+        extensions: Vec<String>,
+
+        /// The hash function naming objects, defaults to sha1
+        #[structopt(long, possible_values = &["sha1", "sha256"])]
+        object_format: Option<String>,
     },
 
     /// Provide contents or details of repository objects
     CatFile {
-        /// Specify the type (blob | commit | tag | tree)
-        #[structopt(default_value = "blob", name = "type", short)]
-        typ: String,
+        /// Show the type of the object
+        #[structopt(short = "t", conflicts_with_all = &["size", "exists", "pretty"])]
+        show_type: bool,
 
-        /// The object to display
-        #[structopt(name = "object")]
-        object: String,
+        /// Show the size of the object
+        #[structopt(short, conflicts_with_all = &["exists", "pretty"])]
+        size: bool,
+
+        /// Exit with zero status if the object exists, with non-zero status otherwise
+        #[structopt(short, conflicts_with = "pretty")]
+        exists: bool,
+
+        /// Pretty-print the content of the object based on its type
+        #[structopt(short)]
+        pretty: bool,
+
+        /// Print the information line, defaults to `%(objectname) %(objecttype) %(objectsize)`,
+        /// and the content of each object named on stdin
+        #[structopt(
+            long,
+            require_equals = true,
+            conflicts_with_all = &["show-type", "size", "exists", "pretty", "batch-check", "batch-command"]
+        )]
+        batch: Option<Option<String>>,
+
+        /// Print the information line of each object named on stdin, `%(rest)` being the
+        /// rest of the line after the name
+        #[structopt(
+            long,
+            require_equals = true,
+            conflicts_with_all = &["show-type", "size", "exists", "pretty", "batch-command"]
+        )]
+        batch_check: Option<Option<String>>,
+
+        /// Read `contents <object>`, `info <object>` and `flush` commands from stdin
+        #[structopt(
+            long,
+            require_equals = true,
+            conflicts_with_all = &["show-type", "size", "exists", "pretty", "batch-all-objects"]
+        )]
+        batch_command: Option<Option<String>>,
+
+        /// Print all the objects of the repository rather than those named on stdin,
+        /// as `--batch-check` does unless `--batch` is given
+        #[structopt(long, conflicts_with_all = &["show-type", "size", "exists", "pretty"])]
+        batch_all_objects: bool,
+
+        /// The expected type (blob | commit | tag | tree), then the object to display.
+        /// Tags and commits are peeled to reach the type.
+        #[structopt(
+            name = "object",
+            min_values = 1,
+            max_values = 2,
+            required_unless_one = &["batch", "batch-check", "batch-command", "batch-all-objects"]
+        )]
+        objects: Vec<String>,
     },
 
     /// Compute object ID and optionally create an object from a file
@@ -37,9 +108,454 @@ pub enum CommandLineParser {
         #[structopt(default_value = "blob", name = "type", short)]
         typ: String,
 
-        #[structopt(name = "path")]
+        /// Read the object from stdin, before the files
+        #[structopt(long)]
+        stdin: bool,
+
+        /// Read the paths of the files from stdin, one per line
+        #[structopt(long, conflicts_with_all = &["stdin", "path"])]
+        stdin_paths: bool,
+
+        /// Allow any type, and skip checking that the content is a well-formed object
+        #[structopt(long)]
+        literally: bool,
+
+        /// Hash the files as they are, without the end-of-line conversion of `core.autocrlf`
+        #[structopt(long)]
+        no_filters: bool,
+
+        #[structopt(name = "path", required_unless_one = &["stdin", "stdin-paths"])]
+        paths: Vec<String>,
+    },
+
+    /// Manage multiple working trees
+    Worktree(WorktreeCommand),
+
+    /// Get and set repository or global options
+    Config {
+        /// Use the global configuration file
+        #[structopt(long)]
+        global: bool,
+
+        /// Use the system-wide configuration file
+        #[structopt(long)]
+        system: bool,
+
+        /// Use the repository configuration file
+        #[structopt(long)]
+        local: bool,
+
+        /// Use the configuration file of the current worktree, with the `worktreeconfig` extension
+        #[structopt(long)]
+        worktree: bool,
+
+        /// Use the given configuration file
+        #[structopt(short, long)]
+        file: Option<String>,
+
+        /// Get the value of name
+        #[structopt(long)]
+        get: bool,
+
+        /// Get all the values of a multi-valued name
+        #[structopt(long)]
+        get_all: bool,
+
+        /// Set name to value
+        #[structopt(long)]
+        set: bool,
+
+        /// Add a value to name, keeping the existing ones
+        #[structopt(long)]
+        add: bool,
+
+        /// Remove the value of name
+        #[structopt(long)]
+        unset: bool,
+
+        /// Remove all the values of name
+        #[structopt(long)]
+        unset_all: bool,
+
+        /// List all the variables
+        #[structopt(short, long)]
+        list: bool,
+
+        /// Show the file each variable comes from
+        #[structopt(long)]
+        show_origin: bool,
+
+        /// Check and canonicalize values (bool | int | path)
+        #[structopt(long = "type", name = "type", possible_values = &["bool", "int", "path"])]
+        typ: Option<String>,
+
+        /// The variable, as `section.key` or `section.subsection.key`
+        #[structopt(required_unless = "list")]
+        name: Option<String>,
+
+        value: Option<String>,
+    },
+
+    /// Clone a repository into a new directory
+    Clone {
+        /// Make a bare repository, mirroring the branches of the source
+        #[structopt(long)]
+        bare: bool,
+
+        /// The path of the repository to clone
+        repository: String,
+
+        /// The directory to clone into, defaults to the name of the source
+        directory: Option<String>,
+    },
+
+    /// Download objects and refs from another repository
+    Fetch {
+        /// The remote to fetch from, defaults to the remote of the current branch or `origin`
+        remote: Option<String>,
+
+        /// The refs to fetch, defaults to `remote.<name>.fetch`
+        refspecs: Vec<String>,
+    },
+
+    /// Update remote refs along with their objects
+    Push {
+        /// Allow non-fast-forward updates
+        #[structopt(short, long)]
+        force: bool,
+
+        /// Make the pushed branches track the remote branches they were pushed to
+        #[structopt(short = "u", long)]
+        set_upstream: bool,
+
+        /// The remote to push to, defaults to the remote of the current branch or `origin`
+        remote: Option<String>,
+
+        /// The refs to push, defaults to `remote.<name>.push` or the current branch
+        refspecs: Vec<String>,
+    },
+
+    /// Manage the set of tracked repositories
+    Remote {
+        /// Show the urls after the names
+        #[structopt(short, long)]
+        verbose: bool,
+
+        #[structopt(subcommand)]
+        command: Option<RemoteCommand>,
+    },
+
+    /// Move objects and refs by archive
+    Bundle(BundleCommand),
+
+    /// Serve repositories over the `git://` protocol on localhost
+    Serve {
+        /// The port to listen on
+        #[structopt(short, long, default_value = "9418")]
+        port: u16,
+
+        /// Allow pushing to the served repositories
+        #[structopt(long)]
+        receive_pack: bool,
+
+        /// The directory holding the served repositories, defaults to the current directory
+        directory: Option<String>,
+    },
+
+    /// Verify the connectivity and validity of the objects in the database
+    Fsck {
+        /// Check the objects in packs and alternates too, not only the loose ones (the default)
+        #[structopt(long, overrides_with = "no-full")]
+        full: bool,
+
+        /// Only check the loose objects, skipping the packs and alternates
+        #[structopt(long, overrides_with = "full")]
+        no_full: bool,
+
+        /// Only check that the objects reachable from the refs are there
+        #[structopt(long)]
+        connectivity_only: bool,
+
+        /// Report all the unreachable objects, not only the dangling ones
+        #[structopt(long)]
+        unreachable: bool,
+
+        /// Report one problem per line, as tab separated fields: the kind of problem,
+        /// the type and the name of the object, then the details
+        #[structopt(long)]
+        porcelain: bool,
+    },
+
+    /// Write and verify the commit-graph file, which speeds up history walks
+    CommitGraph(CommitGraphCommand),
+
+    /// Write and verify the multi-pack-index, which finds objects across many packs at once
+    MultiPackIndex(MultiPackIndexCommand),
+
+    /// Remove the unreachable loose objects older than the grace period
+    Prune {
+        /// Do not remove anything, just report what would be removed
+        #[structopt(short = "n", long)]
+        dry_run: bool,
+
+        /// Report all removed objects
+        #[structopt(short, long)]
+        verbose: bool,
+
+        /// Only remove the objects older than this date, such as `2.weeks.ago` or `now`,
+        /// defaults to `gc.pruneExpire`, else two weeks
+        #[structopt(long)]
+        expire: Option<String>,
+    },
+
+    /// Pack all the reachable objects into a single pack
+    Repack {
+        /// Delete the other packs, and the loose objects the new pack has
+        #[structopt(short = "d")]
+        delete: bool,
+
+        /// Write reachability bitmaps along with the pack
+        #[structopt(short = "b", long)]
+        write_bitmap_index: bool,
+    },
+
+    /// List the commits reachable from some commits but not from others
+    RevList {
+        /// Print how many commits, or objects, would be listed instead
+        #[structopt(long)]
+        count: bool,
+
+        /// List all the objects the commits reach, commits first
+        #[structopt(long)]
+        objects: bool,
+
+        /// Start from all the refs and HEAD
+        #[structopt(long)]
+        all: bool,
+
+        /// `<rev>` includes the commits reachable from rev, `^<rev>` excludes them,
+        /// `<a>..<b>` is `^<a> <b>`
+        revs: Vec<String>,
+
+        /// Only list the commits changing one of these paths, given after `--`
+        #[structopt(last = true)]
+        paths: Vec<String>,
+    },
+
+    /// Count the loose objects and the packs, and their disk usage
+    CountObjects {
+        /// Also report the packed objects, the loose ones a pack has, and the garbage
+        #[structopt(short, long)]
+        verbose: bool,
+    },
+
+    /// Show what makes the repository big: objects by type, biggest blobs, deepest trees,
+    /// longest delta chains and most changed directories
+    Stats {
+        /// How many entries of each list to show
+        #[structopt(long, default_value = "10")]
+        top: usize,
+    },
+
+    /// List the contents of a tree object
+    LsTree {
+        /// Recurse into subtrees
+        #[structopt(short)]
+        recursive: bool,
+
+        /// Show the trees even when recursing into them
+        #[structopt(short = "t")]
+        show_trees: bool,
+
+        /// Show the size of the blobs
+        #[structopt(short, long)]
+        long: bool,
+
+        /// Only show the paths
+        #[structopt(long)]
+        name_only: bool,
+
+        /// The tree, or the commit or tag naming it
+        tree_ish: String,
+
+        /// Only show the entries at or under these paths
+        paths: Vec<String>,
+    },
+
+    /// Create a tree object from the index
+    WriteTree,
+
+    /// Read tree information into the index
+    ReadTree {
+        /// Merge the trees into the index: one tree is read, two move the index from the
+        /// first to the second, three merge the last two with the first as their base
+        #[structopt(short)]
+        merge: bool,
+
+        /// Read the tree under this directory of the index
+        #[structopt(long, conflicts_with = "merge")]
+        prefix: Option<String>,
+
+        /// The trees, or the commits or tags naming them
+        #[structopt(required = true, max_values = 3)]
+        trees: Vec<String>,
+    },
+
+    /// Show the working tree status
+    Status,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum RemoteCommand {
+    /// Add a remote fetching all the branches of the repository at url
+    Add {
+        /// Fetch the remote right after adding it
+        #[structopt(short, long)]
+        fetch: bool,
+
+        name: String,
+        url: String,
+    },
+
+    /// Remove a remote, its remote-tracking branches and the upstream of the branches tracking it
+    #[structopt(alias = "rm")]
+    Remove { name: String },
+
+    /// Rename a remote along with its remote-tracking branches
+    Rename { old: String, new: String },
+
+    /// Change the url of a remote
+    SetUrl {
+        /// Change the url used to push instead
+        #[structopt(long)]
+        push: bool,
+
+        name: String,
+        url: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum CommitGraphCommand {
+    /// Write the commit-graph of the commits reachable from the refs and the HEADs
+    Write {
+        /// Also store the filters of the paths each commit changes, kept once written
+        #[structopt(long)]
+        changed_paths: bool,
+    },
+
+    /// Check the commit-graph against the commits it describes
+    Verify,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum MultiPackIndexCommand {
+    /// Write the multi-pack-index of the packs of the repository
+    Write,
+
+    /// Check the multi-pack-index against the packs it covers
+    Verify,
+
+    /// Delete the packs the multi-pack-index takes no object from
+    Expire,
+
+    /// Gather the objects of the smaller packs of the multi-pack-index into a new pack
+    Repack {
+        /// Only gather packs expected to hold less than this, with an optional k, m or g
+        /// suffix, until their total reaches it. All the packs when 0.
+        #[structopt(long, default_value = "0")]
+        batch_size: String,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum BundleCommand {
+    /// Write a bundle of the refs among revs and the objects they need
+    Create {
+        /// Include every ref
+        #[structopt(long)]
+        all: bool,
+
+        file: String,
+
+        /// The revisions to include, `^<rev>` and `<rev>..<rev>` exclude what their
+        /// first revision reaches
+        revs: Vec<String>,
+    },
+
+    /// Check that the bundle is valid and applies to the current repository
+    Verify { file: String },
+
+    /// List the refs of the bundle
+    ListHeads { file: String },
+
+    /// Store the objects of the bundle in the current repository and list its refs
+    Unbundle { file: String },
+}
+
+#[derive(StructOpt, Debug)]
+pub enum WorktreeCommand {
+    /// Create a new working tree at path, sharing the objects and refs of the repository
+    Add {
+        /// Check out the new working tree even if the branch is checked out elsewhere
+        #[structopt(short, long)]
+        force: bool,
+
+        /// Detach HEAD in the new working tree
+        #[structopt(long)]
+        detach: bool,
+
+        /// Keep the new working tree locked
+        #[structopt(long)]
+        lock: bool,
+
+        /// Create a new branch for the new working tree
+        #[structopt(short = "b")]
+        branch: Option<String>,
+
         path: String,
+
+        /// The commit to check out, defaults to HEAD
+        commit_ish: Option<String>,
+    },
+
+    /// List the main working tree and the linked ones
+    List {
+        /// Output in an easy-to-parse format for scripts
+        #[structopt(long)]
+        porcelain: bool,
     },
+
+    /// Remove a linked working tree and its administrative files
+    Remove {
+        /// Remove the working tree even if it is dirty or locked
+        #[structopt(short, long)]
+        force: bool,
+
+        worktree: String,
+    },
+
+    /// Prune the administrative files of working trees whose directory vanished
+    Prune {
+        /// Do not remove anything, just report what would be removed
+        #[structopt(short = "n", long)]
+        dry_run: bool,
+
+        /// Report all removals
+        #[structopt(short, long)]
+        verbose: bool,
+    },
+
+    /// Prevent a linked working tree from being pruned, moved or removed
+    Lock {
+        #[structopt(long)]
+        reason: Option<String>,
+
+        worktree: String,
+    },
+
+    /// Allow a linked working tree to be pruned, moved or removed again
+    Unlock { worktree: String },
 }
 
 impl CommandLineParser {
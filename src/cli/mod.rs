@@ -1,4 +1,7 @@
 /// Copyright (C) 2023 Muqiu Han
 mod command_line_parser;
 
-pub use command_line_parser::CommandLineParser;
+pub use command_line_parser::{
+    BundleCommand, Command, CommandLineParser, CommitGraphCommand, MultiPackIndexCommand,
+    RemoteCommand, WorktreeCommand,
+};
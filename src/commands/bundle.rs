@@ -0,0 +1,107 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error::{self, Error, Log};
+use crate::remote::bundle::{self, Bundle};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Write a bundle of the refs among revs and the objects they need
+pub struct BundleCreate {
+    /// Include every ref
+    pub all: bool,
+
+    pub file: String,
+
+    /// The revisions to include, `^<rev>` and `<rev>..<rev>` exclude what their
+    /// first revision reaches
+    pub revs: Vec<String>,
+}
+
+/// Check that the bundle is valid and applies to the current repository
+pub struct BundleVerify {
+    pub file: String,
+}
+
+/// List the refs of the bundle
+pub struct BundleListHeads {
+    pub file: String,
+}
+
+/// Store the objects of the bundle in the current repository and list its refs
+pub struct BundleUnbundle {
+    pub file: String,
+}
+
+fn list_refs(bundle: &Bundle) {
+    for (name, sha) in &bundle.refs {
+        println!("{} {}", sha, name);
+    }
+}
+
+impl BundleCreate {
+    pub fn create(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let mut revs = self.revs.clone();
+        if self.all {
+            revs.push("--all".to_string());
+        }
+
+        let count =
+            bundle::create(&repo, Path::new(&self.file), &revs).unwrap_or_else(|e| e.panic());
+        info!("bundle of {} objects written to `{}`", count, self.file);
+    }
+}
+
+impl BundleVerify {
+    pub fn verify(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let bundle = Bundle::open(&self.file).unwrap_or_else(|e| e.panic());
+
+        let missing = bundle.missing_prerequisites(&repo);
+        if !(missing.is_empty()) {
+            Error::Remote(error::remote::Remote::MissingPrerequisites(missing)).panic()
+        }
+
+        list_refs(&bundle);
+        if bundle.prerequisites.is_empty() {
+            println!("The bundle records a complete history.");
+        } else {
+            println!("The bundle requires these prerequisite commits:");
+            for (sha, subject) in &bundle.prerequisites {
+                println!("{} {}", sha, subject);
+            }
+        }
+        println!("{} is okay", self.file);
+    }
+}
+
+impl BundleListHeads {
+    pub fn list_heads(&self) {
+        list_refs(&Bundle::open(&self.file).unwrap_or_else(|e| e.panic()));
+    }
+}
+
+impl BundleUnbundle {
+    pub fn unbundle(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let bundle = Bundle::open(&self.file).unwrap_or_else(|e| e.panic());
+
+        bundle.unbundle(&repo).unwrap_or_else(|e| e.panic());
+        list_refs(&bundle);
+    }
+}
@@ -16,20 +16,76 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
+use crate::error::{self, Log};
 use crate::object;
+use crate::repo::Repo;
+use std::path::Path;
 
 /// Provide contents or details of repository objects
+#[derive(Default)]
 pub struct CatFile {
-    /// Specify the type (blob | commit | tag | tree)
-    pub typ: String,
+    /// Show the type of the object
+    pub show_type: bool,
+
+    /// Show the size of the object
+    pub size: bool,
+
+    /// Exit with zero status if the object exists, with non-zero status otherwise
+    pub exists: bool,
+
+    /// Pretty-print the content of the object based on its type
+    pub pretty: bool,
+
+    /// Print the information line, in the format given if any, and the content of each
+    /// object named on stdin
+    pub batch: Option<Option<String>>,
+
+    /// Print the information line of each object named on stdin
+    pub batch_check: Option<Option<String>>,
+
+    /// Read `contents <object>`, `info <object>` and `flush` commands from stdin
+    pub batch_command: Option<Option<String>>,
+
+    /// Print all the objects of the repository rather than those named on stdin
+    pub batch_all_objects: bool,
+
+    /// The expected type (blob | commit | tag | tree), tags and commits are peeled to reach it
+    pub typ: Option<String>,
 
     /// The object to display
     pub object: String,
 }
 
 impl CatFile {
-    pub fn cat(&self) {
+    pub fn cat(&self, lit_dir: Option<&Path>) {
         info!("get the content of repository objects `{}`", self.object);
-        println!("{}", object::operation::cat(self))
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        if self.batch.is_some()
+            || self.batch_check.is_some()
+            || self.batch_command.is_some()
+            || self.batch_all_objects
+        {
+            let mut output = std::io::BufWriter::new(std::io::stdout().lock());
+            return object::operation::cat_batch(
+                &repo,
+                self,
+                &mut std::io::stdin().lock(),
+                &mut output,
+            )
+            .unwrap_or_else(|e| error::object::Object::Io("stdout".into(), e.to_string()).panic());
+        }
+
+        // Like git, only the exit status tells whether the object exists.
+        if self.exists {
+            std::process::exit(if object::operation::exists(&repo, self) {
+                0
+            } else {
+                1
+            })
+        }
+
+        object::operation::cat(&repo, self, &mut std::io::stdout().lock())
+            .unwrap_or_else(|e| error::object::Object::Io("stdout".into(), e.to_string()).panic())
     }
 }
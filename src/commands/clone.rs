@@ -0,0 +1,49 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::remote;
+
+/// Clone a repository into a new directory
+pub struct CloneRepo {
+    /// Make a bare repository, mirroring the branches of the source
+    pub bare: bool,
+
+    /// The path of the repository to clone
+    pub repository: String,
+
+    /// The directory to clone into, defaults to the name of the source
+    pub directory: Option<String>,
+}
+
+impl CloneRepo {
+    pub fn clone(&self) {
+        info!(
+            "cloning into `{}`...",
+            self.directory.clone().unwrap_or(
+                remote::fetch::clone_directory(&self.repository)
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            )
+        );
+
+        let repo = remote::fetch::clone(self).unwrap_or_else(|e| e.panic());
+        info!("clone ok on {}", repo.worktree.to_str().unwrap());
+    }
+}
@@ -0,0 +1,54 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Write the commit-graph of the commits reachable from the refs
+pub struct CommitGraphWrite {
+    /// Also store the filters of the paths each commit changes
+    pub changed_paths: bool,
+}
+
+/// Check the commit-graph against the commits it describes
+pub struct CommitGraphVerify {}
+
+impl CommitGraphWrite {
+    pub fn write(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let count = repo
+            .write_commit_graph(self.changed_paths)
+            .unwrap_or_else(|e| e.panic());
+        info!("commit-graph of {} commits written", count);
+    }
+}
+
+impl CommitGraphVerify {
+    pub fn verify(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let problems = repo.verify_commit_graph();
+
+        for (name, reason) in &problems {
+            println!("{}: {}", name, reason);
+        }
+        if !(problems.is_empty()) {
+            std::process::exit(1);
+        }
+    }
+}
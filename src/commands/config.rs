@@ -0,0 +1,239 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::{self, file::ConfigFile, Entry, Scope};
+use crate::error::{self, Log};
+use crate::repo::Repo;
+use std::path::{Path, PathBuf};
+
+/// Get and set repository or global options
+pub struct Config {
+    pub global: bool,
+    pub system: bool,
+    pub local: bool,
+    pub worktree: bool,
+    pub file: Option<String>,
+    pub get: bool,
+    pub get_all: bool,
+    pub set: bool,
+    pub add: bool,
+    pub unset: bool,
+    pub unset_all: bool,
+    pub list: bool,
+    pub show_origin: bool,
+
+    /// Check and canonicalize values (bool | int | path)
+    pub typ: Option<String>,
+    pub name: Option<String>,
+    pub value: Option<String>,
+}
+
+impl Config {
+    pub fn config(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), false, lit_dir);
+
+        if self.list {
+            self.read(repo.as_ref())
+                .entries
+                .iter()
+                .for_each(|entry| println!("{}", self.format_entry(entry)));
+            return;
+        }
+
+        // structopt requires a name unless listing.
+        let name = self.name.as_ref().unwrap();
+
+        let result = match (&self.value, self.add || self.set) {
+            _ if self.unset || self.unset_all => self.write(repo.as_ref(), |file| {
+                if self.unset_all {
+                    file.unset_all(name).map(|count| count > 0)
+                } else {
+                    file.unset(name)
+                }
+            }),
+            (Some(value), _) if !(self.get || self.get_all) => {
+                let value = self
+                    .canonicalize(name, Some(value))
+                    .unwrap_or_else(|e| e.panic());
+                self.write(repo.as_ref(), |file| {
+                    if self.add {
+                        file.add(name, &value).map(|_| true)
+                    } else {
+                        file.set(name, &value).map(|_| true)
+                    }
+                })
+            }
+            (None, true) => Err(error::Error::Config(error::config::Config::MissingValue(
+                name.to_string(),
+            ))),
+            _ => self.print(repo.as_ref(), name),
+        };
+
+        // Like git, a missing variable is not an error but is reported by the exit status.
+        match result {
+            Ok(true) => (),
+            Ok(false) => std::process::exit(if self.unset || self.unset_all { 5 } else { 1 }),
+            Err(e) => e.panic(),
+        }
+    }
+
+    /// The file selected by `--file`, `--global`, `--system` or `--local`, if any.
+    fn selected_file(&self, repo: Option<&Repo>) -> Option<(PathBuf, Scope)> {
+        if let Some(file) = &self.file {
+            Some((PathBuf::from(file), Scope::Local))
+        } else if self.global {
+            config::Config::global_write_path().map(|path| (path, Scope::Global))
+        } else if self.system {
+            config::Config::system_path().map(|path| (path, Scope::System))
+        } else if self.local {
+            Some((Self::local_file(repo), Scope::Local))
+        } else if self.worktree {
+            let repo = repo.unwrap_or_else(|| error::repo::Repo::CannotFindLitRepo.panic());
+            Some((repo.worktree_config_path(), Scope::Worktree))
+        } else {
+            None
+        }
+    }
+
+    fn local_file(repo: Option<&Repo>) -> PathBuf {
+        repo.unwrap_or_else(|| error::repo::Repo::CannotFindLitRepo.panic())
+            .common_dir
+            .join("config")
+    }
+
+    /// All the scopes merged, or only the selected one.
+    fn read(&self, repo: Option<&Repo>) -> config::Config {
+        let lit_dir = repo.map(|repo| repo.lit_dir.as_path());
+
+        let conf = if self.global && self.file.is_none() {
+            // Both global files are read, even though only one of them is written.
+            config::Config::global_paths().iter().try_fold(
+                config::Config::default(),
+                |mut conf, path| {
+                    let global = config::Config::load_file(path, Scope::Global, lit_dir)?;
+                    conf.entries.extend(global.entries);
+                    Ok(conf)
+                },
+            )
+        } else if self.file.is_some() || self.system || self.local || self.worktree {
+            match self.selected_file(repo) {
+                Some((path, scope)) => config::Config::load_file(&path, scope, lit_dir),
+                None => Ok(config::Config::default()),
+            }
+        } else {
+            match repo.and_then(|repo| repo.conf.clone()) {
+                Some(conf) => Ok(conf),
+                None => config::Config::load(lit_dir, None),
+            }
+        };
+
+        conf.unwrap_or_else(|e| e.panic())
+    }
+
+    /// Edit the selected file, the repository one by default.
+    fn write<F>(&self, repo: Option<&Repo>, edit: F) -> error::Result<bool>
+    where
+        F: FnOnce(&mut ConfigFile) -> error::Result<bool>,
+    {
+        let path = self
+            .selected_file(repo)
+            .map(|(path, _)| path)
+            .unwrap_or_else(|| Self::local_file(repo));
+
+        let mut file = ConfigFile::open(&path)?;
+        let changed = edit(&mut file)?;
+        if changed {
+            file.save()?;
+        }
+
+        Ok(changed)
+    }
+
+    fn print(&self, repo: Option<&Repo>, name: &str) -> error::Result<bool> {
+        let conf = self.read(repo);
+        let values: Vec<(&Entry, String)> = if self.get_all {
+            conf.get_all(name)?
+                .into_iter()
+                .map(|entry| Ok((entry, self.canonicalize(name, entry.value.as_deref())?)))
+                .collect::<error::Result<_>>()?
+        } else {
+            let value = match self.typ.as_deref() {
+                Some("bool") => conf.get_bool(name)?.map(|value| value.to_string()),
+                Some("int") => conf.get_int(name)?.map(|value| value.to_string()),
+                Some("path") => conf
+                    .get_path(name)?
+                    .map(|value| value.to_str().unwrap().to_string()),
+                _ => conf.get(name),
+            };
+
+            conf.get_entry(name)?.into_iter().zip(value).collect()
+        };
+
+        values.iter().for_each(|(entry, value)| {
+            if self.show_origin {
+                println!("file:{}\t{}", entry.origin.to_str().unwrap(), value)
+            } else {
+                println!("{}", value)
+            }
+        });
+
+        Ok(!(values.is_empty()))
+    }
+
+    /// Check value against `--type`, returning its canonical form.
+    /// A key without value is true.
+    fn canonicalize(&self, name: &str, value: Option<&str>) -> error::Result<String> {
+        if value.is_none() && self.typ.as_deref() != Some("int") {
+            return Ok(if self.typ.is_some() { "true" } else { "" }.to_string());
+        }
+
+        let value = value.unwrap_or_default();
+        let invalid = |typ: &str| {
+            error::Error::Config(error::config::Config::InvalidValue(
+                name.to_string(),
+                value.to_string(),
+                typ.to_string(),
+            ))
+        };
+
+        match self.typ.as_deref() {
+            Some("bool") => config::parse_bool(Some(value))
+                .map(|value| value.to_string())
+                .ok_or_else(|| invalid("bool")),
+            Some("int") => config::parse_int(value)
+                .map(|value| value.to_string())
+                .ok_or_else(|| invalid("int")),
+            Some("path") => Ok(config::expand_path(value).to_str().unwrap().to_string()),
+            Some(typ) => Err(invalid(typ)),
+            None => Ok(value.to_string()),
+        }
+    }
+
+    fn format_entry(&self, entry: &Entry) -> String {
+        let variable = match &entry.value {
+            Some(value) => format!("{}={}", entry.name(), value),
+            None => entry.name(),
+        };
+
+        if self.show_origin {
+            format!("file:{}\t{}", entry.origin.to_str().unwrap(), variable)
+        } else {
+            variable
+        }
+    }
+}
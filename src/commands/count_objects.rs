@@ -0,0 +1,50 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Count the loose objects and the packs, and their disk usage
+pub struct CountObjects {
+    /// Also report the packed objects, the loose ones a pack has, and the garbage
+    pub verbose: bool,
+}
+
+impl CountObjects {
+    pub fn count_objects(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let counts = repo.count_objects();
+
+        if !(self.verbose) {
+            println!("{} objects, {} kilobytes", counts.count, counts.size / 1024);
+            return;
+        }
+
+        for path in &counts.garbage {
+            warn!("garbage found: {}", path.display());
+        }
+        println!("count: {}", counts.count);
+        println!("size: {}", counts.size / 1024);
+        println!("in-pack: {}", counts.in_pack);
+        println!("packs: {}", counts.packs);
+        println!("size-pack: {}", counts.size_pack / 1024);
+        println!("prune-packable: {}", counts.prune_packable);
+        println!("garbage: {}", counts.garbage.len());
+        println!("size-garbage: {}", counts.size_garbage / 1024);
+    }
+}
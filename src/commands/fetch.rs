@@ -0,0 +1,63 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Log};
+use crate::remote::refspec::Refspec;
+use crate::remote::{fetch, Remote, Status};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Download objects and refs from another repository
+pub struct Fetch {
+    /// The remote to fetch from, defaults to the remote of the current branch or `origin`
+    pub remote: Option<String>,
+
+    /// The refs to fetch, defaults to `remote.<name>.fetch`
+    pub refspecs: Vec<String>,
+}
+
+impl Fetch {
+    pub fn fetch(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let name = self
+            .remote
+            .clone()
+            .unwrap_or_else(|| Remote::default_name(&repo));
+        let remote = Remote::load(&repo, &name).unwrap_or_else(|e| e.panic());
+        let refspecs: Vec<Refspec> = self
+            .refspecs
+            .iter()
+            .map(|refspec| Refspec::parse(refspec).unwrap_or_else(|e| e.panic()))
+            .collect();
+
+        let updates = fetch::fetch(&repo, &remote, &refspecs).unwrap_or_else(|e| e.panic());
+        let changed: Vec<_> = updates
+            .iter()
+            .filter(|update| update.status != Status::UpToDate)
+            .collect();
+
+        if !(changed.is_empty()) {
+            println!("From {}", remote.url);
+            changed.iter().for_each(|update| println!("{}", update));
+        }
+
+        if updates.iter().any(|update| update.is_rejected()) {
+            error::remote::Remote::Rejected(remote.url).panic()
+        }
+    }
+}
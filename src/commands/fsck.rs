@@ -0,0 +1,56 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::repo::fsck::Problem;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Verify the connectivity and validity of the objects in the database
+pub struct Fsck {
+    /// Check the objects in packs and alternates too, not only the loose ones
+    pub full: bool,
+
+    /// Only check that the objects reachable from the refs are there
+    pub connectivity_only: bool,
+
+    /// Report all the unreachable objects, not only the dangling ones
+    pub unreachable: bool,
+
+    /// Report one problem per line, as tab separated fields
+    pub porcelain: bool,
+}
+
+impl Fsck {
+    pub fn fsck(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let problems = repo.fsck(self.full, self.connectivity_only, self.unreachable);
+
+        for problem in &problems {
+            if self.porcelain {
+                println!("{}", problem.porcelain());
+            } else {
+                println!("{}", problem);
+            }
+        }
+
+        // Like git, dangling and unreachable objects do not make the repository broken.
+        if problems.iter().any(Problem::is_error) {
+            std::process::exit(1);
+        }
+    }
+}
@@ -16,10 +16,13 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
+use crate::error::{self, Log};
 use crate::object;
 use crate::repo::Repo;
+use std::path::Path;
 
 /// Compute object ID and optionally create an object from a file
+#[derive(Default)]
 pub struct HashObject {
     /// write the object into the object database
     pub write: bool,
@@ -27,19 +30,32 @@ pub struct HashObject {
     /// Specify the type (blob | commit | tag | tree)
     pub typ: String,
 
-    pub path: String,
+    /// Read the object from stdin, before the files
+    pub stdin: bool,
+
+    /// Read the paths of the files from stdin, one per line
+    pub stdin_paths: bool,
+
+    /// Allow any type, and skip checking that the content is a well-formed object
+    pub literally: bool,
+
+    /// Hash the files as they are, without the end-of-line conversion of `core.autocrlf`
+    pub no_filters: bool,
+
+    pub paths: Vec<String>,
 }
 
 impl HashObject {
-    pub fn hash_object(&self) {
-        let repo = {
-            if self.write {
-                Repo::repo_find(&".".to_string(), true)
-            } else {
-                None
-            }
-        };
-
-        println!("{}", object::operation::hash(&self.path, &self.typ, repo))
+    pub fn hash_object(&self, lit_dir: Option<&Path>) {
+        // Outside of a repository, objects are only hashed.
+        let repo = Repo::repo_find(&".".to_string(), self.write, lit_dir);
+
+        object::operation::hash(
+            self,
+            repo.as_ref(),
+            &mut std::io::stdin().lock(),
+            &mut std::io::stdout().lock(),
+        )
+        .unwrap_or_else(|e| error::object::Object::Io("stdout".into(), e.to_string()).panic())
     }
 }
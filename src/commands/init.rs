@@ -22,8 +22,17 @@ pub struct Init {
     /// Force initialization
     pub force: bool,
 
+    /// Create a bare repository, without a worktree
+    pub bare: bool,
+
     /// The repository path, defaults to the current directory (.)
     pub path: String,
+
+    /// Repository extensions to enable, as `name` or `name=value`
+    pub extensions: Vec<String>,
+
+    /// The hash function naming objects (sha1 | sha256), defaults to sha1
+    pub object_format: Option<String>,
 }
 
 impl Init {
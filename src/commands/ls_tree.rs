@@ -0,0 +1,77 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error, Log};
+use crate::object::operation;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// List the contents of a tree object
+pub struct LsTree {
+    /// Recurse into subtrees
+    pub recursive: bool,
+
+    /// Show the trees even when recursing into them
+    pub show_trees: bool,
+
+    /// Show the size of the blobs
+    pub long: bool,
+
+    /// Only show the paths
+    pub name_only: bool,
+
+    /// The tree, or the commit or tag naming it
+    pub tree_ish: String,
+
+    /// Only show the entries at or under these paths
+    pub paths: Vec<String>,
+}
+
+impl LsTree {
+    pub fn ls_tree(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let tree = repo.resolve_tree(&self.tree_ish).unwrap_or_else(|| {
+            Error::Repo(error::repo::Repo::BadRevision(self.tree_ish.clone())).panic()
+        });
+        let entries = repo
+            .ls_tree(&tree, self.recursive, self.show_trees, &self.paths)
+            .unwrap_or_else(|e| e.panic());
+
+        for (path, entry) in entries {
+            if self.name_only {
+                println!("{}", path);
+                continue;
+            }
+
+            let typ = entry.object_type();
+            if self.long {
+                let size = match typ {
+                    "blob" => operation::read_raw(&repo, &entry.sha)
+                        .map_or("-".to_string(), |(_, data)| data.len().to_string()),
+                    _ => "-".to_string(),
+                };
+                println!(
+                    "{:0>6} {} {} {:>7}\t{}",
+                    entry.mode, typ, entry.sha, size, path
+                );
+            } else {
+                println!("{:0>6} {} {}\t{}", entry.mode, typ, entry.sha, path);
+            }
+        }
+    }
+}
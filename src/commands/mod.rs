@@ -16,25 +16,375 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
-use crate::cli::CommandLineParser;
+use crate::cli::{
+    BundleCommand, Command, CommandLineParser, CommitGraphCommand, MultiPackIndexCommand,
+    RemoteCommand, WorktreeCommand,
+};
+use crate::commands::bundle::{BundleCreate, BundleListHeads, BundleUnbundle, BundleVerify};
 use crate::commands::cat_file::CatFile;
+use crate::commands::clone::CloneRepo;
+use crate::commands::commit_graph::{CommitGraphVerify, CommitGraphWrite};
+use crate::commands::config::Config;
+use crate::commands::count_objects::CountObjects;
+use crate::commands::fetch::Fetch;
+use crate::commands::fsck::Fsck;
 use crate::commands::hash_object::HashObject;
 use crate::commands::init::Init;
+use crate::commands::ls_tree::LsTree;
+use crate::commands::multi_pack_index::{
+    MultiPackIndexExpire, MultiPackIndexRepack, MultiPackIndexVerify, MultiPackIndexWrite,
+};
+use crate::commands::prune::Prune;
+use crate::commands::push::Push;
+use crate::commands::read_tree::ReadTree;
+use crate::commands::remote::{RemoteAdd, RemoteList, RemoteRemove, RemoteRename, RemoteSetUrl};
+use crate::commands::repack::Repack;
+use crate::commands::rev_list::RevList;
+use crate::commands::serve::Serve;
+use crate::commands::stats::Stats;
+use crate::commands::status::Status;
+use crate::commands::worktree::{
+    WorktreeAdd, WorktreeList, WorktreeLock, WorktreePrune, WorktreeRemove, WorktreeUnlock,
+};
+use crate::commands::write_tree::WriteTree;
+use std::path::Path;
 
+mod bundle;
 pub mod cat_file;
-mod hash_object;
+pub mod clone;
+mod commit_graph;
+mod config;
+mod count_objects;
+mod fetch;
+mod fsck;
+pub mod hash_object;
 pub mod init;
+mod ls_tree;
+mod multi_pack_index;
+mod prune;
+mod push;
+mod read_tree;
+mod remote;
+mod repack;
+mod rev_list;
+mod serve;
+mod stats;
+mod status;
+pub mod worktree;
+mod write_tree;
 
 pub fn command(args: CommandLineParser) {
-    match args {
-        CommandLineParser::Init { force, path } => {
-            Init { force, path }.init();
+    // `--lit-dir`, or the `LIT_DIR` environment variable, overrides the discovery of the
+    // repository.
+    let lit_dir = args.lit_dir.as_deref().map(Path::new);
+
+    match args.command {
+        Command::Init {
+            force,
+            bare,
+            path,
+            extensions,
+            object_format,
+        } => {
+            Init {
+                force,
+                bare,
+                path,
+                extensions,
+                object_format,
+            }
+            .init();
+        }
+
+        Command::CatFile {
+            show_type,
+            size,
+            exists,
+            pretty,
+            batch,
+            batch_check,
+            batch_command,
+            batch_all_objects,
+            mut objects,
+        } => {
+            // Batch modes name their objects on stdin.
+            let object = objects.pop().unwrap_or_default();
+            CatFile {
+                show_type,
+                size,
+                exists,
+                pretty,
+                batch,
+                batch_check,
+                batch_command,
+                batch_all_objects,
+                typ: objects.pop(),
+                object,
+            }
+            .cat(lit_dir)
+        }
+
+        Command::HashObject {
+            typ,
+            write,
+            stdin,
+            stdin_paths,
+            literally,
+            no_filters,
+            paths,
+        } => {
+            HashObject {
+                typ,
+                write,
+                stdin,
+                stdin_paths,
+                literally,
+                no_filters,
+                paths,
+            }
+            .hash_object(lit_dir);
+        }
+
+        Command::Worktree(command) => match command {
+            WorktreeCommand::Add {
+                force,
+                detach,
+                lock,
+                branch,
+                path,
+                commit_ish,
+            } => WorktreeAdd {
+                force,
+                detach,
+                lock,
+                branch,
+                path,
+                commit_ish,
+            }
+            .add(lit_dir),
+
+            WorktreeCommand::List { porcelain } => WorktreeList { porcelain }.list(lit_dir),
+
+            WorktreeCommand::Remove { force, worktree } => {
+                WorktreeRemove { force, worktree }.remove(lit_dir)
+            }
+
+            WorktreeCommand::Prune { dry_run, verbose } => {
+                WorktreePrune { dry_run, verbose }.prune(lit_dir)
+            }
+
+            WorktreeCommand::Lock { reason, worktree } => {
+                WorktreeLock { reason, worktree }.lock(lit_dir)
+            }
+
+            WorktreeCommand::Unlock { worktree } => WorktreeUnlock { worktree }.unlock(lit_dir),
+        },
+
+        Command::Config {
+            global,
+            system,
+            local,
+            worktree,
+            file,
+            get,
+            get_all,
+            set,
+            add,
+            unset,
+            unset_all,
+            list,
+            show_origin,
+            typ,
+            name,
+            value,
+        } => Config {
+            global,
+            system,
+            local,
+            worktree,
+            file,
+            get,
+            get_all,
+            set,
+            add,
+            unset,
+            unset_all,
+            list,
+            show_origin,
+            typ,
+            name,
+            value,
+        }
+        .config(lit_dir),
+
+        Command::Clone {
+            bare,
+            repository,
+            directory,
+        } => CloneRepo {
+            bare,
+            repository,
+            directory,
+        }
+        .clone(),
+
+        Command::Fetch { remote, refspecs } => Fetch { remote, refspecs }.fetch(lit_dir),
+
+        Command::Push {
+            force,
+            set_upstream,
+            remote,
+            refspecs,
+        } => Push {
+            force,
+            set_upstream,
+            remote,
+            refspecs,
+        }
+        .push(lit_dir),
+
+        Command::Remote { verbose, command } => match command {
+            None => RemoteList { verbose }.list(lit_dir),
+
+            Some(RemoteCommand::Add { fetch, name, url }) => {
+                RemoteAdd { fetch, name, url }.add(lit_dir)
+            }
+
+            Some(RemoteCommand::Remove { name }) => RemoteRemove { name }.remove(lit_dir),
+
+            Some(RemoteCommand::Rename { old, new }) => RemoteRename { old, new }.rename(lit_dir),
+
+            Some(RemoteCommand::SetUrl { push, name, url }) => {
+                RemoteSetUrl { push, name, url }.set_url(lit_dir)
+            }
+        },
+
+        Command::Bundle(command) => match command {
+            BundleCommand::Create { all, file, revs } => {
+                BundleCreate { all, file, revs }.create(lit_dir)
+            }
+
+            BundleCommand::Verify { file } => BundleVerify { file }.verify(lit_dir),
+
+            BundleCommand::ListHeads { file } => BundleListHeads { file }.list_heads(),
+
+            BundleCommand::Unbundle { file } => BundleUnbundle { file }.unbundle(lit_dir),
+        },
+
+        Command::Serve {
+            port,
+            receive_pack,
+            directory,
+        } => Serve {
+            port,
+            receive_pack,
+            directory,
+        }
+        .serve(),
+
+        Command::Fsck {
+            full,
+            no_full,
+            connectivity_only,
+            unreachable,
+            porcelain,
+        } => Fsck {
+            full: full || !no_full,
+            connectivity_only,
+            unreachable,
+            porcelain,
+        }
+        .fsck(lit_dir),
+
+        Command::CommitGraph(command) => match command {
+            CommitGraphCommand::Write { changed_paths } => {
+                CommitGraphWrite { changed_paths }.write(lit_dir)
+            }
+
+            CommitGraphCommand::Verify => CommitGraphVerify {}.verify(lit_dir),
+        },
+
+        Command::MultiPackIndex(command) => match command {
+            MultiPackIndexCommand::Write => MultiPackIndexWrite {}.write(lit_dir),
+
+            MultiPackIndexCommand::Verify => MultiPackIndexVerify {}.verify(lit_dir),
+
+            MultiPackIndexCommand::Expire => MultiPackIndexExpire {}.expire(lit_dir),
+
+            MultiPackIndexCommand::Repack { batch_size } => {
+                MultiPackIndexRepack { batch_size }.repack(lit_dir)
+            }
+        },
+
+        Command::Prune {
+            dry_run,
+            verbose,
+            expire,
+        } => Prune {
+            dry_run,
+            verbose,
+            expire,
+        }
+        .prune(lit_dir),
+
+        Command::Repack {
+            delete,
+            write_bitmap_index,
+        } => Repack {
+            delete,
+            write_bitmap_index,
+        }
+        .repack(lit_dir),
+
+        Command::RevList {
+            count,
+            objects,
+            all,
+            revs,
+            paths,
+        } => RevList {
+            count,
+            objects,
+            all,
+            revs,
+            paths,
+        }
+        .rev_list(lit_dir),
+
+        Command::CountObjects { verbose } => CountObjects { verbose }.count_objects(lit_dir),
+
+        Command::Stats { top } => Stats { top }.stats(lit_dir),
+
+        Command::LsTree {
+            recursive,
+            show_trees,
+            long,
+            name_only,
+            tree_ish,
+            paths,
+        } => LsTree {
+            recursive,
+            show_trees,
+            long,
+            name_only,
+            tree_ish,
+            paths,
         }
+        .ls_tree(lit_dir),
 
-        CommandLineParser::CatFile { typ, object } => CatFile { typ, object }.cat(),
+        Command::WriteTree => WriteTree {}.write_tree(lit_dir),
 
-        CommandLineParser::HashObject { typ, write, path } => {
-            HashObject { typ, write, path }.hash_object();
+        Command::ReadTree {
+            merge,
+            prefix,
+            trees,
+        } => ReadTree {
+            merge,
+            prefix,
+            trees,
         }
+        .read_tree(lit_dir),
+
+        Command::Status => Status {}.status(lit_dir),
     }
 }
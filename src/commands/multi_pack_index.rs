@@ -0,0 +1,103 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::parse_int;
+use crate::error::{self, Error, Log};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Write the multi-pack-index of the packs of the repository
+pub struct MultiPackIndexWrite {}
+
+/// Check the multi-pack-index against the packs it covers
+pub struct MultiPackIndexVerify {}
+
+/// Delete the packs the multi-pack-index takes no object from
+pub struct MultiPackIndexExpire {}
+
+/// Gather the objects of the smaller packs of the multi-pack-index into a new pack
+pub struct MultiPackIndexRepack {
+    /// Only gather packs expected to hold less than this, with an optional k, m or g suffix,
+    /// until their total reaches it. All the packs when 0.
+    pub batch_size: String,
+}
+
+fn repo(lit_dir: Option<&Path>) -> Repo {
+    Repo::repo_find(&".".to_string(), true, lit_dir).unwrap()
+}
+
+impl MultiPackIndexWrite {
+    pub fn write(&self, lit_dir: Option<&Path>) {
+        let (packs, objects) = repo(lit_dir)
+            .pack_database()
+            .write_multi_pack_index()
+            .unwrap_or_else(|e| e.panic());
+        info!(
+            "multi-pack-index of {} objects in {} packs written",
+            objects, packs
+        );
+    }
+}
+
+impl MultiPackIndexVerify {
+    pub fn verify(&self, lit_dir: Option<&Path>) {
+        let problems = repo(lit_dir).pack_database().verify_multi_pack_index();
+
+        for (name, reason) in &problems {
+            println!("{}: {}", name, reason);
+        }
+        if !(problems.is_empty()) {
+            std::process::exit(1);
+        }
+    }
+}
+
+impl MultiPackIndexExpire {
+    pub fn expire(&self, lit_dir: Option<&Path>) {
+        for name in repo(lit_dir)
+            .expire_multi_pack_index()
+            .unwrap_or_else(|e| e.panic())
+        {
+            info!("expired {}", name);
+        }
+    }
+}
+
+impl MultiPackIndexRepack {
+    pub fn repack(&self, lit_dir: Option<&Path>) {
+        let batch_size = parse_int(&self.batch_size)
+            .and_then(|size| u64::try_from(size).ok())
+            .unwrap_or_else(|| {
+                Error::Config(error::config::Config::InvalidValue(
+                    "--batch-size".to_string(),
+                    self.batch_size.clone(),
+                    "size".to_string(),
+                ))
+                .panic()
+            });
+
+        match repo(lit_dir)
+            .pack_database()
+            .repack_multi_pack_index(batch_size)
+            .unwrap_or_else(|e| e.panic())
+        {
+            Some(name) => info!("repacked into {}", name),
+            None => info!("nothing to repack"),
+        }
+    }
+}
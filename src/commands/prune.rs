@@ -0,0 +1,51 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Remove the unreachable loose objects older than the grace period
+pub struct Prune {
+    /// Do not remove anything, just report what would be removed
+    pub dry_run: bool,
+
+    /// Report all removed objects
+    pub verbose: bool,
+
+    /// Only remove the objects older than this date, defaults to `gc.pruneExpire`
+    pub expire: Option<String>,
+}
+
+impl Prune {
+    pub fn prune(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let expire = repo
+            .prune_expire(self.expire.as_deref())
+            .unwrap_or_else(|e| e.panic());
+
+        for (sha, fmt) in repo
+            .prune(expire, self.dry_run)
+            .unwrap_or_else(|e| e.panic())
+        {
+            if self.dry_run || self.verbose {
+                println!("{} {}", sha, fmt);
+            }
+        }
+    }
+}
@@ -0,0 +1,93 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Log};
+use crate::remote::refspec::Refspec;
+use crate::remote::{self, Remote, Status};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Update remote refs along with their objects
+pub struct Push {
+    /// Allow non-fast-forward updates
+    pub force: bool,
+
+    /// Make the pushed branches track the remote branches they were pushed to
+    pub set_upstream: bool,
+
+    /// The remote to push to, defaults to the remote of the current branch or `origin`
+    pub remote: Option<String>,
+
+    /// The refs to push, defaults to `remote.<name>.push` or the current branch
+    pub refspecs: Vec<String>,
+}
+
+impl Push {
+    pub fn push(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let name = self
+            .remote
+            .clone()
+            .unwrap_or_else(|| Remote::default_name(&repo));
+        let remote = Remote::load(&repo, &name).unwrap_or_else(|e| e.panic());
+        let refspecs: Vec<Refspec> = self
+            .refspecs
+            .iter()
+            .map(|refspec| Refspec::parse(refspec).unwrap_or_else(|e| e.panic()))
+            .collect();
+
+        let updates =
+            remote::push::push(&repo, &remote, &refspecs, self.force).unwrap_or_else(|e| e.panic());
+
+        if updates
+            .iter()
+            .all(|update| update.status == Status::UpToDate)
+        {
+            println!("Everything up-to-date");
+        } else {
+            println!("To {}", remote.push_url());
+            updates
+                .iter()
+                .filter(|update| update.status != Status::UpToDate)
+                .for_each(|update| println!("{}", update));
+        }
+
+        if self.set_upstream {
+            for update in updates.iter().filter(|update| {
+                !(update.is_rejected())
+                    && update.new.is_some()
+                    && update.dst.starts_with("refs/heads/")
+            }) {
+                if let Some(branch) = update.src.strip_prefix("refs/heads/") {
+                    remote::set_upstream(&repo, branch, &remote.name, &update.dst)
+                        .unwrap_or_else(|e| e.panic());
+                    println!(
+                        "branch '{}' set up to track '{}/{}'.",
+                        branch,
+                        remote.name,
+                        &update.dst["refs/heads/".len()..]
+                    );
+                }
+            }
+        }
+
+        if updates.iter().any(|update| update.is_rejected()) {
+            error::remote::Remote::Rejected(remote.push_url().to_string()).panic()
+        }
+    }
+}
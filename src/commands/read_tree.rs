@@ -0,0 +1,51 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error, Log};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Read tree information into the index
+pub struct ReadTree {
+    /// Merge the trees into the index instead of replacing it
+    pub merge: bool,
+
+    /// Read the tree under this directory of the index
+    pub prefix: Option<String>,
+
+    /// The trees, or the commits or tags naming them
+    pub trees: Vec<String>,
+}
+
+impl ReadTree {
+    pub fn read_tree(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let trees: Vec<String> = self
+            .trees
+            .iter()
+            .map(|name| {
+                repo.resolve_tree(name).unwrap_or_else(|| {
+                    Error::Repo(error::repo::Repo::BadRevision(name.clone())).panic()
+                })
+            })
+            .collect();
+
+        repo.read_tree(&trees, self.merge, self.prefix.as_deref())
+            .unwrap_or_else(|e| e.panic());
+    }
+}
@@ -0,0 +1,115 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::commands::fetch::Fetch;
+use crate::error::Log;
+use crate::remote::Remote;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// List the remotes
+pub struct RemoteList {
+    /// Show the urls after the names
+    pub verbose: bool,
+}
+
+/// Add a remote fetching all the branches of the repository at url
+pub struct RemoteAdd {
+    /// Fetch the remote right after adding it
+    pub fetch: bool,
+
+    pub name: String,
+    pub url: String,
+}
+
+/// Remove a remote, its remote-tracking branches and the upstream of the branches tracking it
+pub struct RemoteRemove {
+    pub name: String,
+}
+
+/// Rename a remote along with its remote-tracking branches
+pub struct RemoteRename {
+    pub old: String,
+    pub new: String,
+}
+
+/// Change the url of a remote
+pub struct RemoteSetUrl {
+    /// Change the url used to push instead
+    pub push: bool,
+
+    pub name: String,
+    pub url: String,
+}
+
+impl RemoteList {
+    pub fn list(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        for name in Remote::names(&repo) {
+            if !(self.verbose) {
+                println!("{}", name);
+                continue;
+            }
+
+            match Remote::load(&repo, &name) {
+                Ok(remote) => {
+                    println!("{}\t{} (fetch)", name, remote.url);
+                    println!("{}\t{} (push)", name, remote.push_url());
+                }
+                Err(e) => warn!("{}", e.fmt()),
+            }
+        }
+    }
+}
+
+impl RemoteAdd {
+    pub fn add(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        Remote::add(&repo, &self.name, &self.url).unwrap_or_else(|e| e.panic());
+
+        if self.fetch {
+            Fetch {
+                remote: Some(self.name.clone()),
+                refspecs: vec![],
+            }
+            .fetch(lit_dir);
+        }
+    }
+}
+
+impl RemoteRemove {
+    pub fn remove(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        Remote::remove(&repo, &self.name).unwrap_or_else(|e| e.panic());
+    }
+}
+
+impl RemoteRename {
+    pub fn rename(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        Remote::rename(&repo, &self.old, &self.new).unwrap_or_else(|e| e.panic());
+    }
+}
+
+impl RemoteSetUrl {
+    pub fn set_url(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        Remote::set_url(&repo, &self.name, &self.url, self.push).unwrap_or_else(|e| e.panic());
+    }
+}
@@ -0,0 +1,44 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Pack all the reachable objects into a single pack
+pub struct Repack {
+    /// Delete the other packs, and the loose objects the new pack has
+    pub delete: bool,
+
+    /// Write reachability bitmaps along with the pack
+    pub write_bitmap_index: bool,
+}
+
+impl Repack {
+    pub fn repack(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        match repo
+            .repack(self.delete, self.write_bitmap_index)
+            .unwrap_or_else(|e| e.panic())
+        {
+            Some((name, objects)) => info!("{} objects packed into {}", objects, name),
+            None => info!("nothing to pack"),
+        }
+    }
+}
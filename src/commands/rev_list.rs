@@ -0,0 +1,77 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error, Log};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// List the commits reachable from some commits but not from others
+pub struct RevList {
+    /// Print how many commits, or objects, would be listed instead
+    pub count: bool,
+
+    /// List all the objects the commits reach
+    pub objects: bool,
+
+    /// Start from all the refs and HEAD
+    pub all: bool,
+
+    /// `<rev>`, `^<rev>` or `<a>..<b>`
+    pub revs: Vec<String>,
+
+    /// Only list the commits changing one of these paths
+    pub paths: Vec<String>,
+}
+
+impl RevList {
+    pub fn rev_list(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let resolve = |rev: &str| {
+            let rev = if rev.is_empty() { "HEAD" } else { rev };
+            repo.resolve_name(rev).unwrap_or_else(|| {
+                Error::Repo(error::repo::Repo::BadRevision(rev.to_owned())).panic()
+            })
+        };
+
+        let mut include = Vec::new();
+        let mut exclude = Vec::new();
+        if self.all {
+            include.extend(repo.ref_resolve("HEAD"));
+            include.extend(repo.ref_list().into_values());
+        }
+        for rev in &self.revs {
+            if let Some((from, to)) = rev.split_once("..") {
+                exclude.push(resolve(from));
+                include.push(resolve(to));
+            } else if let Some(from) = rev.strip_prefix('^') {
+                exclude.push(resolve(from));
+            } else {
+                include.push(resolve(rev));
+            }
+        }
+
+        let listed = repo
+            .rev_list(&include, &exclude, self.objects, &self.paths)
+            .unwrap_or_else(|e| e.panic());
+        if self.count {
+            println!("{}", listed.len());
+        } else {
+            listed.iter().for_each(|sha| println!("{}", sha));
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error::{self, Log};
+use crate::remote::daemon;
+use std::net::TcpListener;
+use std::path::PathBuf;
+
+/// Serve repositories over the `git://` protocol on localhost
+pub struct Serve {
+    /// The port to listen on
+    pub port: u16,
+
+    /// Allow pushing to the served repositories
+    pub receive_pack: bool,
+
+    /// The directory holding the served repositories, defaults to the current directory
+    pub directory: Option<String>,
+}
+
+impl Serve {
+    pub fn serve(&self) {
+        let root = PathBuf::from(self.directory.clone().unwrap_or(".".to_string()));
+        let address = format!("127.0.0.1:{}", self.port);
+        let listener = TcpListener::bind(&address).unwrap_or_else(|e| {
+            error::remote::Remote::Transport(address.clone(), e.to_string()).panic()
+        });
+
+        info!("serving {} on git://{}/", root.display(), address);
+        daemon::serve(listener, root, self.receive_pack);
+    }
+}
@@ -0,0 +1,59 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Show what makes the repository big
+pub struct Stats {
+    /// How many entries of each list to show
+    pub top: usize,
+}
+
+impl Stats {
+    pub fn stats(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let stats = repo.stats(self.top).unwrap_or_else(|e| e.panic());
+
+        println!("Objects:");
+        for (fmt, (count, size)) in &stats.types {
+            println!("  {:<8} {:>10} {:>14}", fmt, count, size);
+        }
+
+        println!("Biggest blobs:");
+        for (size, sha, path) in &stats.biggest_blobs {
+            println!("  {:>14} {} {}", size, sha, path);
+        }
+
+        println!("Deepest trees:");
+        for (depth, path) in &stats.deepest_trees {
+            println!("  {:>6} {}", depth, path);
+        }
+
+        println!("Longest delta chains:");
+        for (depth, sha) in &stats.delta_chains {
+            println!("  {:>6} {}", depth, sha);
+        }
+
+        println!("Most changed directories:");
+        for (commits, dir) in &stats.churn {
+            println!("  {:>6} {}", commits, dir);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::status::{FileStatus, Tracking};
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Show the working tree status
+pub struct Status {}
+
+/// Upstream names are shown the way users type them, e.g. `origin/master`.
+fn short_ref(name: &str) -> &str {
+    ["refs/heads/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+fn commits(count: usize) -> String {
+    match count {
+        1 => "1 commit".to_string(),
+        count => format!("{} commits", count),
+    }
+}
+
+/// The same sentences as git.
+fn tracking_message(tracking: &Tracking) -> String {
+    let upstream = short_ref(&tracking.upstream);
+
+    match (tracking.ahead, tracking.behind) {
+        _ if tracking.gone => format!(
+            "Your branch is based on '{}', but the upstream is gone.",
+            upstream
+        ),
+        (0, 0) => format!("Your branch is up to date with '{}'.", upstream),
+        (ahead, 0) => format!(
+            "Your branch is ahead of '{}' by {}.",
+            upstream,
+            commits(ahead)
+        ),
+        (0, behind) => format!(
+            "Your branch is behind '{}' by {}, and can be fast-forwarded.",
+            upstream,
+            commits(behind)
+        ),
+        (ahead, behind) => format!(
+            "Your branch and '{}' have diverged,\nand have {} and {} different commits each, respectively.",
+            upstream, ahead, behind
+        ),
+    }
+}
+
+fn print_files(title: &str, files: &[(FileStatus, String)]) {
+    if !(files.is_empty()) {
+        println!("{}:", title);
+        files
+            .iter()
+            .for_each(|(status, path)| println!("\t{:<12}{}", format!("{}:", status), path));
+        println!();
+    }
+}
+
+impl Status {
+    pub fn status(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+        let status = repo.status().unwrap_or_else(|e| e.panic());
+
+        match (&status.branch, &status.head) {
+            (Some(branch), _) => println!("On branch {}", branch),
+            (None, Some(head)) => println!("HEAD detached at {}", &head[..7]),
+            (None, None) => println!("Not currently on any branch."),
+        }
+
+        if let Some(tracking) = &status.tracking {
+            println!("{}", tracking_message(tracking));
+        }
+
+        if status.head.is_none() {
+            println!("\nNo commits yet");
+        }
+        println!();
+
+        print_files("Changes to be committed", &status.staged);
+        print_files("Changes not staged for commit", &status.unstaged);
+
+        if !(status.untracked.is_empty()) {
+            println!("Untracked files:");
+            status
+                .untracked
+                .iter()
+                .for_each(|path| println!("\t{}", path));
+            println!();
+        }
+
+        match (
+            status.staged.is_empty(),
+            status.unstaged.is_empty(),
+            status.untracked.is_empty(),
+        ) {
+            (true, true, true) => println!("nothing to commit, working tree clean"),
+            (true, true, false) => println!("nothing added to commit but untracked files present"),
+            (true, false, _) => println!("no changes added to commit"),
+            _ => (),
+        }
+    }
+}
@@ -0,0 +1,137 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use crate::repo::Repo;
+use std::path::Path;
+
+/// Create a new working tree at path, sharing the objects and refs of the repository
+pub struct WorktreeAdd {
+    /// Check out the new working tree even if the branch is checked out elsewhere
+    pub force: bool,
+
+    /// Detach HEAD in the new working tree
+    pub detach: bool,
+
+    /// Keep the new working tree locked
+    pub lock: bool,
+
+    /// Create a new branch for the new working tree
+    pub branch: Option<String>,
+
+    pub path: String,
+
+    /// The commit to check out, defaults to HEAD
+    pub commit_ish: Option<String>,
+}
+
+/// List the main working tree and the linked ones
+pub struct WorktreeList {
+    /// Output in an easy-to-parse format for scripts
+    pub porcelain: bool,
+}
+
+/// Remove a linked working tree and its administrative files
+pub struct WorktreeRemove {
+    /// Remove the working tree even if it is dirty or locked
+    pub force: bool,
+
+    pub worktree: String,
+}
+
+/// Prune the administrative files of working trees whose directory vanished
+pub struct WorktreePrune {
+    /// Do not remove anything, just report what would be removed
+    pub dry_run: bool,
+
+    /// Report all removals
+    pub verbose: bool,
+}
+
+/// Prevent a linked working tree from being pruned, moved or removed
+pub struct WorktreeLock {
+    pub reason: Option<String>,
+    pub worktree: String,
+}
+
+pub struct WorktreeUnlock {
+    pub worktree: String,
+}
+
+impl WorktreeAdd {
+    pub fn add(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        info!("preparing worktree `{}`...", self.path);
+        let linked = repo.worktree_add(self).unwrap_or_else(|e| e.panic());
+        info!("worktree ready on {}", linked.worktree.to_str().unwrap());
+    }
+}
+
+impl WorktreeList {
+    pub fn list(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        repo.worktree_list_entries().iter().for_each(|entry| {
+            if self.porcelain {
+                println!("{}\n", entry.porcelain())
+            } else {
+                println!("{}", entry)
+            }
+        })
+    }
+}
+
+impl WorktreeRemove {
+    pub fn remove(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        info!("removing worktree `{}`...", self.worktree);
+        repo.worktree_remove(&self.worktree, self.force)
+            .unwrap_or_else(|e| e.panic());
+    }
+}
+
+impl WorktreePrune {
+    pub fn prune(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        repo.worktree_prune(self.dry_run)
+            .iter()
+            .filter(|_| self.verbose || self.dry_run)
+            .for_each(|(name, reason)| println!("Removing worktrees/{}: {}", name, reason))
+    }
+}
+
+impl WorktreeLock {
+    pub fn lock(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        repo.worktree_lock(&self.worktree, self.reason.as_deref())
+            .unwrap_or_else(|e| e.panic());
+    }
+}
+
+impl WorktreeUnlock {
+    pub fn unlock(&self, lit_dir: Option<&Path>) {
+        let repo = Repo::repo_find(&".".to_string(), true, lit_dir).unwrap();
+
+        repo.worktree_unlock(&self.worktree)
+            .unwrap_or_else(|e| e.panic());
+    }
+}
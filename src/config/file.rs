@@ -0,0 +1,314 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::parse::{self, format_section, format_value, Item, Line};
+use crate::config::Name;
+use crate::error::{self, Error};
+use crate::lock;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single configuration file opened for editing.
+/// Edits only touch the lines of the variables they change, comments and layout are kept.
+pub struct ConfigFile {
+    pub path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl ConfigFile {
+    /// Open path, a missing file reads as an empty one.
+    pub fn open(path: &Path) -> error::Result<Self> {
+        let content = fs::read_to_string(path).unwrap_or_default();
+        let file = ConfigFile {
+            path: path.to_owned(),
+            lines: content.lines().map(|line| line.to_string()).collect(),
+        };
+
+        file.parse()?;
+        Ok(file)
+    }
+
+    fn parse(&self) -> error::Result<Vec<Line>> {
+        parse::parse(&self.lines.join("\n"))
+            .map_err(|line| Error::Config(error::config::Config::Parse(self.path.clone(), line)))
+    }
+
+    /// The lines defining name.
+    fn find(&self, name: &Name) -> error::Result<Vec<Line>> {
+        let mut section = None;
+
+        Ok(self
+            .parse()?
+            .into_iter()
+            .filter(|line| match &line.item {
+                Item::Section(current) => {
+                    section = Some(current.clone());
+                    false
+                }
+                Item::Entry(key, _) => section.as_ref().is_some_and(|section| {
+                    section.name == name.section
+                        && section.subsection == name.subsection
+                        && *key == name.key
+                }),
+                Item::Other => false,
+            })
+            .collect())
+    }
+
+    /// The key is written as the user typed it, git does the same.
+    fn format_entry(name: &str, value: &str) -> String {
+        format!(
+            "\t{} = {}",
+            name.rsplit('.').next().unwrap(),
+            format_value(value)
+        )
+    }
+
+    /// Set name to value, replacing its single existing value.
+    pub fn set(&mut self, name: &str, value: &str) -> error::Result<()> {
+        let parsed = Name::parse(name)?;
+
+        match self.find(&parsed)?.as_slice() {
+            [] => self.add(name, value),
+            [line] => {
+                self.lines
+                    .splice(line.start..=line.end, [Self::format_entry(name, value)]);
+                Ok(())
+            }
+            _ => Err(Error::Config(error::config::Config::MultipleValues(
+                name.to_string(),
+            ))),
+        }
+    }
+
+    /// Add a value to name, after the last variable of its section.
+    pub fn add(&mut self, name: &str, value: &str) -> error::Result<()> {
+        let parsed = Name::parse(name)?;
+        let mut position = None;
+        let mut in_section = false;
+
+        for line in self.parse()? {
+            match line.item {
+                Item::Section(section) => {
+                    in_section =
+                        section.name == parsed.section && section.subsection == parsed.subsection;
+                    if in_section {
+                        position = Some(line.end + 1);
+                    }
+                }
+                Item::Entry(_, _) if in_section => position = Some(line.end + 1),
+                _ => (),
+            }
+        }
+
+        let entry = Self::format_entry(name, value);
+        match position {
+            Some(position) => self.lines.insert(position, entry),
+            None => {
+                self.lines.push(format_section(
+                    &parsed.section,
+                    parsed.subsection.as_deref(),
+                ));
+                self.lines.push(entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the single value of name, returns whether there was one.
+    pub fn unset(&mut self, name: &str) -> error::Result<bool> {
+        match self.find(&Name::parse(name)?)?.as_slice() {
+            [] => Ok(false),
+            [line] => {
+                self.lines.drain(line.start..=line.end);
+                Ok(true)
+            }
+            _ => Err(Error::Config(error::config::Config::MultipleValues(
+                name.to_string(),
+            ))),
+        }
+    }
+
+    /// Remove all the values of name, returns how many there were.
+    pub fn unset_all(&mut self, name: &str) -> error::Result<usize> {
+        let lines = self.find(&Name::parse(name)?)?;
+
+        lines.iter().rev().for_each(|line| {
+            self.lines.drain(line.start..=line.end);
+        });
+
+        Ok(lines.len())
+    }
+
+    /// The lines of every `[section "subsection"]`, from its header to the next one.
+    fn section_ranges(
+        &self,
+        section: &str,
+        subsection: Option<&str>,
+    ) -> error::Result<Vec<(usize, usize)>> {
+        let mut ranges: Vec<(usize, usize)> = Vec::new();
+        let mut in_section = false;
+
+        for line in self.parse()? {
+            match line.item {
+                Item::Section(current) => {
+                    in_section = current.name == section.to_lowercase()
+                        && current.subsection.as_deref() == subsection;
+                    if in_section {
+                        ranges.push((line.start, line.end));
+                    }
+                }
+                _ if in_section => ranges.last_mut().unwrap().1 = line.end,
+                _ => (),
+            }
+        }
+
+        Ok(ranges)
+    }
+
+    /// Remove a whole section with its variables, returns whether it existed.
+    pub fn remove_section(
+        &mut self,
+        section: &str,
+        subsection: Option<&str>,
+    ) -> error::Result<bool> {
+        let ranges = self.section_ranges(section, subsection)?;
+
+        ranges.iter().rev().for_each(|(start, end)| {
+            self.lines.drain(start..=end);
+        });
+
+        Ok(!(ranges.is_empty()))
+    }
+
+    /// Rename the subsection of a section, keeping its variables, returns whether it existed.
+    pub fn rename_section(
+        &mut self,
+        section: &str,
+        subsection: Option<&str>,
+        new_subsection: Option<&str>,
+    ) -> error::Result<bool> {
+        let headers: Vec<Line> = self
+            .parse()?
+            .into_iter()
+            .filter(|line| {
+                matches!(&line.item, Item::Section(current)
+                    if current.name == section.to_lowercase()
+                        && current.subsection.as_deref() == subsection)
+            })
+            .collect();
+
+        // A variable following the header on the same line stays after the new header.
+        headers.iter().for_each(|header| {
+            let line = &mut self.lines[header.start];
+            let (_, rest) = parse::parse_section(line.trim_start()).unwrap();
+            let rest = rest.trim().to_string();
+
+            *line = format_section(&section.to_lowercase(), new_subsection);
+            if !(rest.is_empty()) {
+                line.push(' ');
+                line.push_str(&rest);
+            }
+        });
+
+        Ok(!(headers.is_empty()))
+    }
+
+    /// Write the file through a lock file, so that readers never see it half written.
+    pub fn save(&self) -> error::Result<()> {
+        let io = |e: std::io::Error| {
+            Error::Config(error::config::Config::Io(self.path.clone(), e.to_string()))
+        };
+        let mut content = self.lines.join("\n");
+        content.push('\n');
+        lock::write(&self.path, content.as_bytes()).map_err(io)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConfigFile;
+    use crate::config::{Config, Scope};
+    use crate::error;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_edit_preserves_layout() {
+        let _ = fs::remove_dir_all("./test_config_file");
+        fs::create_dir_all("./test_config_file").unwrap();
+        let path = Path::new("./test_config_file/config");
+        fs::write(
+            path,
+            "# keep me\n\
+             [core]\n\
+             \tbare = false\n\
+             \n\
+             [remote \"origin\"]\n\
+             \turl = /srv/a.git\n",
+        )
+        .unwrap();
+
+        let mut file = ConfigFile::open(path).unwrap();
+        file.set("core.bare", "true").unwrap();
+        file.add("core.editor", "vi # not a comment").unwrap();
+        file.add("remote.origin.fetch", "+refs/heads/*:refs/remotes/origin/*")
+            .unwrap();
+        file.add("remote.origin.fetch", "+refs/tags/*:refs/tags/*")
+            .unwrap();
+        file.set("user.name", "lit").unwrap();
+        file.save().unwrap();
+
+        assert_eq!(
+            "# keep me\n\
+             [core]\n\
+             \tbare = true\n\
+             \teditor = \"vi # not a comment\"\n\
+             \n\
+             [remote \"origin\"]\n\
+             \turl = /srv/a.git\n\
+             \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+             \tfetch = +refs/tags/*:refs/tags/*\n\
+             [user]\n\
+             \tname = lit\n",
+            fs::read_to_string(path).unwrap()
+        );
+
+        let mut file = ConfigFile::open(path).unwrap();
+        assert!(matches!(
+            file.unset("remote.origin.fetch"),
+            Err(error::Error::Config(error::config::Config::MultipleValues(
+                _
+            )))
+        ));
+        assert_eq!(2, file.unset_all("remote.origin.fetch").unwrap());
+        assert!(file.unset("core.editor").unwrap());
+        file.save().unwrap();
+
+        let config = Config::load_file(path, Scope::Local, None).unwrap();
+        assert_eq!(Some(true), config.get_bool("core.bare").unwrap());
+        assert_eq!(None, config.get("core.editor"));
+        assert_eq!(
+            Some("/srv/a.git".to_string()),
+            config.get("remote.origin.url")
+        );
+
+        fs::remove_dir_all("./test_config_file").unwrap();
+    }
+}
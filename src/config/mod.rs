@@ -0,0 +1,673 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+pub mod file;
+pub mod parse;
+
+use crate::error::{self, Error};
+use parse::Item;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Includes are followed up to this depth, to break include cycles.
+const MAX_INCLUDE_DEPTH: usize = 10;
+
+/// Where a configuration variable comes from, from the lowest to the highest priority.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    System,
+    Global,
+    Local,
+
+    /// `config.worktree`, with the `worktreeconfig` extension
+    Worktree,
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Scope::System => write!(f, "system"),
+            Scope::Global => write!(f, "global"),
+            Scope::Local => write!(f, "local"),
+            Scope::Worktree => write!(f, "worktree"),
+        }
+    }
+}
+
+/// A variable, with the file that defined it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub section: String,
+    pub subsection: Option<String>,
+    pub key: String,
+
+    /// None for a key written without `=`, which means true
+    pub value: Option<String>,
+    pub origin: PathBuf,
+    pub scope: Scope,
+}
+
+impl Entry {
+    /// The canonical name, e.g. `remote.origin.url`
+    pub fn name(&self) -> String {
+        match &self.subsection {
+            Some(subsection) => format!("{}.{}.{}", self.section, subsection, self.key),
+            None => format!("{}.{}", self.section, self.key),
+        }
+    }
+}
+
+/// A name split into its section, subsection and key.
+/// Section and key are case-insensitive, the subsection is not.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name {
+    pub section: String,
+    pub subsection: Option<String>,
+    pub key: String,
+}
+
+impl Name {
+    pub fn parse(name: &str) -> error::Result<Self> {
+        let invalid = || Error::Config(error::config::Config::InvalidKey(name.to_owned()));
+        let (section, rest) = name.split_once('.').ok_or_else(invalid)?;
+        let (subsection, key) = match rest.rsplit_once('.') {
+            Some((subsection, key)) => (Some(subsection.to_string()), key),
+            None => (None, rest),
+        };
+
+        let valid = |part: &str| {
+            !(part.is_empty()) && part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        };
+
+        if valid(section) && valid(key) && key.chars().next().unwrap().is_ascii_alphabetic() {
+            Ok(Name {
+                section: section.to_lowercase(),
+                subsection,
+                key: key.to_lowercase(),
+            })
+        } else {
+            Err(invalid())
+        }
+    }
+
+    fn matches(&self, entry: &Entry) -> bool {
+        self.section == entry.section
+            && self.subsection == entry.subsection
+            && self.key == entry.key
+    }
+}
+
+/// The configuration of a repository: the system, global and repository files merged,
+/// later variables overriding earlier ones.
+#[derive(Debug, Default, Clone)]
+pub struct Config {
+    pub entries: Vec<Entry>,
+}
+
+impl Config {
+    /// The system-wide file, `/etc/litconfig` unless `LIT_CONFIG_SYSTEM` is set.
+    pub fn system_path() -> Option<PathBuf> {
+        if std::env::var_os("LIT_CONFIG_NOSYSTEM").is_some() {
+            None
+        } else {
+            Some(
+                std::env::var_os("LIT_CONFIG_SYSTEM")
+                    .map(PathBuf::from)
+                    .unwrap_or(PathBuf::from("/etc/litconfig")),
+            )
+        }
+    }
+
+    /// The per-user files, `$XDG_CONFIG_HOME/lit/config` then `~/.litconfig`,
+    /// unless `LIT_CONFIG_GLOBAL` is set.
+    pub fn global_paths() -> Vec<PathBuf> {
+        if let Some(global) = std::env::var_os("LIT_CONFIG_GLOBAL") {
+            return vec![PathBuf::from(global)];
+        }
+
+        let home = home_dir();
+        let xdg = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|xdg| !(xdg.is_empty()))
+            .map(PathBuf::from)
+            .or(home.as_ref().map(|home| home.join(".config")))
+            .map(|xdg| xdg.join("lit").join("config"));
+
+        xdg.into_iter()
+            .chain(home.map(|home| home.join(".litconfig")))
+            .collect()
+    }
+
+    /// The file written by `lit config --global`: `~/.litconfig`, unless only the XDG file exists.
+    pub fn global_write_path() -> Option<PathBuf> {
+        let paths = Self::global_paths();
+
+        match paths.as_slice() {
+            [xdg, home] if xdg.exists() && !(home.exists()) => Some(xdg.clone()),
+            _ => paths.last().cloned(),
+        }
+    }
+
+    /// Load all the scopes for the repository whose lit directory is lit_dir
+    /// and whose configuration file is local.
+    pub fn load(lit_dir: Option<&Path>, local: Option<&Path>) -> error::Result<Self> {
+        let mut config = Config::default();
+
+        if let Some(system) = Self::system_path() {
+            config.read_file(&system, Scope::System, lit_dir, 0)?;
+        }
+
+        for global in Self::global_paths() {
+            config.read_file(&global, Scope::Global, lit_dir, 0)?;
+        }
+
+        if let Some(local) = local {
+            config.read_file(local, Scope::Local, lit_dir, 0)?;
+        }
+
+        Ok(config)
+    }
+
+    /// Merge path over the variables already read, e.g. the `config.worktree` of a worktree.
+    pub fn add_file(
+        &mut self,
+        path: &Path,
+        scope: Scope,
+        lit_dir: Option<&Path>,
+    ) -> error::Result<()> {
+        self.read_file(path, scope, lit_dir, 0)
+    }
+
+    /// Load a single file, with its includes.
+    pub fn load_file(path: &Path, scope: Scope, lit_dir: Option<&Path>) -> error::Result<Self> {
+        let mut config = Config::default();
+        config.read_file(path, scope, lit_dir, 0)?;
+        Ok(config)
+    }
+
+    /// Read path if it exists, following `include.path` and `includeIf.<condition>.path`.
+    fn read_file(
+        &mut self,
+        path: &Path,
+        scope: Scope,
+        lit_dir: Option<&Path>,
+        depth: usize,
+    ) -> error::Result<()> {
+        if depth > MAX_INCLUDE_DEPTH {
+            return Err(Error::Config(error::config::Config::IncludeDepth(
+                path.to_owned(),
+            )));
+        }
+
+        // Like git, bytes which are not UTF-8 are kept as replacement characters
+        // rather than dropping the whole file.
+        let content = match fs::read(path) {
+            Ok(content) => String::from_utf8_lossy(&content).into_owned(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => {
+                return Err(Error::Config(error::config::Config::Read(
+                    path.to_owned(),
+                    e.to_string(),
+                )))
+            }
+        };
+
+        let lines = parse::parse(&content)
+            .map_err(|line| Error::Config(error::config::Config::Parse(path.to_owned(), line)))?;
+
+        let mut section = None;
+        for line in lines {
+            match line.item {
+                Item::Section(current) => section = Some(current),
+                Item::Entry(key, value) => {
+                    let current = match &section {
+                        Some(current) => current,
+                        None => {
+                            return Err(Error::Config(error::config::Config::Parse(
+                                path.to_owned(),
+                                line.start + 1,
+                            )))
+                        }
+                    };
+
+                    let entry = Entry {
+                        section: current.name.clone(),
+                        subsection: current.subsection.clone(),
+                        key,
+                        value,
+                        origin: path.to_owned(),
+                        scope,
+                    };
+
+                    let include = entry.key == "path"
+                        && match (entry.section.as_str(), &entry.subsection) {
+                            ("include", None) => true,
+                            ("includeif", Some(condition)) => {
+                                include_condition(condition, path, lit_dir)
+                            }
+                            _ => false,
+                        };
+
+                    let included = entry
+                        .value
+                        .as_ref()
+                        .filter(|_| include)
+                        .map(|value| resolve_relative(&expand_path(value), path));
+
+                    self.entries.push(entry);
+
+                    if let Some(included) = included {
+                        self.read_file(&included, scope, lit_dir, depth + 1)?;
+                    }
+                }
+                Item::Other => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// All the entries defining name, in the order they were read.
+    pub fn get_all(&self, name: &str) -> error::Result<Vec<&Entry>> {
+        let name = Name::parse(name)?;
+        Ok(self
+            .entries
+            .iter()
+            .filter(|entry| name.matches(entry))
+            .collect())
+    }
+
+    /// The subsections of section in the order they first appear, e.g. the names of the remotes.
+    pub fn subsections(&self, section: &str) -> Vec<String> {
+        let section = section.to_lowercase();
+        let mut subsections: Vec<String> = Vec::new();
+
+        self.entries
+            .iter()
+            .filter(|entry| entry.section == section)
+            .filter_map(|entry| entry.subsection.as_ref())
+            .for_each(|subsection| {
+                if !(subsections.contains(subsection)) {
+                    subsections.push(subsection.clone());
+                }
+            });
+
+        subsections
+    }
+
+    /// The entry defining the effective value of name: the last one.
+    pub fn get_entry(&self, name: &str) -> error::Result<Option<&Entry>> {
+        let name = Name::parse(name)?;
+        Ok(self.entries.iter().rev().find(|entry| name.matches(entry)))
+    }
+
+    /// The effective value of name, a key without value reads as an empty string.
+    pub fn get(&self, name: &str) -> Option<String> {
+        self.get_entry(name)
+            .ok()
+            .flatten()
+            .map(|entry| entry.value.clone().unwrap_or_default())
+    }
+
+    pub fn get_bool(&self, name: &str) -> error::Result<Option<bool>> {
+        match self.get_entry(name)? {
+            None => Ok(None),
+            Some(entry) => parse_bool(entry.value.as_deref())
+                .map(Some)
+                .ok_or_else(|| invalid_value(entry, "bool")),
+        }
+    }
+
+    pub fn get_int(&self, name: &str) -> error::Result<Option<i64>> {
+        match self.get_entry(name)? {
+            None => Ok(None),
+            Some(entry) => entry
+                .value
+                .as_deref()
+                .and_then(parse_int)
+                .map(Some)
+                .ok_or_else(|| invalid_value(entry, "int")),
+        }
+    }
+
+    /// A path value, with a leading `~/` expanded to the home directory.
+    pub fn get_path(&self, name: &str) -> error::Result<Option<PathBuf>> {
+        Ok(self.get(name).map(|value| expand_path(&value)))
+    }
+}
+
+fn invalid_value(entry: &Entry, typ: &str) -> Error {
+    Error::Config(error::config::Config::InvalidValue(
+        entry.name(),
+        entry.value.clone().unwrap_or_default(),
+        typ.to_string(),
+    ))
+}
+
+/// `true`, `yes`, `on` and `1` are true, `false`, `no`, `off`, `0` and the empty string
+/// are false. A key without value is true.
+pub fn parse_bool(value: Option<&str>) -> Option<bool> {
+    match value.map(|value| value.to_lowercase()).as_deref() {
+        None | Some("true") | Some("yes") | Some("on") => Some(true),
+        Some("false") | Some("no") | Some("off") | Some("") => Some(false),
+        Some(value) => parse_int(value).map(|value| value != 0),
+    }
+}
+
+/// An integer with an optional `k`, `m` or `g` suffix, scaling it by 1024, 1024² or 1024³.
+pub fn parse_int(value: &str) -> Option<i64> {
+    let value = value.trim();
+    let (digits, scale) = match value.chars().last()?.to_ascii_lowercase() {
+        'k' => (&value[..value.len() - 1], 1024),
+        'm' => (&value[..value.len() - 1], 1024 * 1024),
+        'g' => (&value[..value.len() - 1], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+
+    digits.parse::<i64>().ok()?.checked_mul(scale)
+}
+
+/// The time before which things expire, in seconds since the epoch, from a value such as
+/// `gc.pruneExpire` takes: `now` expires everything, `never` nothing, otherwise a unix
+/// timestamp, a `YYYY-MM-DD[ HH:MM:SS]` date in UTC, or `<n>.<unit>.ago` counted back from now,
+/// the unit being one of second, minute, hour, day, week, month or year, plural or not.
+pub fn parse_expiry(value: &str, now: u64) -> Option<u64> {
+    let value = value.trim().to_lowercase();
+    match value.as_str() {
+        "now" => return Some(u64::MAX),
+        "never" | "false" => return Some(0),
+        _ => (),
+    }
+
+    if let Ok(timestamp) = value.parse::<u64>() {
+        return Some(timestamp);
+    }
+
+    let words: Vec<&str> = value
+        .split(['.', ' '])
+        .filter(|word| !word.is_empty())
+        .collect();
+    if let [count, unit, "ago"] = words[..] {
+        let seconds = match unit.trim_end_matches('s') {
+            "second" => 1,
+            "minute" => 60,
+            "hour" => 60 * 60,
+            "day" => 24 * 60 * 60,
+            "week" => 7 * 24 * 60 * 60,
+            "month" => 30 * 24 * 60 * 60,
+            "year" => 365 * 24 * 60 * 60,
+            _ => return None,
+        };
+        return Some(now.saturating_sub(count.parse::<u64>().ok()?.checked_mul(seconds)?));
+    }
+
+    parse_date(&value)
+}
+
+/// A `YYYY-MM-DD[ HH:MM:SS]` date in UTC, in seconds since the epoch.
+fn parse_date(value: &str) -> Option<u64> {
+    let (date, time) = value.split_once([' ', 't']).unwrap_or((value, "00:00:00"));
+    let numbers = |text: &str, separator: char| -> Option<Vec<i64>> {
+        text.split(separator)
+            .map(|number| number.parse().ok())
+            .collect()
+    };
+
+    let (year, month, day) = match numbers(date, '-')?[..] {
+        [year, month, day] if (1..=12).contains(&month) && (1..=31).contains(&day) => {
+            (year, month, day)
+        }
+        _ => return None,
+    };
+    let seconds = match numbers(time, ':')?[..] {
+        [hours, minutes, seconds] if hours < 24 && minutes < 60 && seconds < 61 => {
+            hours * 3600 + minutes * 60 + seconds
+        }
+        _ => return None,
+    };
+
+    // Days since the epoch in the proleptic Gregorian calendar, years starting in March.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146097 + day_of_era - 719468;
+
+    u64::try_from(days * 86400 + seconds).ok()
+}
+
+pub fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or(std::env::var_os("USERPROFILE"))
+        .filter(|home| !(home.is_empty()))
+        .map(PathBuf::from)
+}
+
+/// Expand a leading `~/` to the home directory.
+pub fn expand_path(value: &str) -> PathBuf {
+    match (value.strip_prefix("~/"), home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(value),
+    }
+}
+
+/// Included paths are relative to the including file.
+fn resolve_relative(path: &Path, including: &Path) -> PathBuf {
+    if path.is_relative() {
+        including.parent().unwrap_or(Path::new(".")).join(path)
+    } else {
+        path.to_owned()
+    }
+}
+
+/// Evaluate the condition of an `includeIf` section, only `gitdir:` and `gitdir/i:` are known.
+fn include_condition(condition: &str, including: &Path, lit_dir: Option<&Path>) -> bool {
+    let (pattern, case_insensitive) = match (
+        condition.strip_prefix("gitdir:"),
+        condition.strip_prefix("gitdir/i:"),
+    ) {
+        (Some(pattern), _) => (pattern, false),
+        (_, Some(pattern)) => (pattern, true),
+        _ => return false,
+    };
+
+    let lit_dir = match lit_dir.and_then(|dir| fs::canonicalize(dir).ok()) {
+        Some(lit_dir) => lit_dir.to_str().unwrap().replace('\\', "/"),
+        None => return false,
+    };
+
+    // `./` is relative to the including file, other relative patterns match at any depth,
+    // and a trailing `/` matches everything below.
+    let mut pattern = if let Some(rest) = pattern.strip_prefix("./") {
+        resolve_relative(Path::new(rest), including)
+            .to_str()
+            .unwrap()
+            .to_string()
+    } else if pattern.starts_with("~/") {
+        expand_path(pattern).to_str().unwrap().to_string()
+    } else if pattern.starts_with('/') {
+        pattern.to_string()
+    } else {
+        format!("**/{}", pattern)
+    };
+
+    if pattern.ends_with('/') {
+        pattern.push_str("**");
+    }
+
+    if case_insensitive {
+        wildmatch(&pattern.to_lowercase(), &lit_dir.to_lowercase())
+    } else {
+        wildmatch(&pattern, &lit_dir)
+    }
+}
+
+/// Match text against a glob pattern where `*` and `?` do not match `/`, and `**` does.
+pub fn wildmatch(pattern: &str, text: &str) -> bool {
+    fn matches(pattern: &[u8], text: &[u8]) -> bool {
+        match pattern {
+            [] => text.is_empty(),
+            [b'*', b'*', b'/', rest @ ..] => {
+                matches(rest, text)
+                    || (0..text.len()).any(|i| text[i] == b'/' && matches(rest, &text[i + 1..]))
+            }
+            [b'*', b'*', rest @ ..] => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+            [b'*', rest @ ..] => (0..=text.len())
+                .take_while(|i| *i == 0 || text[i - 1] != b'/')
+                .any(|i| matches(rest, &text[i..])),
+            [b'?', rest @ ..] => !(text.is_empty()) && text[0] != b'/' && matches(rest, &text[1..]),
+            [c, rest @ ..] => !(text.is_empty()) && text[0] == *c && matches(rest, &text[1..]),
+        }
+    }
+
+    matches(pattern.as_bytes(), text.as_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{parse_bool, parse_expiry, parse_int, wildmatch, Config, Scope};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_typed_values() {
+        assert_eq!(Some(true), parse_bool(None));
+        assert_eq!(Some(true), parse_bool(Some("Yes")));
+        assert_eq!(Some(false), parse_bool(Some("off")));
+        assert_eq!(Some(false), parse_bool(Some("")));
+        assert_eq!(None, parse_bool(Some("maybe")));
+        assert_eq!(Some(8 * 1024), parse_int("8k"));
+        assert_eq!(Some(2 * 1024 * 1024 * 1024), parse_int("2G"));
+        assert_eq!(Some(-3), parse_int("-3"));
+        assert_eq!(None, parse_int("12q"));
+
+        let now = 1_700_000_000;
+        assert_eq!(Some(now - 14 * 24 * 3600), parse_expiry("2.weeks.ago", now));
+        assert_eq!(Some(now - 3600), parse_expiry("1 hour ago", now));
+        assert_eq!(Some(u64::MAX), parse_expiry("now", now));
+        assert_eq!(Some(0), parse_expiry("never", now));
+        assert_eq!(Some(1234), parse_expiry("1234", now));
+        assert_eq!(
+            Some(1_700_000_000),
+            parse_expiry("2023-11-14 22:13:20", now)
+        );
+        assert_eq!(Some(951_782_400), parse_expiry("2000-02-29", now));
+        assert_eq!(None, parse_expiry("2.fortnights.ago", now));
+        assert_eq!(None, parse_expiry("2023-13-01", now));
+    }
+
+    #[test]
+    pub fn test_wildmatch() {
+        assert!(wildmatch("**/work/**", "/home/lit/work/project/.lit"));
+        assert!(wildmatch("/home/*/work/**", "/home/lit/work/a/.lit"));
+        assert!(!wildmatch("/home/*/work/**", "/home/lit/x/work/a/.lit"));
+        assert!(wildmatch("/srv/repo?.lit", "/srv/repo1.lit"));
+    }
+
+    #[test]
+    pub fn test_includes_and_multi_values() {
+        let _ = fs::remove_dir_all("./test_config_includes");
+        fs::create_dir_all("./test_config_includes/work/project/.lit").unwrap();
+        fs::write(
+            "./test_config_includes/config",
+            "[core]\n\
+             \tbigFileThreshold = 512m\n\
+             [remote \"origin\"]\n\
+             \tfetch = +refs/heads/*:refs/remotes/origin/*\n\
+             \tfetch = +refs/tags/*:refs/tags/*\n\
+             [include]\n\
+             \tpath = included\n\
+             [includeIf \"gitdir:work/\"]\n\
+             \tpath = work.inc\n\
+             [includeIf \"gitdir:elsewhere/\"]\n\
+             \tpath = elsewhere.inc\n",
+        )
+        .unwrap();
+        fs::write("./test_config_includes/included", "[user]\n\tname = lit\n").unwrap();
+        fs::write("./test_config_includes/work.inc", "[user]\n\tname = work\n").unwrap();
+        fs::write(
+            "./test_config_includes/elsewhere.inc",
+            "[user]\n\tname = no\n",
+        )
+        .unwrap();
+
+        let config = Config::load_file(
+            Path::new("./test_config_includes/config"),
+            Scope::Local,
+            Some(Path::new("./test_config_includes/work/project/.lit")),
+        )
+        .unwrap();
+
+        assert_eq!(
+            Some(512 * 1024 * 1024),
+            config.get_int("core.bigfilethreshold").unwrap()
+        );
+        assert_eq!(2, config.get_all("remote.origin.fetch").unwrap().len());
+        assert_eq!(0, config.get_all("remote.ORIGIN.fetch").unwrap().len());
+        assert_eq!(Some("work".to_string()), config.get("user.name"));
+        assert!(config.get_bool("user.name").is_err());
+
+        fs::remove_dir_all("./test_config_includes").unwrap();
+    }
+
+    #[test]
+    pub fn test_include_cycle() {
+        let _ = fs::remove_dir_all("./test_config_cycle");
+        fs::create_dir_all("./test_config_cycle").unwrap();
+        fs::write("./test_config_cycle/config", "[include]\n\tpath = config\n").unwrap();
+
+        assert!(
+            Config::load_file(Path::new("./test_config_cycle/config"), Scope::Local, None).is_err()
+        );
+
+        fs::remove_dir_all("./test_config_cycle").unwrap();
+    }
+
+    #[test]
+    pub fn test_unreadable_and_invalid_utf8() {
+        let _ = fs::remove_dir_all("./test_config_read");
+        fs::create_dir_all("./test_config_read/directory").unwrap();
+        fs::write(
+            "./test_config_read/config",
+            b"# caf\xe9\n[extensions]\n\tobjectFormat = sha256\n",
+        )
+        .unwrap();
+
+        let config =
+            Config::load_file(Path::new("./test_config_read/config"), Scope::Local, None).unwrap();
+        assert_eq!(
+            Some("sha256".to_string()),
+            config.get("extensions.objectformat")
+        );
+
+        // A missing file is empty, one which cannot be read is an error
+        assert!(
+            Config::load_file(Path::new("./test_config_read/missing"), Scope::Local, None).is_ok()
+        );
+        assert!(Config::load_file(
+            Path::new("./test_config_read/directory"),
+            Scope::Local,
+            None
+        )
+        .is_err());
+
+        fs::remove_dir_all("./test_config_read").unwrap();
+    }
+}
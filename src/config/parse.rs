@@ -0,0 +1,327 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+/// A section header, `[section]`, `[section "subsection"]` or the legacy `[section.subsection]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// Lowercased, section names are case-insensitive
+    pub name: String,
+
+    /// Case-sensitive, except with the legacy syntax
+    pub subsection: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Item {
+    Section(Section),
+
+    /// A variable of the last section: lowercased key, and None for a key without `=`
+    Entry(String, Option<String>),
+
+    /// A comment or blank line
+    Other,
+}
+
+/// An item with the range of lines it spans in the file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub start: usize,
+    pub end: usize,
+    pub item: Item,
+}
+
+/// Parse a whole file in git's configuration format, returning the number of the first
+/// malformed line on error. Every item remembers the lines it spans, so that a file can be
+/// edited in place.
+pub fn parse(content: &str) -> Result<Vec<Line>, usize> {
+    let lines: Vec<&str> = content.lines().collect();
+    let mut items = Vec::new();
+    let mut current = 0;
+
+    while current < lines.len() {
+        let start = current;
+        let trimmed = lines[current].trim_start();
+
+        if trimmed.is_empty() || trimmed.starts_with('#') || trimmed.starts_with(';') {
+            items.push(Line {
+                start,
+                end: start,
+                item: Item::Other,
+            });
+            current += 1;
+            continue;
+        }
+
+        let mut rest = trimmed.to_string();
+        if trimmed.starts_with('[') {
+            let (section, after) = parse_section(trimmed).ok_or(start + 1)?;
+            items.push(Line {
+                start,
+                end: start,
+                item: Item::Section(section),
+            });
+
+            // A variable may follow the header on the same line
+            if after.trim().is_empty() || after.trim().starts_with(['#', ';']) {
+                current += 1;
+                continue;
+            }
+            rest = after.trim_start().to_string();
+        }
+
+        // A value ending with a backslash continues on the next line.
+        while rest.ends_with('\\') && !(rest.ends_with("\\\\")) && current + 1 < lines.len() {
+            rest.pop();
+            current += 1;
+            rest.push_str(lines[current]);
+        }
+
+        let (key, value) = parse_entry(&rest).ok_or(start + 1)?;
+        items.push(Line {
+            start,
+            end: current,
+            item: Item::Entry(key, value),
+        });
+        current += 1;
+    }
+
+    Ok(items)
+}
+
+/// Parse a section header at the start of line, returning it with the rest of the line.
+pub(super) fn parse_section(line: &str) -> Option<(Section, &str)> {
+    let line = line.strip_prefix('[')?;
+
+    match line.find(['"', ']']) {
+        Some(pos) if line.as_bytes()[pos] == b'"' => {
+            let name = line[..pos].trim();
+            let mut subsection = String::new();
+            let mut chars = line[pos + 1..].char_indices();
+
+            loop {
+                match chars.next()? {
+                    (_, '\\') => subsection.push(chars.next()?.1),
+                    (i, '"') => {
+                        let after = line[pos + 1 + i + 1..].trim_start().strip_prefix(']')?;
+                        return Some((
+                            Section {
+                                name: valid_name(name)?.to_lowercase(),
+                                subsection: Some(subsection),
+                            },
+                            after,
+                        ));
+                    }
+                    (_, c) => subsection.push(c),
+                }
+            }
+        }
+        Some(pos) => {
+            let header = &line[..pos];
+            let (name, subsection) = match header.split_once('.') {
+                Some((name, subsection)) => (name, Some(subsection.to_lowercase())),
+                None => (header, None),
+            };
+
+            Some((
+                Section {
+                    name: valid_name(name)?.to_lowercase(),
+                    subsection,
+                },
+                &line[pos + 1..],
+            ))
+        }
+        None => None,
+    }
+}
+
+/// Section and key names are alphanumeric characters, `-` and `.` for sections.
+fn valid_name(name: &str) -> Option<&str> {
+    if !(name.is_empty())
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '.')
+    {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+/// Parse `key = value` or a lone `key`, which means true.
+fn parse_entry(line: &str) -> Option<(String, Option<String>)> {
+    let end = line.find(['=', '#', ';']).unwrap_or(line.len());
+    let key = line[..end].trim();
+
+    if key.is_empty()
+        || !(key.chars().next()?.is_ascii_alphabetic())
+        || !(key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+    {
+        return None;
+    }
+
+    if line[end..].starts_with('=') {
+        Some((key.to_lowercase(), Some(parse_value(&line[end + 1..])?)))
+    } else {
+        Some((key.to_lowercase(), None))
+    }
+}
+
+/// Unquote a value: whitespace is trimmed outside of double quotes, comments are removed
+/// and the escapes `\\`, `\"`, `\n`, `\t` and `\b` are interpreted.
+pub fn parse_value(raw: &str) -> Option<String> {
+    let mut value = String::new();
+    let mut quoted = false;
+
+    // Whitespace is kept only if something else follows it
+    let mut pending_space = String::new();
+    let mut chars = raw.trim_start().chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                quoted = !(quoted);
+            }
+            '#' | ';' if !(quoted) => break,
+            c if c.is_whitespace() && !(quoted) => pending_space.push(c),
+            '\\' => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                value.push(match chars.next()? {
+                    'n' => '\n',
+                    't' => '\t',
+                    'b' => '\u{8}',
+                    c @ ('\\' | '"') => c,
+                    _ => return None,
+                })
+            }
+            c => {
+                value.push_str(&pending_space);
+                pending_space.clear();
+                value.push(c)
+            }
+        }
+    }
+
+    if quoted {
+        None
+    } else {
+        Some(value)
+    }
+}
+
+/// Quote a value so that `parse_value` reads it back unchanged.
+pub fn format_value(value: &str) -> String {
+    let escaped = value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t");
+
+    if value.starts_with(char::is_whitespace)
+        || value.ends_with(char::is_whitespace)
+        || value.contains(['#', ';'])
+    {
+        format!("\"{}\"", escaped)
+    } else {
+        escaped
+    }
+}
+
+/// Format a section header, using the subsection syntax when there is one.
+pub fn format_section(name: &str, subsection: Option<&str>) -> String {
+    match subsection {
+        Some(subsection) => format!(
+            "[{} \"{}\"]",
+            name,
+            subsection.replace('\\', "\\\\").replace('"', "\\\"")
+        ),
+        None => format!("[{}]", name),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{format_value, parse, parse_value, Item, Section};
+
+    #[test]
+    pub fn test_parse_sections() {
+        let lines = parse(
+            "# comment\n\
+             [core]\n\
+             \tbare = false\n\
+             [remote \"Origin\"]\n\
+             \turl = /srv/repo.git ; where\n\
+             [Branch.Main]\n\
+             \trebase\n",
+        )
+        .unwrap();
+
+        assert_eq!(Item::Other, lines[0].item);
+        assert_eq!(
+            Item::Section(Section {
+                name: "remote".to_string(),
+                subsection: Some("Origin".to_string())
+            }),
+            lines[3].item
+        );
+        assert_eq!(
+            Item::Entry("url".to_string(), Some("/srv/repo.git".to_string())),
+            lines[4].item
+        );
+        assert_eq!(
+            Item::Section(Section {
+                name: "branch".to_string(),
+                subsection: Some("main".to_string())
+            }),
+            lines[5].item
+        );
+        assert_eq!(Item::Entry("rebase".to_string(), None), lines[6].item);
+    }
+
+    #[test]
+    pub fn test_parse_continuation() {
+        let lines = parse("[alias]\n\tlg = log \\\n --oneline\n").unwrap();
+
+        assert_eq!(2, lines[1].end);
+        assert_eq!(
+            Item::Entry("lg".to_string(), Some("log  --oneline".to_string())),
+            lines[1].item
+        );
+    }
+
+    #[test]
+    pub fn test_parse_value() {
+        assert_eq!(
+            Some("a \"b\" c".to_string()),
+            parse_value(" \"a \\\"b\\\" c\"  ")
+        );
+        assert_eq!(Some(" keep ".to_string()), parse_value("\" keep \""));
+        assert_eq!(None, parse_value("\"unterminated"));
+        assert_eq!(
+            Some("x # y".to_string()),
+            parse_value(&format_value("x # y"))
+        );
+    }
+
+    #[test]
+    pub fn test_parse_error_line() {
+        assert_eq!(Err(2), parse("[core]\n=oops\n"));
+    }
+}
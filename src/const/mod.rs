@@ -20,3 +20,19 @@ pub mod project_info;
 
 #[allow(dead_code)]
 pub const LIT_DIR: &str = ".lit";
+pub const GIT_DIR: &str = ".git";
+
+/// Directory names identifying a repository, in order of precedence
+pub const LIT_DIR_NAMES: [&str; 2] = [LIT_DIR, GIT_DIR];
+
+/// Environment variable overriding the discovery of the lit directory
+pub const LIT_DIR_ENV: &str = "LIT_DIR";
+
+/// Environment variable overriding `LIT_DIR_NAMES`, a colon separated list
+pub const LIT_DIR_NAMES_ENV: &str = "LIT_DIR_NAMES";
+
+/// Environment variable, `0` forbids the transports whose policy is `user`
+pub const LIT_PROTOCOL_FROM_USER_ENV: &str = "LIT_PROTOCOL_FROM_USER";
+
+/// Environment variable naming the program asking for usernames and passwords
+pub const LIT_ASKPASS_ENV: &str = "LIT_ASKPASS";
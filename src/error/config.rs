@@ -0,0 +1,62 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Config {
+    InvalidKey(String),
+    InvalidValue(String, String, String),
+    Parse(PathBuf, usize),
+    IncludeDepth(PathBuf),
+    MultipleValues(String),
+    MissingValue(String),
+    Io(PathBuf, String),
+    Read(PathBuf, String),
+}
+
+impl Log for Config {
+    fn fmt(&self) -> String {
+        match self {
+            Config::InvalidKey(name) => format!("Invalid key `{}`", name),
+            Config::InvalidValue(name, value, typ) => {
+                format!("Invalid {} value `{}` for `{}`", typ, value, name)
+            }
+            Config::Parse(path, line) => format!(
+                "Bad config line {} in file `{}`",
+                line,
+                path.to_str().unwrap()
+            ),
+            Config::IncludeDepth(path) => format!(
+                "Exceeded the maximum include depth while including `{}`",
+                path.to_str().unwrap()
+            ),
+            Config::MultipleValues(name) => {
+                format!("`{}` has multiple values, use --unset-all or --add", name)
+            }
+            Config::MissingValue(name) => format!("No value given for `{}`", name),
+            Config::Io(path, reason) => {
+                format!("Cannot write `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Config::Read(path, reason) => {
+                format!("Cannot read `{}`: {}", path.to_str().unwrap(), reason)
+            }
+        }
+    }
+}
@@ -0,0 +1,70 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Index {
+    Corrupt(String),
+    UnsupportedVersion(u32),
+    UnsupportedExtension(String),
+    Unmerged(String),
+    WouldOverwrite(String),
+    NotUptodate(String),
+    PrefixExists(String),
+    Locked(PathBuf),
+    Io(PathBuf, String),
+    Read(PathBuf, String),
+}
+
+impl Log for Index {
+    fn fmt(&self) -> String {
+        match self {
+            Index::Corrupt(reason) => format!("Corrupt index file: {}", reason),
+            Index::UnsupportedVersion(version) => {
+                format!("Unsupported index version `{}`", version)
+            }
+            Index::UnsupportedExtension(signature) => {
+                format!(
+                    "Cannot write an index with the `{}` extension, which lit does not support",
+                    signature
+                )
+            }
+            Index::Unmerged(path) => format!("`{}` is unmerged, resolve it first", path),
+            Index::WouldOverwrite(path) => {
+                format!(
+                    "Entry `{}` would be overwritten by merge, cannot merge",
+                    path
+                )
+            }
+            Index::NotUptodate(path) => format!("Entry `{}` not uptodate, cannot merge", path),
+            Index::PrefixExists(prefix) => format!("Subdirectory `{}` already exists", prefix),
+            Index::Locked(path) => format!(
+                "Unable to lock `{}`: the lock file exists, another lit process seems to be running",
+                path.to_str().unwrap()
+            ),
+            Index::Io(path, reason) => {
+                format!("Cannot write `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Index::Read(path, reason) => {
+                format!("Cannot read `{}`: {}", path.to_str().unwrap(), reason)
+            }
+        }
+    }
+}
@@ -16,16 +16,39 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
+pub mod config;
+pub mod index;
 pub mod object;
+pub mod pack;
+pub mod remote;
 pub mod repo;
+pub mod worktree;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
 #[derive(Debug)]
-#[allow(dead_code)]
 pub enum Error {
     Repo(repo::Repo),
     Object(object::Object),
+    Index(index::Index),
+    Worktree(worktree::Worktree),
+    Config(config::Config),
+    Remote(remote::Remote),
+    Pack(pack::Pack),
+}
+
+impl Log for Error {
+    fn fmt(&self) -> String {
+        match self {
+            Error::Repo(error) => error.fmt(),
+            Error::Object(error) => error.fmt(),
+            Error::Index(error) => error.fmt(),
+            Error::Worktree(error) => error.fmt(),
+            Error::Config(error) => error.fmt(),
+            Error::Remote(error) => error.fmt(),
+            Error::Pack(error) => error.fmt(),
+        }
+    }
 }
 
 pub trait Log {
@@ -17,12 +17,21 @@
  */
 
 use crate::error::Log;
+use std::path::PathBuf;
 
 #[derive(Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum Object {
     Malformed(String, usize),
     UnknownType(String, String),
+    NotFound(String),
+    UnexpectedType(String, String, String),
+    ReadOnly(String),
+    Io(PathBuf, String),
+    Unreadable(PathBuf, String),
+    Invalid(String, String, String),
+    BadFormat(String),
+    BadBatchCommand(String),
 }
 
 impl Log for Object {
@@ -34,6 +43,22 @@ impl Log for Object {
             Object::UnknownType(typ, sha) => {
                 format!("Unknown type `{}` for object `{}`", typ, sha)
             }
+            Object::NotFound(sha) => format!("Object `{}` not found", sha),
+            Object::UnexpectedType(sha, expected, actual) => {
+                format!("Object `{}` is a {}, not a {}", sha, actual, expected)
+            }
+            Object::ReadOnly(storage) => format!("Cannot write objects to {}", storage),
+            Object::Io(path, reason) => {
+                format!("Cannot write `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Object::Unreadable(path, reason) => {
+                format!("Cannot read `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Object::Invalid(name, typ, reason) => {
+                format!("Invalid {} `{}`: {}", typ, name, reason)
+            }
+            Object::BadFormat(atom) => format!("Unknown format element `{}`", atom),
+            Object::BadBatchCommand(command) => format!("Unknown batch command `{}`", command),
         }
     }
 }
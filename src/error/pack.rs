@@ -0,0 +1,36 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+
+#[derive(Debug)]
+pub enum Pack {
+    Malformed(String),
+    BadChecksum,
+    MissingBase(String),
+}
+
+impl Log for Pack {
+    fn fmt(&self) -> String {
+        match self {
+            Pack::Malformed(reason) => format!("Malformed pack: {}", reason),
+            Pack::BadChecksum => "The pack checksum does not match its content".to_string(),
+            Pack::MissingBase(sha) => format!("The base object `{}` of a delta is missing", sha),
+        }
+    }
+}
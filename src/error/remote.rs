@@ -0,0 +1,92 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Remote {
+    NotFound(String),
+    AlreadyExists(String),
+    InvalidName(String),
+    NotARepository(String),
+    InvalidRefspec(String),
+    ObjectFormatMismatch(String, String),
+    DetachedHead,
+    NoMatchingRef(String),
+    Rejected(String),
+    Io(PathBuf, String),
+    UnsupportedUrl(String),
+    ProtocolNotAllowed(String),
+    Transport(String, String),
+    Protocol(String, String),
+    ServerError(String),
+    AuthenticationFailed(String),
+    BadRequest(String),
+    BadRevision(String),
+    BadBundle(String, String),
+    EmptyBundle,
+    MissingPrerequisites(Vec<String>),
+}
+
+impl Log for Remote {
+    fn fmt(&self) -> String {
+        match self {
+            Remote::NotFound(name) => format!("No such remote `{}`", name),
+            Remote::AlreadyExists(name) => format!("Remote `{}` already exists", name),
+            Remote::InvalidName(name) => format!("`{}` is not a valid remote name", name),
+            Remote::NotARepository(url) => {
+                format!("`{}` does not appear to be a lit repository", url)
+            }
+            Remote::InvalidRefspec(refspec) => format!("Invalid refspec `{}`", refspec),
+            Remote::ObjectFormatMismatch(local, remote) => format!(
+                "The local repository uses {} but the remote one uses {}",
+                local, remote
+            ),
+            Remote::DetachedHead => {
+                "You are not currently on a branch, name the refs to push".to_string()
+            }
+            Remote::NoMatchingRef(refspec) => format!("`{}` does not match any ref", refspec),
+            Remote::Rejected(url) => format!("Failed to update some refs with `{}`", url),
+            Remote::Io(path, reason) => {
+                format!("Cannot write `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Remote::UnsupportedUrl(url) => format!("Unsupported url `{}`", url),
+            Remote::ProtocolNotAllowed(scheme) => format!(
+                "Transport `{}` not allowed, see `protocol.{}.allow`",
+                scheme, scheme
+            ),
+            Remote::Transport(url, reason) => format!("Cannot talk to `{}`: {}", url, reason),
+            Remote::Protocol(url, reason) => {
+                format!("Unexpected response from `{}`: {}", url, reason)
+            }
+            Remote::ServerError(message) => format!("remote error: {}", message),
+            Remote::AuthenticationFailed(url) => format!("Authentication failed for `{}`", url),
+            Remote::BadRequest(reason) => format!("Bad request from the client: {}", reason),
+            Remote::BadRevision(revision) => format!("Bad revision `{}`", revision),
+            Remote::BadBundle(path, reason) => {
+                format!("`{}` is not a valid bundle: {}", path, reason)
+            }
+            Remote::EmptyBundle => "Refusing to create an empty bundle".to_string(),
+            Remote::MissingPrerequisites(commits) => format!(
+                "The repository lacks these prerequisite commits: {}",
+                commits.join(", ")
+            ),
+        }
+    }
+}
@@ -28,6 +28,15 @@ pub enum Repo {
     NotEmpty(PathBuf),
     MissingConfigFile(PathBuf),
     UnsupportedRepositoryFormatVersion(String),
+    BareRepository(PathBuf),
+    InvalidReference(String),
+    UnknownExtension(String),
+    InvalidExtension(String, String),
+    PreciousObjects(PathBuf),
+    InvalidDate(String),
+    BadRevision(String),
+    Locked(PathBuf),
+    Io(PathBuf, String),
 }
 
 impl Log for Repo {
@@ -42,6 +51,31 @@ impl Log for Repo {
             Repo::UnsupportedRepositoryFormatVersion(version) => {
                 format!("Unsupported repositoryformatversion `{}`", version)
             }
+            Repo::BareRepository(dir) => format!(
+                "`{}` is a bare repository, this operation must be run in a work tree",
+                dir.to_str().unwrap()
+            ),
+            Repo::InvalidReference(name) => format!("Invalid reference `{}`", name),
+            Repo::UnknownExtension(name) => {
+                format!("Unknown repository extension `extensions.{}`", name)
+            }
+            Repo::InvalidExtension(name, value) => format!(
+                "Unsupported value `{}` for repository extension `extensions.{}`",
+                value, name
+            ),
+            Repo::PreciousObjects(dir) => format!(
+                "The objects of `{}` are precious (`extensions.preciousobjects`), refusing to delete them",
+                dir.to_str().unwrap()
+            ),
+            Repo::InvalidDate(value) => format!("Invalid date `{}`", value),
+            Repo::BadRevision(revision) => format!("Bad revision `{}`", revision),
+            Repo::Locked(path) => format!(
+                "Unable to lock `{}`: the lock file exists, another lit process seems to be running",
+                path.to_str().unwrap()
+            ),
+            Repo::Io(path, reason) => {
+                format!("Cannot write `{}`: {}", path.to_str().unwrap(), reason)
+            }
             Repo::MissingConfigFile(config_file_path) => format!(
                 "Missing configuration file `{}`",
                 config_file_path.to_str().unwrap()
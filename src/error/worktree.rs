@@ -0,0 +1,69 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::Log;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Worktree {
+    LocalChanges(Vec<String>),
+    UntrackedOverwritten(Vec<String>),
+    Io(PathBuf, String),
+    AlreadyExists(PathBuf),
+    NotFound(String),
+    Locked(String, String),
+    Dirty(PathBuf),
+    BranchExists(String),
+    BranchCheckedOut(String, PathBuf),
+}
+
+impl Log for Worktree {
+    fn fmt(&self) -> String {
+        match self {
+            Worktree::LocalChanges(paths) => format!(
+                "Your local changes to the following files would be overwritten: {}",
+                paths.join(", ")
+            ),
+            Worktree::UntrackedOverwritten(paths) => format!(
+                "The following untracked working tree files would be overwritten: {}",
+                paths.join(", ")
+            ),
+            Worktree::Io(path, reason) => {
+                format!("Cannot update `{}`: {}", path.to_str().unwrap(), reason)
+            }
+            Worktree::AlreadyExists(path) => {
+                format!("`{}` already exists", path.to_str().unwrap())
+            }
+            Worktree::NotFound(worktree) => format!("`{}` is not a working tree", worktree),
+            Worktree::Locked(worktree, reason) => format!(
+                "`{}` is locked ({}), use --force to override",
+                worktree, reason
+            ),
+            Worktree::Dirty(path) => format!(
+                "`{}` contains modified or untracked files, use --force to delete it",
+                path.to_str().unwrap()
+            ),
+            Worktree::BranchExists(branch) => format!("A branch named `{}` already exists", branch),
+            Worktree::BranchCheckedOut(branch, path) => format!(
+                "`{}` is already checked out at `{}`",
+                branch,
+                path.to_str().unwrap()
+            ),
+        }
+    }
+}
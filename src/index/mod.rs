@@ -0,0 +1,561 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error;
+use crate::lock;
+use crate::object::format::{from_hex, to_hex, ObjectFormat};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const SIGNATURE: &[u8] = b"DIRC";
+const VERSION: u32 = 2;
+const HEADER_LEN: usize = 12;
+
+/// Version 3 adds the extended flags, version 4 compresses the paths.
+const VERSION_EXTENDED: u32 = 3;
+const VERSION_COMPRESSED: u32 = 4;
+
+/// Size of the stat part of an entry: ten 32-bit fields, followed by the sha and 16-bit flags.
+const STAT_LEN: usize = 40;
+
+const FLAG_ASSUME_VALID: u16 = 0x8000;
+const FLAG_EXTENDED: u16 = 0x4000;
+const FLAG_SKIP_WORKTREE: u16 = 0x4000;
+const FLAG_INTENT_TO_ADD: u16 = 0x2000;
+
+/// Extensions describing the entries as they were written: lit does not maintain them, they
+/// are dropped when it writes the index. The cache tree, the offsets of the entries and the
+/// fsmonitor state.
+const STALE_EXTENSIONS: [&[u8; 4]; 4] = [b"TREE", b"EOIE", b"IEOT", b"FSMN"];
+
+/// One file tracked by the index (the staging area)
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct IndexEntry {
+    pub ctime: (u32, u32),
+    pub mtime: (u32, u32),
+    pub dev: u32,
+    pub ino: u32,
+
+    /// The file mode, e.g. 0o100644, 0o100755 or 0o120000
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    pub size: u32,
+
+    /// Hex encoded object name of the staged blob
+    pub sha: String,
+
+    /// Merge stage, 0 for a normal entry
+    pub stage: u16,
+    pub path: String,
+
+    /// The file is taken as unchanged in the worktree, `update-index --assume-unchanged`
+    pub assume_valid: bool,
+
+    /// The file is left out of the worktree, as sparse checkouts do (version 3 and above)
+    pub skip_worktree: bool,
+
+    /// The file is to be added, its content is not staged yet (version 3 and above)
+    pub intent_to_add: bool,
+}
+
+impl IndexEntry {
+    /// Build an entry for `path` from the stat data of the file on disk.
+    pub fn from_metadata(path: &str, sha: &str, mode: u32, meta: &fs::Metadata) -> Self {
+        let mut entry = IndexEntry {
+            mode,
+            sha: sha.to_owned(),
+            path: path.to_owned(),
+            size: meta.len() as u32,
+            ..Default::default()
+        };
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+
+            entry.ctime = (meta.ctime() as u32, meta.ctime_nsec() as u32);
+            entry.mtime = (meta.mtime() as u32, meta.mtime_nsec() as u32);
+            entry.dev = meta.dev() as u32;
+            entry.ino = meta.ino() as u32;
+            entry.uid = meta.uid();
+            entry.gid = meta.gid();
+        }
+
+        #[cfg(not(unix))]
+        if let Ok(mtime) = meta.modified() {
+            let mtime = mtime
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap_or_default();
+            entry.mtime = (mtime.as_secs() as u32, mtime.subsec_nanos());
+            entry.ctime = entry.mtime;
+        }
+
+        entry
+    }
+
+    /// The mode as written in a tree entry (`100644`, `40000`, ...)
+    pub fn tree_mode(&self) -> String {
+        format!("{:o}", self.mode)
+    }
+
+    /// Whether the entry needs the extended flags of version 3.
+    fn is_extended(&self) -> bool {
+        self.skip_worktree || self.intent_to_add
+    }
+
+    /// Whether the stat data recorded in the index still matches the file on disk.
+    pub fn is_fresh(&self, meta: &fs::Metadata) -> bool {
+        let other = Self::from_metadata(&self.path, &self.sha, self.mode, meta);
+        other.mtime == self.mtime && other.size == self.size && other.ino == self.ino
+    }
+}
+
+/// The index file, a binary file in `LIT_DIR/index` compatible with git's index versions 2
+/// to 4. Object names and the trailing checksum use the object format of the repository.
+#[derive(Debug)]
+pub struct Index {
+    /// The version the index is written with, 2 and 3 being chosen from the entries
+    pub version: u32,
+    pub entries: Vec<IndexEntry>,
+
+    /// The extensions following the entries, by signature, kept byte for byte
+    pub extensions: Vec<([u8; 4], Vec<u8>)>,
+}
+
+impl Default for Index {
+    fn default() -> Self {
+        Index {
+            version: VERSION,
+            entries: Vec::new(),
+            extensions: Vec::new(),
+        }
+    }
+}
+
+impl Index {
+    /// Read the index at path, an absent index is an empty one.
+    pub fn read(path: &Path, format: ObjectFormat) -> error::Result<Self> {
+        match fs::read(path) {
+            Ok(data) => Self::parse(&data, format),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Index::default()),
+            Err(e) => Err(error::Error::Index(error::index::Index::Read(
+                path.to_owned(),
+                e.to_string(),
+            ))),
+        }
+    }
+
+    pub fn parse(data: &[u8], format: ObjectFormat) -> error::Result<Self> {
+        let checksum_len = format.raw_len();
+        let fixed_len = entry_fixed_len(format);
+
+        let corrupt = |reason: &str| {
+            Err(error::Error::Index(error::index::Index::Corrupt(
+                reason.to_string(),
+            )))
+        };
+
+        if data.len() < HEADER_LEN + checksum_len {
+            return corrupt("file too short");
+        }
+
+        if &data[0..4] != SIGNATURE {
+            return corrupt("bad signature");
+        }
+
+        let version = read_u32(data, 4);
+        if !(VERSION..=VERSION_COMPRESSED).contains(&version) {
+            return Err(error::Error::Index(
+                error::index::Index::UnsupportedVersion(version),
+            ));
+        }
+
+        let (body, checksum) = data.split_at(data.len() - checksum_len);
+        if format.digest(body) != checksum {
+            return corrupt("bad checksum");
+        }
+
+        let count = read_u32(data, 8) as usize;
+        let mut entries = Vec::with_capacity(count.min(body.len() / fixed_len));
+        let mut pos = HEADER_LEN;
+        let mut path = Vec::new();
+
+        for _ in 0..count {
+            if pos + fixed_len > body.len() {
+                return corrupt("truncated entry");
+            }
+
+            let field = |i: usize| read_u32(body, pos + i * 4);
+            let flags = u16::from_be_bytes([body[pos + fixed_len - 2], body[pos + fixed_len - 1]]);
+            let mut name_start = pos + fixed_len;
+            let extended = if flags & FLAG_EXTENDED == 0 {
+                0
+            } else if version < VERSION_EXTENDED || name_start + 2 > body.len() {
+                return corrupt("unexpected extended flags");
+            } else {
+                name_start += 2;
+                u16::from_be_bytes([body[name_start - 2], body[name_start - 1]])
+            };
+            if extended & !(FLAG_SKIP_WORKTREE | FLAG_INTENT_TO_ADD) != 0 {
+                return corrupt("unknown extended flags");
+            }
+
+            // Version 4 paths drop the end of the previous one, then add a suffix.
+            let mut name_pos = name_start;
+            if version == VERSION_COMPRESSED {
+                match read_varint(body, &mut name_pos) {
+                    Some(strip) if strip <= path.len() => path.truncate(path.len() - strip),
+                    _ => return corrupt("bad path compression"),
+                }
+            } else {
+                path.clear();
+            }
+            let name_end = match body[name_pos..].iter().position(|byte| *byte == 0x00u8) {
+                Some(len) => name_pos + len,
+                None => return corrupt("unterminated path"),
+            };
+            path.extend_from_slice(&body[name_pos..name_end]);
+
+            entries.push(IndexEntry {
+                ctime: (field(0), field(1)),
+                mtime: (field(2), field(3)),
+                dev: field(4),
+                ino: field(5),
+                mode: field(6),
+                uid: field(7),
+                gid: field(8),
+                size: field(9),
+                sha: to_hex(&body[pos + STAT_LEN..pos + fixed_len - 2]),
+                stage: (flags >> 12) & 0x3,
+                path: String::from_utf8_lossy(&path).to_string(),
+                assume_valid: flags & FLAG_ASSUME_VALID != 0,
+                skip_worktree: extended & FLAG_SKIP_WORKTREE != 0,
+                intent_to_add: extended & FLAG_INTENT_TO_ADD != 0,
+            });
+
+            // Entries are padded with 1-8 NUL bytes to a multiple of eight bytes, but in
+            // version 4.
+            pos = match version {
+                VERSION_COMPRESSED => name_end + 1,
+                _ => pos + entry_len(name_start - pos, name_end - name_start),
+            };
+        }
+
+        let mut extensions = Vec::new();
+        while pos < body.len() {
+            if pos + 8 > body.len() {
+                return corrupt("truncated extension");
+            }
+            let signature: [u8; 4] = body[pos..pos + 4].try_into().unwrap();
+            let size = read_u32(body, pos + 4) as usize;
+            let data = body
+                .get(pos + 8..)
+                .and_then(|data| data.get(..size))
+                .ok_or_else(|| {
+                    error::Error::Index(error::index::Index::Corrupt(
+                        "truncated extension".to_string(),
+                    ))
+                })?;
+            extensions.push((signature, data.to_vec()));
+            pos += 8 + size;
+        }
+
+        Ok(Index {
+            version,
+            entries,
+            extensions,
+        })
+    }
+
+    /// Fails when the index has an extension lit does not understand but must not ignore,
+    /// those whose signature does not start with an uppercase letter.
+    pub fn check_writable(&self) -> error::Result<()> {
+        match self
+            .extensions
+            .iter()
+            .find(|(signature, _)| !(signature[0].is_ascii_uppercase()))
+        {
+            Some((signature, _)) => Err(error::Error::Index(
+                error::index::Index::UnsupportedExtension(
+                    String::from_utf8_lossy(signature).to_string(),
+                ),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// The index as a file. Versions 2 and 3 are written as the entries need, the one read
+    /// otherwise. The extensions are kept, but those `STALE_EXTENSIONS` lists. Fails when
+    /// the index cannot be written, a split index for instance.
+    pub fn serialize(&self, format: ObjectFormat) -> error::Result<Vec<u8>> {
+        self.check_writable()?;
+
+        let mut entries = self.entries.clone();
+        entries.sort_by(|a, b| (a.path.as_bytes(), a.stage).cmp(&(b.path.as_bytes(), b.stage)));
+
+        let version = match self.version {
+            VERSION_COMPRESSED => VERSION_COMPRESSED,
+            _ if entries.iter().any(IndexEntry::is_extended) => VERSION_EXTENDED,
+            _ => VERSION,
+        };
+
+        let mut data = Vec::new();
+        data.extend_from_slice(SIGNATURE);
+        data.extend_from_slice(&version.to_be_bytes());
+        data.extend_from_slice(&(entries.len() as u32).to_be_bytes());
+
+        let mut previous = "";
+        entries.iter().for_each(|entry| {
+            let start = data.len();
+
+            [
+                entry.ctime.0,
+                entry.ctime.1,
+                entry.mtime.0,
+                entry.mtime.1,
+                entry.dev,
+                entry.ino,
+                entry.mode,
+                entry.uid,
+                entry.gid,
+                entry.size,
+            ]
+            .iter()
+            .for_each(|field| data.extend_from_slice(&field.to_be_bytes()));
+
+            data.extend_from_slice(&from_hex(&entry.sha));
+
+            let name_len = entry.path.len().min(0xfff) as u16;
+            let mut flags = (entry.stage << 12) | name_len;
+            if entry.assume_valid {
+                flags |= FLAG_ASSUME_VALID;
+            }
+            if entry.is_extended() {
+                flags |= FLAG_EXTENDED;
+            }
+            data.extend_from_slice(&flags.to_be_bytes());
+            if entry.is_extended() {
+                let mut extended = 0;
+                if entry.skip_worktree {
+                    extended |= FLAG_SKIP_WORKTREE;
+                }
+                if entry.intent_to_add {
+                    extended |= FLAG_INTENT_TO_ADD;
+                }
+                data.extend_from_slice(&extended.to_be_bytes());
+            }
+
+            if version == VERSION_COMPRESSED {
+                let common = previous
+                    .bytes()
+                    .zip(entry.path.bytes())
+                    .take_while(|(a, b)| a == b)
+                    .count();
+                write_varint(&mut data, previous.len() - common);
+                data.extend_from_slice(&entry.path.as_bytes()[common..]);
+                data.push(0x00u8);
+                previous = &entry.path;
+            } else {
+                let fixed_len = data.len() - start;
+                data.extend_from_slice(entry.path.as_bytes());
+                data.resize(start + entry_len(fixed_len, entry.path.len()), 0x00u8);
+            }
+        });
+
+        for (signature, extension) in &self.extensions {
+            if STALE_EXTENSIONS.contains(&signature) {
+                continue;
+            }
+            data.extend_from_slice(signature);
+            data.extend_from_slice(&(extension.len() as u32).to_be_bytes());
+            data.extend_from_slice(extension);
+        }
+
+        let checksum = format.digest(&data);
+        data.extend_from_slice(&checksum);
+        Ok(data)
+    }
+
+    /// Write the index to path through a lock file, so readers never see a partial index.
+    /// Fails when the lock file exists, someone else is writing the index.
+    pub fn write(&self, path: &Path, format: ObjectFormat) -> error::Result<()> {
+        lock::write(path, &self.serialize(format)?).map_err(|e| {
+            error::Error::Index(match e.kind() {
+                io::ErrorKind::AlreadyExists => error::index::Index::Locked(path.to_owned()),
+                _ => error::index::Index::Io(path.to_owned(), e.to_string()),
+            })
+        })
+    }
+
+    pub fn get(&self, path: &str) -> Option<&IndexEntry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path && entry.stage == 0)
+    }
+}
+
+fn read_u32(data: &[u8], pos: usize) -> u32 {
+    u32::from_be_bytes([data[pos], data[pos + 1], data[pos + 2], data[pos + 3]])
+}
+
+/// Read the variable length integer of version 4 at pos: 7 bits a byte, the most significant
+/// first, each continuation adding one.
+fn read_varint(data: &[u8], pos: &mut usize) -> Option<usize> {
+    let mut byte = *data.get(*pos)?;
+    *pos += 1;
+    let mut value = (byte & 0x7f) as usize;
+
+    while byte & 0x80 != 0 {
+        byte = *data.get(*pos)?;
+        *pos += 1;
+        value = value.checked_add(1)?.checked_mul(0x80)? | (byte & 0x7f) as usize;
+    }
+
+    Some(value)
+}
+
+fn write_varint(data: &mut Vec<u8>, mut value: usize) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    while value >= 0x80 {
+        value = (value >> 7) - 1;
+        bytes.push(0x80 | (value & 0x7f) as u8);
+    }
+    data.extend(bytes.iter().rev());
+}
+
+fn entry_fixed_len(format: ObjectFormat) -> usize {
+    STAT_LEN + format.raw_len() + 2
+}
+
+fn entry_len(fixed_len: usize, name_len: usize) -> usize {
+    (fixed_len + name_len + 8) & !7
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Index, IndexEntry};
+    use crate::object::format::ObjectFormat;
+
+    #[test]
+    pub fn test_index_roundtrip() {
+        let index = Index {
+            entries: vec![
+                IndexEntry {
+                    mode: 0o100644,
+                    size: 25,
+                    sha: "9ca6e1d93dfc2343e4e404a6b742220b148649a0".to_string(),
+                    path: "src/main.rs".to_string(),
+                    ..Default::default()
+                },
+                IndexEntry {
+                    mode: 0o100755,
+                    sha: "4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string(),
+                    path: "build.sh".to_string(),
+                    ..Default::default()
+                },
+            ],
+            ..Default::default()
+        };
+
+        let parsed = Index::parse(
+            &index.serialize(ObjectFormat::Sha1).unwrap(),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+
+        assert_eq!(2, parsed.entries.len());
+        assert_eq!("build.sh", parsed.entries[0].path);
+        assert_eq!("100755", parsed.entries[0].tree_mode());
+        assert_eq!(index.entries[0], parsed.entries[1]);
+    }
+
+    #[test]
+    pub fn test_index_bad_checksum() {
+        let mut data = Index::default().serialize(ObjectFormat::Sha1).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 0xff;
+
+        assert!(Index::parse(&data, ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    pub fn test_index_sha256() {
+        let sha = "6bd8e6c3d2ed8a1f4d2a3b1bc1ae8e5fae6c1c9b0b6f2f9d0b9c4b0b8c9f6a2d";
+        let index = Index {
+            entries: vec![IndexEntry {
+                mode: 0o100644,
+                sha: sha.to_string(),
+                path: "file".to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let data = index.serialize(ObjectFormat::Sha256).unwrap();
+        let parsed = Index::parse(&data, ObjectFormat::Sha256).unwrap();
+
+        assert_eq!(index.entries, parsed.entries);
+        assert!(Index::parse(&data, ObjectFormat::Sha1).is_err());
+    }
+
+    #[test]
+    pub fn test_index_versions() {
+        let entry = |path: &str| IndexEntry {
+            mode: 0o100644,
+            sha: "9ca6e1d93dfc2343e4e404a6b742220b148649a0".to_string(),
+            path: path.to_string(),
+            ..Default::default()
+        };
+        let mut index = Index {
+            entries: vec![entry("dir/a"), entry("dir/b"), entry("dir/sub/c")],
+            ..Default::default()
+        };
+
+        // Version 2 is kept until an entry needs the extended flags of version 3.
+        let data = index.serialize(ObjectFormat::Sha1).unwrap();
+        assert_eq!(2, Index::parse(&data, ObjectFormat::Sha1).unwrap().version);
+        index.entries[1].skip_worktree = true;
+        index.entries[2].intent_to_add = true;
+        index.entries[2].assume_valid = true;
+        let parsed = Index::parse(
+            &index.serialize(ObjectFormat::Sha1).unwrap(),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+        assert_eq!(3, parsed.version);
+        assert_eq!(index.entries, parsed.entries);
+
+        // Version 4 compresses the paths. Unknown extensions are kept, the cache tree is not.
+        index.version = 4;
+        index.extensions = vec![(*b"TREE", vec![0, 1]), (*b"ZZZZ", vec![1, 2, 3])];
+        let parsed = Index::parse(
+            &index.serialize(ObjectFormat::Sha1).unwrap(),
+            ObjectFormat::Sha1,
+        )
+        .unwrap();
+        assert_eq!(4, parsed.version);
+        assert_eq!(index.entries, parsed.entries);
+        assert_eq!(vec![(*b"ZZZZ", vec![1, 2, 3])], parsed.extensions);
+
+        // A split index is read, not written.
+        index.extensions.push((*b"link", vec![0; 20]));
+        assert!(index.serialize(ObjectFormat::Sha1).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// The lock file `<path>.lock` of a file being rewritten. Creating it fails when it exists,
+/// so that a single writer holds it at a time. The new content is written to the lock file
+/// and renamed over path on commit, so readers never see it half written. A lock dropped
+/// before being committed is removed, leaving path as it was.
+pub struct Lock {
+    path: PathBuf,
+    lock: PathBuf,
+    file: Option<File>,
+}
+
+impl Lock {
+    /// Take the lock of path, creating the directory holding it. Fails with
+    /// `AlreadyExists` when someone else holds it.
+    pub fn acquire(path: &Path) -> io::Result<Self> {
+        let lock = path.with_file_name(format!(
+            "{}.lock",
+            path.file_name().unwrap().to_str().unwrap()
+        ));
+
+        if let Some(parent) = path
+            .parent()
+            .filter(|parent| !(parent.as_os_str().is_empty()))
+        {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&lock)?;
+        Ok(Lock {
+            path: path.to_owned(),
+            lock,
+            file: Some(file),
+        })
+    }
+
+    /// Append data to the new content of path.
+    pub fn write(&mut self, data: &[u8]) -> io::Result<()> {
+        self.file.as_mut().unwrap().write_all(data)
+    }
+
+    /// Replace path with what was written, releasing the lock.
+    pub fn commit(mut self) -> io::Result<()> {
+        drop(self.file.take());
+        fs::rename(&self.lock, &self.path).inspect_err(|_| {
+            let _ = fs::remove_file(&self.lock);
+        })
+    }
+
+    /// Delete path, releasing the lock.
+    pub fn delete(self) -> io::Result<()> {
+        match fs::remove_file(&self.path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        if self.file.take().is_some() {
+            let _ = fs::remove_file(&self.lock);
+        }
+    }
+}
+
+/// Write data to path through its lock.
+pub fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    let mut lock = Lock::acquire(path)?;
+    lock.write(data)?;
+    lock.commit()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write, Lock};
+    use std::fs;
+    use std::io;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_lock() {
+        let _ = fs::remove_dir_all("./test_lock");
+        let path = Path::new("./test_lock/dir/file");
+
+        write(path, b"one").unwrap();
+        assert_eq!("one", fs::read_to_string(path).unwrap());
+
+        // A held lock excludes other writers, and a dropped one leaves the file as it was.
+        let mut lock = Lock::acquire(path).unwrap();
+        lock.write(b"two").unwrap();
+        assert_eq!(
+            io::ErrorKind::AlreadyExists,
+            write(path, b"three").unwrap_err().kind()
+        );
+        drop(lock);
+        assert_eq!("one", fs::read_to_string(path).unwrap());
+        assert!(!(Path::new("./test_lock/dir/file.lock").exists()));
+
+        Lock::acquire(path).unwrap().delete().unwrap();
+        assert!(!(path.exists()));
+
+        fs::remove_dir_all("./test_lock").unwrap();
+    }
+}
@@ -1,10 +1,16 @@
 /// Copyright (C) 2023 Muqiu Han
 mod cli;
 mod commands;
+mod config;
 mod r#const;
 mod error;
+mod index;
+mod lock;
 mod object;
+mod remote;
 mod repo;
+#[cfg(test)]
+mod test_util;
 
 #[macro_use]
 extern crate log;
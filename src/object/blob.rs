@@ -21,32 +21,32 @@ use super::Object;
 /// Blobs are user data: the content of every file you put in lit (main.c, logo.png, README.md) is stored as a blob.
 pub struct Blob {
     fmt: String,
-    data: String,
+    data: Vec<u8>,
 }
 
 impl Blob {
-    pub fn new(data: String) -> Self {
+    pub fn new<T: Into<Vec<u8>>>(data: T) -> Self {
         Blob {
             fmt: "blob".to_string(),
-            data,
+            data: data.into(),
         }
     }
 }
 
 impl Object for Blob {
     fn to_string(&self) -> String {
-        self.data.clone()
+        String::from_utf8_lossy(&self.data).to_string()
     }
 
-    fn deserialize(object: &str) -> Self
+    fn deserialize(object: &[u8]) -> Self
     where
         Self: Sized,
     {
-        Self::new(object.to_string())
+        Self::new(object)
     }
 
-    fn serialize(&self) -> &[u8] {
-        self.data.as_bytes()
+    fn serialize(&self) -> Vec<u8> {
+        self.data.clone()
     }
 
     fn fmt(&self) -> &String {
@@ -0,0 +1,218 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::commit::Kvlm;
+use crate::object::format::ObjectFormat;
+use crate::object::tree::Tree;
+
+/// The modes git writes in trees
+const TREE_MODES: [&str; 5] = ["100644", "100755", "120000", "40000", "160000"];
+
+/// The names of the repository directories, which no worktree path may go through
+const REPO_DIRS: [&str; 2] = [".git", ".lit"];
+
+/// The types of objects
+pub const TYPES: [&str; 4] = ["blob", "tree", "commit", "tag"];
+
+/// Check that data is a well-formed object of type fmt in a repository using format,
+/// returns what is wrong otherwise. Blobs are always well-formed.
+pub fn check(fmt: &str, data: &[u8], format: ObjectFormat) -> Result<(), String> {
+    match fmt {
+        "blob" => Ok(()),
+        "tree" => check_tree(data, format),
+        "commit" => check_commit(data, format),
+        "tag" => check_tag(data, format),
+        _ => Err(format!("unknown type `{}`", fmt)),
+    }
+}
+
+fn check_tree(data: &[u8], format: ObjectFormat) -> Result<(), String> {
+    let entries = Tree::parse(data, format).ok_or("truncated entry")?;
+
+    for entry in &entries {
+        if !TREE_MODES.contains(&entry.mode.as_str()) {
+            return Err(format!("bad mode `{}` of `{}`", entry.mode, entry.name));
+        }
+        check_entry_name(&entry.name)?;
+    }
+
+    // Entries are sorted, as directories with a trailing slash, without duplicates.
+    let mut sorted = Tree::new(entries.clone()).entries;
+    sorted.dedup_by(|a, b| a.name == b.name);
+    if sorted.len() != entries.len() {
+        return Err("duplicate entries".to_string());
+    }
+    if sorted
+        .iter()
+        .zip(&entries)
+        .any(|(sorted, entry)| sorted.name != entry.name)
+    {
+        return Err("entries not sorted".to_string());
+    }
+
+    Ok(())
+}
+
+/// Check that name can name a tree entry: a single path component.
+pub fn check_entry_name(name: &str) -> Result<(), String> {
+    if matches!(name, "" | "." | "..") || name.contains('/') {
+        return Err(format!("bad entry name `{}`", name));
+    }
+
+    Ok(())
+}
+
+/// Check that name can be written in a worktree: an entry name that is not a repository
+/// directory, whatever its case, as case-insensitive filesystems would take it for one.
+pub fn check_worktree_name(name: &str) -> Result<(), String> {
+    check_entry_name(name)?;
+    if REPO_DIRS.iter().any(|dir| name.eq_ignore_ascii_case(dir)) {
+        return Err(format!("entry name `{}` is a repository directory", name));
+    }
+
+    Ok(())
+}
+
+/// The headers of a commit or tag, after checking that keys come in order: each of keys,
+/// the required ones being those without a trailing `?`, may be followed by more headers.
+fn check_headers(data: &[u8], keys: &[&str]) -> Result<Kvlm, String> {
+    let kvlm = Kvlm::parse(data).ok_or("malformed headers")?;
+    let mut headers = kvlm.headers.iter().peekable();
+
+    for key in keys {
+        let (key, optional) = match key.strip_suffix('?') {
+            Some(key) => (key, true),
+            None => (*key, false),
+        };
+
+        if headers.next_if(|(k, _)| k == key).is_none() && !optional {
+            return Err(format!("missing `{}` header", key));
+        }
+    }
+
+    Ok(kvlm)
+}
+
+fn check_name(kvlm: &Kvlm, key: &str, format: ObjectFormat) -> Result<(), String> {
+    kvlm.get_all(key)
+        .into_iter()
+        .find(|sha| !format.is_full_name(sha))
+        .map_or(Ok(()), |sha| {
+            Err(format!("bad `{}` object name `{}`", key, sha))
+        })
+}
+
+/// An identity is `<name> <<email>> <timestamp> <timezone>`.
+fn check_ident(kvlm: &Kvlm, key: &str) -> Result<(), String> {
+    let bad = |ident: &str| format!("bad `{}` identity `{}`", key, ident);
+
+    for ident in kvlm.get_all(key) {
+        let (_, rest) = ident.split_once(" <").ok_or_else(|| bad(ident))?;
+        let (email, date) = rest.split_once("> ").ok_or_else(|| bad(ident))?;
+        let (timestamp, timezone) = date.split_once(' ').ok_or_else(|| bad(ident))?;
+
+        if email.contains(['<', '>'])
+            || timestamp.is_empty()
+            || !timestamp.chars().all(|c| c.is_ascii_digit())
+            || timezone.len() != 5
+            || !timezone.starts_with(['+', '-'])
+            || !timezone[1..].chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(bad(ident));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_commit(data: &[u8], format: ObjectFormat) -> Result<(), String> {
+    let kvlm = check_headers(data, &["tree", "parent?", "author", "committer"])?;
+    if kvlm.get_all("tree").len() > 1 {
+        return Err("multiple `tree` headers".to_string());
+    }
+
+    check_name(&kvlm, "tree", format)?;
+    check_name(&kvlm, "parent", format)?;
+    check_ident(&kvlm, "author")?;
+    check_ident(&kvlm, "committer")
+}
+
+fn check_tag(data: &[u8], format: ObjectFormat) -> Result<(), String> {
+    let kvlm = check_headers(data, &["object", "type", "tag", "tagger?"])?;
+
+    check_name(&kvlm, "object", format)?;
+    let typ = kvlm.get("type").unwrap_or_default();
+    if !TYPES.contains(&typ) {
+        return Err(format!("bad tagged type `{}`", typ));
+    }
+    if kvlm.get("tag").unwrap_or_default().is_empty() {
+        return Err("empty tag name".to_string());
+    }
+    check_ident(&kvlm, "tagger")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{check, check_worktree_name};
+    use crate::object::format::ObjectFormat;
+    use crate::test_util::{commit_data, tree_data, IDENT};
+
+    #[test]
+    pub fn test_check() {
+        let sha = "a".repeat(40);
+        let tree = |entries: &[(&str, &str)]| {
+            let entries: Vec<(&str, &str, &str)> = entries
+                .iter()
+                .map(|(mode, name)| (*mode, *name, sha.as_str()))
+                .collect();
+            tree_data(&entries)
+        };
+        let check = |fmt: &str, data: &[u8]| check(fmt, data, ObjectFormat::Sha1);
+
+        assert!(check("tree", &tree(&[("100644", "a"), ("40000", "b")])).is_ok());
+        assert!(check("tree", &tree(&[("100644", "b"), ("100644", "a")])).is_err());
+        assert!(check("tree", &tree(&[("100644", "a"), ("100644", "a")])).is_err());
+        assert!(check("tree", &tree(&[("100666", "a")])).is_err());
+        assert!(check("tree", &tree(&[("100644", "a/b")])).is_err());
+        assert!(check("tree", &tree(&[("100644", "a")])[..20]).is_err());
+
+        let commit = commit_data(&sha, &[&sha], "message");
+        assert!(check("commit", commit.as_bytes()).is_ok());
+        assert!(check("commit", commit.replace("tree", "tree1").as_bytes()).is_err());
+        assert!(check("commit", commit.replace("+0000", "CET").as_bytes()).is_err());
+        assert!(check(
+            "commit",
+            format!("tree {}\nauthor {}\n\n", sha, IDENT).as_bytes()
+        )
+        .is_err());
+
+        let tag = format!(
+            "object {}\ntype commit\ntag v1\ntagger {}\n\nmessage\n",
+            sha, IDENT
+        );
+        assert!(check("tag", tag.as_bytes()).is_ok());
+        assert!(check("tag", tag.replace("type commit", "type bogus").as_bytes()).is_err());
+        assert!(check("blob", b"anything \0 goes").is_ok());
+        assert!(check("bogus", b"").is_err());
+
+        assert!(check_worktree_name(".gitignore").is_ok());
+        for name in ["", ".", "..", "a/b", ".git", ".Git", ".LIT"] {
+            assert!(check_worktree_name(name).is_err());
+        }
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::format::ObjectFormat;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// An entry of the table of contents: a 4-byte chunk id and a 64-bit offset
+const ENTRY_LEN: usize = 12;
+
+/// The chunks of a file in git's chunk format, as the commit-graph and the multi-pack-index
+/// use: a header, a table of contents of count chunks ending with a zero id giving the end of
+/// the last chunk, the chunks, then the checksum of everything before it.
+pub struct Chunks {
+    ranges: BTreeMap<[u8; 4], Range<usize>>,
+}
+
+impl Chunks {
+    /// Read the table of contents starting at start, checking that the chunks fit in data
+    /// before its checksum.
+    pub fn parse(data: &[u8], start: usize, count: usize, format: ObjectFormat) -> Option<Self> {
+        let end = data.len().checked_sub(format.raw_len())?;
+        let entry = |i: usize| -> Option<([u8; 4], usize)> {
+            let raw = data.get(start + i * ENTRY_LEN..start + (i + 1) * ENTRY_LEN)?;
+            let offset = u64::from_be_bytes(raw[4..].try_into().unwrap());
+            Some((raw[..4].try_into().unwrap(), usize::try_from(offset).ok()?))
+        };
+
+        let mut ranges = BTreeMap::new();
+        for i in 0..count {
+            let (id, offset) = entry(i)?;
+            let (_, next) = entry(i + 1)?;
+            if id == [0; 4] || offset > next || next > end {
+                return None;
+            }
+            ranges.insert(id, offset..next);
+        }
+
+        (entry(count)?.0 == [0; 4]).then_some(Chunks { ranges })
+    }
+
+    /// Where chunk id is, if the file has it.
+    pub fn get(&self, id: &[u8; 4]) -> Option<Range<usize>> {
+        self.ranges.get(id).cloned()
+    }
+}
+
+/// A file made of header, then chunks in the given order, then its checksum. The number of
+/// chunks is left for the caller to put in the header.
+pub fn write(header: &[u8], chunks: &[(&[u8; 4], Vec<u8>)], format: ObjectFormat) -> Vec<u8> {
+    let mut data = header.to_vec();
+    let mut offset = header.len() + (chunks.len() + 1) * ENTRY_LEN;
+
+    for (id, chunk) in chunks {
+        data.extend_from_slice(*id);
+        data.extend_from_slice(&(offset as u64).to_be_bytes());
+        offset += chunk.len();
+    }
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(&(offset as u64).to_be_bytes());
+
+    for (_, chunk) in chunks {
+        data.extend_from_slice(chunk);
+    }
+    let checksum = format.digest(&data);
+    data.extend_from_slice(&checksum);
+    data
+}
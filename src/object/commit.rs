@@ -0,0 +1,192 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use super::Object;
+
+/// Key-Value List with Message: the format shared by commits and tags.
+/// Headers keep their order, and a key may appear several times (e.g. `parent`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Kvlm {
+    pub headers: Vec<(String, String)>,
+    pub message: String,
+}
+
+impl Kvlm {
+    /// Parse the raw body, returning None if it is malformed.
+    /// Continuation lines of a multi-line value (e.g. `gpgsig`) start with a space.
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let data = std::str::from_utf8(data).ok()?;
+        let mut kvlm = Kvlm::default();
+        let mut rest = data;
+
+        loop {
+            // A blank line separates the headers from the message
+            if let Some(message) = rest.strip_prefix('\n') {
+                kvlm.message = message.to_string();
+                return Some(kvlm);
+            }
+
+            if rest.is_empty() {
+                return Some(kvlm);
+            }
+
+            let space = rest.find(' ')?;
+            let mut end = rest.find('\n').unwrap_or(rest.len());
+
+            if space > end {
+                return None;
+            }
+
+            while rest[end..].starts_with("\n ") {
+                end = end + 1 + rest[end + 1..].find('\n').unwrap_or(rest.len() - end - 1);
+            }
+
+            kvlm.headers.push((
+                rest[..space].to_string(),
+                rest[space + 1..end].replace("\n ", "\n"),
+            ));
+
+            rest = rest.get(end + 1..).unwrap_or("");
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut data = String::new();
+
+        self.headers.iter().for_each(|(key, value)| {
+            data.push_str(&format!("{} {}\n", key, value.replace('\n', "\n ")));
+        });
+
+        data.push('\n');
+        data.push_str(&self.message);
+        data.into_bytes()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.headers
+            .iter()
+            .filter(|(k, _)| k == key)
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+}
+
+/// A commit records a tree, its parents, and who made the change, when and why.
+pub struct Commit {
+    fmt: String,
+    pub kvlm: Kvlm,
+}
+
+impl Commit {
+    pub fn new(kvlm: Kvlm) -> Self {
+        Commit {
+            fmt: "commit".to_string(),
+            kvlm,
+        }
+    }
+
+    pub fn tree(&self) -> Option<&str> {
+        self.kvlm.get("tree")
+    }
+
+    pub fn parents(&self) -> Vec<&str> {
+        self.kvlm.get_all("parent")
+    }
+}
+
+impl Object for Commit {
+    fn to_string(&self) -> String {
+        String::from_utf8_lossy(&self.kvlm.serialize()).to_string()
+    }
+
+    fn deserialize(data: &[u8]) -> Self
+    where
+        Self: Sized,
+    {
+        Self::new(Kvlm::parse(data).unwrap_or_default())
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        self.kvlm.serialize()
+    }
+
+    fn fmt(&self) -> &String {
+        &self.fmt
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Commit, Kvlm};
+    use crate::object::Object;
+
+    const COMMIT: &str = concat!(
+        "tree 29ff16c9c14e2652b22f8b78bb08a5a07930c147\n",
+        "parent 206941306e8a8af65b66eaaaea388a7ae24d49a0\n",
+        "parent 6ad6f3ba06a3dc7a5ef2a9dc0b25c1a2b4e3c7b8\n",
+        "author Muqiu Han <muqiu-han@outlook.com> 1527025023 +0200\n",
+        "committer Muqiu Han <muqiu-han@outlook.com> 1527025044 +0200\n",
+        "gpgsig -----BEGIN PGP SIGNATURE-----\n",
+        " \n",
+        " iQIzBAABCAAdFiEExwXquOM8bWb4Q2zVGxM2FxoLkGQFAlsEjZQACgkQGxM2FxoL\n",
+        " -----END PGP SIGNATURE-----\n",
+        "\n",
+        "Create first draft\n"
+    );
+
+    #[test]
+    pub fn test_commit_parse() {
+        let commit = Commit::deserialize(COMMIT.as_bytes());
+
+        assert_eq!(
+            Some("29ff16c9c14e2652b22f8b78bb08a5a07930c147"),
+            commit.tree()
+        );
+        assert_eq!(
+            vec![
+                "206941306e8a8af65b66eaaaea388a7ae24d49a0",
+                "6ad6f3ba06a3dc7a5ef2a9dc0b25c1a2b4e3c7b8"
+            ],
+            commit.parents()
+        );
+        assert_eq!("Create first draft\n", commit.kvlm.message);
+        assert!(commit
+            .kvlm
+            .get("gpgsig")
+            .unwrap()
+            .ends_with("SIGNATURE-----"));
+    }
+
+    #[test]
+    pub fn test_commit_roundtrip() {
+        let commit = Commit::deserialize(COMMIT.as_bytes());
+        assert_eq!(COMMIT.as_bytes(), commit.serialize().as_slice());
+    }
+
+    #[test]
+    pub fn test_kvlm_malformed() {
+        assert!(Kvlm::parse(b"tree\nno separator").is_none());
+    }
+}
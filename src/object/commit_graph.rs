@@ -0,0 +1,463 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::chunk::{self, Chunks};
+use crate::object::format::{from_hex, to_hex, ObjectFormat};
+use crate::object::lookup::{self, NameLookup};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+const SIGNATURE: &[u8] = b"CGPH";
+const HEADER_LEN: usize = 8;
+
+const OID_FANOUT: &[u8; 4] = b"OIDF";
+const OID_LOOKUP: &[u8; 4] = b"OIDL";
+const COMMIT_DATA: &[u8; 4] = b"CDAT";
+const EXTRA_EDGES: &[u8; 4] = b"EDGE";
+const BLOOM_INDEXES: &[u8; 4] = b"BIDX";
+const BLOOM_DATA: &[u8; 4] = b"BDAT";
+
+/// The parent positions of a commit without a second parent
+const PARENT_NONE: u32 = 0x7000_0000;
+
+/// Set on the second parent position when it is an index in the extra edges, and on the
+/// last parent of such a list
+const EDGE_FLAG: u32 = 0x8000_0000;
+
+/// Generation numbers are 30 bits, higher ones are stored as this
+pub const MAX_GENERATION: u32 = 0x3fff_ffff;
+
+/// Commits changing more paths than this get a filter answering "maybe" for any path
+pub const MAX_CHANGED_PATHS: usize = 512;
+
+/// The changed-path filters written, version 1 hashing paths as git 2.39 does
+const BLOOM_VERSION: u32 = 1;
+const BLOOM_HASHES: u32 = 7;
+const BLOOM_BITS_PER_ENTRY: u32 = 10;
+const BLOOM_SEEDS: (u32, u32) = (0x293a_e76f, 0x7e64_6e2c);
+const BLOOM_HEADER_LEN: usize = 12;
+
+/// The filter of commits changing too many paths
+const BLOOM_TRUNCATED: [u8; 1] = [0xff];
+
+/// What the commit-graph stores of a commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GraphCommit {
+    pub tree: String,
+    pub parents: Vec<String>,
+
+    /// One more than the highest generation of the parents, 1 for root commits
+    pub generation: u32,
+
+    /// The committer date, in seconds since the epoch
+    pub time: u64,
+}
+
+/// Hash version of the file header
+fn hash_version(format: ObjectFormat) -> u8 {
+    match format {
+        ObjectFormat::Sha1 => 1,
+        ObjectFormat::Sha256 => 2,
+    }
+}
+
+/// MurmurHash3 (x86, 32 bits). Version 1 filters hash the bytes of a path as C's signed
+/// chars, which only differs for bytes above 0x7f.
+fn murmur3(seed: u32, data: &[u8], signed: bool) -> u32 {
+    let (c1, c2) = (0xcc9e_2d51u32, 0x1b87_3593u32);
+    let byte = |byte: u8| match signed {
+        true => byte as i8 as u32,
+        false => byte as u32,
+    };
+    let mix = |k: u32| k.wrapping_mul(c1).rotate_left(15).wrapping_mul(c2);
+
+    let mut hash = seed;
+    let blocks = data.chunks_exact(4);
+    let tail = blocks.remainder();
+    for block in blocks {
+        let k = byte(block[0]) | byte(block[1]) << 8 | byte(block[2]) << 16 | byte(block[3]) << 24;
+        hash = (hash ^ mix(k))
+            .rotate_left(13)
+            .wrapping_mul(5)
+            .wrapping_add(0xe654_6b64);
+    }
+
+    if !(tail.is_empty()) {
+        let k = tail
+            .iter()
+            .enumerate()
+            .fold(0, |k, (i, b)| k ^ byte(*b) << (8 * i));
+        hash ^= mix(k);
+    }
+
+    hash ^= data.len() as u32;
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(0xc2b2_ae35);
+    hash ^ (hash >> 16)
+}
+
+/// The bits path sets in a filter, before reducing them to its size.
+fn bloom_keys(path: &str, version: u32, hashes: u32) -> Vec<u32> {
+    let signed = version == 1;
+    let first = murmur3(BLOOM_SEEDS.0, path.as_bytes(), signed);
+    let second = murmur3(BLOOM_SEEDS.1, path.as_bytes(), signed);
+    (0..hashes)
+        .map(|i| first.wrapping_add(i.wrapping_mul(second)))
+        .collect()
+}
+
+/// The changed-path filter of a commit changing the files paths, or changing too many of them
+/// when None: every path and every directory holding one is added, 10 bits each.
+pub fn bloom_filter(paths: Option<&[String]>) -> Vec<u8> {
+    let paths = match paths {
+        Some(paths) if paths.len() <= MAX_CHANGED_PATHS => paths,
+        _ => return BLOOM_TRUNCATED.to_vec(),
+    };
+
+    let mut entries = BTreeSet::new();
+    for path in paths {
+        let mut path = path.as_str();
+        entries.insert(path);
+        while let Some((parent, _)) = path.rsplit_once('/') {
+            entries.insert(parent);
+            path = parent;
+        }
+    }
+
+    let len = (entries.len() * BLOOM_BITS_PER_ENTRY as usize)
+        .div_ceil(8)
+        .max(1);
+    let mut filter = vec![0u8; len];
+    for entry in entries {
+        for key in bloom_keys(entry, BLOOM_VERSION, BLOOM_HASHES) {
+            let bit = key as usize % (len * 8);
+            filter[bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    filter
+}
+
+/// A commit-graph file, `objects/info/commit-graph`: the names of the commits sorted, with a
+/// fanout table, then for each commit its tree, the positions of its parents, its generation
+/// and its date, the parents after the second in the extra edges, and optionally the filters
+/// of the paths each commit changes.
+pub struct CommitGraph {
+    data: Vec<u8>,
+    format: ObjectFormat,
+    count: usize,
+    chunks: Chunks,
+    lookup: NameLookup,
+}
+
+impl CommitGraph {
+    pub fn parse(data: Vec<u8>, format: ObjectFormat) -> Option<Self> {
+        let header = data.get(..HEADER_LEN)?;
+        if !(header.starts_with(SIGNATURE)) || header[4] != 1 || header[5] != hash_version(format) {
+            return None;
+        }
+
+        let chunks = Chunks::parse(&data, HEADER_LEN, header[6] as usize, format)?;
+        let fanout = chunks
+            .get(OID_FANOUT)
+            .filter(|range| range.len() == 256 * 4)?;
+        let count =
+            u32::from_be_bytes(data[fanout.end - 4..fanout.end].try_into().unwrap()) as usize;
+
+        let raw_len = format.raw_len();
+        let has_len = |id, len| chunks.get(id).is_some_and(|range| range.len() == len);
+        if !(has_len(OID_LOOKUP, count * raw_len) && has_len(COMMIT_DATA, count * (raw_len + 16))) {
+            return None;
+        }
+
+        let lookup = NameLookup::new(fanout.start, chunks.get(OID_LOOKUP)?.start, count, format);
+        Some(CommitGraph {
+            data,
+            format,
+            count,
+            chunks,
+            lookup,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    fn u32_at(&self, pos: usize) -> u32 {
+        u32::from_be_bytes(self.data[pos..pos + 4].try_into().unwrap())
+    }
+
+    fn chunk(&self, id: &[u8; 4]) -> Range<usize> {
+        self.chunks.get(id).unwrap_or_default()
+    }
+
+    pub fn name(&self, i: usize) -> String {
+        to_hex(self.lookup.name(&self.data, i))
+    }
+
+    /// The position of commit sha.
+    pub fn find(&self, sha: &str) -> Option<usize> {
+        self.lookup.find(&self.data, sha)
+    }
+
+    /// The commit at position i, None when the file is corrupt.
+    pub fn commit_at(&self, i: usize) -> Option<GraphCommit> {
+        let raw_len = self.format.raw_len();
+        let start = self.chunk(COMMIT_DATA).start + i * (raw_len + 16);
+        let parent =
+            |position: u32| (position < self.count as u32).then(|| self.name(position as usize));
+
+        let mut parents = Vec::new();
+        match self.u32_at(start + raw_len) {
+            PARENT_NONE => (),
+            position => parents.push(parent(position)?),
+        }
+        match self.u32_at(start + raw_len + 4) {
+            PARENT_NONE => (),
+            edge if edge & EDGE_FLAG != 0 => {
+                let edges = self.chunks.get(EXTRA_EDGES)?;
+                let mut pos = edges.start + 4 * (edge & !EDGE_FLAG) as usize;
+                loop {
+                    if pos + 4 > edges.end {
+                        return None;
+                    }
+                    let position = self.u32_at(pos);
+                    parents.push(parent(position & !EDGE_FLAG)?);
+                    if position & EDGE_FLAG != 0 {
+                        break;
+                    }
+                    pos += 4;
+                }
+            }
+            position => parents.push(parent(position)?),
+        }
+
+        let high = self.u32_at(start + raw_len + 8);
+        let low = self.u32_at(start + raw_len + 12);
+        Some(GraphCommit {
+            tree: to_hex(&self.data[start..start + raw_len]),
+            parents,
+            generation: high >> 2,
+            time: ((high as u64 & 0x3) << 32) | low as u64,
+        })
+    }
+
+    pub fn commit(&self, sha: &str) -> Option<GraphCommit> {
+        self.commit_at(self.find(sha)?)
+    }
+
+    /// The version, number of hashes and bits per entry of the changed-path filters.
+    fn bloom_settings(&self) -> Option<(u32, u32)> {
+        let data = self.chunks.get(BLOOM_DATA)?;
+        let indexes = self.chunks.get(BLOOM_INDEXES)?;
+        if data.len() < BLOOM_HEADER_LEN || indexes.len() != self.count * 4 {
+            return None;
+        }
+
+        let version = self.u32_at(data.start);
+        let hashes = self.u32_at(data.start + 4);
+        (matches!(version, 1 | 2) && hashes > 0).then_some((version, hashes))
+    }
+
+    /// The changed-path filter of the commit at position i, with the version hashing its paths.
+    pub fn bloom_filter_at(&self, i: usize) -> Option<(u32, &[u8])> {
+        let (version, _) = self.bloom_settings()?;
+        let indexes = self.chunk(BLOOM_INDEXES).start;
+        let data = self.chunk(BLOOM_DATA);
+        let end = |i: usize| self.u32_at(indexes + 4 * i) as usize;
+
+        let start = match i {
+            0 => 0,
+            i => end(i - 1),
+        };
+        let range = data.start + BLOOM_HEADER_LEN + start..data.start + BLOOM_HEADER_LEN + end(i);
+        (range.start <= range.end && range.end <= data.end).then(|| (version, &self.data[range]))
+    }
+
+    /// Whether commit sha may change path compared to its first parent: false means it
+    /// certainly does not, and history walks limited to path can skip it. None when the
+    /// commit has no filter.
+    pub fn maybe_changed(&self, sha: &str, path: &str) -> Option<bool> {
+        let (_, hashes) = self.bloom_settings()?;
+        let (version, filter) = self.bloom_filter_at(self.find(sha)?)?;
+        if filter.is_empty() {
+            return None;
+        }
+        if filter == BLOOM_TRUNCATED {
+            return Some(true);
+        }
+
+        let path = path.trim_end_matches('/');
+        Some(bloom_keys(path, version, hashes).into_iter().all(|key| {
+            let bit = key as usize % (filter.len() * 8);
+            filter[bit / 8] & (1 << (bit % 8)) != 0
+        }))
+    }
+
+    /// Whether the checksum at the end of the file matches its content.
+    pub fn checksum_matches(&self) -> bool {
+        lookup::checksum_matches(&self.data, self.format)
+    }
+
+    /// The file holding commits, with the changed-path filter of each one when filters is
+    /// given. Every parent must be among commits.
+    pub fn serialize(
+        commits: &BTreeMap<String, GraphCommit>,
+        filters: Option<&BTreeMap<String, Vec<u8>>>,
+        format: ObjectFormat,
+    ) -> Vec<u8> {
+        let positions: BTreeMap<&str, u32> = commits
+            .keys()
+            .enumerate()
+            .map(|(i, sha)| (sha.as_str(), i as u32))
+            .collect();
+
+        let mut oids = Vec::new();
+        let mut data = Vec::new();
+        let mut edges: Vec<u32> = Vec::new();
+
+        for (sha, commit) in commits {
+            oids.extend_from_slice(&from_hex(sha));
+
+            let parents: Vec<u32> = commit
+                .parents
+                .iter()
+                .map(|parent| positions[parent.as_str()])
+                .collect();
+            let (first, second) = match parents[..] {
+                [] => (PARENT_NONE, PARENT_NONE),
+                [first] => (first, PARENT_NONE),
+                [first, second] => (first, second),
+                [first, ref others @ ..] => {
+                    let edge = EDGE_FLAG | edges.len() as u32;
+                    edges.extend_from_slice(others);
+                    *edges.last_mut().unwrap() |= EDGE_FLAG;
+                    (first, edge)
+                }
+            };
+
+            let generation = commit.generation.min(MAX_GENERATION);
+            data.extend_from_slice(&from_hex(&commit.tree));
+            data.extend_from_slice(&first.to_be_bytes());
+            data.extend_from_slice(&second.to_be_bytes());
+            data.extend_from_slice(
+                &(generation << 2 | (commit.time >> 32) as u32 & 0x3).to_be_bytes(),
+            );
+            data.extend_from_slice(&(commit.time as u32).to_be_bytes());
+        }
+
+        let mut chunks = vec![
+            (OID_FANOUT, lookup::fanout(commits.keys())),
+            (OID_LOOKUP, oids),
+            (COMMIT_DATA, data),
+        ];
+        if !(edges.is_empty()) {
+            chunks.push((
+                EXTRA_EDGES,
+                edges.iter().flat_map(|edge| edge.to_be_bytes()).collect(),
+            ));
+        }
+
+        if let Some(filters) = filters {
+            let mut indexes = Vec::new();
+            let mut data = Vec::new();
+            for setting in [BLOOM_VERSION, BLOOM_HASHES, BLOOM_BITS_PER_ENTRY] {
+                data.extend_from_slice(&setting.to_be_bytes());
+            }
+            for sha in commits.keys() {
+                data.extend_from_slice(filters.get(sha).map_or(&[][..], |filter| filter));
+                indexes.extend_from_slice(&((data.len() - BLOOM_HEADER_LEN) as u32).to_be_bytes());
+            }
+            chunks.push((BLOOM_INDEXES, indexes));
+            chunks.push((BLOOM_DATA, data));
+        }
+
+        let header = [SIGNATURE, &[1, hash_version(format), chunks.len() as u8, 0]].concat();
+        chunk::write(&header, &chunks, format)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{bloom_filter, murmur3, CommitGraph, GraphCommit};
+    use crate::object::format::ObjectFormat;
+    use std::collections::BTreeMap;
+
+    #[test]
+    pub fn test_commit_graph() {
+        // The test vectors of git's t0095-bloom.
+        assert_eq!(0x0000_0000, murmur3(0, b"", false));
+        assert_eq!(0x627b_0c2c, murmur3(0, b"Hello world!", false));
+        assert_eq!(
+            0x2e4f_f723,
+            murmur3(0, b"The quick brown fox jumps over the lazy dog", false)
+        );
+        // Signed chars only change the hash of bytes above 0x7f.
+        assert_eq!(
+            0xdd92_776e,
+            murmur3(0, b"\x99\xaa\xbb\xcc\xdd\xee\xff", true)
+        );
+        assert_eq!(
+            0xa183_ccfd,
+            murmur3(0, b"\x99\xaa\xbb\xcc\xdd\xee\xff", false)
+        );
+
+        let commit = |tree: &str, parents: &[&str], generation| GraphCommit {
+            tree: tree.repeat(40),
+            parents: parents.iter().map(|parent| parent.repeat(40)).collect(),
+            generation,
+            time: 0x2_0000_0001,
+        };
+        let commits = BTreeMap::from([
+            ("a".repeat(40), commit("1", &[], 1)),
+            ("b".repeat(40), commit("2", &["a"], 2)),
+            ("c".repeat(40), commit("3", &["a"], 2)),
+            ("d".repeat(40), commit("4", &["a"], 2)),
+            ("e".repeat(40), commit("5", &["b", "c", "d"], 3)),
+        ]);
+        let filters = BTreeMap::from([
+            (
+                "a".repeat(40),
+                bloom_filter(Some(&["dir/file".to_string()])),
+            ),
+            ("b".repeat(40), bloom_filter(None)),
+        ]);
+
+        let data = CommitGraph::serialize(&commits, Some(&filters), ObjectFormat::Sha1);
+        let graph = CommitGraph::parse(data, ObjectFormat::Sha1).unwrap();
+        assert!(graph.checksum_matches());
+        assert_eq!(5, graph.count());
+        for (sha, commit) in &commits {
+            assert_eq!(Some(commit), graph.commit(sha).as_ref());
+        }
+        assert_eq!(None, graph.commit(&"f".repeat(40)));
+
+        let root = "a".repeat(40);
+        assert_eq!(Some(true), graph.maybe_changed(&root, "dir/file"));
+        assert_eq!(Some(true), graph.maybe_changed(&root, "dir"));
+        assert_eq!(Some(false), graph.maybe_changed(&root, "other"));
+        assert_eq!(Some(true), graph.maybe_changed(&"b".repeat(40), "other"));
+        assert_eq!(None, graph.maybe_changed(&"c".repeat(40), "other"));
+
+        assert!(CommitGraph::parse(b"CGPH".to_vec(), ObjectFormat::Sha1).is_none());
+        let data = CommitGraph::serialize(&commits, None, ObjectFormat::Sha1);
+        assert!(CommitGraph::parse(data, ObjectFormat::Sha256).is_none());
+    }
+}
@@ -0,0 +1,379 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::format::ObjectFormat;
+use std::collections::HashMap;
+
+const SIGNATURE: &[u8] = b"BITM";
+const VERSION: u16 = 1;
+
+/// The pack holds everything its objects reach, git refuses bitmaps without it
+const OPT_FULL_DAG: u16 = 0x1;
+
+/// A bitmap is stored as the XOR of its own and the one this many entries before at most
+const MAX_XOR_OFFSET: usize = 160;
+
+/// The types of the type bitmaps, in their order in the file
+pub const TYPES: [&str; 4] = ["commit", "tree", "blob", "tag"];
+
+/// The words of a bitmap compressed with EWAH hold both these
+const RUNNING_BITS: u32 = 32;
+const LITERAL_BITS: u32 = 31;
+
+/// A set of positions, bit i of word i / 64 being position i.
+#[derive(Clone, Debug, Default)]
+pub struct Bitmap {
+    words: Vec<u64>,
+}
+
+impl Bitmap {
+    pub fn set(&mut self, i: usize) {
+        if self.words.len() <= i / 64 {
+            self.words.resize(i / 64 + 1, 0);
+        }
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    pub fn get(&self, i: usize) -> bool {
+        self.words
+            .get(i / 64)
+            .is_some_and(|word| word & (1 << (i % 64)) != 0)
+    }
+
+    /// Add the positions of other.
+    pub fn or(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        self.words
+            .iter_mut()
+            .zip(&other.words)
+            .for_each(|(word, other)| *word |= other);
+    }
+
+    fn xor(&mut self, other: &Bitmap) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        self.words
+            .iter_mut()
+            .zip(&other.words)
+            .for_each(|(word, other)| *word ^= other);
+    }
+
+    /// The positions set, in increasing order.
+    pub fn ones(&self) -> impl Iterator<Item = usize> + '_ {
+        self.words.iter().enumerate().flat_map(|(i, word)| {
+            (0..64)
+                .filter(move |bit| word & (1 << bit) != 0)
+                .map(move |bit| i * 64 + bit)
+        })
+    }
+
+    /// Read a bitmap compressed with EWAH at pos: its size in bits, the number of words, the
+    /// words, then the position of the last marker word. Each marker word tells how many
+    /// words of only zeros or only ones, its bit 0, come next, then how many literal words
+    /// follow it.
+    fn read_ewah(data: &[u8], pos: &mut usize) -> Option<Bitmap> {
+        let u32_at = |pos: usize| -> Option<usize> {
+            Some(u32::from_be_bytes(data.get(pos..pos + 4)?.try_into().unwrap()) as usize)
+        };
+        let len = u32_at(*pos + 4)?;
+        let buffer: Vec<u64> = data
+            .get(*pos + 8..*pos + 8 + 8 * len)?
+            .chunks(8)
+            .map(|word| u64::from_be_bytes(word.try_into().unwrap()))
+            .collect();
+        *pos += 8 + 8 * len + 4;
+        if data.len() < *pos {
+            return None;
+        }
+
+        let mut words = Vec::new();
+        let mut i = 0;
+        while i < buffer.len() {
+            let marker = buffer[i];
+            let running = (marker >> 1) & ((1 << RUNNING_BITS) - 1);
+            let literals = (marker >> (1 + RUNNING_BITS)) as usize;
+            let clean = if marker & 1 == 1 { u64::MAX } else { 0 };
+
+            words.extend(std::iter::repeat_n(clean, running as usize));
+            words.extend_from_slice(buffer.get(i + 1..i + 1 + literals)?);
+            i += 1 + literals;
+        }
+
+        Some(Bitmap { words })
+    }
+
+    /// Compress the bitmap with EWAH, as read_ewah reads it.
+    fn write_ewah(&self, data: &mut Vec<u8>) {
+        let len = self
+            .words
+            .iter()
+            .rposition(|word| *word != 0)
+            .map_or(0, |last| last + 1);
+        let words = &self.words[..len];
+        let bits = words.last().map_or(0, |last| {
+            64 * (len - 1) + 64 - last.leading_zeros() as usize
+        });
+        let is_clean = |word: u64| word == 0 || word == u64::MAX;
+
+        let mut buffer = Vec::new();
+        let mut marker;
+        let mut i = 0;
+        loop {
+            marker = buffer.len();
+            buffer.push(0);
+
+            let clean = words.get(i).copied().filter(|word| is_clean(*word));
+            let mut running = 0u64;
+            while running < (1 << RUNNING_BITS) - 1
+                && clean.is_some()
+                && words.get(i) == clean.as_ref()
+            {
+                running += 1;
+                i += 1;
+            }
+            let mut literals = 0u64;
+            while literals < (1 << LITERAL_BITS) - 1
+                && words.get(i).is_some_and(|word| !(is_clean(*word)))
+            {
+                buffer.push(words[i]);
+                literals += 1;
+                i += 1;
+            }
+
+            buffer[marker] =
+                (clean == Some(u64::MAX)) as u64 | running << 1 | literals << (1 + RUNNING_BITS);
+            if i >= words.len() {
+                break;
+            }
+        }
+
+        data.extend((bits as u32).to_be_bytes());
+        data.extend((buffer.len() as u32).to_be_bytes());
+        data.extend(buffer.iter().flat_map(|word| word.to_be_bytes()));
+        data.extend((marker as u32).to_be_bytes());
+    }
+}
+
+/// The reachability bitmaps of a pack, `pack-<checksum>.bitmap`, version 1: after the
+/// checksum of the pack, a bitmap of the objects of each type, then for selected commits
+/// the bitmap of the objects they reach, all of them in the pack. Positions are those of
+/// the objects in the pack, by offset, while commits are named by their position in the
+/// index. A commit bitmap may be stored XORed with one of the previous ones.
+#[derive(Default)]
+pub struct PackBitmap {
+    objects: Vec<String>,
+    positions: HashMap<String, usize>,
+    types: Vec<Bitmap>,
+    commits: HashMap<String, Bitmap>,
+}
+
+impl PackBitmap {
+    /// The bitmaps of a pack holding objects, in the order of the pack, and their types.
+    /// Commit bitmaps are added with `insert`.
+    pub fn new(objects: &[(String, String)]) -> Self {
+        let mut types = vec![Bitmap::default(); TYPES.len()];
+        for (position, (_, fmt)) in objects.iter().enumerate() {
+            if let Some(typ) = TYPES.iter().position(|typ| typ == fmt) {
+                types[typ].set(position);
+            }
+        }
+
+        let objects: Vec<String> = objects.iter().map(|(sha, _)| sha.clone()).collect();
+        PackBitmap {
+            positions: Self::positions(&objects),
+            objects,
+            types,
+            commits: HashMap::new(),
+        }
+    }
+
+    fn positions(objects: &[String]) -> HashMap<String, usize> {
+        objects
+            .iter()
+            .enumerate()
+            .map(|(position, sha)| (sha.clone(), position))
+            .collect()
+    }
+
+    /// Read the bitmaps of the pack holding objects, in the order of the pack, whose checksum
+    /// is pack_checksum.
+    pub fn parse(
+        data: &[u8],
+        objects: Vec<String>,
+        pack_checksum: &[u8],
+        format: ObjectFormat,
+    ) -> Option<Self> {
+        let header_len = 12 + format.raw_len();
+        let header = data.get(..header_len)?;
+        let options = u16::from_be_bytes(header[6..8].try_into().unwrap());
+        if !(header.starts_with(SIGNATURE))
+            || header[4..6] != VERSION.to_be_bytes()
+            || options & OPT_FULL_DAG == 0
+            || &header[12..] != pack_checksum
+        {
+            return None;
+        }
+        let count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+
+        let mut pos = header_len;
+        let types = (0..TYPES.len())
+            .map(|_| Bitmap::read_ewah(data, &mut pos))
+            .collect::<Option<Vec<Bitmap>>>()?;
+
+        let mut sorted = objects.clone();
+        sorted.sort();
+        let mut entries: Vec<(String, Bitmap)> = Vec::with_capacity(count);
+        for i in 0..count {
+            let entry = data.get(pos..pos + 6)?;
+            let sha = sorted
+                .get(u32::from_be_bytes(entry[..4].try_into().unwrap()) as usize)?
+                .clone();
+            let xor_offset = entry[4] as usize;
+            if xor_offset > MAX_XOR_OFFSET {
+                return None;
+            }
+            pos += 6;
+
+            let mut bitmap = Bitmap::read_ewah(data, &mut pos)?;
+            if xor_offset > 0 {
+                bitmap.xor(&entries.get(i.checked_sub(xor_offset)?)?.1);
+            }
+            entries.push((sha, bitmap));
+        }
+
+        Some(PackBitmap {
+            positions: Self::positions(&objects),
+            objects,
+            types,
+            commits: entries.into_iter().collect(),
+        })
+    }
+
+    /// The position of object sha in the pack.
+    pub fn position(&self, sha: &str) -> Option<usize> {
+        self.positions.get(sha).copied()
+    }
+
+    pub fn object(&self, position: usize) -> &str {
+        &self.objects[position]
+    }
+
+    /// The objects commit sha reaches, when it has a bitmap.
+    pub fn commit(&self, sha: &str) -> Option<&Bitmap> {
+        self.commits.get(sha)
+    }
+
+    pub fn insert(&mut self, commit: &str, bitmap: Bitmap) {
+        self.commits.insert(commit.to_owned(), bitmap);
+    }
+
+    /// The bitmap file of the pack whose checksum is pack_checksum, commits sorted by name
+    /// and stored whole.
+    pub fn serialize(&self, pack_checksum: &[u8], format: ObjectFormat) -> Vec<u8> {
+        let mut sorted = self.objects.clone();
+        sorted.sort();
+        let mut commits: Vec<(&String, &Bitmap)> = self.commits.iter().collect();
+        commits.sort_by_key(|(sha, _)| *sha);
+
+        let mut data = SIGNATURE.to_vec();
+        data.extend(VERSION.to_be_bytes());
+        data.extend(OPT_FULL_DAG.to_be_bytes());
+        data.extend((commits.len() as u32).to_be_bytes());
+        data.extend_from_slice(pack_checksum);
+        self.types
+            .iter()
+            .for_each(|bitmap| bitmap.write_ewah(&mut data));
+
+        for (sha, bitmap) in commits {
+            let index_position = sorted.binary_search(sha).unwrap();
+            data.extend((index_position as u32).to_be_bytes());
+            // Neither XORed with a previous bitmap nor flagged.
+            data.extend([0, 0]);
+            bitmap.write_ewah(&mut data);
+        }
+
+        let checksum = format.digest(&data);
+        data.extend(checksum);
+        data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Bitmap, PackBitmap};
+    use crate::object::format::ObjectFormat;
+
+    fn bitmap(positions: &[usize]) -> Bitmap {
+        let mut bitmap = Bitmap::default();
+        positions.iter().for_each(|position| bitmap.set(*position));
+        bitmap
+    }
+
+    #[test]
+    pub fn test_bitmap() {
+        // A marker with one literal, the literal, a marker with a run of two empty words and
+        // one literal, the literal, then the position of the last marker.
+        let mut data = Vec::new();
+        bitmap(&[0, 1, 200]).write_ewah(&mut data);
+        let mut expected = vec![0, 0, 0, 201, 0, 0, 0, 4];
+        expected.extend((1u64 << 33).to_be_bytes());
+        expected.extend(3u64.to_be_bytes());
+        expected.extend((1u64 << 33 | 2 << 1).to_be_bytes());
+        expected.extend((1u64 << 8).to_be_bytes());
+        expected.extend(2u32.to_be_bytes());
+        assert_eq!(expected, data);
+
+        let mut full: Vec<usize> = (64..640).collect();
+        full.push(1000);
+        for positions in [vec![], vec![0, 1, 200], full] {
+            let mut data = Vec::new();
+            bitmap(&positions).write_ewah(&mut data);
+            let mut pos = 0;
+            let read = Bitmap::read_ewah(&data, &mut pos).unwrap();
+            assert_eq!(data.len(), pos);
+            assert_eq!(positions, read.ones().collect::<Vec<_>>());
+        }
+
+        let objects: Vec<(String, String)> = ["commit", "commit", "tree", "blob"]
+            .iter()
+            .enumerate()
+            .map(|(i, fmt)| (format!("{:040x}", 4 - i), fmt.to_string()))
+            .collect();
+        let mut written = PackBitmap::new(&objects);
+        written.insert(&objects[0].0, bitmap(&[0, 1, 2, 3]));
+        written.insert(&objects[1].0, bitmap(&[1, 2, 3]));
+        let checksum = [7; 20];
+        let data = written.serialize(&checksum, ObjectFormat::Sha1);
+
+        let names: Vec<String> = objects.iter().map(|(sha, _)| sha.clone()).collect();
+        assert!(PackBitmap::parse(&data, names.clone(), &[8; 20], ObjectFormat::Sha1).is_none());
+        let read = PackBitmap::parse(&data, names, &checksum, ObjectFormat::Sha1).unwrap();
+        assert_eq!(Some(3), read.position(&objects[3].0));
+        assert_eq!(
+            vec![1, 2, 3],
+            read.commit(&objects[1].0)
+                .unwrap()
+                .ones()
+                .collect::<Vec<_>>()
+        );
+        assert!(read.commit(&objects[2].0).is_none());
+    }
+}
@@ -0,0 +1,373 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error::{self, Error, Log};
+use crate::object::database::{encode, encode_stream, ObjectDatabase, SizedReader};
+use crate::object::format::ObjectFormat;
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Numbers the temporary files of objects being written, which threads may write at once
+static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+/// Objects stored one per file, compressed, as `<objects>/<first 2 hex digits>/<the others>`.
+pub struct LooseDatabase {
+    path: PathBuf,
+    format: ObjectFormat,
+}
+
+impl LooseDatabase {
+    pub fn new(path: &Path, format: ObjectFormat) -> Self {
+        LooseDatabase {
+            path: path.to_owned(),
+            format,
+        }
+    }
+
+    pub fn object_path(&self, sha: &str) -> Option<PathBuf> {
+        (sha.len() > 2 && sha.chars().all(|c| c.is_ascii_hexdigit()))
+            .then(|| self.path.join(&sha[0..2]).join(&sha[2..]))
+    }
+
+    /// The type and size in the header `<type> <size>\0` starting raw, and where the content starts.
+    fn parse_header(raw: &[u8]) -> Option<(String, usize, usize)> {
+        let end = raw.iter().position(|byte| *byte == 0)?;
+        let (fmt, size) = std::str::from_utf8(&raw[..end]).ok()?.split_once(' ')?;
+        Some((fmt.to_string(), size.parse().ok()?, end + 1))
+    }
+
+    /// A path next to path to write an object to before renaming it.
+    fn temp_path(path: &Path) -> PathBuf {
+        path.with_extension(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            TEMP_FILES.fetch_add(1, Ordering::Relaxed)
+        ))
+    }
+
+    fn io_error(path: &Path, e: std::io::Error) -> Error {
+        Error::Object(error::object::Object::Io(path.to_owned(), e.to_string()))
+    }
+
+    /// Store an object of size bytes read from input, deflating it into a temporary file while
+    /// hashing it, unless exists says it is stored already.
+    pub fn write_stream_unless(
+        &self,
+        fmt: &str,
+        size: usize,
+        input: &mut dyn Read,
+        exists: impl Fn(&str) -> bool,
+    ) -> error::Result<String> {
+        // The name is only known at the end, the object is written aside in the objects directory.
+        let temp = Self::temp_path(&self.path.join("tmp_obj"));
+        fs::create_dir_all(&self.path).map_err(|e| Self::io_error(&self.path, e))?;
+
+        let written = File::create(&temp).and_then(|file| {
+            let mut compress = ZlibEncoder::new(BufWriter::new(file), Compression::default());
+            let sha = encode_stream(self.format, fmt, size, input, &mut compress)?;
+            compress.finish()?.flush()?;
+            Ok(sha)
+        });
+
+        let sha = match written {
+            Ok(sha) => sha,
+            Err(e) => {
+                let _ = fs::remove_file(&temp);
+                return Err(Self::io_error(&temp, e));
+            }
+        };
+
+        if exists(&sha) {
+            let _ = fs::remove_file(&temp);
+            return Ok(sha);
+        }
+
+        let path = self.object_path(&sha).unwrap();
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temp);
+                Self::io_error(&path, e)
+            })?;
+
+        Ok(sha)
+    }
+
+    /// Inflate object sha, checking its header, its size and its name.
+    fn verify_object(&self, sha: &str) -> Result<(), String> {
+        let file = File::open(self.object_path(sha).unwrap()).map_err(|e| e.to_string())?;
+        let mut decoder = BufReader::new(ZlibDecoder::new(BufReader::new(file)));
+        let mut header = Vec::new();
+        decoder
+            .by_ref()
+            .take(64)
+            .read_until(0, &mut header)
+            .map_err(|e| e.to_string())?;
+
+        let (fmt, size, _) = Self::parse_header(&header).ok_or("malformed header")?;
+        match encode_stream(self.format, &fmt, size, &mut decoder, &mut io::sink()) {
+            Ok(name) if name == sha => Ok(()),
+            Ok(name) => Err(format!("hash mismatch, the content is named {}", name)),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    /// The temporary files left by writes that did not finish, in the objects directory and
+    /// its fan-out directories.
+    pub fn temp_files(&self) -> Vec<PathBuf> {
+        let fan_out = fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir());
+
+        std::iter::once(self.path.clone())
+            .chain(fan_out)
+            .flat_map(|dir| fs::read_dir(dir).into_iter().flatten().flatten())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.is_file()
+                    && path
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("tmp_obj") || name.contains(".tmp-"))
+            })
+            .collect()
+    }
+
+    /// The files of the fan-out directories which are not objects, temporary files included.
+    pub fn garbage(&self) -> Vec<PathBuf> {
+        let len = self.format.hex_len() - 2;
+
+        fs::read_dir(&self.path)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit())
+            })
+            .flat_map(|entry| fs::read_dir(entry.path()).into_iter().flatten().flatten())
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                !(name.len() == len && name.chars().all(|c| c.is_ascii_hexdigit()))
+            })
+            .map(|entry| entry.path())
+            .collect()
+    }
+
+    /// The names of the objects in the directory of the names starting with dir.
+    fn names_in(&self, dir: &str) -> impl Iterator<Item = String> + '_ {
+        let dir = dir.to_string();
+        let len = self.format.hex_len() - 2;
+
+        fs::read_dir(self.path.join(&dir))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(move |entry| {
+                let name = entry.file_name().into_string().ok()?;
+                (name.len() == len && name.chars().all(|c| c.is_ascii_hexdigit()))
+                    .then(|| format!("{}{}", dir, name))
+            })
+    }
+}
+
+impl ObjectDatabase for LooseDatabase {
+    /// Only the start of the object is inflated.
+    fn read_header(&self, sha: &str) -> Option<(String, usize)> {
+        let mut decoder = ZlibDecoder::new(File::open(self.object_path(sha)?).ok()?);
+        let mut raw = Vec::new();
+        let mut chunk = [0u8; 32];
+
+        while !(raw.contains(&0)) && raw.len() < 64 {
+            let len = decoder.read(&mut chunk).ok()?;
+            if len == 0 {
+                break;
+            }
+            raw.extend_from_slice(&chunk[..len]);
+        }
+
+        Self::parse_header(&raw).map(|(fmt, size, _)| (fmt, size))
+    }
+
+    /// A corrupt object is reported as missing, `fsck` tells what is wrong with it.
+    fn read(&self, sha: &str) -> Option<(String, Vec<u8>)> {
+        let data = fs::read(self.object_path(sha)?).ok()?;
+        let mut raw = Vec::new();
+        let header = ZlibDecoder::new(data.as_slice())
+            .read_to_end(&mut raw)
+            .ok()
+            .and_then(|_| Self::parse_header(&raw));
+
+        match header {
+            Some((fmt, size, start)) if size == raw.len() - start => {
+                Some((fmt, raw[start..].to_vec()))
+            }
+            _ => {
+                warn!(
+                    "{}",
+                    error::object::Object::Malformed(sha.to_owned(), raw.len()).fmt()
+                );
+                None
+            }
+        }
+    }
+
+    /// The object is inflated as its content is read.
+    fn read_stream(&self, sha: &str) -> Option<(String, usize, Box<dyn Read + '_>)> {
+        let file = BufReader::new(File::open(self.object_path(sha)?).ok()?);
+        let mut decoder = BufReader::new(ZlibDecoder::new(file));
+        let mut header = Vec::new();
+        decoder.by_ref().take(64).read_until(0, &mut header).ok()?;
+
+        match Self::parse_header(&header) {
+            Some((fmt, size, _)) => Some((fmt, size, Box::new(SizedReader::new(decoder, size)))),
+            None => {
+                warn!(
+                    "{}",
+                    error::object::Object::Malformed(sha.to_owned(), 0).fmt()
+                );
+                None
+            }
+        }
+    }
+
+    /// The object is written aside then renamed, so that it never appears half written.
+    fn write(&self, fmt: &str, data: &[u8]) -> error::Result<String> {
+        let loose = encode(fmt, data);
+        let sha = self.format.hex_digest(&loose);
+        let path = self.object_path(&sha).unwrap();
+        if path.exists() {
+            return Ok(sha);
+        }
+
+        let temp = Self::temp_path(&path);
+        let mut compress = ZlibEncoder::new(Vec::new(), Compression::default());
+        compress.write_all(&loose).unwrap();
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&temp, compress.finish().unwrap()))
+            .map_err(|e| Self::io_error(&temp, e))?;
+        fs::rename(&temp, &path).map_err(|e| Self::io_error(&path, e))?;
+
+        Ok(sha)
+    }
+
+    fn write_stream(&self, fmt: &str, size: usize, input: &mut dyn Read) -> error::Result<String> {
+        self.write_stream_unless(fmt, size, input, |sha| self.exists(sha))
+    }
+
+    fn exists(&self, sha: &str) -> bool {
+        self.object_path(sha).is_some_and(|path| path.is_file())
+    }
+
+    /// Objects are inflated as they are hashed, they are never held in memory.
+    fn verify(&self, _full: bool) -> Vec<(String, String)> {
+        self.iter()
+            .filter_map(|sha| self.verify_object(&sha).err().map(|reason| (sha, reason)))
+            .collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        Box::new(
+            fs::read_dir(&self.path)
+                .into_iter()
+                .flatten()
+                .flatten()
+                .filter_map(|entry| entry.file_name().into_string().ok())
+                .filter(|dir| dir.len() == 2 && dir.chars().all(|c| c.is_ascii_hexdigit()))
+                .flat_map(|dir| self.names_in(&dir).collect::<Vec<_>>()),
+        )
+    }
+
+    fn lookup_prefix(&self, prefix: &str) -> Vec<String> {
+        if prefix.len() < 2 {
+            return self.iter().filter(|sha| sha.starts_with(prefix)).collect();
+        }
+
+        self.names_in(&prefix[0..2])
+            .filter(|sha| sha.starts_with(prefix))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::LooseDatabase;
+    use crate::object::database::{ObjectDatabase, STREAM_BUFFER_SIZE};
+    use crate::object::format::ObjectFormat;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::path::Path;
+
+    #[test]
+    pub fn test_loose_stream() {
+        let _ = fs::remove_dir_all("./test_loose_stream");
+        let path = Path::new("./test_loose_stream/objects");
+        let database = LooseDatabase::new(path, ObjectFormat::Sha256);
+
+        // Several chunks, the last one partial.
+        let data: Vec<u8> = (0..STREAM_BUFFER_SIZE * 3 + 17)
+            .map(|i| (i % 251) as u8)
+            .collect();
+        let sha = database
+            .write_stream("blob", data.len(), &mut data.as_slice())
+            .unwrap();
+        assert_eq!(
+            ObjectFormat::Sha256.hex_digest(&super::encode("blob", &data)),
+            sha
+        );
+        assert_eq!(sha, database.write("blob", &data).unwrap());
+
+        let (fmt, size, mut content) = database.read_stream(&sha).unwrap();
+        let mut streamed = Vec::new();
+        content.read_to_end(&mut streamed).unwrap();
+        assert_eq!(("blob", data.len()), (fmt.as_str(), size));
+        assert!(streamed == data);
+
+        // An input of another size than announced is not stored, nor left aside.
+        assert!(database
+            .write_stream("blob", data.len() + 1, &mut data.as_slice())
+            .is_err());
+        assert!(database
+            .write_stream("blob", 3, &mut data.as_slice())
+            .is_err());
+        assert_eq!(1, database.iter().count());
+        assert_eq!(1, fs::read_dir(path).unwrap().count());
+
+        // A truncated object is an error, not a short content.
+        let mut compress = ZlibEncoder::new(Vec::new(), Compression::default());
+        compress.write_all(b"blob 100\0truncated").unwrap();
+        let name = "0".repeat(64);
+        let file = database.object_path(&name).unwrap();
+        fs::create_dir_all(file.parent().unwrap()).unwrap();
+        fs::write(&file, compress.finish().unwrap()).unwrap();
+        let (_, size, mut content) = database.read_stream(&name).unwrap();
+        assert_eq!(100, size);
+        assert!(content.read_to_end(&mut Vec::new()).is_err());
+
+        fs::remove_dir_all("./test_loose_stream").unwrap();
+    }
+}
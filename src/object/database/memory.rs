@@ -0,0 +1,86 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error;
+use crate::object::database::{check_name, encode, ObjectDatabase};
+use crate::object::format::ObjectFormat;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// Objects kept in memory, gone with the database: a scratch storage for tests.
+pub struct MemoryDatabase {
+    format: ObjectFormat,
+    objects: RefCell<BTreeMap<String, (String, Vec<u8>)>>,
+}
+
+impl MemoryDatabase {
+    pub fn new(format: ObjectFormat) -> Self {
+        MemoryDatabase {
+            format,
+            objects: RefCell::new(BTreeMap::new()),
+        }
+    }
+}
+
+impl ObjectDatabase for MemoryDatabase {
+    fn read_header(&self, sha: &str) -> Option<(String, usize)> {
+        self.objects
+            .borrow()
+            .get(sha)
+            .map(|(fmt, data)| (fmt.clone(), data.len()))
+    }
+
+    fn read(&self, sha: &str) -> Option<(String, Vec<u8>)> {
+        self.objects.borrow().get(sha).cloned()
+    }
+
+    fn write(&self, fmt: &str, data: &[u8]) -> error::Result<String> {
+        let sha = self.format.hex_digest(&encode(fmt, data));
+        self.objects
+            .borrow_mut()
+            .entry(sha.clone())
+            .or_insert_with(|| (fmt.to_string(), data.to_vec()));
+        Ok(sha)
+    }
+
+    fn verify(&self, _full: bool) -> Vec<(String, String)> {
+        self.objects
+            .borrow()
+            .iter()
+            .filter_map(|(sha, (fmt, data))| {
+                check_name(self.format, sha, fmt, data)
+                    .err()
+                    .map(|reason| (sha.clone(), reason))
+            })
+            .collect()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        let names: Vec<String> = self.objects.borrow().keys().cloned().collect();
+        Box::new(names.into_iter())
+    }
+
+    fn lookup_prefix(&self, prefix: &str) -> Vec<String> {
+        self.objects
+            .borrow()
+            .range(prefix.to_string()..)
+            .map(|(sha, _)| sha)
+            .take_while(|sha| sha.starts_with(prefix))
+            .cloned()
+            .collect()
+    }
+}
@@ -0,0 +1,203 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::chunk::{self, Chunks};
+use crate::object::format::{from_hex, to_hex, ObjectFormat};
+use crate::object::lookup::{self, NameLookup};
+use std::ops::Range;
+
+pub const FILE_NAME: &str = "multi-pack-index";
+
+const SIGNATURE: &[u8] = b"MIDX";
+const HEADER_LEN: usize = 12;
+
+const PACK_NAMES: &[u8; 4] = b"PNAM";
+const OID_FANOUT: &[u8; 4] = b"OIDF";
+const OID_LOOKUP: &[u8; 4] = b"OIDL";
+const OBJECT_OFFSETS: &[u8; 4] = b"OOFF";
+const LARGE_OFFSETS: &[u8; 4] = b"LOFF";
+
+/// Set on an offset which is an index in the large offsets, when the file has them
+const LARGE_OFFSET: u32 = 0x8000_0000;
+
+/// An object of a multi-pack-index: its name, the position of its pack and its offset there.
+pub type MidxEntry = (String, u32, usize);
+
+fn hash_version(format: ObjectFormat) -> u8 {
+    match format {
+        ObjectFormat::Sha1 => 1,
+        ObjectFormat::Sha256 => 2,
+    }
+}
+
+/// A multi-pack-index, `objects/pack/multi-pack-index`, version 1: the names of the indexes
+/// of the packs it covers, sorted, then the names of their objects sorted, with a fanout
+/// table, and for each object the position of the pack holding it and its offset there.
+/// Offsets past 4 GiB are in a table of 64-bit offsets.
+pub struct MultiPackIndex {
+    data: Vec<u8>,
+    format: ObjectFormat,
+    count: usize,
+    chunks: Chunks,
+    lookup: NameLookup,
+
+    /// The names of the pack indexes, `pack-<checksum>.idx`
+    pub packs: Vec<String>,
+}
+
+impl MultiPackIndex {
+    pub fn parse(data: Vec<u8>, format: ObjectFormat) -> Option<Self> {
+        let header = data.get(..HEADER_LEN)?;
+        if !(header.starts_with(SIGNATURE)) || header[4] != 1 || header[5] != hash_version(format) {
+            return None;
+        }
+        let pack_count = u32::from_be_bytes(header[8..12].try_into().unwrap()) as usize;
+
+        let chunks = Chunks::parse(&data, HEADER_LEN, header[6] as usize, format)?;
+        let fanout = chunks
+            .get(OID_FANOUT)
+            .filter(|range| range.len() == 256 * 4)?;
+        let count =
+            u32::from_be_bytes(data[fanout.end - 4..fanout.end].try_into().unwrap()) as usize;
+        let has_len = |id, len| chunks.get(id).is_some_and(|range| range.len() == len);
+        if !(has_len(OID_LOOKUP, count * format.raw_len()) && has_len(OBJECT_OFFSETS, count * 8)) {
+            return None;
+        }
+
+        let packs: Vec<String> = data[chunks.get(PACK_NAMES)?]
+            .split(|byte| *byte == 0)
+            .filter(|name| !(name.is_empty()))
+            .map(|name| String::from_utf8_lossy(name).to_string())
+            .collect();
+        if packs.len() != pack_count {
+            return None;
+        }
+
+        let lookup = NameLookup::new(fanout.start, chunks.get(OID_LOOKUP)?.start, count, format);
+        Some(MultiPackIndex {
+            data,
+            format,
+            count,
+            chunks,
+            lookup,
+            packs,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    fn u32_at(&self, pos: usize) -> u32 {
+        u32::from_be_bytes(self.data[pos..pos + 4].try_into().unwrap())
+    }
+
+    fn chunk(&self, id: &[u8; 4]) -> Range<usize> {
+        self.chunks.get(id).unwrap_or_default()
+    }
+
+    /// The positions of the names starting with byte.
+    pub fn range(&self, byte: u8) -> Range<usize> {
+        self.lookup.range(&self.data, byte)
+    }
+
+    pub fn name(&self, i: usize) -> String {
+        to_hex(self.lookup.name(&self.data, i))
+    }
+
+    pub fn names(&self, range: Range<usize>) -> impl Iterator<Item = String> + '_ {
+        range.map(|i| self.name(i))
+    }
+
+    /// The position of sha among the names.
+    pub fn find(&self, sha: &str) -> Option<usize> {
+        self.lookup.find(&self.data, sha)
+    }
+
+    /// The position of the pack holding the object at position i, and its offset there.
+    pub fn location(&self, i: usize) -> Option<(usize, usize)> {
+        let start = self.chunk(OBJECT_OFFSETS).start + 8 * i;
+        let pack = self.u32_at(start) as usize;
+        let offset = self.u32_at(start + 4);
+
+        let offset = match self.chunks.get(LARGE_OFFSETS) {
+            Some(large) if offset & LARGE_OFFSET != 0 => {
+                let pos = large.start + 8 * (offset & !LARGE_OFFSET) as usize;
+                let raw = self.data.get(pos..(pos + 8).min(large.end))?;
+                u64::from_be_bytes(raw.try_into().ok()?) as usize
+            }
+            _ => offset as usize,
+        };
+        (pack < self.packs.len()).then_some((pack, offset))
+    }
+
+    /// Whether the checksum at the end of the file matches its content.
+    pub fn checksum_matches(&self) -> bool {
+        lookup::checksum_matches(&self.data, self.format)
+    }
+
+    /// The multi-pack-index of the packs whose indexes are named packs, sorted, holding
+    /// entries sorted by name, one per object.
+    pub fn serialize(packs: &[String], entries: &[MidxEntry], format: ObjectFormat) -> Vec<u8> {
+        let mut names: Vec<u8> = packs
+            .iter()
+            .flat_map(|name| name.bytes().chain([0]))
+            .collect();
+        names.resize(names.len().next_multiple_of(4), 0);
+
+        let oids: Vec<u8> = entries
+            .iter()
+            .flat_map(|(sha, _, _)| from_hex(sha))
+            .collect();
+
+        // As git does, offsets up to 4 GiB are stored as they are unless some are past it.
+        let large_needed = entries
+            .iter()
+            .any(|(_, _, offset)| *offset > u32::MAX as usize);
+        let mut offsets = Vec::new();
+        let mut large = Vec::new();
+        for (_, pack, offset) in entries {
+            offsets.extend_from_slice(&pack.to_be_bytes());
+            if large_needed && *offset >= LARGE_OFFSET as usize {
+                let index = LARGE_OFFSET | (large.len() / 8) as u32;
+                offsets.extend_from_slice(&index.to_be_bytes());
+                large.extend_from_slice(&(*offset as u64).to_be_bytes());
+            } else {
+                offsets.extend_from_slice(&(*offset as u32).to_be_bytes());
+            }
+        }
+
+        let mut chunks = vec![
+            (PACK_NAMES, names),
+            (
+                OID_FANOUT,
+                lookup::fanout(entries.iter().map(|(sha, _, _)| sha)),
+            ),
+            (OID_LOOKUP, oids),
+            (OBJECT_OFFSETS, offsets),
+        ];
+        if large_needed {
+            chunks.push((LARGE_OFFSETS, large));
+        }
+
+        let mut header = SIGNATURE.to_vec();
+        header.extend_from_slice(&[1, hash_version(format), chunks.len() as u8, 0]);
+        header.extend_from_slice(&(packs.len() as u32).to_be_bytes());
+        chunk::write(&header, &chunks, format)
+    }
+}
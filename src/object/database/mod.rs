@@ -0,0 +1,376 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+pub mod bitmap;
+pub mod loose;
+#[cfg(test)]
+pub mod memory;
+pub mod midx;
+pub mod packed;
+
+use crate::error;
+use crate::object::format::ObjectFormat;
+use loose::LooseDatabase;
+use packed::PackDatabase;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Cursor, Read, Write};
+use std::path::Path;
+
+/// Alternates listing alternates are followed this deep
+const MAX_ALTERNATE_DEPTH: usize = 5;
+
+/// Streamed objects are read and written by chunks of this size
+pub(crate) const STREAM_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Where the objects of a repository are stored, by name.
+pub trait ObjectDatabase {
+    /// The type and size of object sha, reading as little of it as the storage allows.
+    fn read_header(&self, sha: &str) -> Option<(String, usize)>;
+
+    /// The type and content of object sha.
+    fn read(&self, sha: &str) -> Option<(String, Vec<u8>)>;
+
+    /// The type and size of object sha, then a reader of its content, for objects too
+    /// large to be held in memory. Storages that cannot stream read the whole object.
+    fn read_stream(&self, sha: &str) -> Option<(String, usize, Box<dyn Read + '_>)> {
+        self.read(sha).map(|(fmt, data)| {
+            let size = data.len();
+            (fmt, size, Box::new(Cursor::new(data)) as Box<dyn Read>)
+        })
+    }
+
+    /// Store an object given by its type and content, returns its name.
+    fn write(&self, fmt: &str, data: &[u8]) -> error::Result<String>;
+
+    /// Store an object of size bytes read from input, returns its name.
+    /// Storages that cannot stream read the whole input first.
+    fn write_stream(&self, fmt: &str, size: usize, input: &mut dyn Read) -> error::Result<String> {
+        let mut data = Vec::with_capacity(size);
+        let unreadable = |reason: String| {
+            error::Error::Object(error::object::Object::Unreadable("input".into(), reason))
+        };
+
+        input
+            .read_to_end(&mut data)
+            .map_err(|e| unreadable(e.to_string()))?;
+        if data.len() != size {
+            return Err(unreadable(format!(
+                "expected {} bytes, read {}",
+                size,
+                data.len()
+            )));
+        }
+
+        self.write(fmt, &data)
+    }
+
+    fn exists(&self, sha: &str) -> bool {
+        self.read_header(sha).is_some()
+    }
+
+    /// Check the objects stored, and the storage itself: that each object can be read, to as
+    /// many bytes as its header says, and is named after its content. Returns the names of the
+    /// corrupt objects, or files, with what is wrong. Storages holding others only check their
+    /// own objects unless full.
+    fn verify(&self, full: bool) -> Vec<(String, String)>;
+
+    /// The names of the objects stored.
+    fn iter(&self) -> Box<dyn Iterator<Item = String> + '_>;
+
+    /// The names of the objects starting with prefix, lowercase hexadecimal.
+    fn lookup_prefix(&self, prefix: &str) -> Vec<String>;
+}
+
+/// What an object is named after, and how it is stored loose: a header giving
+/// its type and size, then its content.
+pub fn encode(fmt: &str, data: &[u8]) -> Vec<u8> {
+    let mut result = format!("{} {}\0", fmt, data.len()).into_bytes();
+    result.extend_from_slice(data);
+    result
+}
+
+/// Copy the object of size bytes read from input to output as it is stored loose, by chunks,
+/// returns its name. Fails if input does not hold exactly size bytes.
+pub fn encode_stream(
+    format: ObjectFormat,
+    fmt: &str,
+    size: usize,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> io::Result<String> {
+    let header = format!("{} {}\0", fmt, size).into_bytes();
+    let mut hasher = format.hasher();
+    hasher.update(&header);
+    output.write_all(&header)?;
+
+    let mut chunk = vec![0u8; STREAM_BUFFER_SIZE];
+    let mut total = 0;
+    loop {
+        let len = match input.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        total += len;
+        if total > size {
+            break;
+        }
+        hasher.update(&chunk[..len]);
+        output.write_all(&chunk[..len])?;
+    }
+
+    if total != size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("expected {} bytes, read {}", size, total),
+        ));
+    }
+
+    Ok(hasher.hex_digest())
+}
+
+/// Reads exactly size bytes of an object from a stream, failing if the stream ends before,
+/// as it does for a truncated object.
+pub struct SizedReader<R: Read> {
+    inner: R,
+    remaining: usize,
+}
+
+impl<R: Read> SizedReader<R> {
+    pub fn new(inner: R, size: usize) -> Self {
+        SizedReader {
+            inner,
+            remaining: size,
+        }
+    }
+}
+
+impl<R: Read> Read for SizedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.remaining == 0 || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let max = buf.len().min(self.remaining);
+        let len = self.inner.read(&mut buf[..max])?;
+        if len == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("object truncated, {} bytes missing", self.remaining),
+            ));
+        }
+        self.remaining -= len;
+        Ok(len)
+    }
+}
+
+/// Check that the object of type fmt with content data is named sha in format.
+pub fn check_name(format: ObjectFormat, sha: &str, fmt: &str, data: &[u8]) -> Result<(), String> {
+    match format.hex_digest(&encode(fmt, data)) {
+        name if name == sha => Ok(()),
+        name => Err(format!("hash mismatch, the content is named {}", name)),
+    }
+}
+
+/// The objects of an `objects` directory: loose objects, where new objects go, packs in
+/// `pack`, then the directories `info/alternates` lists, one per line.
+pub struct ObjectDirectory {
+    format: ObjectFormat,
+    loose: LooseDatabase,
+    packs: PackDatabase,
+    alternates: Vec<ObjectDirectory>,
+}
+
+impl ObjectDirectory {
+    pub fn open(path: &Path, format: ObjectFormat) -> Self {
+        Self::open_alternate(path, format, 0)
+    }
+
+    fn open_alternate(path: &Path, format: ObjectFormat, depth: usize) -> Self {
+        let alternates = fs::read_to_string(path.join("info").join("alternates"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::trim)
+            .filter(|line| !(line.is_empty() || line.starts_with('#')))
+            .filter_map(|line| {
+                if depth < MAX_ALTERNATE_DEPTH {
+                    Some(Self::open_alternate(&path.join(line), format, depth + 1))
+                } else {
+                    warn!("Ignoring alternate `{}`, nested too deep", line);
+                    None
+                }
+            })
+            .collect();
+
+        ObjectDirectory {
+            format,
+            loose: LooseDatabase::new(path, format),
+            packs: PackDatabase::new(&path.join("pack"), format),
+            alternates,
+        }
+    }
+
+    fn databases(&self) -> impl Iterator<Item = &dyn ObjectDatabase> {
+        [
+            &self.loose as &dyn ObjectDatabase,
+            &self.packs as &dyn ObjectDatabase,
+        ]
+        .into_iter()
+        .chain(
+            self.alternates
+                .iter()
+                .map(|alternate| alternate as &dyn ObjectDatabase),
+        )
+    }
+}
+
+impl ObjectDatabase for ObjectDirectory {
+    fn read_header(&self, sha: &str) -> Option<(String, usize)> {
+        self.databases()
+            .find_map(|database| database.read_header(sha))
+    }
+
+    fn read(&self, sha: &str) -> Option<(String, Vec<u8>)> {
+        self.databases().find_map(|database| database.read(sha))
+    }
+
+    fn read_stream(&self, sha: &str) -> Option<(String, usize, Box<dyn Read + '_>)> {
+        self.databases()
+            .find_map(|database| database.read_stream(sha))
+    }
+
+    /// Objects stored anywhere already are not written again.
+    fn write(&self, fmt: &str, data: &[u8]) -> error::Result<String> {
+        let sha = self.format.hex_digest(&encode(fmt, data));
+
+        if self.exists(&sha) {
+            Ok(sha)
+        } else {
+            self.loose.write(fmt, data)
+        }
+    }
+
+    fn write_stream(&self, fmt: &str, size: usize, input: &mut dyn Read) -> error::Result<String> {
+        self.loose
+            .write_stream_unless(fmt, size, input, |sha| self.exists(sha))
+    }
+
+    /// Only loose objects are checked unless full, packs are trusted.
+    fn verify(&self, full: bool) -> Vec<(String, String)> {
+        if !full {
+            return self.loose.verify(full);
+        }
+
+        self.databases()
+            .flat_map(|database| database.verify(full))
+            .collect()
+    }
+
+    fn exists(&self, sha: &str) -> bool {
+        self.databases().any(|database| database.exists(sha))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        let names: BTreeSet<String> = self
+            .databases()
+            .flat_map(|database| database.iter())
+            .collect();
+        Box::new(names.into_iter())
+    }
+
+    fn lookup_prefix(&self, prefix: &str) -> Vec<String> {
+        let names: BTreeSet<String> = self
+            .databases()
+            .flat_map(|database| database.lookup_prefix(prefix))
+            .collect();
+        names.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::memory::MemoryDatabase;
+    use crate::commands::init::Init;
+    use crate::object::blob::Blob;
+    use crate::object::operation::{read_raw, write};
+    use crate::repo::Repo;
+    use std::fs;
+
+    #[test]
+    pub fn test_alternates_and_memory() {
+        let _ = fs::remove_dir_all("./test_object_database");
+        let init = |path: &str| {
+            Repo::create(&Init {
+                force: false,
+                bare: true,
+                path: path.to_string(),
+                extensions: vec![],
+                object_format: None,
+            })
+        };
+        let shared = init("./test_object_database/shared.git");
+        let borrowed = write(Box::new(Blob::new("shared")), Some(&shared));
+
+        // Objects of the alternate are read, new objects go to the repository itself.
+        init("./test_object_database/repo.git");
+        fs::write(
+            "./test_object_database/repo.git/objects/info/alternates",
+            "# relative to the objects directory\n../../shared.git/objects\n",
+        )
+        .unwrap();
+        let repo =
+            Repo::repo_open(std::path::Path::new("./test_object_database/repo.git")).unwrap();
+        assert!(repo.has_object(&borrowed));
+        assert_eq!(
+            Some(("blob".to_string(), b"shared".to_vec())),
+            read_raw(&repo, &borrowed)
+        );
+        assert_eq!(borrowed, write(Box::new(Blob::new("shared")), Some(&repo)));
+        assert!(
+            !(repo
+                .common_dir
+                .join("objects")
+                .join(&borrowed[..2])
+                .exists())
+        );
+
+        let own = write(Box::new(Blob::new("own")), Some(&repo));
+        assert!(!(shared.has_object(&own)));
+        let mut names: Vec<String> = repo.objects.iter().collect();
+        names.sort();
+        let mut expected = vec![borrowed.clone(), own.clone()];
+        expected.sort();
+        assert_eq!(expected, names);
+
+        // Objects written to a storage in memory never reach the disk.
+        let mut repo = repo;
+        repo.objects = Box::new(MemoryDatabase::new(repo.object_format()));
+        let memory = write(Box::new(Blob::new("memory")), Some(&repo));
+        assert!(!(repo.common_dir.join("objects").join(&memory[..2]).exists()));
+        assert_eq!(Some(memory.clone()), repo.resolve_name(&memory[..7]));
+        assert_eq!(
+            Some(("blob".to_string(), 6)),
+            repo.objects.read_header(&memory)
+        );
+        assert!(!(repo.has_object(&own)));
+
+        fs::remove_dir_all("./test_object_database").unwrap();
+    }
+}
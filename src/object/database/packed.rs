@@ -0,0 +1,1249 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error::{self, Error};
+use crate::object::database::bitmap::PackBitmap;
+use crate::object::database::midx::{self, MidxEntry, MultiPackIndex};
+use crate::object::database::{check_name, ObjectDatabase, SizedReader, STREAM_BUFFER_SIZE};
+use crate::object::format::{from_hex, to_hex, ObjectFormat};
+use crate::object::lookup::{self, NameLookup};
+use crate::object::pack::{self, PackData, PackEntry};
+use flate2::read::ZlibDecoder;
+use std::borrow::Cow;
+use std::cell::{OnceCell, RefCell};
+use std::fs::{self, File};
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+const INDEX_SIGNATURE: &[u8] = b"\xfftOc";
+const INDEX_HEADER_LEN: usize = 8;
+
+/// The header of an entry, type and size, fits in this many bytes
+const MAX_ENTRY_HEADER_LEN: usize = 16;
+
+/// 256 counts of the names starting with each byte or a lower one
+const FANOUT_LEN: usize = 256 * 4;
+
+/// The files a pack may have, all named after it
+const PACK_EXTENSIONS: [&str; 7] = ["pack", "idx", "keep", "bitmap", "rev", "promisor", "mtimes"];
+
+/// A pack index, version 2: the fanout table, the sorted names, their CRC-32,
+/// their offsets in the pack, with offsets past 2 GiB in a table of 64-bit offsets,
+/// then the checksums of the pack and of the index.
+struct PackIndex {
+    data: Vec<u8>,
+    format: ObjectFormat,
+    count: usize,
+    lookup: NameLookup,
+}
+
+impl PackIndex {
+    fn parse(data: Vec<u8>, format: ObjectFormat) -> Option<Self> {
+        if !(data.starts_with(INDEX_SIGNATURE)) || data.get(4..8)? != 2u32.to_be_bytes() {
+            return None;
+        }
+
+        let count = u32::from_be_bytes(
+            data.get(INDEX_HEADER_LEN + FANOUT_LEN - 4..INDEX_HEADER_LEN + FANOUT_LEN)?
+                .try_into()
+                .unwrap(),
+        ) as usize;
+        let min_len =
+            INDEX_HEADER_LEN + FANOUT_LEN + count * (format.raw_len() + 8) + 2 * format.raw_len();
+
+        (data.len() >= min_len).then_some(PackIndex {
+            data,
+            format,
+            count,
+            lookup: NameLookup::new(
+                INDEX_HEADER_LEN,
+                INDEX_HEADER_LEN + FANOUT_LEN,
+                count,
+                format,
+            ),
+        })
+    }
+
+    fn u32_at(&self, pos: usize) -> usize {
+        u32::from_be_bytes(self.data[pos..pos + 4].try_into().unwrap()) as usize
+    }
+
+    fn range(&self, byte: u8) -> Range<usize> {
+        self.lookup.range(&self.data, byte)
+    }
+
+    fn name(&self, i: usize) -> &[u8] {
+        self.lookup.name(&self.data, i)
+    }
+
+    fn offset(&self, i: usize) -> usize {
+        let table = INDEX_HEADER_LEN + FANOUT_LEN + self.count * (self.format.raw_len() + 4);
+        let offset = self.u32_at(table + 4 * i);
+
+        if offset & 0x8000_0000 == 0 {
+            offset
+        } else {
+            let large = table + 4 * self.count + 8 * (offset & 0x7fff_ffff);
+            u64::from_be_bytes(self.data[large..large + 8].try_into().unwrap()) as usize
+        }
+    }
+
+    /// The position of sha among the names.
+    fn find(&self, sha: &str) -> Option<usize> {
+        self.lookup.find(&self.data, sha)
+    }
+
+    fn names(&self, range: Range<usize>) -> impl Iterator<Item = String> + '_ {
+        range.map(|i| to_hex(self.name(i)))
+    }
+
+    /// The checksum of the pack, before the one of the index.
+    fn pack_checksum(&self) -> &[u8] {
+        let raw_len = self.format.raw_len();
+        &self.data[self.data.len() - 2 * raw_len..self.data.len() - raw_len]
+    }
+
+    /// The names of the objects, in the order of the pack.
+    fn pack_order(&self) -> Vec<String> {
+        let mut objects: Vec<(usize, String)> = (0..self.count)
+            .map(|i| (self.offset(i), to_hex(self.name(i))))
+            .collect();
+        objects.sort();
+        objects.into_iter().map(|(_, sha)| sha).collect()
+    }
+
+    /// The index of the pack whose entries are given, ending with the checksum of the pack.
+    fn serialize(entries: &[PackEntry], pack_checksum: &[u8], format: ObjectFormat) -> Vec<u8> {
+        let mut entries = entries.to_vec();
+        entries.sort();
+
+        let mut data = INDEX_SIGNATURE.to_vec();
+        data.extend_from_slice(&2u32.to_be_bytes());
+        data.extend(lookup::fanout(entries.iter().map(|(sha, _, _)| sha)));
+        data.extend(entries.iter().flat_map(|(sha, _, _)| from_hex(sha)));
+        data.extend(entries.iter().flat_map(|(_, crc, _)| crc.to_be_bytes()));
+
+        let mut large = Vec::new();
+        for (_, _, offset) in &entries {
+            if *offset < 0x8000_0000 {
+                data.extend_from_slice(&(*offset as u32).to_be_bytes());
+            } else {
+                data.extend_from_slice(&(0x8000_0000 | (large.len() / 8) as u32).to_be_bytes());
+                large.extend_from_slice(&(*offset as u64).to_be_bytes());
+            }
+        }
+        data.extend(large);
+        data.extend_from_slice(pack_checksum);
+
+        let checksum = format.digest(&data);
+        data.extend(checksum);
+        data
+    }
+}
+
+/// A pack and its index, each read when first needed.
+struct PackFile {
+    path: PathBuf,
+    format: ObjectFormat,
+    index: OnceCell<Option<PackIndex>>,
+    file: OnceCell<Option<(File, usize)>>,
+    offsets: OnceCell<Vec<usize>>,
+}
+
+impl PackFile {
+    fn new(path: PathBuf, format: ObjectFormat) -> Self {
+        PackFile {
+            path,
+            format,
+            index: OnceCell::new(),
+            file: OnceCell::new(),
+            offsets: OnceCell::new(),
+        }
+    }
+
+    fn index(&self) -> Option<&PackIndex> {
+        self.index
+            .get_or_init(|| {
+                fs::read(self.path.with_extension("idx"))
+                    .ok()
+                    .and_then(|data| PackIndex::parse(data, self.format))
+            })
+            .as_ref()
+    }
+
+    /// The pack file open, with its length. Entries are read from it one at a time.
+    fn file(&self) -> Option<&(File, usize)> {
+        self.file
+            .get_or_init(|| {
+                let mut file = File::open(&self.path).ok()?;
+                let mut signature = [0u8; 4];
+                file.read_exact(&mut signature).ok()?;
+                let len = file.metadata().ok()?.len() as usize;
+                (signature == *b"PACK").then_some((file, len))
+            })
+            .as_ref()
+    }
+
+    /// Where the entry at offset ends: at the next entry, or at the checksum of the pack.
+    fn entry_end(&self, offset: usize, len: usize) -> usize {
+        let offsets = self.offsets.get_or_init(|| {
+            let mut offsets: Vec<usize> = self
+                .index()
+                .map(|index| (0..index.count).map(|i| index.offset(i)).collect())
+                .unwrap_or_default();
+            offsets.sort_unstable();
+            offsets
+        });
+        let end = len.saturating_sub(self.format.raw_len());
+
+        match offsets.get(offsets.partition_point(|start| *start <= offset)) {
+            Some(next) => (*next).min(end),
+            None => end,
+        }
+    }
+
+    /// Read at most max bytes of the entry at offset.
+    fn read_entry_bytes(&self, offset: usize, max: usize) -> error::Result<Vec<u8>> {
+        let io_error =
+            |reason: String| Error::Object(error::object::Object::Io(self.path.clone(), reason));
+        let (file, len) = self
+            .file()
+            .ok_or_else(|| io_error("unreadable pack".to_string()))?;
+        let end = self.entry_end(offset, *len);
+        if offset >= end {
+            return Err(Error::Pack(error::pack::Pack::Malformed(
+                "entry past the end of the pack".to_string(),
+            )));
+        }
+
+        let mut data = vec![0u8; (end - offset).min(max)];
+        let mut file = file;
+        file.seek(SeekFrom::Start(offset as u64))
+            .and_then(|_| file.read_exact(&mut data))
+            .map_err(|e| io_error(e.to_string()))?;
+        Ok(data)
+    }
+
+    /// The offset of object sha in the pack.
+    fn offset_of(&self, sha: &str) -> Option<usize> {
+        let index = self.index()?;
+        index.find(sha).map(|i| index.offset(i))
+    }
+
+    /// Read the entry at offset, a corrupt entry is reported as missing.
+    fn read_at(&self, offset: usize) -> Option<(String, Vec<u8>)> {
+        let find = |sha: &str| self.offset_of(sha);
+
+        match pack::read_entry(self, offset, self.format, &find) {
+            Ok((fmt, data)) => Some((fmt.to_string(), data)),
+            Err(e) => {
+                warn!("{}: {}", self.path.display(), error::Log::fmt(&e));
+                None
+            }
+        }
+    }
+
+    /// The header of an entry which is not a delta is enough.
+    fn read_header_at(&self, offset: usize) -> Option<(String, usize)> {
+        let head = self.read_entry_bytes(offset, MAX_ENTRY_HEADER_LEN).ok()?;
+        match pack::content_start(&head).ok()? {
+            Some((fmt, size, _)) => Some((fmt.to_string(), size)),
+            None => self.read_at(offset).map(|(fmt, data)| (fmt, data.len())),
+        }
+    }
+
+    /// A reader inflating the entry at offset from the pack file, None for a delta.
+    fn read_stream_at(&self, offset: usize) -> Option<(String, usize, Box<dyn Read>)> {
+        let head = self.read_entry_bytes(offset, MAX_ENTRY_HEADER_LEN).ok()?;
+        let (fmt, size, start) = pack::content_start(&head).ok()??;
+        let end = self.entry_end(offset, self.file()?.1);
+
+        let mut file = File::open(&self.path).ok()?;
+        file.seek(SeekFrom::Start((offset + start) as u64)).ok()?;
+        let compressed = file.take(end.checked_sub(offset + start)? as u64);
+        Some((
+            fmt.to_string(),
+            size,
+            Box::new(SizedReader::new(ZlibDecoder::new(compressed), size)),
+        ))
+    }
+
+    /// The name of the index, as a multi-pack-index lists it.
+    fn index_name(&self) -> String {
+        self.path
+            .with_extension("idx")
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string()
+    }
+
+    /// The last modification of the pack, in seconds since the epoch.
+    fn mtime(&self) -> u64 {
+        fs::metadata(&self.path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |mtime| mtime.as_secs())
+    }
+
+    /// Whether a `.keep` file protects the pack from being repacked or deleted.
+    fn is_kept(&self) -> bool {
+        self.path.with_extension("keep").exists()
+    }
+}
+
+impl PackData for PackFile {
+    fn entry(&self, offset: usize) -> error::Result<Cow<'_, [u8]>> {
+        self.read_entry_bytes(offset, usize::MAX).map(Cow::Owned)
+    }
+}
+
+impl PackFile {
+    /// Check the checksums of the pack and of its index, and each object of the pack.
+    fn verify(&self) -> Vec<(String, String)> {
+        let path = self.path.display().to_string();
+        let format = self.format;
+
+        let pack_index = match self.index() {
+            Some(index) => index,
+            None => return vec![(path, "unreadable index".to_string())],
+        };
+        let (digest, checksum) = match self.checksums() {
+            Ok(checksums) => checksums,
+            Err(_) => return vec![(path, "unreadable pack".to_string())],
+        };
+        let mut problems = Vec::new();
+        if digest != to_hex(&checksum) {
+            problems.push((path.clone(), "pack checksum mismatch".to_string()));
+        }
+
+        if !(lookup::checksum_matches(&pack_index.data, format)) {
+            problems.push((path.clone(), "index checksum mismatch".to_string()));
+        } else if pack_index.pack_checksum() != checksum {
+            problems.push((path, "index of another pack".to_string()));
+        }
+
+        let find = |sha: &str| self.offset_of(sha);
+        problems.extend((0..pack_index.count).filter_map(|i| {
+            let sha = to_hex(pack_index.name(i));
+            let checked = pack::read_entry(self, pack_index.offset(i), format, &find)
+                .map_err(|e| error::Log::fmt(&e))
+                .and_then(|(fmt, content)| check_name(format, &sha, fmt, &content));
+            checked.err().map(|reason| (sha, reason))
+        }));
+        problems
+    }
+
+    /// The digest of the pack, computed by chunks, and the checksum at its end.
+    fn checksums(&self) -> io::Result<(String, Vec<u8>)> {
+        let raw_len = self.format.raw_len();
+        let (file, len) = self
+            .file()
+            .filter(|(_, len)| *len >= 12 + raw_len)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "not a pack"))?;
+
+        let mut file = file;
+        file.seek(SeekFrom::Start(0))?;
+        let mut hasher = self.format.hasher();
+        let mut chunk = vec![0u8; STREAM_BUFFER_SIZE];
+        let mut remaining = len - raw_len;
+        while remaining > 0 {
+            let read = chunk.len().min(remaining);
+            file.read_exact(&mut chunk[..read])?;
+            hasher.update(&chunk[..read]);
+            remaining -= read;
+        }
+
+        let mut checksum = vec![0u8; raw_len];
+        file.read_exact(&mut checksum)?;
+        Ok((hasher.hex_digest(), checksum))
+    }
+}
+
+/// The packs a multi-pack-index covers, in its order.
+struct MultiPack {
+    index: MultiPackIndex,
+    packs: Vec<PackFile>,
+}
+
+impl MultiPack {
+    /// The pack the multi-pack-index picked for object sha, and its offset there.
+    fn locate(&self, sha: &str) -> Option<(&PackFile, usize)> {
+        let (pack, offset) = self.index.location(self.index.find(sha)?)?;
+        Some((self.packs.get(pack)?, offset))
+    }
+}
+
+/// The packs of a directory, each `pack-<checksum>.pack` with its `pack-<checksum>.idx`.
+/// Objects are first looked up in the multi-pack-index, when there is one, which spares
+/// reading the indexes of the packs it covers. The directory is scanned again when an
+/// object is not found, it may have a new pack.
+pub struct PackDatabase {
+    dir: PathBuf,
+    format: ObjectFormat,
+    multi_pack: OnceCell<Option<MultiPack>>,
+    packs: RefCell<Vec<PackFile>>,
+}
+
+impl PackDatabase {
+    pub fn new(dir: &Path, format: ObjectFormat) -> Self {
+        PackDatabase {
+            dir: dir.to_owned(),
+            format,
+            multi_pack: OnceCell::new(),
+            packs: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn multi_pack(&self) -> Option<&MultiPack> {
+        self.multi_pack
+            .get_or_init(|| {
+                let path = self.dir.join(midx::FILE_NAME);
+                let index = MultiPackIndex::parse(fs::read(&path).ok()?, self.format);
+                if index.is_none() {
+                    warn!("Ignoring the malformed {}", path.display());
+                }
+
+                let index = index?;
+                let packs: Vec<PackFile> = index
+                    .packs
+                    .iter()
+                    .map(|name| {
+                        PackFile::new(self.dir.join(name).with_extension("pack"), self.format)
+                    })
+                    .collect();
+
+                // Where their entries end is known from the multi-pack-index as well.
+                let mut offsets = vec![Vec::new(); packs.len()];
+                for (pack, offset) in (0..index.count()).filter_map(|i| index.location(i)) {
+                    if let Some(offsets) = offsets.get_mut(pack) {
+                        offsets.push(offset);
+                    }
+                }
+                for (pack, mut offsets) in packs.iter().zip(offsets) {
+                    offsets.sort_unstable();
+                    let _ = pack.offsets.set(offsets);
+                }
+
+                Some(MultiPack { index, packs })
+            })
+            .as_ref()
+    }
+
+    /// The packs of the directory with a readable index, sorted by name.
+    fn pack_files(&self) -> Vec<PackFile> {
+        let mut indexes: Vec<PathBuf> = fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|extension| extension == "idx"))
+            .filter(|path| path.with_extension("pack").is_file())
+            .collect();
+        indexes.sort();
+
+        indexes
+            .into_iter()
+            .filter_map(|index| {
+                let pack = PackFile::new(index.with_extension("pack"), self.format);
+                if pack.index().is_none() {
+                    warn!("Ignoring unsupported pack index `{}`", index.display());
+                    return None;
+                }
+                Some(pack)
+            })
+            .collect()
+    }
+
+    /// Load the packs not known yet, returns whether there were any. The packs of the
+    /// multi-pack-index are left to it.
+    fn scan(&self) -> bool {
+        let covered = self
+            .multi_pack()
+            .map(|multi_pack| multi_pack.index.packs.clone())
+            .unwrap_or_default();
+        let mut packs = self.packs.borrow_mut();
+        let before = packs.len();
+
+        for pack in self.pack_files() {
+            if !(covered.contains(&pack.index_name())
+                || packs.iter().any(|known| known.path == pack.path))
+            {
+                packs.push(pack);
+            }
+        }
+
+        packs.len() != before
+    }
+
+    /// Apply f to the packs until it finds something, scanning the directory when it does not.
+    fn find_map<T>(&self, f: impl Fn(&PackFile) -> Option<T>) -> Option<T> {
+        let found = self.packs.borrow().iter().find_map(&f);
+        found.or_else(|| {
+            self.scan()
+                .then(|| self.packs.borrow().iter().find_map(&f))
+                .flatten()
+        })
+    }
+}
+
+impl ObjectDatabase for PackDatabase {
+    fn read_header(&self, sha: &str) -> Option<(String, usize)> {
+        self.multi_pack()
+            .and_then(|multi_pack| {
+                let (pack, offset) = multi_pack.locate(sha)?;
+                pack.read_header_at(offset)
+            })
+            .or_else(|| self.find_map(|pack| pack.read_header_at(pack.offset_of(sha)?)))
+    }
+
+    fn read(&self, sha: &str) -> Option<(String, Vec<u8>)> {
+        self.multi_pack()
+            .and_then(|multi_pack| {
+                let (pack, offset) = multi_pack.locate(sha)?;
+                pack.read_at(offset)
+            })
+            .or_else(|| self.find_map(|pack| pack.read_at(pack.offset_of(sha)?)))
+    }
+
+    /// Entries stored whole are inflated from the pack as they are read, deltas are rebuilt
+    /// in memory.
+    fn read_stream(&self, sha: &str) -> Option<(String, usize, Box<dyn Read + '_>)> {
+        let stream = self
+            .multi_pack()
+            .and_then(|multi_pack| {
+                let (pack, offset) = multi_pack.locate(sha)?;
+                Some(pack.read_stream_at(offset))
+            })
+            .or_else(|| self.find_map(|pack| Some(pack.read_stream_at(pack.offset_of(sha)?))))?;
+
+        stream.or_else(|| {
+            self.read(sha).map(|(fmt, data)| {
+                let size = data.len();
+                (fmt, size, Box::new(Cursor::new(data)) as Box<dyn Read>)
+            })
+        })
+    }
+
+    fn write(&self, _fmt: &str, _data: &[u8]) -> error::Result<String> {
+        Err(Error::Object(error::object::Object::ReadOnly(format!(
+            "the packs of `{}`",
+            self.dir.display()
+        ))))
+    }
+
+    fn exists(&self, sha: &str) -> bool {
+        self.multi_pack()
+            .is_some_and(|multi_pack| multi_pack.locate(sha).is_some())
+            || self.find_map(|pack| pack.offset_of(sha)).is_some()
+    }
+
+    fn verify(&self, _full: bool) -> Vec<(String, String)> {
+        self.scan();
+        let mut problems = self.verify_multi_pack_index();
+        if let Some(multi_pack) = self.multi_pack() {
+            problems.extend(multi_pack.packs.iter().flat_map(PackFile::verify));
+        }
+        problems.extend(self.packs.borrow().iter().flat_map(PackFile::verify));
+        problems
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = String> + '_> {
+        self.scan();
+        let mut names: Vec<String> = self
+            .multi_pack()
+            .map(|multi_pack| {
+                multi_pack
+                    .index
+                    .names(0..multi_pack.index.count())
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.extend(
+            self.packs
+                .borrow()
+                .iter()
+                .filter_map(PackFile::index)
+                .flat_map(|index| index.names(0..index.count)),
+        );
+        Box::new(names.into_iter())
+    }
+
+    fn lookup_prefix(&self, prefix: &str) -> Vec<String> {
+        self.scan();
+        let first = u8::from_str_radix(prefix.get(0..2).unwrap_or_default(), 16).ok();
+
+        let mut names: Vec<String> = self
+            .multi_pack()
+            .map(|multi_pack| {
+                let index = &multi_pack.index;
+                let range = match first {
+                    Some(byte) => index.range(byte),
+                    None => 0..index.count(),
+                };
+                index
+                    .names(range)
+                    .filter(|sha| sha.starts_with(prefix))
+                    .collect()
+            })
+            .unwrap_or_default();
+        names.extend(
+            self.packs
+                .borrow()
+                .iter()
+                .filter_map(PackFile::index)
+                .flat_map(|index| {
+                    let range = match first {
+                        Some(byte) => index.range(byte),
+                        None => 0..index.count,
+                    };
+                    index
+                        .names(range)
+                        .filter(|sha| sha.starts_with(prefix))
+                        .collect::<Vec<_>>()
+                }),
+        );
+        names
+    }
+}
+
+impl PackDatabase {
+    fn io_error(path: &Path, e: std::io::Error) -> Error {
+        Error::Object(error::object::Object::Io(path.to_owned(), e.to_string()))
+    }
+
+    /// Write data to path through a temporary file, so that readers never see it partly written.
+    fn write_file(path: &Path, data: &[u8]) -> error::Result<()> {
+        let temp = path.with_extension("lock");
+        fs::write(&temp, data)
+            .and_then(|_| fs::rename(&temp, path))
+            .map_err(|e| {
+                let _ = fs::remove_file(&temp);
+                Self::io_error(path, e)
+            })
+    }
+
+    /// Delete a pack with its index and the files going along.
+    fn remove_pack(pack: &PackFile) -> error::Result<()> {
+        for extension in ["bitmap", "rev", "idx", "pack"] {
+            let path = pack.path.with_extension(extension);
+            if path.exists() {
+                fs::remove_file(&path).map_err(|e| Self::io_error(&path, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a pack of objects read from database, with its index, and its bitmaps when
+    /// given, which replace those of the other packs. Returns the name of the pack.
+    pub fn write_pack(
+        &self,
+        objects: &[String],
+        database: &dyn ObjectDatabase,
+        bitmap: Option<&PackBitmap>,
+    ) -> error::Result<String> {
+        let (data, entries) = pack::write_entries(objects, self.format, database)?;
+        let name = self.write_pack_data(&data, &entries)?;
+        let path = self.dir.join(&name).with_extension("pack");
+        let checksum = &data[data.len() - self.format.raw_len()..];
+
+        if let Some(bitmap) = bitmap {
+            for pack in self.pack_files() {
+                let other = pack.path.with_extension("bitmap");
+                if pack.path != path && other.exists() {
+                    fs::remove_file(&other).map_err(|e| Self::io_error(&other, e))?;
+                }
+            }
+            Self::write_file(
+                &path.with_extension("bitmap"),
+                &bitmap.serialize(checksum, self.format),
+            )?;
+        }
+
+        Ok(name)
+    }
+
+    /// The path of the `.pack` which has object sha.
+    pub fn pack_path(&self, sha: &str) -> Option<PathBuf> {
+        self.multi_pack()
+            .and_then(|multi_pack| multi_pack.locate(sha))
+            .map(|(pack, _)| pack.path.clone())
+            .or_else(|| self.find_map(|pack| pack.offset_of(sha).map(|_| pack.path.clone())))
+    }
+
+    /// Write a pack, whose objects are at entries, with its index. Returns the name of the
+    /// pack, without extension.
+    pub fn write_pack_data(&self, data: &[u8], entries: &[PackEntry]) -> error::Result<String> {
+        let checksum = &data[data.len() - self.format.raw_len()..];
+        let name = format!("pack-{}", to_hex(checksum));
+        let path = self.dir.join(&name).with_extension("pack");
+        Self::write_file(&path, data)?;
+        Self::write_file(
+            &path.with_extension("idx"),
+            &PackIndex::serialize(entries, checksum, self.format),
+        )?;
+
+        Ok(name)
+    }
+
+    /// Delete the packs other than the one named kept, unless a `.keep` file protects them,
+    /// then write the multi-pack-index again if there is one. Returns the names of the
+    /// deleted packs.
+    pub fn remove_packs_except(&self, kept: &str) -> error::Result<Vec<String>> {
+        let mut removed = Vec::new();
+        for pack in self.pack_files() {
+            let name = pack.index_name();
+            if name.trim_end_matches(".idx") != kept && !(pack.is_kept()) {
+                Self::remove_pack(&pack)?;
+                removed.push(name);
+            }
+        }
+
+        if !(removed.is_empty()) && self.dir.join(midx::FILE_NAME).exists() {
+            self.write_multi_pack_index()?;
+        }
+        Ok(removed)
+    }
+
+    /// How many packs the directory has, how many objects they hold, and the size of the
+    /// packs and of their indexes.
+    pub fn counts(&self) -> (usize, usize, u64) {
+        let packs = self.pack_files();
+        let size = |path: PathBuf| fs::metadata(path).map_or(0, |meta| meta.len());
+
+        (
+            packs.len(),
+            packs
+                .iter()
+                .map(|pack| pack.index().map_or(0, |index| index.count))
+                .sum(),
+            packs
+                .iter()
+                .map(|pack| size(pack.path.clone()) + size(pack.path.with_extension("idx")))
+                .sum(),
+        )
+    }
+
+    /// The files of the directory which are neither packs, their indexes, nor the files going
+    /// along a pack having both, such as `.keep` and `.bitmap`.
+    pub fn garbage(&self) -> Vec<PathBuf> {
+        fs::read_dir(&self.dir)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| {
+                let name = path.file_name().unwrap().to_string_lossy();
+                let known = path.extension().is_some_and(|extension| {
+                    PACK_EXTENSIONS.iter().any(|known| extension == *known)
+                });
+                !(name.starts_with(midx::FILE_NAME)
+                    || known
+                        && path.with_extension("pack").is_file()
+                        && path.with_extension("idx").is_file())
+            })
+            .collect()
+    }
+
+    /// The objects of the packs stored as deltas, with the length of their delta chain.
+    pub fn delta_depths(&self) -> Vec<(String, usize)> {
+        let mut depths = Vec::new();
+        for pack in self.pack_files() {
+            let index = match pack.index() {
+                Some(index) => index,
+                None => continue,
+            };
+            let find = |sha: &str| pack.offset_of(sha);
+
+            depths.extend((0..index.count).filter_map(|i| {
+                match pack::delta_depth(&pack, index.offset(i), self.format, &find) {
+                    Ok(0) | Err(_) => None,
+                    Ok(depth) => Some((to_hex(index.name(i)), depth)),
+                }
+            }));
+        }
+        depths
+    }
+
+    /// The bitmaps of the first pack having some, git only reading one of them.
+    pub fn pack_bitmap(&self) -> Option<PackBitmap> {
+        self.pack_files().into_iter().find_map(|pack| {
+            let path = pack.path.with_extension("bitmap");
+            let data = fs::read(&path).ok()?;
+            let index = pack.index()?;
+
+            let bitmap = PackBitmap::parse(
+                &data,
+                index.pack_order(),
+                index.pack_checksum(),
+                self.format,
+            );
+            if bitmap.is_none() {
+                warn!("Ignoring the malformed {}", path.display());
+            }
+            bitmap
+        })
+    }
+
+    /// Write the multi-pack-index of the packs of the directory. An object in several packs
+    /// is taken from the most recently modified one, as git does. Returns the number of packs
+    /// and of objects.
+    pub fn write_multi_pack_index(&self) -> error::Result<(usize, usize)> {
+        let packs = self.pack_files();
+        let mut entries: Vec<(String, u64, u32, usize)> = Vec::new();
+        for (id, pack) in packs.iter().enumerate() {
+            let index = pack.index().unwrap();
+            let mtime = pack.mtime();
+            entries.extend(
+                (0..index.count)
+                    .map(|i| (to_hex(index.name(i)), mtime, id as u32, index.offset(i))),
+            );
+        }
+        entries.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+        entries.dedup_by(|duplicate, kept| duplicate.0 == kept.0);
+
+        let entries: Vec<MidxEntry> = entries
+            .into_iter()
+            .map(|(sha, _, pack, offset)| (sha, pack, offset))
+            .collect();
+        let names: Vec<String> = packs.iter().map(PackFile::index_name).collect();
+        Self::write_file(
+            &self.dir.join(midx::FILE_NAME),
+            &MultiPackIndex::serialize(&names, &entries, self.format),
+        )?;
+
+        Ok((names.len(), entries.len()))
+    }
+
+    /// Check the checksum of the multi-pack-index, the order of its objects, and that each
+    /// one is where it says in its pack.
+    pub fn verify_multi_pack_index(&self) -> Vec<(String, String)> {
+        let path = self.dir.join(midx::FILE_NAME);
+        let file = path.display().to_string();
+        if !(path.exists()) {
+            return Vec::new();
+        }
+        let multi_pack = match self.multi_pack() {
+            Some(multi_pack) => multi_pack,
+            None => return vec![(file, "malformed multi-pack-index".to_string())],
+        };
+
+        let mut problems = Vec::new();
+        let index = &multi_pack.index;
+        if !(index.checksum_matches()) {
+            problems.push((file, "checksum mismatch".to_string()));
+        }
+        for pack in &multi_pack.packs {
+            if pack.index().is_none() {
+                problems.push((pack.path.display().to_string(), "missing pack".to_string()));
+            }
+        }
+
+        for i in 0..index.count() {
+            let sha = index.name(i);
+            if i > 0 && index.name(i - 1) >= sha {
+                problems.push((sha.clone(), "objects out of order".to_string()));
+            }
+            if index.find(&sha) != Some(i) {
+                problems.push((sha.clone(), "wrong fanout".to_string()));
+            }
+
+            let located = index
+                .location(i)
+                .and_then(|(pack, offset)| Some((multi_pack.packs.get(pack)?, offset)));
+            match located {
+                Some((pack, offset)) => match pack.offset_of(&sha) {
+                    Some(actual) if actual == offset => (),
+                    Some(actual) => problems.push((
+                        sha,
+                        format!(
+                            "offset {} instead of {} in {}",
+                            offset,
+                            actual,
+                            pack.index_name()
+                        ),
+                    )),
+                    None => problems.push((sha, format!("not in {}", pack.index_name()))),
+                },
+                None => problems.push((sha, "invalid pack position".to_string())),
+            }
+        }
+
+        problems
+    }
+
+    /// How many objects the multi-pack-index takes from each of its packs.
+    fn referenced_objects(multi_pack: &MultiPack) -> Vec<usize> {
+        let mut referenced = vec![0; multi_pack.packs.len()];
+        for i in 0..multi_pack.index.count() {
+            if let Some(count) = multi_pack
+                .index
+                .location(i)
+                .and_then(|(pack, _)| referenced.get_mut(pack))
+            {
+                *count += 1;
+            }
+        }
+        referenced
+    }
+
+    /// Delete the packs of the multi-pack-index it takes no object from, unless they are
+    /// kept, then write it again. Returns the names of the deleted packs.
+    pub fn expire_multi_pack_index(&self) -> error::Result<Vec<String>> {
+        let multi_pack = match self.multi_pack() {
+            Some(multi_pack) => multi_pack,
+            None => return Ok(Vec::new()),
+        };
+
+        let mut expired = Vec::new();
+        for (pack, referenced) in multi_pack
+            .packs
+            .iter()
+            .zip(Self::referenced_objects(multi_pack))
+        {
+            if referenced > 0 || pack.is_kept() || !(pack.path.exists()) {
+                continue;
+            }
+
+            Self::remove_pack(pack)?;
+            expired.push(pack.index_name());
+        }
+
+        if !(expired.is_empty()) {
+            self.write_multi_pack_index()?;
+        }
+        Ok(expired)
+    }
+
+    /// Gather into a new pack the objects the multi-pack-index takes from its smaller packs,
+    /// then write it again, the old packs being left for `expire_multi_pack_index`. As git
+    /// does, packs are taken from the oldest while their expected size, their size scaled by
+    /// the share of their objects the multi-pack-index takes from them, is below batch_size,
+    /// until the total reaches batch_size. A batch_size of 0 takes all the packs. Returns the
+    /// name of the new pack, if at least two packs were selected.
+    pub fn repack_multi_pack_index(&self, batch_size: u64) -> error::Result<Option<String>> {
+        let multi_pack = match self.multi_pack() {
+            Some(multi_pack) => multi_pack,
+            None => return Ok(None),
+        };
+        let referenced = Self::referenced_objects(multi_pack);
+
+        let mut order: Vec<usize> = (0..multi_pack.packs.len()).collect();
+        order.sort_by_key(|pack| (multi_pack.packs[*pack].mtime(), *pack));
+
+        let mut selected = vec![false; multi_pack.packs.len()];
+        let mut total = 0;
+        for pack in order {
+            let file = &multi_pack.packs[pack];
+            let count = file.index().map_or(0, |index| index.count);
+            if file.is_kept() || count == 0 || referenced[pack] == 0 {
+                continue;
+            }
+            if batch_size > 0 {
+                if total >= batch_size {
+                    break;
+                }
+                let size = fs::metadata(&file.path).map_or(0, |meta| meta.len());
+                let expected = size * referenced[pack] as u64 / count as u64;
+                if expected >= batch_size {
+                    continue;
+                }
+                total += expected;
+            }
+            selected[pack] = true;
+        }
+        if selected.iter().filter(|selected| **selected).count() < 2 {
+            return Ok(None);
+        }
+
+        let objects: Vec<String> = (0..multi_pack.index.count())
+            .filter(|i| {
+                multi_pack
+                    .index
+                    .location(*i)
+                    .is_some_and(|(pack, _)| selected.get(pack) == Some(&true))
+            })
+            .map(|i| multi_pack.index.name(i))
+            .collect();
+        let name = self.write_pack(&objects, self, None)?;
+
+        self.write_multi_pack_index()?;
+        Ok(Some(name))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{midx, PackDatabase, MAX_ENTRY_HEADER_LEN};
+    use crate::commands::init::Init;
+    use crate::object::database::ObjectDatabase;
+    use crate::object::pack;
+    use crate::repo::Repo;
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::process::{Command, Stdio};
+
+    /// Pack objects of the repository at dir with git, returns the name of the pack.
+    fn git_pack(dir: &str, objects: &[String]) -> String {
+        let mut git = Command::new("git")
+            .args(["--git-dir", dir, "pack-objects", "-q"])
+            .arg(format!("{}/objects/pack/pack", dir))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        git.stdin
+            .take()
+            .unwrap()
+            .write_all(objects.join("\n").as_bytes())
+            .unwrap();
+        let output = git.wait_with_output().unwrap();
+        assert!(output.status.success());
+        format!("pack-{}", String::from_utf8(output.stdout).unwrap().trim())
+    }
+
+    fn git(dir: &str, args: &[&str]) -> bool {
+        Command::new("git")
+            .args(["--git-dir", dir])
+            .args(args)
+            .stdout(Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    #[test]
+    pub fn test_pack_database() {
+        let _ = fs::remove_dir_all("./test_pack_database");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_pack_database"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        // Versions of a file differing by a line, which the pack stores as deltas.
+        let lines: Vec<String> = (0..2000).map(|i| format!("line {}\n", i)).collect();
+        let contents: Vec<Vec<u8>> = (0..5)
+            .map(|version| {
+                let mut lines = lines.clone();
+                lines[version * 300] = format!("version {}\n", version);
+                lines.concat().into_bytes()
+            })
+            .collect();
+        let names: Vec<String> = contents
+            .iter()
+            .map(|content| repo.objects.write("blob", content).unwrap())
+            .collect();
+
+        let mut git = Command::new("git")
+            .args(["--git-dir", "./test_pack_database", "pack-objects", "-q"])
+            .arg("./test_pack_database/objects/pack/pack")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .unwrap();
+        git.stdin
+            .take()
+            .unwrap()
+            .write_all(names.join("\n").as_bytes())
+            .unwrap();
+        assert!(git.wait().unwrap().success());
+        assert!(Command::new("git")
+            .args(["--git-dir", "./test_pack_database", "prune-packed"])
+            .status()
+            .unwrap()
+            .success());
+
+        let packs = PackDatabase::new(
+            &repo.common_dir.join("objects").join("pack"),
+            repo.object_format(),
+        );
+        for (name, content) in names.iter().zip(&contents) {
+            assert!(packs.exists(name));
+            assert_eq!(
+                Some(("blob".to_string(), content.clone())),
+                packs.read(name)
+            );
+            assert_eq!(
+                Some(("blob".to_string(), content.len())),
+                packs.read_header(name)
+            );
+
+            let (fmt, size, mut stream) = packs.read_stream(name).unwrap();
+            let mut streamed = Vec::new();
+            stream.read_to_end(&mut streamed).unwrap();
+            assert_eq!(("blob", content.len()), (fmt.as_str(), size));
+            assert_eq!(content, &streamed);
+        }
+        assert!(names.iter().any(|name| {
+            let pack = &packs.packs.borrow()[0];
+            let offset = pack.offset_of(name).unwrap();
+            let head = pack.read_entry_bytes(offset, MAX_ENTRY_HEADER_LEN).unwrap();
+            pack::content_start(&head).unwrap().is_none()
+        }));
+
+        let mut all: Vec<String> = packs.iter().collect();
+        all.sort();
+        let mut expected = names.clone();
+        expected.sort();
+        assert_eq!(expected, all);
+        assert_eq!(vec![names[0].clone()], packs.lookup_prefix(&names[0][..6]));
+        assert!(packs.write("blob", b"new").is_err());
+
+        // The repository finds the packed objects, and writes new ones loose.
+        let repo = Repo::repo_open(std::path::Path::new("./test_pack_database")).unwrap();
+        assert_eq!(Some(names[2].clone()), repo.resolve_name(&names[2][..8]));
+        assert_eq!(names[3], repo.objects.write("blob", &contents[3]).unwrap());
+        assert!(
+            !(repo
+                .common_dir
+                .join("objects")
+                .join(&names[3][..2])
+                .exists())
+        );
+
+        let blob = repo.objects.write("blob", b"new").unwrap();
+        assert!(repo
+            .common_dir
+            .join("objects")
+            .join(&blob[..2])
+            .join(&blob[2..])
+            .is_file());
+
+        fs::remove_dir_all("./test_pack_database").unwrap();
+    }
+
+    #[test]
+    pub fn test_multi_pack_index() {
+        let dir = "./test_multi_pack_index";
+        let _ = fs::remove_dir_all(dir);
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from(dir),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        let names: Vec<String> = (0..6)
+            .map(|i| {
+                repo.objects
+                    .write("blob", format!("blob {}", i).as_bytes())
+                    .unwrap()
+            })
+            .collect();
+        let first = git_pack(dir, &names[..4]);
+        let second = git_pack(dir, &names[3..]);
+        assert!(git(dir, &["prune-packed"]));
+
+        let pack_dir = repo.common_dir.join("objects").join("pack");
+        assert_eq!(
+            (2, 6),
+            PackDatabase::new(&pack_dir, repo.object_format())
+                .write_multi_pack_index()
+                .unwrap()
+        );
+        assert!(git(dir, &["multi-pack-index", "verify"]));
+
+        // The packs it covers are found through it, without reading their indexes.
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        for name in &names {
+            assert_eq!(
+                name,
+                &repo
+                    .objects
+                    .write("blob", &packs.read(name).unwrap().1)
+                    .unwrap()
+            );
+        }
+        assert!(packs
+            .multi_pack()
+            .unwrap()
+            .packs
+            .iter()
+            .all(|pack| pack.index.get().is_none()));
+        assert!(!(packs.scan()));
+        assert!(packs.verify_multi_pack_index().is_empty());
+        assert_eq!(6, packs.iter().count());
+        assert_eq!(vec![names[0].clone()], packs.lookup_prefix(&names[0][..6]));
+
+        // Nothing to expire, every pack holds objects the multi-pack-index takes.
+        assert!(packs.expire_multi_pack_index().unwrap().is_empty());
+        let repacked = packs.repack_multi_pack_index(0).unwrap().unwrap();
+        assert!(git(
+            dir,
+            &[
+                "verify-pack",
+                &format!("{}/objects/pack/{}.idx", dir, repacked)
+            ]
+        ));
+
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        let mut expired = packs.expire_multi_pack_index().unwrap();
+        expired.sort();
+        let mut packed = vec![format!("{}.idx", first), format!("{}.idx", second)];
+        packed.sort();
+        assert_eq!(packed, expired);
+        assert!(!(pack_dir.join(format!("{}.pack", first)).exists()));
+        assert!(git(dir, &["multi-pack-index", "verify"]));
+
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        assert_eq!(
+            vec![format!("{}.idx", repacked)],
+            packs.multi_pack().unwrap().index.packs
+        );
+        assert!(names.iter().all(|name| packs.exists(name)));
+        assert!(packs.verify(true).is_empty());
+
+        // A corrupt multi-pack-index is reported, and lookups go through the packs.
+        let path = pack_dir.join(midx::FILE_NAME);
+        let mut data = fs::read(&path).unwrap();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        fs::write(&path, data).unwrap();
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        assert_eq!(1, packs.verify_multi_pack_index().len());
+        fs::write(&path, b"MIDX").unwrap();
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        assert!(names.iter().all(|name| packs.read(name).is_some()));
+
+        // Pack positions past the packs it lists are errors, not panics.
+        let mut entries: Vec<midx::MidxEntry> =
+            names.iter().map(|name| (name.clone(), 7, 12)).collect();
+        entries.sort();
+        fs::write(
+            &path,
+            midx::MultiPackIndex::serialize(
+                &[format!("{}.idx", repacked)],
+                &entries,
+                repo.object_format(),
+            ),
+        )
+        .unwrap();
+        let packs = PackDatabase::new(&pack_dir, repo.object_format());
+        assert!(packs.read(&names[0]).is_none());
+        assert_eq!(names.len(), packs.verify_multi_pack_index().len());
+        assert!(packs.repack_multi_pack_index(0).unwrap().is_none());
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,154 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+/// The hash function naming objects, chosen per repository by `extensions.objectformat`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ObjectFormat {
+    #[default]
+    Sha1,
+    Sha256,
+}
+
+impl ObjectFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "sha1" => Some(ObjectFormat::Sha1),
+            "sha256" => Some(ObjectFormat::Sha256),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ObjectFormat::Sha1 => "sha1",
+            ObjectFormat::Sha256 => "sha256",
+        }
+    }
+
+    /// Length in bytes of a raw object name, as stored in trees and in the index
+    pub fn raw_len(&self) -> usize {
+        match self {
+            ObjectFormat::Sha1 => 20,
+            ObjectFormat::Sha256 => 32,
+        }
+    }
+
+    /// Length of a hex encoded object name
+    pub fn hex_len(&self) -> usize {
+        self.raw_len() * 2
+    }
+
+    pub fn digest(&self, data: &[u8]) -> Vec<u8> {
+        match self {
+            ObjectFormat::Sha1 => Sha1::digest(data).to_vec(),
+            ObjectFormat::Sha256 => Sha256::digest(data).to_vec(),
+        }
+    }
+
+    pub fn hex_digest(&self, data: &[u8]) -> String {
+        to_hex(&self.digest(data))
+    }
+
+    /// A hasher for data given in chunks, e.g. an object too large to be held in memory.
+    pub fn hasher(&self) -> Hasher {
+        match self {
+            ObjectFormat::Sha1 => Hasher::Sha1(Sha1::new()),
+            ObjectFormat::Sha256 => Hasher::Sha256(Sha256::new()),
+        }
+    }
+
+    /// Whether name is a full object name in this format.
+    pub fn is_full_name(&self, name: &str) -> bool {
+        name.len() == self.hex_len() && name.chars().all(|c| c.is_ascii_hexdigit())
+    }
+}
+
+impl fmt::Display for ObjectFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The incremental form of `ObjectFormat::digest`.
+pub enum Hasher {
+    Sha1(Sha1),
+    Sha256(Sha256),
+}
+
+impl Hasher {
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Sha1(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+        }
+    }
+
+    pub fn hex_digest(self) -> String {
+        match self {
+            Hasher::Sha1(hasher) => to_hex(&hasher.finalize()),
+            Hasher::Sha256(hasher) => to_hex(&hasher.finalize()),
+        }
+    }
+}
+
+pub fn to_hex(raw: &[u8]) -> String {
+    raw.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+/// Decode a hex encoded object name, which must be valid.
+pub fn from_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::ObjectFormat;
+
+    #[test]
+    pub fn test_object_format() {
+        assert_eq!(
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709",
+            ObjectFormat::Sha1.hex_digest(b"")
+        );
+        assert_eq!(
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855",
+            ObjectFormat::Sha256.hex_digest(b"")
+        );
+
+        let mut hasher = ObjectFormat::Sha256.hasher();
+        hasher.update(b"blob 3\0");
+        hasher.update(b"abc");
+        assert_eq!(
+            ObjectFormat::Sha256.hex_digest(b"blob 3\0abc"),
+            hasher.hex_digest()
+        );
+        assert_eq!(
+            Some(ObjectFormat::Sha256),
+            ObjectFormat::from_name("SHA256")
+        );
+        assert!(ObjectFormat::Sha256.is_full_name(&"a".repeat(64)));
+        assert!(!ObjectFormat::Sha1.is_full_name(&"a".repeat(64)));
+    }
+}
@@ -0,0 +1,111 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::format::{from_hex, ObjectFormat};
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// The names of a pack index, a multi-pack-index or a commit-graph: a fanout table of 256
+/// counts of the names starting with each byte or a lower one, then the names sorted, raw.
+/// Both are found at positions of the file they are read from.
+pub struct NameLookup {
+    fanout: usize,
+    names: usize,
+    count: usize,
+    format: ObjectFormat,
+}
+
+impl NameLookup {
+    /// The lookup of a file whose fanout table starts at fanout and its count names at names.
+    pub fn new(fanout: usize, names: usize, count: usize, format: ObjectFormat) -> Self {
+        NameLookup {
+            fanout,
+            names,
+            count,
+            format,
+        }
+    }
+
+    /// The number of names starting with byte or a lower one, 0 past the end of data.
+    fn fanout(&self, data: &[u8], byte: usize) -> usize {
+        let pos = self.fanout + 4 * byte;
+        data.get(pos..pos + 4).map_or(0, |raw| {
+            u32::from_be_bytes(raw.try_into().unwrap()) as usize
+        })
+    }
+
+    /// The positions of the names starting with byte.
+    pub fn range(&self, data: &[u8], byte: u8) -> Range<usize> {
+        let start = match byte {
+            0 => 0,
+            byte => self.fanout(data, byte as usize - 1),
+        };
+        let end = self.fanout(data, byte as usize).min(self.count);
+        start.min(end)..end
+    }
+
+    /// The name at position i, raw.
+    pub fn name<'a>(&self, data: &'a [u8], i: usize) -> &'a [u8] {
+        let start = self.names + i * self.format.raw_len();
+        &data[start..start + self.format.raw_len()]
+    }
+
+    /// The position of sha among the names.
+    pub fn find(&self, data: &[u8], sha: &str) -> Option<usize> {
+        if !(self.format.is_full_name(sha)) {
+            return None;
+        }
+
+        let raw = from_hex(sha);
+        let range = self.range(data, raw[0]);
+        let (mut low, mut high) = (range.start, range.end);
+
+        while low < high {
+            let middle = (low + high) / 2;
+            match self.name(data, middle).cmp(&raw) {
+                Ordering::Less => low = middle + 1,
+                Ordering::Greater => high = middle,
+                Ordering::Equal => return Some(middle),
+            }
+        }
+
+        None
+    }
+}
+
+/// The fanout table of names, sorted.
+pub fn fanout<'a>(names: impl Iterator<Item = &'a String>) -> Vec<u8> {
+    let mut fanout = vec![0u32; 256];
+    for sha in names {
+        fanout[from_hex(sha)[0] as usize..]
+            .iter_mut()
+            .for_each(|count| *count += 1);
+    }
+    fanout
+        .iter()
+        .flat_map(|count| count.to_be_bytes())
+        .collect()
+}
+
+/// Whether the checksum at the end of data matches what comes before it.
+pub fn checksum_matches(data: &[u8], format: ObjectFormat) -> bool {
+    match data.len().checked_sub(format.raw_len()) {
+        Some(end) => format.digest(&data[..end]) == data[end..],
+        None => false,
+    }
+}
@@ -16,18 +16,27 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
-mod blob;
+pub mod blob;
+pub mod check;
+pub mod chunk;
+pub mod commit;
+pub mod commit_graph;
+pub mod database;
+pub mod format;
+pub mod lookup;
 pub mod operation;
+pub mod pack;
+pub mod tree;
 
 pub trait Object {
     fn to_string(&self) -> String;
-    fn deserialize(data: &str) -> Self
+    fn deserialize(data: &[u8]) -> Self
     where
         Self: Sized;
 
     /// It must read the object's contents from data, a byte string, and do
     /// whatever it takes to convert it into a meaningful representation.
-    fn serialize(&self) -> &[u8];
+    fn serialize(&self) -> Vec<u8>;
 
     fn fmt(&self) -> &String;
 }
@@ -1,130 +1,416 @@
 use crate::commands::cat_file::CatFile;
+use crate::commands::hash_object::HashObject;
+use crate::config;
 use crate::error;
 use crate::error::Log;
-use crate::object::{blob, Object};
+use crate::object::commit::Kvlm;
+use crate::object::format::ObjectFormat;
+use crate::object::{blob, check, commit, database, tree, Object};
 use crate::repo::Repo;
-use flate2::read::ZlibDecoder;
-use flate2::write::ZlibEncoder;
-use flate2::Compression;
-use sha1::{Digest, Sha1};
-use std::fs;
-use std::io::prelude::*;
-use std::io::Read;
+use std::fs::File;
+use std::io::{self, BufRead, Read, Write};
+
+/// Read object sha from lit repository repo.
+/// Return the object type together with its raw content.
+pub fn read_raw(repo: &Repo, sha: &str) -> Option<(String, Vec<u8>)> {
+    repo.objects.read(sha)
+}
 
 /// Read object sha from lit repository repo.
 /// Return a Object whose exact type depends on the object.
 pub fn read(repo: &Repo, sha: &str) -> Option<Box<dyn Object>> {
-    let path = Repo::repo_file(&repo.lit_dir, &["objects", &sha[0..2], &sha[2..]], false).unwrap();
+    let (fmt, data) = read_raw(repo, sha)?;
 
-    if !(path.is_file()) {
-        None
-    } else {
-        let mut raw = Vec::new();
-        ZlibDecoder::new(std::fs::read(path).unwrap().as_slice())
-            .read_to_end(&mut raw)
-            .unwrap();
+    match fmt.as_str() {
+        // "tag" => Tag(&raw[y + 1..]),
+        "commit" => Some(Box::new(commit::Commit::deserialize(&data))),
+        "tree" => Some(Box::new(tree::Tree::deserialize_with_format(
+            &data,
+            repo.object_format(),
+        ))),
+        "blob" => Some(Box::new(blob::Blob::deserialize(&data))),
+        typ => error::object::Object::UnknownType(typ.to_string(), sha.to_owned()).panic(),
+    }
+}
 
-        // Read the object type
-        let x = raw.iter().position(|byte| *byte == 0x20u8).unwrap();
-        let fmt = std::str::from_utf8(&raw[0..x]).unwrap();
+/// The name of object in a repository using format, without writing it.
+pub fn hash_object(object: &dyn Object, format: ObjectFormat) -> String {
+    format.hex_digest(&database::encode(object.fmt(), &object.serialize()))
+}
 
-        // Read and validate object size
-        let y = raw[x..].iter().position(|byte| *byte == 0x00u8).unwrap();
-        let size = std::str::from_utf8(&raw[x + 1..x + y])
-            .unwrap()
-            .parse::<usize>()
-            .unwrap();
+/// Write object to repo if provided, naming it with the object format of repo (SHA-1 without repo).
+#[cfg(test)]
+pub fn write(object: Box<dyn Object>, repo: Option<&Repo>) -> String {
+    match repo {
+        Some(repo) => write_raw(repo, object.fmt(), &object.serialize()),
+        None => hash_object(object.as_ref(), ObjectFormat::default()),
+    }
+}
+
+/// Write an object given by its type and content to repo, e.g. one received in a pack.
+pub fn write_raw(repo: &Repo, fmt: &str, data: &[u8]) -> String {
+    repo.objects.write(fmt, data).unwrap_or_else(|e| e.panic())
+}
+
+/// The reason for this strange small function is that
+/// lit has a lot of ways to refer to objects: full hash, short hash, tags...
+/// This function is the name resolution function.
+/// With fmt, the object must be of that type, unless follow and it can be peeled to it:
+/// tags to the object they tag, commits to their tree.
+fn find(repo: &Repo, name: &str, fmt: Option<&str>, follow: bool) -> String {
+    let mut sha = repo.resolve_name(name).unwrap_or(name.to_owned());
+    let fmt = match fmt {
+        Some(fmt) => fmt,
+        None => return sha,
+    };
 
-        // X itself occupied one bit
-        if size != raw.len() - (x + y + 1) {
-            error::object::Object::Malformed(sha.to_owned(), size).panic();
+    // Missing objects are left to the caller to report.
+    while let Some((actual, _)) = repo.objects.read_header(&sha) {
+        if actual == fmt {
+            break;
+        }
+
+        let key = match actual.as_str() {
+            "tag" if follow => "object",
+            "commit" if follow && fmt == "tree" => "tree",
+            _ => error::object::Object::UnexpectedType(sha, fmt.to_string(), actual).panic(),
+        };
+        sha = read_raw(repo, &sha)
+            .and_then(|(_, data)| Kvlm::parse(&data))
+            .and_then(|kvlm| kvlm.get(key).map(str::to_string))
+            .unwrap_or_else(|| error::object::Object::Malformed(sha.clone(), 0).panic());
+    }
+
+    sha
+}
+
+/// Whether the object args names exists.
+pub fn exists(repo: &Repo, args: &CatFile) -> bool {
+    repo.has_object(&find(repo, &args.object, None, false))
+}
+
+/// Write the object args names to output: its type, its size, or its content. Type and size
+/// are read from the header of the object, and content is copied as it is inflated, as objects
+/// may be too large to be held in memory. Trees are listed, unless their type is given.
+pub fn cat(repo: &Repo, args: &CatFile, output: &mut dyn Write) -> io::Result<()> {
+    let sha = find(repo, &args.object, args.typ.as_deref(), true);
+    let not_found = || error::object::Object::NotFound(args.object.clone());
+
+    if args.show_type || args.size {
+        let (fmt, size) = repo
+            .objects
+            .read_header(&sha)
+            .unwrap_or_else(|| not_found().panic());
+        return if args.show_type {
+            writeln!(output, "{}", fmt)
         } else {
-            match fmt {
-                // "commit" => Commit(&raw[y + 1..]),
-                // "tree" => Tree(&raw[y + 1..]),
-                // "tag" => Tag(&raw[y + 1..]),
-                "blob" => Some(Box::new(blob::Blob::deserialize(
-                    std::str::from_utf8(&raw[x + y + 1..]).unwrap(),
-                ))),
-                typ => error::object::Object::UnknownType(typ.to_string(), sha.to_owned()).panic(),
-            }
+            writeln!(output, "{}", size)
+        };
+    }
+
+    let (fmt, _, mut content) = repo
+        .objects
+        .read_stream(&sha)
+        .unwrap_or_else(|| not_found().panic());
+    if fmt == "tree" && (args.pretty || args.typ.is_none()) {
+        let listing = read(repo, &sha).unwrap().to_string();
+        if !listing.is_empty() {
+            writeln!(output, "{}", listing)?;
         }
+        return Ok(());
     }
+
+    io::copy(&mut content, output).map(|_| ())
 }
 
-pub fn write(object: Box<dyn Object>, repo: Option<Repo>) -> String {
-    let data = object.serialize();
+/// The information line of batch modes, by default
+const DEFAULT_BATCH_FORMAT: &str = "%(objectname) %(objecttype) %(objectsize)";
+
+/// A piece of the information line of batch modes.
+enum BatchAtom {
+    Literal(String),
+    ObjectName,
+    ObjectType,
+    ObjectSize,
+    /// What follows the object name on the input line
+    Rest,
+}
 
-    let result = format!(
-        "{} {}\0{}",
-        object.fmt(),
-        &data.len(),
-        std::str::from_utf8(data).unwrap()
-    );
+impl BatchAtom {
+    /// The pieces of format, made of text and `%(<atom>)`.
+    fn parse(format: &str) -> Vec<BatchAtom> {
+        let mut atoms = Vec::new();
+        let mut rest = format;
 
-    let sha = {
-        let mut hasher = Sha1::new();
-        hasher.update(&result);
-        format!("{:x}", hasher.finalize())
-    };
+        while let Some(start) = rest.find("%(") {
+            if start > 0 {
+                atoms.push(BatchAtom::Literal(rest[..start].to_string()));
+            }
 
-    repo.iter().for_each(|repo| {
-        let path =
-            Repo::repo_file(&repo.lit_dir, &["objects", &sha[0..2], &sha[2..]], true).unwrap();
+            let end = rest[start..]
+                .find(')')
+                .map(|end| start + end)
+                .unwrap_or_else(|| error::object::Object::BadFormat(rest.to_string()).panic());
+            atoms.push(match &rest[start + 2..end] {
+                "objectname" => BatchAtom::ObjectName,
+                "objecttype" => BatchAtom::ObjectType,
+                "objectsize" => BatchAtom::ObjectSize,
+                "rest" => BatchAtom::Rest,
+                _ => error::object::Object::BadFormat(rest[start..=end].to_string()).panic(),
+            });
+            rest = &rest[end + 1..];
+        }
 
-        if !(path.exists()) {
-            fs::write(path, {
-                let mut compress = ZlibEncoder::new(Vec::new(), Compression::default());
+        if !rest.is_empty() {
+            atoms.push(BatchAtom::Literal(rest.to_string()));
+        }
+        atoms
+    }
 
-                compress.write_all(result.as_bytes()).unwrap();
-                compress.finish().unwrap()
+    fn expand(atoms: &[BatchAtom], sha: &str, fmt: &str, size: usize, rest: &str) -> String {
+        atoms
+            .iter()
+            .map(|atom| match atom {
+                BatchAtom::Literal(text) => text.to_string(),
+                BatchAtom::ObjectName => sha.to_string(),
+                BatchAtom::ObjectType => fmt.to_string(),
+                BatchAtom::ObjectSize => size.to_string(),
+                BatchAtom::Rest => rest.to_string(),
             })
-            .unwrap();
+            .collect()
+    }
+}
+
+/// Write the information line of object name, then its content if contents, as batch modes do.
+/// Objects that cannot be found are reported as `<name> missing`.
+fn cat_batch_object(
+    repo: &Repo,
+    atoms: &[BatchAtom],
+    name: &str,
+    rest: &str,
+    contents: bool,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let sha = find(repo, name, None, false);
+
+    if !contents {
+        return match repo.objects.read_header(&sha) {
+            Some((fmt, size)) => writeln!(
+                output,
+                "{}",
+                BatchAtom::expand(atoms, &sha, &fmt, size, rest)
+            ),
+            None => writeln!(output, "{} missing", name),
+        };
+    }
+
+    match repo.objects.read_stream(&sha) {
+        Some((fmt, size, mut content)) => {
+            writeln!(
+                output,
+                "{}",
+                BatchAtom::expand(atoms, &sha, &fmt, size, rest)
+            )?;
+            io::copy(&mut content, output)?;
+            writeln!(output)
         }
-    });
+        None => writeln!(output, "{} missing", name),
+    }
+}
 
-    sha.to_string()
+/// Write the objects named by the lines of input, or all the objects with `--batch-all-objects`,
+/// to output in the batch mode of args, reading them from a single repository.
+/// Output is flushed after each line of input, for callers waiting on it.
+pub fn cat_batch(
+    repo: &Repo,
+    args: &CatFile,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let (format, contents) = match (&args.batch, &args.batch_check, &args.batch_command) {
+        (Some(format), _, _) => (format, true),
+        (_, Some(format), _) | (_, _, Some(format)) => (format, false),
+        _ => (&None, false),
+    };
+    let atoms = BatchAtom::parse(format.as_deref().unwrap_or(DEFAULT_BATCH_FORMAT));
+
+    if args.batch_all_objects {
+        for sha in repo.objects.iter() {
+            cat_batch_object(repo, &atoms, &sha, "", contents, output)?;
+        }
+        return output.flush();
+    }
+
+    // The name ends at the first whitespace only when the rest of the line is asked for.
+    let uses_rest = atoms.iter().any(|atom| matches!(atom, BatchAtom::Rest));
+    for line in input.lines() {
+        let line = line?;
+        let (line, contents) = match args.batch_command {
+            None => (line.as_str(), contents),
+            Some(_) => match line.split_once(' ') {
+                Some(("contents", object)) => (object, true),
+                Some(("info", object)) => (object, false),
+                _ if line == "flush" => {
+                    output.flush()?;
+                    continue;
+                }
+                _ => error::object::Object::BadBatchCommand(line.clone()).panic(),
+            },
+        };
+
+        let (name, rest) = match line.split_once(char::is_whitespace) {
+            Some((name, rest)) if uses_rest => (name, rest.trim_start()),
+            _ => (line, ""),
+        };
+        cat_batch_object(repo, &atoms, name, rest, contents, output)?;
+        output.flush()?;
+    }
+
+    output.flush()
 }
 
-/// The reason for this strange small function is that
-/// lit has a lot of ways to refer to objects: full hash, short hash, tags...
-/// This function is the name resolution function.
-fn find(_repo: &Repo, name: &str, _fmt: &str, _follow: bool) -> String {
-    name.to_owned()
+/// Hash the objects args gives, the one read from stdin first, then the files, writing their
+/// names to output. The objects are written to repo with `-w`, and named with its object
+/// format, SHA-1 without repo.
+pub fn hash(
+    args: &HashObject,
+    repo: Option<&Repo>,
+    stdin: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    let unreadable =
+        |e: io::Error| error::object::Object::Unreadable("stdin".into(), e.to_string());
+
+    if args.stdin {
+        let mut data = Vec::new();
+        stdin
+            .read_to_end(&mut data)
+            .unwrap_or_else(|e| unreadable(e).panic());
+        writeln!(output, "{}", hash_data(args, repo, "stdin", data))?;
+    }
+
+    let paths: Box<dyn Iterator<Item = io::Result<String>>> = if args.stdin_paths {
+        Box::new(stdin.lines())
+    } else {
+        Box::new(args.paths.iter().cloned().map(Ok))
+    };
+    for path in paths {
+        let path = path.unwrap_or_else(|e| unreadable(e).panic());
+        writeln!(output, "{}", hash_file(args, repo, &path))?;
+        output.flush()?;
+    }
+
+    Ok(())
 }
 
-pub fn cat(args: &CatFile) -> String {
-    let repo = Repo::repo_find(&".".to_owned(), true).unwrap();
-    let object = read(&repo, find(&repo, &args.object, &args.typ, true).as_str()).unwrap();
-    std::str::from_utf8(object.serialize()).unwrap().to_string()
+/// Whether the end of lines of the files hashed by args are converted, as `core.autocrlf` asks.
+fn filters(args: &HashObject, repo: Option<&Repo>) -> bool {
+    let autocrlf = repo
+        .and_then(|repo| repo.conf.as_ref())
+        .and_then(|conf| conf.get("core.autocrlf"));
+
+    !args.no_filters
+        && args.typ == "blob"
+        && autocrlf
+            .is_some_and(|value| value == "input" || config::parse_bool(Some(&value)) == Some(true))
 }
 
-/// Hash object, writing it to repo if provided
-pub fn hash(file: &String, fmt: &str, repo: Option<Repo>) -> String {
-    let data = std::fs::read_to_string(file).unwrap();
-    let object = match fmt {
-        // "commit" => Commit(&raw[y + 1..]),
-        // "tree" => Tree(&raw[y + 1..]),
-        // "tag" => Tag(&raw[y + 1..]),
-        "blob" => Box::new(blob::Blob::new(data)),
-        typ => error::object::Object::UnknownType(typ.to_string(), file.to_owned()).panic(),
-    };
+/// Convert the CRLF ends of line of text data to LF. Data with NUL bytes or lone CRs is binary,
+/// and left as it is.
+fn convert_crlf(data: Vec<u8>) -> Vec<u8> {
+    let lone_cr = data
+        .iter()
+        .enumerate()
+        .any(|(i, byte)| *byte == b'\r' && data.get(i + 1) != Some(&b'\n'));
+    if lone_cr || data.contains(&0) || !data.contains(&b'\r') {
+        return data;
+    }
+
+    let mut converted = Vec::with_capacity(data.len());
+    for (i, byte) in data.iter().enumerate() {
+        if !(*byte == b'\r' && data.get(i + 1) == Some(&b'\n')) {
+            converted.push(*byte);
+        }
+    }
+    converted
+}
+
+/// Hash the file at path as args says. Blobs left as they are are hashed, and compressed, as
+/// they are read, they are never held in memory.
+fn hash_file(args: &HashObject, repo: Option<&Repo>, path: &str) -> String {
+    let unreadable = |e: io::Error| error::object::Object::Unreadable(path.into(), e.to_string());
+    let mut input = File::open(path).unwrap_or_else(|e| unreadable(e).panic());
+
+    if args.typ == "blob" && !filters(args, repo) {
+        let size = input
+            .metadata()
+            .unwrap_or_else(|e| unreadable(e).panic())
+            .len() as usize;
+
+        return match repo {
+            Some(repo) if args.write => repo
+                .objects
+                .write_stream(&args.typ, size, &mut input)
+                .unwrap_or_else(|e| e.panic()),
+            _ => database::encode_stream(
+                repo.map_or(ObjectFormat::default(), Repo::object_format),
+                &args.typ,
+                size,
+                &mut input,
+                &mut io::sink(),
+            )
+            .unwrap_or_else(|e| unreadable(e).panic()),
+        };
+    }
+
+    let mut data = Vec::new();
+    input
+        .read_to_end(&mut data)
+        .unwrap_or_else(|e| unreadable(e).panic());
+    if filters(args, repo) {
+        data = convert_crlf(data);
+    }
+    hash_data(args, repo, path, data)
+}
+
+/// Hash the content of object name as args says, checking it is well-formed unless `--literally`.
+fn hash_data(args: &HashObject, repo: Option<&Repo>, name: &str, data: Vec<u8>) -> String {
+    let format = repo.map_or(ObjectFormat::default(), Repo::object_format);
+
+    if !args.literally {
+        if !check::TYPES.contains(&args.typ.as_str()) {
+            error::object::Object::UnknownType(args.typ.clone(), name.to_string()).panic()
+        }
+        check::check(&args.typ, &data, format).unwrap_or_else(|reason| {
+            error::object::Object::Invalid(name.to_string(), args.typ.clone(), reason).panic()
+        });
+    }
 
-    write(object, repo)
+    match repo {
+        Some(repo) if args.write => write_raw(repo, &args.typ, &data),
+        _ => format.hex_digest(&database::encode(&args.typ, &data)),
+    }
 }
 
 #[cfg(test)]
 mod test {
     use crate::commands::cat_file::CatFile;
+    use crate::commands::hash_object::HashObject;
     use crate::commands::init::Init;
     use crate::object::blob::Blob;
+    use crate::object::commit::{Commit, Kvlm};
     use crate::object::operation::write;
+    use crate::object::tree::{Tree, TreeEntry};
     use crate::repo;
 
+    use crate::object::format::ObjectFormat;
     use flate2::bufread::ZlibDecoder;
-    use std::{fs, io::Read, path::PathBuf};
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::path::{Path, PathBuf};
 
-    use super::cat;
+    use super::{cat, cat_batch, exists, hash, hash_object};
 
     #[test]
     pub fn test_read_blob() {
@@ -159,11 +445,14 @@ mod test {
         let _ = fs::remove_dir_all(".lit");
         let repo = repo::Repo::create(&Init {
             force: false,
+            bare: false,
             path: String::from("."),
+            extensions: vec![],
+            object_format: None,
         });
 
         let object = Blob::new("Ok, this is a blob object".to_string());
-        let sha1 = write(Box::new(object), Some(repo));
+        let sha1 = write(Box::new(object), Some(&repo));
 
         assert_eq!("9ca6e1d93dfc2343e4e404a6b742220b148649a0", sha1.as_str());
         assert!(PathBuf::from(".lit/objects/9c/a6e1d93dfc2343e4e404a6b742220b148649a0").exists());
@@ -177,18 +466,237 @@ mod test {
 
         let repo = repo::Repo::create(&Init {
             force: false,
+            bare: false,
             path: String::from("."),
+            extensions: vec![],
+            object_format: None,
         });
 
         let object = Blob::new("Ok, this is a blob object".to_string());
-        let sha1 = write(Box::new(object), Some(repo));
+        let sha1 = write(Box::new(object), Some(&repo));
+        let tree = write(
+            Box::new(Tree::new(vec![TreeEntry {
+                mode: "100644".to_string(),
+                name: "file".to_string(),
+                sha: sha1.clone(),
+            }])),
+            Some(&repo),
+        );
+        let commit = write(
+            Box::new(Commit::new(Kvlm {
+                headers: vec![("tree".to_string(), tree.clone())],
+                message: "message\n".to_string(),
+            })),
+            Some(&repo),
+        );
+
+        let cat_file = |flag: char, typ: Option<&str>, object: &str| {
+            let mut content = Vec::new();
+            cat(
+                &repo,
+                &CatFile {
+                    show_type: flag == 't',
+                    size: flag == 's',
+                    pretty: flag == 'p',
+                    typ: typ.map(str::to_string),
+                    object: object.to_string(),
+                    ..Default::default()
+                },
+                &mut content,
+            )
+            .unwrap();
+            String::from_utf8_lossy(&content).to_string()
+        };
 
-        let content = cat(&CatFile {
-            typ: "blob".to_string(),
-            object: sha1,
-        });
+        let content = cat_file(' ', Some("blob"), &sha1);
+        assert_eq!("Ok, this is a blob object", content);
+        assert_eq!("blob\n", cat_file('t', None, &sha1));
+        assert_eq!("25\n", cat_file('s', None, &sha1));
+        assert_eq!(
+            format!("100644 blob {}\tfile\n", sha1),
+            cat_file('p', None, &tree)
+        );
+        assert_eq!(
+            format!("tree {}\n\nmessage\n", tree),
+            cat_file('p', None, &commit)
+        );
+
+        // Commits are peeled to their tree, given raw with its type.
+        assert_eq!("tree\n", cat_file('t', Some("tree"), &commit));
+        assert!(cat_file(' ', Some("tree"), &commit).starts_with("100644 file\0"));
+        assert!(catch_unwind(AssertUnwindSafe(|| cat_file(' ', Some("commit"), &sha1))).is_err());
+
+        let exists = |object: &str| {
+            exists(
+                &repo,
+                &CatFile {
+                    exists: true,
+                    object: object.to_string(),
+                    ..Default::default()
+                },
+            )
+        };
+        assert!(exists(&commit));
+        assert!(!exists(&"0".repeat(40)));
+
+        let batch = |args: CatFile, input: &str| {
+            let mut output = Vec::new();
+            cat_batch(&repo, &args, &mut input.as_bytes(), &mut output).unwrap();
+            String::from_utf8_lossy(&output).to_string()
+        };
+        assert_eq!(
+            format!(
+                "{} blob 25\nOk, this is a blob object\nnothing missing\n",
+                sha1
+            ),
+            batch(
+                CatFile {
+                    batch: Some(None),
+                    ..Default::default()
+                },
+                &format!("{}\nnothing\n", sha1)
+            )
+        );
+        assert_eq!(
+            format!("tree 32 {} (the tree)\n", tree),
+            batch(
+                CatFile {
+                    batch_check: Some(Some(
+                        "%(objecttype) %(objectsize) %(objectname) %(rest)".to_string()
+                    )),
+                    ..Default::default()
+                },
+                &format!("{}   (the tree)\n", tree)
+            )
+        );
+        assert_eq!(
+            format!(
+                "{} blob 25\n{} blob 25\nOk, this is a blob object\n",
+                sha1, sha1
+            ),
+            batch(
+                CatFile {
+                    batch_command: Some(None),
+                    ..Default::default()
+                },
+                &format!("info {}\nflush\ncontents {}\n", sha1, sha1)
+            )
+        );
+
+        // All the objects, by name.
+        let mut all = [
+            format!("{} commit\n", commit),
+            format!("{} blob\n", sha1),
+            format!("{} tree\n", tree),
+        ];
+        all.sort();
+        assert_eq!(
+            all.concat(),
+            batch(
+                CatFile {
+                    batch_check: Some(Some("%(objectname) %(objecttype)".to_string())),
+                    batch_all_objects: true,
+                    ..Default::default()
+                },
+                ""
+            )
+        );
 
         fs::remove_dir_all(".lit").unwrap();
-        assert_eq!("Ok, this is a blob object".to_string(), content);
+    }
+
+    #[test]
+    pub fn test_hash_object() {
+        let _ = fs::remove_dir_all("./test_hash_object");
+        let repo = repo::Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_hash_object/repo.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        fs::write("./test_hash_object/text", "one\r\ntwo\r\n").unwrap();
+        fs::write("./test_hash_object/binary", "one\r\n\0").unwrap();
+
+        let run = |args: HashObject, repo: &repo::Repo, stdin: &str| {
+            let mut output = Vec::new();
+            hash(&args, Some(repo), &mut stdin.as_bytes(), &mut output).unwrap();
+            String::from_utf8(output).unwrap()
+        };
+        let blob = |data: &str| hash_object(&Blob::new(data), ObjectFormat::Sha1);
+        let paths = vec![
+            "./test_hash_object/text".to_string(),
+            "./test_hash_object/binary".to_string(),
+        ];
+
+        let raw = format!("{}\n{}\n", blob("one\r\ntwo\r\n"), blob("one\r\n\0"));
+        let hashed = run(
+            HashObject {
+                typ: "blob".to_string(),
+                paths: paths.clone(),
+                ..Default::default()
+            },
+            &repo,
+            "",
+        );
+        assert_eq!(raw, hashed);
+        assert!(!repo.has_object(&blob("one\r\ntwo\r\n")));
+
+        // Text files are converted with `core.autocrlf`, unless `--no-filters`.
+        let mut config = fs::OpenOptions::new()
+            .append(true)
+            .open("./test_hash_object/repo.git/config")
+            .unwrap();
+        writeln!(config, "[core]\n\tautocrlf = input").unwrap();
+        let repo = repo::Repo::repo_open(Path::new("./test_hash_object/repo.git")).unwrap();
+        let written = run(
+            HashObject {
+                write: true,
+                typ: "blob".to_string(),
+                stdin_paths: true,
+                ..Default::default()
+            },
+            &repo,
+            &paths.join("\n"),
+        );
+        assert_eq!(
+            format!("{}\n{}\n", blob("one\ntwo\n"), blob("one\r\n\0")),
+            written
+        );
+        assert!(repo.has_object(&blob("one\ntwo\n")));
+        let unfiltered = run(
+            HashObject {
+                typ: "blob".to_string(),
+                no_filters: true,
+                paths,
+                ..Default::default()
+            },
+            &repo,
+            "",
+        );
+        assert_eq!(raw, unfiltered);
+
+        // Objects other than blobs are checked, unless `--literally`.
+        let commit = |typ: &str, literally: bool| HashObject {
+            write: true,
+            typ: typ.to_string(),
+            stdin: true,
+            literally,
+            ..Default::default()
+        };
+        assert!(catch_unwind(AssertUnwindSafe(|| run(
+            commit("commit", false),
+            &repo,
+            "tree\n"
+        )))
+        .is_err());
+        assert!(catch_unwind(AssertUnwindSafe(|| run(commit("bogus", false), &repo, ""))).is_err());
+        let bogus = run(commit("bogus", true), &repo, "tree\n");
+        assert_eq!(
+            Some(("bogus".to_string(), 5)),
+            repo.objects.read_header(bogus.trim_end())
+        );
+
+        fs::remove_dir_all("./test_hash_object").unwrap();
     }
 }
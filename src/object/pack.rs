@@ -0,0 +1,737 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::object::database::{self, ObjectDatabase};
+use crate::object::format::to_hex;
+use crate::object::format::ObjectFormat;
+use crate::repo::Repo;
+use flate2::bufread::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Read, Write};
+
+const SIGNATURE: &[u8] = b"PACK";
+const HEADER_LEN: usize = 12;
+
+const OBJ_OFS_DELTA: u8 = 6;
+const OBJ_REF_DELTA: u8 = 7;
+
+/// Longer chains of deltas are taken for a loop in a corrupt pack
+const MAX_DELTA_CHAIN: usize = 10_000;
+
+/// The most allocated ahead for sizes read from a pack, which may be forged
+const MAX_PREALLOCATION: usize = 64 * 1024;
+
+/// The object type of a pack entry which is not a delta.
+fn type_name(typ: u8) -> Option<&'static str> {
+    match typ {
+        1 => Some("commit"),
+        2 => Some("tree"),
+        3 => Some("blob"),
+        4 => Some("tag"),
+        _ => None,
+    }
+}
+
+fn malformed(reason: &str) -> Error {
+    Error::Pack(error::pack::Pack::Malformed(reason.to_owned()))
+}
+
+/// Where the entries of a pack are read from: the pack in memory, or its file read an entry
+/// at a time.
+pub trait PackData {
+    /// The bytes of the pack from offset, up to the end of the entry there at least.
+    fn entry(&self, offset: usize) -> error::Result<Cow<'_, [u8]>>;
+}
+
+impl PackData for Vec<u8> {
+    fn entry(&self, offset: usize) -> error::Result<Cow<'_, [u8]>> {
+        self.get(offset..)
+            .map(Cow::Borrowed)
+            .ok_or_else(|| malformed("entry past the end of the pack"))
+    }
+}
+
+/// What a delta applies to: an earlier entry of the pack, or an object named by its sha.
+enum Base {
+    Offset(usize),
+    Sha(String),
+}
+
+/// Check and index a pack received from a remote, in git's pack format version 2 or 3, as
+/// `git index-pack --fix-thin` does. The entries are named one at a time, each delta being
+/// rebuilt from the pack, so that only the chain of the one at hand is held in memory.
+/// The bases of deltas the pack lacks are read from database and appended to it.
+/// Returns the pack thus completed, and where each of its objects is.
+pub fn index(
+    data: &[u8],
+    format: ObjectFormat,
+    database: &dyn ObjectDatabase,
+) -> error::Result<(Vec<u8>, Vec<PackEntry>)> {
+    let trailer = data
+        .len()
+        .checked_sub(format.raw_len())
+        .filter(|trailer| *trailer >= HEADER_LEN && data.starts_with(SIGNATURE))
+        .ok_or_else(|| malformed("not a pack"))?;
+
+    let version = u32::from_be_bytes(data[4..8].try_into().unwrap());
+    if !(version == 2 || version == 3) {
+        return Err(malformed(&format!("unsupported version {}", version)));
+    }
+
+    if format.digest(&data[..trailer]) != data[trailer..] {
+        return Err(Error::Pack(error::pack::Pack::BadChecksum));
+    }
+
+    let count = u32::from_be_bytes(data[8..12].try_into().unwrap()) as usize;
+    let mut pos = HEADER_LEN;
+
+    // The entries named so far, by offset and by name, and the deltas left with their CRC.
+    let mut entries: Vec<PackEntry> = Vec::with_capacity(count.min(MAX_PREALLOCATION));
+    let mut named: HashSet<usize> = HashSet::new();
+    let mut offsets: HashMap<String, usize> = HashMap::new();
+    let mut deltas: Vec<(usize, u32, Base)> = Vec::new();
+
+    for _ in 0..count {
+        let offset = pos;
+        let (typ, size) = read_entry_header(data, &mut pos)?;
+
+        let base = match typ {
+            OBJ_OFS_DELTA => Some(Base::Offset(
+                offset
+                    .checked_sub(read_offset(data, &mut pos)?)
+                    .ok_or_else(|| malformed("delta base before the pack"))?,
+            )),
+            OBJ_REF_DELTA => {
+                let sha = data
+                    .get(pos..pos + format.raw_len())
+                    .ok_or_else(|| malformed("truncated entry"))?;
+                pos += format.raw_len();
+                Some(Base::Sha(to_hex(sha)))
+            }
+            _ => None,
+        };
+
+        let content = inflate(data, &mut pos, size)?;
+        let crc = crc32(&data[offset..pos]);
+        match base {
+            Some(base) => deltas.push((offset, crc, base)),
+            None => {
+                let fmt = type_name(typ).ok_or_else(|| malformed("unknown object type"))?;
+                let sha = format.hex_digest(&database::encode(fmt, &content));
+                named.insert(offset);
+                offsets.insert(sha.clone(), offset);
+                entries.push((sha, crc, offset));
+            }
+        }
+    }
+
+    if pos != trailer {
+        return Err(malformed("garbage after the last entry"));
+    }
+
+    // A delta may come before its base, name them until no more can be, then complete the
+    // pack with the bases it lacks.
+    let mut pack = data[..trailer].to_vec();
+    let mut appended = 0;
+    while !(deltas.is_empty()) {
+        let mut resolved = Vec::new();
+        let mut unresolved = Vec::new();
+        let find = |sha: &str| offsets.get(sha).copied();
+
+        for (offset, crc, base) in deltas {
+            let ready = match &base {
+                Base::Offset(base) => named.contains(base),
+                Base::Sha(sha) => offsets.contains_key(sha),
+            };
+            if !(ready) {
+                unresolved.push((offset, crc, base));
+                continue;
+            }
+
+            let (fmt, content) = read_entry(&pack, offset, format, &find)?;
+            let sha = format.hex_digest(&database::encode(fmt, &content));
+            resolved.push((sha, crc, offset));
+        }
+
+        if resolved.is_empty() {
+            let mut missing: Vec<&String> = unresolved
+                .iter()
+                .filter_map(|(_, _, base)| match base {
+                    Base::Sha(sha) => Some(sha),
+                    Base::Offset(_) => None,
+                })
+                .collect();
+            missing.sort();
+            missing.dedup();
+
+            for sha in missing {
+                let Some((fmt, content)) = database.read(sha) else {
+                    continue;
+                };
+                let typ = type_code(&fmt).ok_or_else(|| malformed("unknown object type"))?;
+                let offset = pack.len();
+                write_entry(&mut pack, typ, &content);
+                resolved.push((sha.clone(), crc32(&pack[offset..]), offset));
+                appended += 1;
+            }
+
+            if resolved.is_empty() {
+                return Err(Error::Pack(error::pack::Pack::MissingBase(
+                    match &unresolved[0].2 {
+                        Base::Sha(sha) => sha.clone(),
+                        Base::Offset(offset) => format!("at offset {}", offset),
+                    },
+                )));
+            }
+        }
+
+        for (sha, crc, offset) in resolved {
+            named.insert(offset);
+            offsets.insert(sha.clone(), offset);
+            entries.push((sha, crc, offset));
+        }
+        deltas = unresolved;
+    }
+
+    if appended > 0 {
+        let count = u32::try_from(count + appended).map_err(|_| malformed("too many entries"))?;
+        pack[8..12].copy_from_slice(&count.to_be_bytes());
+    }
+    pack.extend(format.digest(&pack));
+
+    entries.sort();
+    entries.dedup_by(|a, b| a.0 == b.0);
+    Ok((pack, entries))
+}
+
+/// Store a pack received from a remote in repo, with its index, as `git index-pack` does.
+/// Deltas may be based on objects repo already has. Returns how many objects the pack held.
+pub fn index_pack(repo: &Repo, data: &[u8]) -> error::Result<usize> {
+    let (pack, entries) = index(data, repo.object_format(), repo.objects.as_ref())?;
+
+    if !(entries.is_empty()) {
+        repo.pack_database().write_pack_data(&pack, &entries)?;
+    }
+    Ok(entries.len())
+}
+
+/// Keeps what is consumed from the reader it wraps.
+struct Recorder<'a> {
+    inner: &'a mut dyn BufRead,
+    data: Vec<u8>,
+}
+
+impl Read for Recorder<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for Recorder<'_> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        // The buffer was just filled, this does not read anything.
+        if let Ok(buffer) = self.inner.fill_buf() {
+            self.data.extend_from_slice(&buffer[..amt]);
+        }
+        self.inner.consume(amt);
+    }
+}
+
+/// Read a pack from a stream, which carries nothing telling where the pack ends:
+/// the entries are walked, leaving what follows the trailer unread.
+pub fn read(input: &mut dyn BufRead, format: ObjectFormat) -> io::Result<Vec<u8>> {
+    let mut recorder = Recorder {
+        inner: input,
+        data: Vec::new(),
+    };
+    let mut header = [0u8; HEADER_LEN];
+    recorder.read_exact(&mut header)?;
+    if !(header.starts_with(SIGNATURE)) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a pack"));
+    }
+
+    let count = u32::from_be_bytes(header[8..12].try_into().unwrap());
+    for _ in 0..count {
+        let mut byte = [0u8];
+        recorder.read_exact(&mut byte)?;
+        let typ = (byte[0] >> 4) & 0x07;
+
+        // The size goes on while the high bit is set, so does the offset of a delta base.
+        while byte[0] & 0x80 != 0 {
+            recorder.read_exact(&mut byte)?;
+        }
+        match typ {
+            OBJ_OFS_DELTA => loop {
+                recorder.read_exact(&mut byte)?;
+                if byte[0] & 0x80 == 0 {
+                    break;
+                }
+            },
+            OBJ_REF_DELTA => recorder.read_exact(&mut vec![0u8; format.raw_len()])?,
+            _ => (),
+        }
+
+        io::copy(&mut ZlibDecoder::new(&mut recorder), &mut io::sink())?;
+    }
+
+    let mut trailer = vec![0u8; format.raw_len()];
+    recorder.read_exact(&mut trailer)?;
+    Ok(recorder.data)
+}
+
+/// A version 2 pack holding objects of repo whole, without deltas.
+pub fn write(repo: &Repo, objects: &[String]) -> error::Result<Vec<u8>> {
+    write_entries(objects, repo.object_format(), repo.objects.as_ref()).map(|(pack, _)| pack)
+}
+
+/// Where an entry of a pack is: the name of its object, the CRC-32 of the entry, as pack
+/// indexes record it, and its offset.
+pub type PackEntry = (String, u32, usize);
+
+/// A version 2 pack holding objects of database whole, and where each entry is.
+pub fn write_entries(
+    objects: &[String],
+    format: ObjectFormat,
+    database: &dyn ObjectDatabase,
+) -> error::Result<(Vec<u8>, Vec<PackEntry>)> {
+    let mut pack = SIGNATURE.to_vec();
+    pack.extend(2u32.to_be_bytes());
+    pack.extend((objects.len() as u32).to_be_bytes());
+    let mut entries = Vec::new();
+
+    for sha in objects {
+        let (fmt, data) =
+            database
+                .read(sha)
+                .ok_or(Error::Object(error::object::Object::NotFound(
+                    sha.to_owned(),
+                )))?;
+        let typ = type_code(&fmt).ok_or_else(|| malformed("unknown object type"))?;
+        let offset = pack.len();
+        write_entry(&mut pack, typ, &data);
+        entries.push((sha.clone(), crc32(&pack[offset..]), offset));
+    }
+
+    pack.extend(format.digest(&pack));
+    Ok((pack, entries))
+}
+
+/// Append to pack an entry holding data whole.
+fn write_entry(pack: &mut Vec<u8>, typ: u8, data: &[u8]) {
+    // The size continues in groups of 7 bits after the 4 of the first byte.
+    let mut size = data.len();
+    let mut header = vec![(typ << 4) | (size & 0x0f) as u8];
+    size >>= 4;
+    while size > 0 {
+        *header.last_mut().unwrap() |= 0x80;
+        header.push((size & 0x7f) as u8);
+        size >>= 7;
+    }
+    pack.extend(header);
+
+    let mut compress = ZlibEncoder::new(pack, Compression::default());
+    compress.write_all(data).unwrap();
+    compress.finish().unwrap();
+}
+
+/// The type of pack entry holding an object of type fmt whole.
+fn type_code(fmt: &str) -> Option<u8> {
+    (1..=4).find(|typ| type_name(*typ) == Some(fmt))
+}
+
+/// The CRC-32 (IEEE 802.3) of data.
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        (0..8).fold(crc ^ *byte as u32, |crc, _| {
+            (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// The type and size of an entry starting entry, which holds its header at least, and where
+/// its zlib stream starts; None for a delta.
+pub fn content_start(entry: &[u8]) -> error::Result<Option<(&'static str, usize, usize)>> {
+    let mut pos = 0;
+    let (typ, size) = read_entry_header(entry, &mut pos)?;
+    Ok(type_name(typ).map(|fmt| (fmt, size, pos)))
+}
+
+/// The offset of the base of the entry at offset, for a delta, its header being read up to
+/// pos in entry: offset deltas are based on earlier entries, sha deltas on the entries find
+/// locates in the pack.
+fn delta_base(
+    entry: &[u8],
+    offset: usize,
+    typ: u8,
+    pos: &mut usize,
+    format: ObjectFormat,
+    find: &dyn Fn(&str) -> Option<usize>,
+) -> error::Result<Option<usize>> {
+    match typ {
+        OBJ_OFS_DELTA => offset
+            .checked_sub(read_offset(entry, pos)?)
+            .filter(|base| *base < offset)
+            .map(Some)
+            .ok_or_else(|| malformed("bad delta base offset")),
+        OBJ_REF_DELTA => {
+            let sha = to_hex(
+                entry
+                    .get(*pos..*pos + format.raw_len())
+                    .ok_or_else(|| malformed("truncated entry"))?,
+            );
+            *pos += format.raw_len();
+            find(&sha)
+                .map(Some)
+                .ok_or(Error::Pack(error::pack::Pack::MissingBase(sha)))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Read the entry of a pack at offset, applying the chain of deltas leading to it.
+pub fn read_entry(
+    data: &dyn PackData,
+    offset: usize,
+    format: ObjectFormat,
+    find: &dyn Fn(&str) -> Option<usize>,
+) -> error::Result<(&'static str, Vec<u8>)> {
+    let mut deltas: Vec<Vec<u8>> = Vec::new();
+    let mut offset = offset;
+
+    while deltas.len() < MAX_DELTA_CHAIN {
+        let entry = data.entry(offset)?;
+        let mut pos = 0;
+        let (typ, size) = read_entry_header(&entry, &mut pos)?;
+
+        let base = match delta_base(&entry, offset, typ, &mut pos, format, find)? {
+            Some(base) => base,
+            None => {
+                let fmt = type_name(typ).ok_or_else(|| malformed("unknown object type"))?;
+                let content = inflate(&entry, &mut pos, size)?;
+                return deltas
+                    .iter()
+                    .rev()
+                    .try_fold(content, |base, delta| apply_delta(&base, delta))
+                    .map(|content| (fmt, content));
+            }
+        };
+
+        deltas.push(inflate(&entry, &mut pos, size)?);
+        offset = base;
+    }
+
+    Err(malformed("delta chain too long"))
+}
+
+/// How many deltas lead to the entry of a pack at offset, 0 when it is stored whole.
+pub fn delta_depth(
+    data: &dyn PackData,
+    offset: usize,
+    format: ObjectFormat,
+    find: &dyn Fn(&str) -> Option<usize>,
+) -> error::Result<usize> {
+    let mut offset = offset;
+
+    for depth in 0..MAX_DELTA_CHAIN {
+        let entry = data.entry(offset)?;
+        let mut pos = 0;
+        let (typ, _) = read_entry_header(&entry, &mut pos)?;
+        match delta_base(&entry, offset, typ, &mut pos, format, find)? {
+            Some(base) => offset = base,
+            None => return Ok(depth),
+        }
+    }
+
+    Err(malformed("delta chain too long"))
+}
+
+/// The type and inflated size of an entry: 3 bits of type and a variable length size.
+fn read_entry_header(data: &[u8], pos: &mut usize) -> error::Result<(u8, usize)> {
+    let mut byte = next_byte(data, pos)?;
+    let typ = (byte >> 4) & 0x07;
+    let mut size = (byte & 0x0f) as usize;
+    let mut shift = 4;
+
+    while byte & 0x80 != 0 {
+        byte = next_byte(data, pos)?;
+        size = add_bits(size, byte, shift)?;
+        shift += 7;
+    }
+
+    Ok((typ, size))
+}
+
+/// Add the low 7 bits of byte to a size, at shift, failing when they do not fit in 64 bits.
+fn add_bits(size: usize, byte: u8, shift: u32) -> error::Result<usize> {
+    let bits = (byte & 0x7f) as usize;
+    bits.checked_shl(shift)
+        .filter(|shifted| shifted >> shift == bits)
+        .map(|shifted| size | shifted)
+        .ok_or_else(|| malformed("size too large"))
+}
+
+/// The distance to the base of an offset delta, in git's "offset encoding".
+fn read_offset(data: &[u8], pos: &mut usize) -> error::Result<usize> {
+    let mut byte = next_byte(data, pos)?;
+    let mut offset = (byte & 0x7f) as usize;
+
+    while byte & 0x80 != 0 {
+        byte = next_byte(data, pos)?;
+        offset = offset
+            .checked_add(1)
+            .and_then(|offset| offset.checked_mul(0x80))
+            .ok_or_else(|| malformed("delta base offset too large"))?
+            | (byte & 0x7f) as usize;
+    }
+
+    Ok(offset)
+}
+
+fn next_byte(data: &[u8], pos: &mut usize) -> error::Result<u8> {
+    let byte = *data.get(*pos).ok_or_else(|| malformed("truncated entry"))?;
+    *pos += 1;
+    Ok(byte)
+}
+
+/// Inflate the zlib stream at pos, which must inflate to size bytes, and move past it.
+/// The size is not trusted: no more than a byte past it is inflated.
+fn inflate(data: &[u8], pos: &mut usize, size: usize) -> error::Result<Vec<u8>> {
+    let mut decoder = ZlibDecoder::new(&data[*pos..]);
+    let mut content = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+
+    (&mut decoder)
+        .take(size as u64 + 1)
+        .read_to_end(&mut content)
+        .map_err(|e| malformed(&e.to_string()))?;
+    if content.len() != size {
+        return Err(malformed("entry size mismatch"));
+    }
+
+    *pos += decoder.total_in() as usize;
+    Ok(content)
+}
+
+/// A size at the start of a delta: little-endian groups of 7 bits.
+fn read_delta_size(delta: &[u8], pos: &mut usize) -> error::Result<usize> {
+    let mut size = 0;
+    let mut shift = 0;
+
+    loop {
+        let byte = next_byte(delta, pos)?;
+        size = add_bits(size, byte, shift)?;
+        shift += 7;
+
+        if byte & 0x80 == 0 {
+            return Ok(size);
+        }
+    }
+}
+
+/// Rebuild an object from its base and a delta: a list of instructions copying
+/// ranges of the base or inserting new data.
+pub fn apply_delta(base: &[u8], delta: &[u8]) -> error::Result<Vec<u8>> {
+    let mut pos = 0;
+    if read_delta_size(delta, &mut pos)? != base.len() {
+        return Err(malformed("delta base size mismatch"));
+    }
+
+    let size = read_delta_size(delta, &mut pos)?;
+    let mut result = Vec::with_capacity(size.min(MAX_PREALLOCATION));
+
+    while pos < delta.len() {
+        let instruction = next_byte(delta, &mut pos)?;
+
+        if instruction & 0x80 != 0 {
+            // Which of the 4 offset bytes and 3 size bytes follow is given by the low bits.
+            let mut offset = 0;
+            let mut length = 0;
+            for bit in 0..7 {
+                if instruction & (1 << bit) != 0 {
+                    let byte = next_byte(delta, &mut pos)? as usize;
+                    match bit {
+                        0..=3 => offset |= byte << (8 * bit),
+                        _ => length |= byte << (8 * (bit - 4)),
+                    }
+                }
+            }
+
+            let length = if length == 0 { 0x10000 } else { length };
+            result.extend_from_slice(
+                offset
+                    .checked_add(length)
+                    .and_then(|end| base.get(offset..end))
+                    .ok_or_else(|| malformed("delta copies outside of its base"))?,
+            );
+        } else if instruction != 0 {
+            let length = instruction as usize;
+            result.extend_from_slice(
+                delta
+                    .get(pos..pos + length)
+                    .ok_or_else(|| malformed("truncated delta"))?,
+            );
+            pos += length;
+        } else {
+            return Err(malformed("reserved delta instruction"));
+        }
+
+        if result.len() > size {
+            return Err(malformed("delta result size mismatch"));
+        }
+    }
+
+    if result.len() != size {
+        return Err(malformed("delta result size mismatch"));
+    }
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{index_pack, read, write};
+    use crate::commands::init::Init;
+    use crate::object::format::from_hex;
+    use crate::object::operation::read_raw;
+    use crate::repo::Repo;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+    use std::path::Path;
+
+    fn entry(typ: u8, extra: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut size = content.len();
+        let mut header = vec![(typ << 4) | (size & 0x0f) as u8];
+        size >>= 4;
+        while size > 0 {
+            *header.last_mut().unwrap() |= 0x80;
+            header.push((size & 0x7f) as u8);
+            size >>= 7;
+        }
+
+        let mut compress = ZlibEncoder::new(Vec::new(), Compression::default());
+        compress.write_all(content).unwrap();
+
+        [header, extra.to_vec(), compress.finish().unwrap()].concat()
+    }
+
+    fn pack_of(repo: &Repo, entries: &[Vec<u8>]) -> Vec<u8> {
+        let count = entries.len() as u8;
+        let mut pack = [b"PACK".as_slice(), &[0, 0, 0, 2, 0, 0, 0, count]].concat();
+        pack.extend(entries.concat());
+        pack.extend(repo.object_format().digest(&pack));
+        pack
+    }
+
+    #[test]
+    pub fn test_index_pack() {
+        let _ = fs::remove_dir_all("./test_pack");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_pack"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        // "hello " copied from the base, then "lit\n" inserted
+        let delta = [12, 10, 0x90, 6, 4, b'l', b'i', b't', b'\n'];
+        let base = entry(3, &[], b"hello world\n");
+        let ofs_delta = entry(6, &[base.len() as u8], &delta);
+        let ref_delta = entry(
+            7,
+            &from_hex("3b18e512dba79e4c8300dd08aeb37f8e728b8dad"),
+            &delta,
+        );
+
+        // The ref delta comes before its base, both deltas make the same object.
+        let mut pack = pack_of(&repo, &[ref_delta.clone(), base.clone(), ofs_delta]);
+        assert_eq!(2, index_pack(&repo, &pack).unwrap());
+        assert_eq!(
+            Some(("blob".to_string(), b"hello lit\n".to_vec())),
+            read_raw(&repo, "93b9efc6aa5ef6f04964a34afc7210ad3ba81224")
+        );
+
+        // The pack is kept with its index, not exploded into loose objects.
+        assert_eq!(2, fs::read_dir("./test_pack/objects/pack").unwrap().count());
+        assert!(!(Path::new("./test_pack/objects/93").exists()));
+
+        let last = pack.len() - 1;
+        pack[last] ^= 1;
+        assert!(index_pack(&repo, &pack).is_err());
+
+        // A thin pack is completed with the bases the repository has.
+        let thin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_pack/thin"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let delta_only = pack_of(&thin, &[ref_delta]);
+        assert!(index_pack(&thin, &delta_only).is_err());
+        assert_eq!(1, index_pack(&thin, &pack_of(&thin, &[base])).unwrap());
+        assert_eq!(2, index_pack(&thin, &delta_only).unwrap());
+        assert!(thin.has_object("93b9efc6aa5ef6f04964a34afc7210ad3ba81224"));
+
+        // Sizes are not trusted, neither past 64 bits nor for what is allocated.
+        let mut endless = vec![0xbf];
+        endless.extend([0xff; 10]);
+        endless.push(0x01);
+        assert!(index_pack(&repo, &pack_of(&repo, &[endless])).is_err());
+        let mut forged = entry(3, &[], b"hi");
+        forged[0] = 0xb0;
+        forged.splice(1..1, [0xff, 0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(index_pack(&repo, &pack_of(&repo, &[forged])).is_err());
+
+        // A pack is read from a stream up to its end.
+        let mut stream = [pack.as_slice(), b"rest"].concat();
+        let mut input = stream.as_slice();
+        assert_eq!(pack, read(&mut input, repo.object_format()).unwrap());
+        assert_eq!(b"rest", input);
+        stream.truncate(pack.len() - 1);
+        assert!(read(&mut stream.as_slice(), repo.object_format()).is_err());
+
+        // A pack written by lit is stored with the same objects.
+        let objects = ["93b9efc6aa5ef6f04964a34afc7210ad3ba81224".to_string()];
+        let pack = write(&repo, &objects).unwrap();
+        let copy = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_pack/copy"),
+            extensions: vec![],
+            object_format: None,
+        });
+        assert_eq!(1, index_pack(&copy, &pack).unwrap());
+        assert!(copy.has_object(&objects[0]));
+
+        fs::remove_dir_all("./test_pack").unwrap();
+    }
+}
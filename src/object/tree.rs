@@ -0,0 +1,210 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use super::format::{from_hex, to_hex, ObjectFormat};
+use super::Object;
+
+/// A single line of a tree: `mode SP name NUL sha`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeEntry {
+    /// The file mode, as written by git (e.g. `100644`, `100755`, `120000`, `40000`)
+    pub mode: String,
+    pub name: String,
+
+    /// Hex encoded object name
+    pub sha: String,
+}
+
+impl TreeEntry {
+    pub fn is_tree(&self) -> bool {
+        self.mode == "40000" || self.mode == "040000"
+    }
+
+    /// The type of the object the entry names: submodules are commits of another repository.
+    pub fn object_type(&self) -> &'static str {
+        match self.mode.as_str() {
+            "160000" => "commit",
+            _ if self.is_tree() => "tree",
+            _ => "blob",
+        }
+    }
+
+    /// Git sorts tree entries as if directories had a trailing slash.
+    fn sort_key(&self) -> Vec<u8> {
+        let mut key = self.name.as_bytes().to_vec();
+        if self.is_tree() {
+            key.push(b'/');
+        }
+        key
+    }
+}
+
+/// Trees associate blobs to paths: the content of every directory is stored as a tree.
+pub struct Tree {
+    fmt: String,
+    pub entries: Vec<TreeEntry>,
+}
+
+impl Tree {
+    pub fn new(mut entries: Vec<TreeEntry>) -> Self {
+        entries.sort_by_key(|entry| entry.sort_key());
+        Tree {
+            fmt: "tree".to_string(),
+            entries,
+        }
+    }
+
+    /// Parse a tree of a repository using format, whose entries hold raw object names
+    /// of format's length.
+    pub fn deserialize_with_format(data: &[u8], format: ObjectFormat) -> Self {
+        Self::new(Self::parse(data, format).unwrap_or_default())
+    }
+
+    /// Parse the raw tree body, returning None if it is malformed.
+    pub fn parse(data: &[u8], format: ObjectFormat) -> Option<Vec<TreeEntry>> {
+        let raw_len = format.raw_len();
+        let mut entries = Vec::new();
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let space = pos + data[pos..].iter().position(|byte| *byte == b' ')?;
+            let null = space + data[space..].iter().position(|byte| *byte == 0x00u8)?;
+
+            if null + 1 + raw_len > data.len() {
+                return None;
+            }
+
+            entries.push(TreeEntry {
+                mode: std::str::from_utf8(&data[pos..space]).ok()?.to_string(),
+                name: std::str::from_utf8(&data[space + 1..null])
+                    .ok()?
+                    .to_string(),
+                sha: to_hex(&data[null + 1..null + 1 + raw_len]),
+            });
+
+            pos = null + 1 + raw_len;
+        }
+
+        Some(entries)
+    }
+}
+
+impl Object for Tree {
+    fn to_string(&self) -> String {
+        self.entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{:0>6} {} {}\t{}",
+                    entry.mode,
+                    entry.object_type(),
+                    entry.sha,
+                    entry.name
+                )
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Trees of SHA-1 repositories, see `deserialize_with_format` for the others.
+    fn deserialize(data: &[u8]) -> Self
+    where
+        Self: Sized,
+    {
+        Self::deserialize_with_format(data, ObjectFormat::Sha1)
+    }
+
+    fn serialize(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+
+        self.entries.iter().for_each(|entry| {
+            data.extend_from_slice(entry.mode.trim_start_matches('0').as_bytes());
+            data.push(b' ');
+            data.extend_from_slice(entry.name.as_bytes());
+            data.push(0x00u8);
+            data.extend_from_slice(&from_hex(&entry.sha));
+        });
+
+        data
+    }
+
+    fn fmt(&self) -> &String {
+        &self.fmt
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Tree, TreeEntry};
+    use crate::object::format::ObjectFormat;
+    use crate::object::Object;
+
+    fn entry(mode: &str, name: &str, sha: &str) -> TreeEntry {
+        TreeEntry {
+            mode: mode.to_string(),
+            name: name.to_string(),
+            sha: sha.to_string(),
+        }
+    }
+
+    #[test]
+    pub fn test_tree_roundtrip() {
+        let tree = Tree::new(vec![
+            entry(
+                "100644",
+                "b.txt",
+                "9ca6e1d93dfc2343e4e404a6b742220b148649a0",
+            ),
+            entry("40000", "a", "4b825dc642cb6eb9a060e54bf8d69288fbee4904"),
+            entry("100755", "a.sh", "9ca6e1d93dfc2343e4e404a6b742220b148649a0"),
+        ]);
+
+        let parsed = Tree::deserialize(&tree.serialize());
+        assert_eq!(tree.entries, parsed.entries);
+    }
+
+    #[test]
+    pub fn test_tree_sha256() {
+        let sha = "6bd8e6c3d2ed8a1f4d2a3b1bc1ae8e5fae6c1c9b0b6f2f9d0b9c4b0b8c9f6a2d";
+        let tree = Tree::new(vec![entry("100644", "file", sha)]);
+        let data = tree.serialize();
+
+        assert_eq!("100644 file\0".len() + 32, data.len());
+        assert_eq!(
+            tree.entries,
+            Tree::deserialize_with_format(&data, ObjectFormat::Sha256).entries
+        );
+    }
+
+    #[test]
+    pub fn test_tree_sort_order() {
+        // `a.sh` sorts before the directory `a` because `.` < `/`
+        let tree = Tree::new(vec![
+            entry("40000", "a", "4b825dc642cb6eb9a060e54bf8d69288fbee4904"),
+            entry("100644", "a.sh", "9ca6e1d93dfc2343e4e404a6b742220b148649a0"),
+        ]);
+
+        assert_eq!("a.sh", tree.entries[0].name);
+        assert_eq!("a", tree.entries[1].name);
+    }
+
+    #[test]
+    pub fn test_tree_malformed() {
+        assert!(Tree::parse(b"100644 truncated\0abc", ObjectFormat::Sha1).is_none());
+    }
+}
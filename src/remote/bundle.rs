@@ -0,0 +1,411 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+use crate::error::{self, Error};
+use crate::object::commit::Commit;
+use crate::object::format::ObjectFormat;
+use crate::object::{operation, pack, Object};
+use crate::remote::{check_object_format, AdvertisedRef, Transport};
+use crate::repo::refs::check_ref_format;
+use crate::repo::Repo;
+use std::collections::{BTreeSet, HashSet};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+const SIGNATURE_V2: &str = "# v2 git bundle\n";
+const SIGNATURE_V3: &str = "# v3 git bundle\n";
+
+/// A bundle file: the refs it carries, the commits its objects build on, and a pack of
+/// these objects, which together let a repository be fetched from without a network.
+pub struct Bundle {
+    pub object_format: ObjectFormat,
+
+    /// The commits the receiving repository must have, with the subject of each
+    pub prerequisites: Vec<(String, String)>,
+
+    /// The refs of the bundle by name, with the object each points to
+    pub refs: Vec<(String, String)>,
+
+    /// The content of the file, and where its pack starts
+    data: Vec<u8>,
+    pack_start: usize,
+}
+
+impl Bundle {
+    /// Whether the file at path starts like a bundle.
+    pub fn is_bundle(path: &Path) -> bool {
+        let mut signature = [0; SIGNATURE_V2.len()];
+
+        File::open(path)
+            .and_then(|mut file| file.read_exact(&mut signature))
+            .is_ok()
+            && [SIGNATURE_V2, SIGNATURE_V3]
+                .iter()
+                .any(|expected| expected.as_bytes() == signature)
+    }
+
+    /// Read the bundle at path: its signature, the `@<capability>` lines of a version 3
+    /// bundle, the `-<sha> <subject>` lines of the prerequisites and the `<sha> <ref>` lines,
+    /// then an empty line before the pack.
+    pub fn open(path: &str) -> error::Result<Self> {
+        let bad = |reason: &str| {
+            Error::Remote(error::remote::Remote::BadBundle(
+                path.to_owned(),
+                reason.to_owned(),
+            ))
+        };
+
+        let data = fs::read(path).map_err(|e| bad(&e.to_string()))?;
+        let version_3 = if data.starts_with(SIGNATURE_V2.as_bytes()) {
+            false
+        } else if data.starts_with(SIGNATURE_V3.as_bytes()) {
+            true
+        } else {
+            return Err(bad("unknown signature"));
+        };
+
+        let mut bundle = Bundle {
+            object_format: ObjectFormat::Sha1,
+            prerequisites: Vec::new(),
+            refs: Vec::new(),
+            pack_start: SIGNATURE_V2.len(),
+            data: Vec::new(),
+        };
+
+        loop {
+            let end = data[bundle.pack_start..]
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map(|end| bundle.pack_start + end)
+                .ok_or_else(|| bad("truncated header"))?;
+            let line = std::str::from_utf8(&data[bundle.pack_start..end])
+                .map_err(|_| bad("malformed header"))?;
+            bundle.pack_start = end + 1;
+
+            if line.is_empty() {
+                break;
+            }
+
+            if let Some(capability) = line.strip_prefix('@').filter(|_| version_3) {
+                bundle.object_format = match capability.split_once('=') {
+                    Some(("object-format", name)) => {
+                        ObjectFormat::from_name(name).ok_or_else(|| bad(line))?
+                    }
+                    _ => return Err(bad(&format!("unsupported capability {}", capability))),
+                };
+                continue;
+            }
+
+            let (sha, rest) = line.split_once(' ').unwrap_or((line, ""));
+            match sha.strip_prefix('-') {
+                Some(sha) => bundle
+                    .prerequisites
+                    .push((sha.to_string(), rest.to_string())),
+                None => bundle.refs.push((rest.to_string(), sha.to_string())),
+            }
+        }
+
+        let malformed = bundle
+            .prerequisites
+            .iter()
+            .map(|(sha, _)| sha)
+            .chain(bundle.refs.iter().map(|(_, sha)| sha))
+            .any(|sha| !(bundle.object_format.is_full_name(sha)));
+        if malformed {
+            return Err(bad("malformed header"));
+        }
+        if let Some((name, _)) = bundle
+            .refs
+            .iter()
+            .find(|(name, _)| !(check_ref_format(name)))
+        {
+            return Err(bad(&format!("invalid ref name `{}`", name)));
+        }
+
+        bundle.data = data;
+        Ok(bundle)
+    }
+
+    /// The prerequisites repo lacks.
+    pub fn missing_prerequisites(&self, repo: &Repo) -> Vec<String> {
+        self.prerequisites
+            .iter()
+            .filter(|(sha, _)| !(repo.has_object(sha)))
+            .map(|(sha, _)| sha.clone())
+            .collect()
+    }
+
+    /// Store the objects of the bundle in repo, which must have its prerequisites.
+    /// Returns how many objects were stored.
+    pub fn unbundle(&self, repo: &Repo) -> error::Result<usize> {
+        check_object_format(repo, self.object_format)?;
+
+        let missing = self.missing_prerequisites(repo);
+        if !(missing.is_empty()) {
+            return Err(Error::Remote(error::remote::Remote::MissingPrerequisites(
+                missing,
+            )));
+        }
+
+        pack::index_pack(repo, &self.data[self.pack_start..])
+    }
+}
+
+/// Write a bundle of repo at path. revs are what `rev-list` takes: `<rev>` includes the
+/// commits reachable from rev, `^<rev>` excludes those reachable from rev, `<a>..<b>` is
+/// `^<a> <b>` and `--all` includes every ref. The refs among the included revs become
+/// the refs of the bundle. Returns how many objects were written.
+pub fn create(repo: &Repo, path: &Path, revs: &[String]) -> error::Result<usize> {
+    let resolve = |rev: &str| {
+        let rev = if rev.is_empty() { "HEAD" } else { rev };
+        repo.resolve_name(rev)
+            .ok_or_else(|| Error::Remote(error::remote::Remote::BadRevision(rev.to_owned())))
+    };
+
+    let mut refs = Vec::new();
+    let mut tips = Vec::new();
+    let mut excluded = Vec::new();
+    for rev in revs {
+        let included = if rev == "--all" {
+            refs.extend(
+                repo.ref_resolve("HEAD")
+                    .map(|sha| ("HEAD".to_string(), sha)),
+            );
+            refs.extend(repo.ref_list());
+            continue;
+        } else if let Some((from, to)) = rev.split_once("..") {
+            excluded.push(resolve(from)?);
+            to
+        } else if let Some(from) = rev.strip_prefix('^') {
+            excluded.push(resolve(from)?);
+            continue;
+        } else {
+            rev
+        };
+
+        let sha = resolve(included)?;
+        match repo.ref_full_name(if included.is_empty() {
+            "HEAD"
+        } else {
+            included
+        }) {
+            Some(name) => refs.push((name, sha)),
+            None => tips.push(sha),
+        }
+    }
+
+    let mut seen = HashSet::new();
+    refs.retain(|(name, _)| seen.insert(name.clone()));
+    if refs.is_empty() {
+        return Err(Error::Remote(error::remote::Remote::EmptyBundle));
+    }
+    tips.extend(refs.iter().map(|(_, sha)| sha.clone()));
+
+    let excluded: HashSet<String> = repo
+        .missing_objects(&excluded, &|_| false)?
+        .into_iter()
+        .collect();
+    let objects = repo.missing_objects(&tips, &|sha| excluded.contains(sha))?;
+
+    // The excluded commits which included ones build on.
+    let mut prerequisites = BTreeSet::new();
+    for sha in &objects {
+        if let Some((fmt, data)) = operation::read_raw(repo, sha) {
+            if fmt == "commit" {
+                prerequisites.extend(
+                    Commit::deserialize(&data)
+                        .parents()
+                        .into_iter()
+                        .filter(|parent| excluded.contains(*parent))
+                        .map(|parent| parent.to_string()),
+                );
+            }
+        }
+    }
+
+    let format = repo.object_format();
+    let mut data = match format {
+        ObjectFormat::Sha1 => SIGNATURE_V2.to_string(),
+        _ => format!("{}@object-format={}\n", SIGNATURE_V3, format.name()),
+    };
+    for sha in &prerequisites {
+        let subject = operation::read_raw(repo, sha)
+            .map(|(_, data)| Commit::deserialize(&data).kvlm.message)
+            .and_then(|message| message.lines().next().map(String::from))
+            .unwrap_or_default();
+        data.push_str(&format!("-{} {}\n", sha, subject));
+    }
+    for (name, sha) in &refs {
+        data.push_str(&format!("{} {}\n", sha, name));
+    }
+    data.push('\n');
+
+    let mut data = data.into_bytes();
+    data.extend(pack::write(repo, &objects)?);
+    fs::write(path, data)
+        .map_err(|e| Error::Remote(error::remote::Remote::Io(path.to_owned(), e.to_string())))?;
+
+    Ok(objects.len())
+}
+
+/// Fetching from a bundle file, as from a repository holding only its refs.
+pub struct BundleTransport {
+    bundle: Bundle,
+}
+
+impl BundleTransport {
+    pub fn open(path: &str) -> error::Result<Self> {
+        Ok(BundleTransport {
+            bundle: Bundle::open(path)?,
+        })
+    }
+}
+
+impl Transport for BundleTransport {
+    fn object_format(&self) -> ObjectFormat {
+        self.bundle.object_format
+    }
+
+    /// The refs of the bundle, its `HEAD` pointing to the first branch it matches.
+    fn refs(&mut self) -> error::Result<Vec<AdvertisedRef>> {
+        let refs = &self.bundle.refs;
+
+        Ok(refs
+            .iter()
+            .map(|(name, sha)| AdvertisedRef {
+                name: name.clone(),
+                sha: sha.clone(),
+                symref_target: (name == "HEAD")
+                    .then(|| {
+                        refs.iter()
+                            .find(|(branch, head)| branch.starts_with("refs/heads/") && head == sha)
+                            .map(|(branch, _)| branch.clone())
+                    })
+                    .flatten(),
+                peeled: None,
+            })
+            .collect())
+    }
+
+    /// A bundle has one pack, stored whole unless repo already has all the wants.
+    fn fetch_objects(&mut self, repo: &Repo, wants: &[String]) -> error::Result<usize> {
+        if wants.iter().all(|want| repo.has_object(want)) {
+            return Ok(0);
+        }
+
+        self.bundle.unbundle(repo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{create, Bundle};
+    use crate::commands::clone::CloneRepo;
+    use crate::commands::init::Init;
+    use crate::remote::fetch::{clone, fetch};
+    use crate::remote::{Remote, Status};
+    use crate::repo::Repo;
+    use crate::test_util::commit;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_bundle() {
+        let _ = fs::remove_dir_all("./test_bundle");
+        let init = |path: &str| {
+            Repo::create(&Init {
+                force: false,
+                bare: true,
+                path: path.to_string(),
+                extensions: vec![],
+                object_format: None,
+            })
+        };
+        let origin = init("./test_bundle/origin.git");
+        let first = commit(&origin, None, "one");
+        let second = commit(&origin, Some(&first), "two");
+        let revs = |revs: &[&str]| revs.iter().map(|rev| rev.to_string()).collect::<Vec<_>>();
+
+        assert_eq!(Some(first.clone()), origin.resolve_name("master~1"));
+        assert_eq!(Some(first.clone()), origin.resolve_name("master^"));
+        assert_eq!(Some(second.clone()), origin.resolve_name("HEAD^0"));
+        assert_eq!(None, origin.resolve_name("master~2"));
+
+        assert_eq!(
+            6,
+            create(
+                &origin,
+                Path::new("./test_bundle/full.bundle"),
+                &revs(&["HEAD", "master"])
+            )
+            .unwrap()
+        );
+        let repo = clone(&CloneRepo {
+            bare: false,
+            repository: "./test_bundle/full.bundle".to_string(),
+            directory: Some("./test_bundle/clone".to_string()),
+        })
+        .unwrap();
+        assert_eq!(
+            "two",
+            fs::read_to_string("./test_bundle/clone/file").unwrap()
+        );
+        assert_eq!(
+            Some("refs/heads/master".to_string()),
+            repo.ref_symbolic_target("HEAD")
+        );
+
+        // An incremental bundle needs the commit it builds on.
+        let third = commit(&origin, Some(&second), "three");
+        create(
+            &origin,
+            Path::new("./test_bundle/incremental.bundle"),
+            &revs(&["master~1..master"]),
+        )
+        .unwrap();
+        let bundle = Bundle::open("./test_bundle/incremental.bundle").unwrap();
+        assert_eq!(
+            vec![(second.clone(), "two".to_string())],
+            bundle.prerequisites
+        );
+        assert_eq!(
+            vec![("refs/heads/master".to_string(), third.clone())],
+            bundle.refs
+        );
+
+        let empty = init("./test_bundle/empty.git");
+        assert_eq!(vec![second.clone()], bundle.missing_prerequisites(&empty));
+        assert!(bundle.unbundle(&empty).is_err());
+
+        let mut remote = Remote::load(&repo, "origin").unwrap();
+        remote.url = "./test_bundle/incremental.bundle".to_string();
+        let updates = fetch(&repo, &remote, &[]).unwrap();
+        assert_eq!(Status::FastForward, updates[0].status);
+        assert_eq!(Some(third), repo.ref_resolve("refs/remotes/origin/master"));
+
+        // A bundle carries refs, a revision which is none leaves it empty.
+        assert!(create(
+            &origin,
+            Path::new("./test_bundle/empty.bundle"),
+            &revs(&["master~1"])
+        )
+        .is_err());
+
+        fs::remove_dir_all("./test_bundle").unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::Config;
+use crate::r#const::LIT_ASKPASS_ENV;
+use std::io::{BufRead, IsTerminal, Write};
+use std::process::{Command, Stdio};
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Credential {
+    pub username: String,
+    pub password: String,
+}
+
+impl Credential {
+    /// The value of an `Authorization` header using HTTP basic authentication.
+    pub fn basic_auth(&self) -> String {
+        let data = format!("{}:{}", self.username, self.password).into_bytes();
+        let mut encoded = String::from("Basic ");
+
+        for chunk in data.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, byte)| {
+                bits | (*byte as u32) << (16 - 8 * i)
+            });
+
+            for i in 0..4 {
+                if i <= chunk.len() {
+                    encoded.push(BASE64[(bits >> (18 - 6 * i) & 0x3f) as usize] as char);
+                } else {
+                    encoded.push('=');
+                }
+            }
+        }
+
+        encoded
+    }
+}
+
+/// What a credential is asked for, described to helpers as git does.
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub protocol: String,
+
+    /// The host, with its port if the url has one
+    pub host: String,
+    pub path: String,
+
+    /// The username given in the url
+    pub username: Option<String>,
+}
+
+impl Request {
+    /// The `key=value` lines git's credential helpers read, ending with an empty line.
+    fn describe(&self, credential: Option<&Credential>) -> String {
+        let mut lines = vec![
+            format!("protocol={}", self.protocol),
+            format!("host={}", self.host),
+            format!("path={}", self.path),
+        ];
+
+        match credential {
+            Some(credential) => {
+                lines.push(format!("username={}", credential.username));
+                lines.push(format!("password={}", credential.password));
+            }
+            None => lines.extend(
+                self.username
+                    .iter()
+                    .map(|username| format!("username={}", username)),
+            ),
+        }
+
+        lines.join("\n") + "\n\n"
+    }
+}
+
+/// Where credentials come from, and what learns whether they worked.
+pub trait CredentialHelper {
+    fn fill(&mut self, request: &Request) -> Option<Credential>;
+
+    /// The credential was accepted.
+    fn approve(&mut self, request: &Request, credential: &Credential);
+
+    /// The credential was refused.
+    fn reject(&mut self, request: &Request, credential: &Credential);
+}
+
+/// The helpers of `credential.helper`, in order, falling back on asking the user.
+/// A helper is run as git does: `!` starts a shell command, a path is run as is,
+/// and any other name is the `git credential-<name>` helper.
+pub struct ConfiguredHelpers {
+    helpers: Vec<String>,
+
+    /// The program asking for what helpers do not know, `LIT_ASKPASS` or `core.askPass`
+    askpass: Option<String>,
+}
+
+impl ConfiguredHelpers {
+    pub fn new(conf: Option<&Config>) -> Self {
+        let helpers = conf
+            .and_then(|conf| conf.get_all("credential.helper").ok())
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|entry| entry.value.clone())
+            .collect::<Vec<String>>();
+
+        // An empty value clears the helpers listed before it.
+        let helpers = match helpers.iter().rposition(|helper| helper.is_empty()) {
+            Some(last) => helpers[last + 1..].to_vec(),
+            None => helpers,
+        };
+
+        ConfiguredHelpers {
+            helpers,
+            askpass: std::env::var(LIT_ASKPASS_ENV)
+                .ok()
+                .or_else(|| conf?.get("core.askpass")),
+        }
+    }
+
+    /// Run helper with action, giving it input, returns what it printed.
+    fn run(helper: &str, action: &str, input: &str) -> Option<String> {
+        let mut command = if let Some(shell) = helper.strip_prefix('!') {
+            let mut command = Command::new("sh");
+            command.arg("-c").arg(format!("{} {}", shell, action));
+            command
+        } else if helper.contains('/') {
+            let mut command = Command::new(helper);
+            command.arg(action);
+            command
+        } else {
+            let mut command = Command::new("git");
+            command.arg(format!("credential-{}", helper)).arg(action);
+            command
+        };
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| warn!("Cannot run credential helper `{}`: {}", helper, e))
+            .ok()?;
+
+        let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+        let output = child.wait_with_output().ok()?;
+        Some(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Ask the user with the askpass program, or on the terminal.
+    fn prompt(&self, prompt: &str, secret: bool) -> Option<String> {
+        if let Some(askpass) = &self.askpass {
+            let output = Command::new(askpass).arg(prompt).output().ok()?;
+            return Some(
+                String::from_utf8_lossy(&output.stdout)
+                    .trim_end_matches(['\r', '\n'])
+                    .to_string(),
+            );
+        }
+
+        let stdin = std::io::stdin();
+        if !(stdin.is_terminal()) {
+            return None;
+        }
+
+        eprint!("{}: ", prompt);
+        let echo = |on: bool| {
+            let _ = Command::new("stty")
+                .arg(if on { "echo" } else { "-echo" })
+                .stdin(Stdio::inherit())
+                .status();
+        };
+
+        if secret {
+            echo(false);
+        }
+        let mut line = String::new();
+        let read = stdin.lock().read_line(&mut line);
+        if secret {
+            echo(true);
+            eprintln!();
+        }
+
+        read.ok()?;
+        Some(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+}
+
+impl CredentialHelper for ConfiguredHelpers {
+    fn fill(&mut self, request: &Request) -> Option<Credential> {
+        let mut username = request.username.clone();
+
+        for helper in &self.helpers {
+            let Some(output) = Self::run(helper, "get", &request.describe(None)) else {
+                continue;
+            };
+
+            let mut password = None;
+            for line in output.lines() {
+                match line.split_once('=') {
+                    Some(("username", value)) => username = Some(value.to_string()),
+                    Some(("password", value)) => password = Some(value.to_string()),
+                    _ => (),
+                }
+            }
+
+            if let (Some(username), Some(password)) = (&username, password) {
+                return Some(Credential {
+                    username: username.clone(),
+                    password,
+                });
+            }
+        }
+
+        let url = format!("{}://{}", request.protocol, request.host);
+        let username = match username {
+            Some(username) => username,
+            None => self.prompt(&format!("Username for '{}'", url), false)?,
+        };
+        let password = self.prompt(
+            &format!(
+                "Password for '{}://{}@{}'",
+                request.protocol, username, request.host
+            ),
+            true,
+        )?;
+
+        Some(Credential { username, password })
+    }
+
+    fn approve(&mut self, request: &Request, credential: &Credential) {
+        for helper in &self.helpers {
+            Self::run(helper, "store", &request.describe(Some(credential)));
+        }
+    }
+
+    fn reject(&mut self, request: &Request, credential: &Credential) {
+        for helper in &self.helpers {
+            Self::run(helper, "erase", &request.describe(Some(credential)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Credential;
+
+    #[test]
+    pub fn test_basic_auth() {
+        let credential = |username: &str, password: &str| Credential {
+            username: username.to_string(),
+            password: password.to_string(),
+        };
+
+        assert_eq!(
+            "Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==",
+            credential("Aladdin", "open sesame").basic_auth()
+        );
+        assert_eq!("Basic bGl0OnM=", credential("lit", "s").basic_auth());
+        assert_eq!("Basic bGl0OnNl", credential("lit", "se").basic_auth());
+    }
+}
@@ -0,0 +1,180 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error, Log};
+use crate::remote::pkt_line::{self, PktReader};
+use crate::remote::server;
+use crate::repo::Repo;
+use std::io::{BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+/// The port of the `git://` protocol
+pub const DEFAULT_PORT: u16 = 9418;
+
+/// Serve the repositories under root on the `git://` protocol, a connection per thread.
+/// Pushing is refused unless receive_pack.
+pub fn serve(listener: TcpListener, root: PathBuf, receive_pack: bool) {
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("Cannot accept a connection: {}", e);
+                continue;
+            }
+        };
+
+        let root = root.clone();
+        thread::spawn(move || {
+            let peer = stream
+                .peer_addr()
+                .map_or("unknown".to_string(), |addr| addr.to_string());
+
+            if let Err(e) = handle(stream, &root, receive_pack) {
+                warn!("{}: {}", peer, e.fmt());
+            }
+        });
+    }
+}
+
+/// The repository a request names: a path under root, tried with `.git` as git does.
+fn find_repo(root: &Path, path: &str) -> Option<Repo> {
+    let relative = Path::new(path.trim_start_matches('/'));
+    if relative
+        .components()
+        .any(|component| !(matches!(component, Component::Normal(_))))
+    {
+        return None;
+    }
+
+    let path = root.join(relative);
+    Repo::repo_open(&path).or_else(|| Repo::repo_open(&path.with_extension("git")))
+}
+
+/// Answer the request starting a connection: `<service> <path>\0host=<host>\0`, followed
+/// by `\0version=2\0` when the client speaks protocol version 2.
+fn handle(stream: TcpStream, root: &Path, receive_pack: bool) -> error::Result<()> {
+    let transport = |e: std::io::Error| {
+        Error::Remote(error::remote::Remote::Transport(
+            "client".to_string(),
+            e.to_string(),
+        ))
+    };
+    let mut input = BufReader::new(stream.try_clone().map_err(transport)?);
+    let mut output = stream;
+
+    let request = PktReader::new(&mut input)
+        .read()
+        .map_err(transport)?
+        .line()
+        .unwrap_or_default();
+    let mut fields = request.split('\0');
+    let (service, path) = fields
+        .next()
+        .and_then(|command| command.split_once(' '))
+        .unwrap_or_default();
+    let version_2 = fields.any(|param| param == "version=2");
+    debug!("{} {}", service, path);
+
+    let refuse = |output: &mut TcpStream, message: &str| {
+        pkt_line::write_line(output, &format!("ERR {}", message))
+            .and_then(|_| output.flush())
+            .map_err(transport)
+    };
+
+    let repo = match find_repo(root, path) {
+        Some(repo) => repo,
+        None => return refuse(&mut output, &format!("no such repository: {}", path)),
+    };
+
+    match service {
+        "git-upload-pack" if version_2 => server::upload_pack(&repo, &mut input, &mut output),
+        "git-upload-pack" => refuse(&mut output, "only protocol version 2 is supported"),
+        "git-receive-pack" if receive_pack => server::receive_pack(&repo, &mut input, &mut output),
+        "git-receive-pack" => refuse(&mut output, "pushing is not enabled"),
+        _ => refuse(&mut output, &format!("unknown service {}", service)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::serve;
+    use crate::commands::clone::CloneRepo;
+    use crate::commands::init::Init;
+    use crate::remote::fetch::{clone, fetch};
+    use crate::remote::push::push;
+    use crate::remote::{Remote, Status};
+    use crate::repo::Repo;
+    use crate::test_util::commit;
+    use std::fs;
+    use std::net::TcpListener;
+    use std::thread;
+
+    #[test]
+    pub fn test_daemon() {
+        let _ = fs::remove_dir_all("./test_daemon");
+        let origin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_daemon/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let first = commit(&origin, None, "one");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("git://{}", listener.local_addr().unwrap());
+        let root = fs::canonicalize("./test_daemon").unwrap();
+        thread::spawn(move || serve(listener, root, true));
+
+        // The `.git` of the repository may be left out.
+        let repo = clone(&CloneRepo {
+            bare: false,
+            repository: format!("{}/origin", url),
+            directory: Some("./test_daemon/clone".to_string()),
+        })
+        .unwrap();
+        assert_eq!(
+            "one",
+            fs::read_to_string("./test_daemon/clone/file").unwrap()
+        );
+        assert_eq!(Some(first.clone()), repo.ref_resolve("refs/heads/master"));
+
+        let second = commit(&repo, Some(&first), "two");
+        let remote = Remote::load(&repo, "origin").unwrap();
+        let updates = push(&repo, &remote, &[], false).unwrap();
+        assert_eq!(Status::FastForward, updates[0].status);
+        assert_eq!(
+            Some(second.clone()),
+            origin.ref_resolve("refs/heads/master")
+        );
+
+        let third = commit(&origin, Some(&second), "three");
+        let updates = fetch(&repo, &remote, &[]).unwrap();
+        assert_eq!(Status::FastForward, updates[0].status);
+        assert_eq!(Some(third), repo.ref_resolve("refs/remotes/origin/master"));
+
+        // Nothing outside the served directory is reachable.
+        let mut outside = Remote::load(&repo, "origin").unwrap();
+        outside.url = format!("{}/../test_daemon/origin.git", url);
+        assert!(fetch(&repo, &outside, &[]).is_err());
+
+        fs::remove_dir_all("./test_daemon").unwrap();
+    }
+}
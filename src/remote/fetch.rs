@@ -0,0 +1,291 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::commands::clone::CloneRepo;
+use crate::commands::init::Init;
+use crate::config::file::ConfigFile;
+use crate::config::Config;
+use crate::error::{self, Error};
+use crate::object::format::ObjectFormat;
+use crate::remote::refspec::{is_excluded, Refspec};
+use crate::remote::{
+    check_advertised, check_object_format, connect, expand_ref, RefUpdate, Remote, Transport,
+};
+use crate::repo::Repo;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Fetch the refs matched by refspecs (the configured ones if empty) and their objects,
+/// updating the local refs they map to. Tags pointing into the fetched history follow.
+pub fn fetch(repo: &Repo, remote: &Remote, refspecs: &[Refspec]) -> error::Result<Vec<RefUpdate>> {
+    let mut transport = connect(&remote.url, repo.conf.as_ref())?;
+    fetch_with(repo, remote, transport.as_mut(), refspecs)
+}
+
+fn fetch_with(
+    repo: &Repo,
+    remote: &Remote,
+    transport: &mut dyn Transport,
+    refspecs: &[Refspec],
+) -> error::Result<Vec<RefUpdate>> {
+    check_object_format(repo, transport.object_format())?;
+
+    // Negative refspecs alone narrow down the configured ones.
+    let refspecs: Vec<Refspec> = if refspecs.iter().all(|refspec| refspec.negative) {
+        remote.fetch.iter().chain(refspecs).cloned().collect()
+    } else {
+        refspecs.to_vec()
+    };
+
+    let advertised = transport.refs()?;
+    check_advertised(
+        &remote.url,
+        advertised
+            .iter()
+            .flat_map(|advertised| [Some(&advertised.name), advertised.symref_target.as_ref()])
+            .flatten(),
+    )?;
+    let remote_refs: BTreeMap<String, String> = advertised
+        .iter()
+        .map(|advertised| (advertised.name.clone(), advertised.sha.clone()))
+        .collect();
+
+    // (remote ref, object, local ref, force)
+    let mut wanted: Vec<(String, String, Option<String>, bool)> = Vec::new();
+    for refspec in refspecs.iter().filter(|refspec| !(refspec.negative)) {
+        if refspec.is_wildcard() {
+            remote_refs
+                .iter()
+                .filter_map(|(name, sha)| Some((name, sha, refspec.map(name)?)))
+                .for_each(|(name, sha, dst)| {
+                    wanted.push((name.clone(), sha.clone(), Some(dst), refspec.force))
+                });
+        } else {
+            let (name, sha) = expand_ref(&remote_refs, &refspec.src).ok_or(Error::Remote(
+                error::remote::Remote::NoMatchingRef(refspec.src.clone()),
+            ))?;
+
+            // Without a destination, the remote-tracking ref is still updated.
+            let dst = match &refspec.dst {
+                Some(dst) if dst.starts_with("refs/") => Some(dst.clone()),
+                Some(dst) => Some(format!("refs/heads/{}", dst)),
+                None => remote.fetch.iter().find_map(|fetch| fetch.map(name)),
+            };
+            wanted.push((name.clone(), sha.clone(), dst, refspec.force));
+        }
+    }
+    wanted.retain(|(name, _, _, _)| !(is_excluded(&refspecs, name)));
+
+    let tips: Vec<String> = wanted.iter().map(|(_, sha, _, _)| sha.clone()).collect();
+    let count = transport.fetch_objects(repo, &tips)?;
+    debug!("{} objects received from {}", count, remote.name);
+
+    let mut updates: Vec<RefUpdate> = wanted
+        .iter()
+        .filter_map(|(src, sha, dst, force)| {
+            Some(RefUpdate::new(
+                repo,
+                src,
+                dst.as_ref()?,
+                Some(sha.clone()),
+                *force,
+            ))
+        })
+        .collect();
+
+    let follows: Vec<(&String, &String)> = advertised
+        .iter()
+        .filter(|tag| {
+            tag.name.starts_with("refs/tags/")
+                && repo.ref_resolve(&tag.name).is_none()
+                && !(updates.iter().any(|update| update.dst == tag.name))
+                && repo.has_object(tag.peeled.as_ref().unwrap_or(&tag.sha))
+        })
+        .map(|tag| (&tag.name, &tag.sha))
+        .collect();
+
+    if !(follows.is_empty()) {
+        let tags: Vec<String> = follows.iter().map(|(_, sha)| sha.to_string()).collect();
+        transport.fetch_objects(repo, &tags)?;
+        follows.iter().for_each(|(name, sha)| {
+            updates.push(RefUpdate::new(
+                repo,
+                name,
+                name,
+                Some(sha.to_string()),
+                false,
+            ))
+        });
+    }
+
+    updates.iter().try_for_each(|update| update.apply(repo))?;
+    Ok(updates)
+}
+
+/// The directory `lit clone` creates by default: the name of the source repository,
+/// without the `.git` suffix of bare repositories or the `.bundle` suffix of bundles.
+pub fn clone_directory(url: &str) -> PathBuf {
+    let url = url.strip_prefix("file://").unwrap_or(url);
+    let url = match url.strip_prefix("ext::") {
+        Some(command) => command.rsplit(' ').next().unwrap_or(command),
+        None => url,
+    };
+
+    let path = fs::canonicalize(url).unwrap_or(PathBuf::from(url));
+    let path = match path.file_name().and_then(|name| name.to_str()) {
+        Some(".git") | Some(".lit") => path.parent().unwrap_or(&path).to_owned(),
+        _ => path,
+    };
+
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or("repository");
+    PathBuf::from(
+        name.strip_suffix(".git")
+            .or(name.strip_suffix(".lit"))
+            .or(name.strip_suffix(".bundle"))
+            .unwrap_or(name),
+    )
+}
+
+/// Create a repository at the destination of args, with the source as its `origin` remote,
+/// fetch everything, and check out the branch the source's `HEAD` points to.
+pub fn clone(args: &CloneRepo) -> error::Result<Repo> {
+    let mut transport = connect(&args.repository, Some(&Config::load(None, None)?))?;
+    let url = source_url(&args.repository);
+    let path = args
+        .directory
+        .as_ref()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| clone_directory(&args.repository));
+
+    if path.exists() && path.read_dir().map_or(true, |mut dir| dir.next().is_some()) {
+        return Err(Error::Worktree(error::worktree::Worktree::AlreadyExists(
+            path,
+        )));
+    }
+
+    let object_format = transport.object_format();
+    let repo = Repo::create(&Init {
+        force: false,
+        bare: args.bare,
+        path: path.to_str().unwrap().to_string(),
+        extensions: vec![],
+        object_format: (object_format != ObjectFormat::Sha1)
+            .then(|| object_format.name().to_string()),
+    });
+
+    let advertised = transport.refs()?;
+    check_advertised(
+        &url,
+        advertised
+            .iter()
+            .flat_map(|advertised| [Some(&advertised.name), advertised.symref_target.as_ref()])
+            .flatten(),
+    )?;
+    let head = advertised.iter().find(|head| head.name == "HEAD");
+    let branch = head
+        .and_then(|head| head.symref_target.as_ref())
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .map(|branch| branch.to_string());
+    let head = head.map(|head| head.sha.clone());
+
+    // A bare clone mirrors the branches, a normal one tracks them as remote branches.
+    let heads = if args.bare {
+        "+refs/heads/*:refs/heads/*".to_string()
+    } else {
+        "+refs/heads/*:refs/remotes/origin/*".to_string()
+    };
+
+    let mut conf = ConfigFile::open(&repo.common_dir.join("config"))?;
+    conf.set("remote.origin.url", &url)?;
+    if !(args.bare) {
+        conf.set("remote.origin.fetch", &heads)?;
+        if let Some(branch) = &branch {
+            conf.set(&format!("branch.{}.remote", branch), "origin")?;
+            conf.set(
+                &format!("branch.{}.merge", branch),
+                &format!("refs/heads/{}", branch),
+            )?;
+        }
+    }
+    conf.save()?;
+
+    let repo = Repo::repo_open(&path).unwrap();
+    let remote = Remote::load(&repo, "origin")?;
+    fetch_with(
+        &repo,
+        &remote,
+        transport.as_mut(),
+        &[
+            Refspec::parse(&heads)?,
+            Refspec::parse("+refs/tags/*:refs/tags/*")?,
+        ],
+    )?;
+
+    match (&branch, &head) {
+        (Some(branch), Some(sha)) => {
+            let local = format!("refs/heads/{}", branch);
+            repo.ref_update(&local, sha)?;
+            repo.ref_update_symbolic("HEAD", &local)?;
+
+            if !(args.bare) {
+                repo.ref_update_symbolic(
+                    "refs/remotes/origin/HEAD",
+                    &format!("refs/remotes/origin/{}", branch),
+                )?;
+            }
+        }
+        (Some(branch), None) => {
+            warn!("You appear to have cloned an empty repository.");
+            repo.ref_update_symbolic("HEAD", &format!("refs/heads/{}", branch))?;
+        }
+        (None, Some(sha)) => repo.ref_update("HEAD", sha)?,
+        (None, None) if advertised.is_empty() => {
+            warn!("You appear to have cloned an empty repository.")
+        }
+        (None, None) => warn!("The remote has no HEAD, nothing is checked out."),
+    }
+
+    if let (false, Some(tree)) = (args.bare, head.and_then(|sha| repo.resolve_tree(&sha))) {
+        repo.checkout_tree(&tree, false, &mut |progress| {
+            debug!(
+                "checkout {}/{} {}",
+                progress.completed,
+                progress.total,
+                progress.change.path()
+            )
+        })?;
+    }
+
+    Ok(repo)
+}
+
+/// Local sources are recorded with their absolute path, so that the clone
+/// keeps working when run from another directory.
+fn source_url(url: &str) -> String {
+    if url.contains("://") || url.starts_with("ext::") {
+        return url.to_owned();
+    }
+
+    fs::canonicalize(url)
+        .map(|path| path.to_str().unwrap().to_string())
+        .unwrap_or(url.to_owned())
+}
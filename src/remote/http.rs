@@ -0,0 +1,422 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::r#const::project_info;
+use crate::remote::credential::{Credential, CredentialHelper, Request};
+use crate::remote::protocol::Channel;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+
+/// git's smart HTTP: the server advertises at `<url>/info/refs?service=<service>`, then
+/// each message is the body of a POST to `<url>/<service>` and the response its body.
+pub struct HttpChannel {
+    /// The url of the repository, without credentials nor trailing slash
+    url: String,
+    service: String,
+    protocol_v2: bool,
+    agent: ureq::Agent,
+
+    /// What credentials are asked for, described from url
+    request: Request,
+    helper: Box<dyn CredentialHelper>,
+    credential: Option<Credential>,
+
+    /// Whether the credential comes from the helper, and has not been approved yet
+    filled: bool,
+    response: Box<dyn Read + Send + Sync>,
+}
+
+/// Split the credentials out of url: the url without them, what to ask credentials for,
+/// and the password given in url.
+fn parse_url(url: &str) -> Option<(String, Request, Option<String>)> {
+    let (protocol, rest) = url.split_once("://")?;
+    let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+    let (userinfo, host) = match authority.rsplit_once('@') {
+        Some((userinfo, host)) => (Some(userinfo), host),
+        None => (None, authority),
+    };
+    let (username, password) = match userinfo.map(|userinfo| userinfo.split_once(':')) {
+        Some(Some((username, password))) => (Some(username), Some(password.to_string())),
+        Some(None) => (userinfo, None),
+        None => (None, None),
+    };
+
+    let path = path.trim_end_matches('/');
+    Some((
+        format!("{}://{}/{}", protocol, host, path)
+            .trim_end_matches('/')
+            .to_string(),
+        Request {
+            protocol: protocol.to_string(),
+            host: host.to_string(),
+            path: path.to_string(),
+            username: username.map(String::from),
+        },
+        password,
+    ))
+}
+
+impl HttpChannel {
+    /// Request the advertisement of service, which the response then holds.
+    pub fn open(
+        url: &str,
+        service: &str,
+        protocol_v2: bool,
+        helper: Box<dyn CredentialHelper>,
+    ) -> error::Result<Self> {
+        let (base, request, password) = parse_url(url).ok_or(Error::Remote(
+            error::remote::Remote::UnsupportedUrl(url.to_owned()),
+        ))?;
+
+        let mut channel = HttpChannel {
+            credential: request
+                .username
+                .clone()
+                .zip(password)
+                .map(|(username, password)| Credential { username, password }),
+            url: base,
+            service: service.to_owned(),
+            protocol_v2,
+            agent: ureq::AgentBuilder::new()
+                .user_agent(&format!(
+                    "{}/{}",
+                    project_info::NAME,
+                    env!("CARGO_PKG_VERSION")
+                ))
+                .build(),
+            request,
+            helper,
+            filled: false,
+            response: Box::new(std::io::empty()),
+        };
+
+        let response = channel.call(None)?;
+        let expected = format!("application/x-{}-advertisement", service);
+        if response.content_type() != expected {
+            return Err(Error::Remote(error::remote::Remote::Protocol(
+                channel.url.clone(),
+                "not a smart HTTP server, which lit requires".to_string(),
+            )));
+        }
+
+        channel.response = response.into_reader();
+        Ok(channel)
+    }
+
+    fn transport_error(&self, reason: String) -> Error {
+        Error::Remote(error::remote::Remote::Transport(self.url.clone(), reason))
+    }
+
+    /// GET the advertisement, or POST body to the service. The credential helper
+    /// is asked once when the server wants authentication, and told how it went.
+    fn call(&mut self, body: Option<&[u8]>) -> error::Result<ureq::Response> {
+        loop {
+            let mut request = match body {
+                Some(_) => self
+                    .agent
+                    .post(&format!("{}/{}", self.url, self.service))
+                    .set(
+                        "Content-Type",
+                        &format!("application/x-{}-request", self.service),
+                    )
+                    .set("Accept", &format!("application/x-{}-result", self.service)),
+                None => self
+                    .agent
+                    .get(&format!("{}/info/refs?service={}", self.url, self.service)),
+            };
+            if self.protocol_v2 {
+                request = request.set("Git-Protocol", "version=2");
+            }
+            if let Some(credential) = &self.credential {
+                request = request.set("Authorization", &credential.basic_auth());
+            }
+
+            // Packs do not shrink, only the requests of fetches are worth compressing.
+            let result = match body {
+                Some(body) if self.service == "git-upload-pack" => {
+                    let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+                    gzip.write_all(body).unwrap();
+                    request
+                        .set("Content-Encoding", "gzip")
+                        .send_bytes(&gzip.finish().unwrap())
+                }
+                Some(body) => request.send_bytes(body),
+                None => request.call(),
+            };
+
+            match result {
+                Ok(response) => {
+                    if let (true, Some(credential)) = (self.filled, &self.credential) {
+                        self.helper.approve(&self.request, credential);
+                        self.filled = false;
+                    }
+                    return Ok(response);
+                }
+                Err(ureq::Error::Status(401, _)) if !(self.filled) => {
+                    self.credential =
+                        Some(self.helper.fill(&self.request).ok_or(Error::Remote(
+                            error::remote::Remote::AuthenticationFailed(self.url.clone()),
+                        ))?);
+                    self.filled = true;
+                }
+                Err(ureq::Error::Status(401, _)) => {
+                    if let Some(credential) = &self.credential {
+                        self.helper.reject(&self.request, credential);
+                    }
+                    return Err(Error::Remote(error::remote::Remote::AuthenticationFailed(
+                        self.url.clone(),
+                    )));
+                }
+                Err(ureq::Error::Status(code, response)) => {
+                    return Err(self.transport_error(format!(
+                        "HTTP {} {}",
+                        code,
+                        response.status_text()
+                    )))
+                }
+                Err(ureq::Error::Transport(e)) => return Err(self.transport_error(e.to_string())),
+            }
+        }
+    }
+}
+
+impl Channel for HttpChannel {
+    fn send(&mut self, message: &[u8]) -> error::Result<()> {
+        let response = self.call(Some(message))?;
+        self.response = response.into_reader();
+        Ok(())
+    }
+
+    fn response(&mut self) -> &mut dyn Read {
+        &mut self.response
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_url;
+    use crate::commands::clone::CloneRepo;
+    use crate::commands::init::Init;
+    use crate::config::file::ConfigFile;
+    use crate::remote::push::push;
+    use crate::remote::{fetch, Remote, Status};
+    use crate::repo::Repo;
+    use crate::test_util::commit;
+    use std::fs;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::path::{Path, PathBuf};
+    use std::process::{Command, Stdio};
+    use std::thread;
+
+    /// `lit:secret`, the only credential the test server accepts
+    const AUTHORIZATION: &str = "Basic bGl0OnNlY3JldA==";
+
+    /// Answer one request with `git http-backend`, pushing requires authentication.
+    fn handle(stream: TcpStream, root: &Path) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        let mut fields = line.split(' ');
+        let (method, target) = (fields.next().unwrap(), fields.next().unwrap());
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+
+        let mut headers = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            match line.trim_end().split_once(": ") {
+                Some((name, value)) => headers.push((name.to_lowercase(), value.to_string())),
+                None => break,
+            }
+        }
+        let header = |name: &str| {
+            headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.clone())
+                .unwrap_or_default()
+        };
+
+        let mut body = vec![0; header("content-length").parse().unwrap_or(0)];
+        reader.read_exact(&mut body).unwrap();
+
+        let mut stream = stream;
+        let authorized = header("authorization") == AUTHORIZATION;
+        if (path.ends_with("git-receive-pack") || query.contains("git-receive-pack"))
+            && !(authorized)
+        {
+            stream
+                .write_all(b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"lit\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            return;
+        }
+
+        let mut backend = Command::new("git")
+            .arg("http-backend")
+            .env("GIT_PROJECT_ROOT", root)
+            .env("GIT_HTTP_EXPORT_ALL", "1")
+            .env("PATH_INFO", path)
+            .env("QUERY_STRING", query)
+            .env("REQUEST_METHOD", method)
+            .env("CONTENT_TYPE", header("content-type"))
+            .env("CONTENT_LENGTH", body.len().to_string())
+            .env("HTTP_CONTENT_ENCODING", header("content-encoding"))
+            .env("GIT_PROTOCOL", header("git-protocol"))
+            .env("REMOTE_USER", if authorized { "lit" } else { "" })
+            .env("REMOTE_ADDR", "127.0.0.1")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        backend.stdin.take().unwrap().write_all(&body).unwrap();
+        let output = backend.wait_with_output().unwrap().stdout;
+
+        // A CGI response: headers, an empty line, then the body.
+        let split = output
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .unwrap();
+        let cgi_headers = String::from_utf8_lossy(&output[..split]).to_string();
+        let status = cgi_headers
+            .lines()
+            .find_map(|line| line.strip_prefix("Status: "))
+            .unwrap_or("200 OK");
+
+        let content = &output[split + 4..];
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\n{}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            cgi_headers,
+            content.len()
+        )
+        .unwrap();
+        stream.write_all(content).unwrap();
+    }
+
+    /// Serve the repositories under root over smart HTTP, returns the url of root.
+    fn serve(root: PathBuf) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                handle(stream.unwrap(), &root);
+            }
+        });
+        url
+    }
+
+    #[test]
+    pub fn test_parse_url() {
+        let (url, request, password) = parse_url("https://me:pw@host:8080/a/repo.git/").unwrap();
+        assert_eq!("https://host:8080/a/repo.git", url);
+        assert_eq!(
+            ("https", "host:8080", "a/repo.git", Some("me")),
+            (
+                request.protocol.as_str(),
+                request.host.as_str(),
+                request.path.as_str(),
+                request.username.as_deref()
+            )
+        );
+        assert_eq!(Some("pw".to_string()), password);
+    }
+
+    /// Against a server running the `git http-backend` of git, which must be installed.
+    #[test]
+    pub fn test_smart_http() {
+        let _ = fs::remove_dir_all("./test_http");
+        let origin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_http/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let first = commit(&origin, None, "one");
+        let url = format!(
+            "{}/origin.git",
+            serve(fs::canonicalize("./test_http").unwrap())
+        );
+
+        let repo = fetch::clone(&CloneRepo {
+            bare: false,
+            repository: url.clone(),
+            directory: Some("./test_http/clone".to_string()),
+        })
+        .unwrap();
+        assert_eq!("one", fs::read_to_string("./test_http/clone/file").unwrap());
+        assert_eq!(Some(first.clone()), repo.ref_resolve("refs/heads/master"));
+
+        // Pushing asks the credential helper, whose credential must be right.
+        let second = commit(&repo, Some(&first), "two");
+        let remote = Remote::load(&repo, "origin").unwrap();
+        let helper = |password: &str| {
+            let mut conf = ConfigFile::open(&repo.common_dir.join("config")).unwrap();
+            conf.set(
+                "credential.helper",
+                &format!(
+                    "!f() {{ test $1 = get && echo username=lit && echo password={}; }}; f",
+                    password
+                ),
+            )
+            .unwrap();
+            conf.save().unwrap();
+            Repo::repo_open(&repo.worktree).unwrap()
+        };
+
+        assert!(push(&helper("wrong"), &remote, &[], false).is_err());
+        let updates = push(&helper("secret"), &remote, &[], false).unwrap();
+        assert_eq!(Status::FastForward, updates[0].status);
+        assert_eq!(
+            Some(second.clone()),
+            origin.ref_resolve("refs/heads/master")
+        );
+        assert_eq!(
+            Some(second.clone()),
+            repo.ref_resolve("refs/remotes/origin/master")
+        );
+
+        // Without the new commit of the origin, the update is not even sent.
+        let third = commit(&origin, Some(&second), "three");
+        let repo = helper("secret");
+        commit(&repo, Some(&first), "diverged");
+        let updates = push(&repo, &remote, &[], false).unwrap();
+        assert_eq!(
+            Status::Rejected("fetch first".to_string()),
+            updates[0].status
+        );
+
+        // The server may refuse an update it receives.
+        let mut conf = ConfigFile::open(&origin.common_dir.join("config")).unwrap();
+        conf.set("receive.denynonfastforwards", "true").unwrap();
+        conf.save().unwrap();
+        let updates = push(&repo, &remote, &[], true).unwrap();
+        assert!(updates[0].is_rejected());
+        assert_eq!(Some(third.clone()), origin.ref_resolve("refs/heads/master"));
+
+        let updates = fetch::fetch(&repo, &remote, &[]).unwrap();
+        assert_eq!(Some(third), updates[0].new);
+
+        fs::remove_dir_all("./test_http").unwrap();
+    }
+}
@@ -0,0 +1,303 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::object::database::loose::LooseDatabase;
+use crate::object::format::ObjectFormat;
+use crate::remote::{AdvertisedRef, PushTransport, RefUpdate, Status, Transport};
+use crate::repo::Repo;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+
+/// The transport for remotes which are paths to repositories on a local or shared filesystem.
+/// Objects go from one object directory to the other, and refs are read and written directly.
+/// A `file://` url names the same repository as its path.
+pub fn open(url: &str) -> error::Result<Repo> {
+    let path = url.strip_prefix("file://").unwrap_or(url);
+
+    Repo::repo_open(Path::new(path)).ok_or(Error::Remote(error::remote::Remote::NotARepository(
+        url.to_owned(),
+    )))
+}
+
+/// Fetching from and pushing to a local repository reads and writes its refs and objects
+/// directly.
+pub struct LocalTransport {
+    source: Repo,
+}
+
+impl LocalTransport {
+    pub fn open(url: &str) -> error::Result<Self> {
+        Ok(LocalTransport { source: open(url)? })
+    }
+}
+
+impl Transport for LocalTransport {
+    fn object_format(&self) -> ObjectFormat {
+        self.source.object_format()
+    }
+
+    fn refs(&mut self) -> error::Result<Vec<AdvertisedRef>> {
+        Ok(advertised_refs(&self.source)
+            .into_iter()
+            .map(|(name, sha)| AdvertisedRef {
+                symref_target: self.source.ref_symbolic_target(&name),
+                peeled: self.source.peel(&sha).filter(|peeled| *peeled != sha),
+                name,
+                sha,
+            })
+            .collect())
+    }
+
+    fn fetch_objects(&mut self, repo: &Repo, wants: &[String]) -> error::Result<usize> {
+        copy_objects(&self.source, repo, wants)
+    }
+}
+
+/// The refs a repository offers: its `HEAD` and everything under `refs/`.
+pub fn advertised_refs(repo: &Repo) -> BTreeMap<String, String> {
+    let mut refs = repo.ref_list();
+    if let Some(head) = repo.ref_resolve("HEAD") {
+        refs.insert("HEAD".to_string(), head);
+    }
+    refs
+}
+
+/// Copy the objects reachable from tips that to lacks, hard-linking their loose files and
+/// the packs which have them when both repositories are on the same device. Objects are
+/// never decompressed, except those from alternates. Returns how many objects were transferred.
+fn copy_objects(from: &Repo, to: &Repo, tips: &[String]) -> error::Result<usize> {
+    let missing = from.missing_objects(tips, &|sha| to.has_object(sha))?;
+    let format = from.object_format();
+    let loose = LooseDatabase::new(&from.common_dir.join("objects"), format);
+    let target = LooseDatabase::new(&to.common_dir.join("objects"), format);
+    let packs = from.pack_database();
+    let mut pack_paths = BTreeSet::new();
+
+    for sha in &missing {
+        if let Some(source) = loose.object_path(sha).filter(|path| path.is_file()) {
+            link_or_copy(&source, &target.object_path(sha).unwrap())?;
+        } else if let Some(pack) = packs.pack_path(sha) {
+            pack_paths.insert(pack);
+        } else {
+            let (fmt, data) =
+                from.objects
+                    .read(sha)
+                    .ok_or(Error::Object(error::object::Object::NotFound(
+                        sha.to_owned(),
+                    )))?;
+            to.objects.write(&fmt, &data)?;
+        }
+    }
+
+    // The index goes last: a pack is only looked at once it has one.
+    let pack_dir = to.common_dir.join("objects").join("pack");
+    for pack in pack_paths {
+        for extension in ["pack", "idx"] {
+            let source = pack.with_extension(extension);
+            link_or_copy(&source, &pack_dir.join(source.file_name().unwrap()))?;
+        }
+    }
+
+    Ok(missing.len())
+}
+
+/// Hard-link source to target, or copy it when they are on different devices.
+fn link_or_copy(source: &Path, target: &Path) -> error::Result<()> {
+    fs::create_dir_all(target.parent().unwrap()).map_err(|e| io_error(target, e))?;
+    if fs::hard_link(source, target).is_err() && !(target.exists()) {
+        let temp = target.with_extension("lit-tmp");
+        fs::copy(source, &temp).map_err(|e| io_error(&temp, e))?;
+        fs::rename(&temp, target).map_err(|e| io_error(target, e))?;
+    }
+    Ok(())
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> Error {
+    Error::Remote(error::remote::Remote::Io(path.to_owned(), e.to_string()))
+}
+
+impl PushTransport for LocalTransport {
+    fn object_format(&self) -> ObjectFormat {
+        self.source.object_format()
+    }
+
+    fn refs(&mut self) -> error::Result<BTreeMap<String, String>> {
+        Ok(self.source.ref_list())
+    }
+
+    fn push_objects(&mut self, repo: &Repo, updates: &mut [RefUpdate]) -> error::Result<usize> {
+        let target = &self.source;
+        let tips: Vec<String> = updates
+            .iter()
+            .filter(|update| !(update.is_rejected()))
+            .filter_map(|update| update.new.clone())
+            .collect();
+        let count = copy_objects(repo, target, &tips)?;
+
+        for update in updates.iter_mut() {
+            // Moving the branch of a worktree under its feet would make it look modified.
+            if update.status != Status::UpToDate
+                && target.worktree_with_branch(&update.dst).is_some()
+            {
+                update.status = Status::Rejected("branch is currently checked out".to_string());
+            }
+            update.apply(target)?;
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::clone::CloneRepo;
+    use crate::commands::init::Init;
+    use crate::remote::fetch::{clone, fetch};
+    use crate::remote::push::push;
+    use crate::remote::refspec::Refspec;
+    use crate::remote::{Remote, Status};
+    use crate::repo::Repo;
+    use crate::test_util::commit;
+    use std::fs;
+
+    #[test]
+    pub fn test_clone_fetch_push() {
+        let _ = fs::remove_dir_all("./test_local_transport");
+        let origin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_local_transport/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let first = commit(&origin, None, "one");
+        origin.ref_update("refs/tags/v1", &first).unwrap();
+
+        let repo = clone(&CloneRepo {
+            bare: false,
+            repository: "./test_local_transport/origin.git".to_string(),
+            directory: Some("./test_local_transport/clone".to_string()),
+        })
+        .unwrap();
+
+        assert_eq!(
+            "one",
+            fs::read_to_string("./test_local_transport/clone/file").unwrap()
+        );
+        assert_eq!(
+            Some(first.clone()),
+            repo.ref_resolve("refs/remotes/origin/master")
+        );
+        assert_eq!(Some(first.clone()), repo.ref_resolve("refs/tags/v1"));
+        assert_eq!(
+            Some("refs/heads/master".to_string()),
+            repo.ref_symbolic_target("HEAD")
+        );
+        assert_eq!(
+            Some("origin".to_string()),
+            repo.conf.as_ref().unwrap().get("branch.master.remote")
+        );
+
+        // The origin moves on, fetch follows it.
+        let second = commit(&origin, Some(&first), "two");
+        let remote = Remote::load(&repo, "origin").unwrap();
+        let updates = fetch(&repo, &remote, &[]).unwrap();
+        assert_eq!(Status::FastForward, updates[0].status);
+        assert_eq!(
+            Some(second.clone()),
+            repo.ref_resolve("refs/remotes/origin/master")
+        );
+
+        // A commit which is not based on the origin's master is rejected unless forced.
+        let diverged = commit(&repo, Some(&first), "three");
+        let updates = push(&repo, &remote, &[], false).unwrap();
+        assert!(updates[0].is_rejected());
+        assert_eq!(
+            Some(second.clone()),
+            origin.ref_resolve("refs/heads/master")
+        );
+
+        let updates = push(&repo, &remote, &[], true).unwrap();
+        assert_eq!(Status::Forced, updates[0].status);
+        assert_eq!(
+            Some(diverged.clone()),
+            origin.ref_resolve("refs/heads/master")
+        );
+        assert_eq!(
+            Some(diverged.clone()),
+            repo.ref_resolve("refs/remotes/origin/master")
+        );
+
+        // Pushing into the checked out branch of a non-bare repository is refused.
+        commit(&origin, Some(&diverged), "four");
+        let mut remote = Remote::load(&repo, "origin").unwrap();
+        remote.url = "./test_local_transport/clone".to_string();
+        let updates = push(
+            &origin,
+            &remote,
+            &[Refspec::parse("+master:master").unwrap()],
+            false,
+        )
+        .unwrap();
+        assert!(updates[0].is_rejected());
+
+        fs::remove_dir_all("./test_local_transport").unwrap();
+    }
+
+    #[test]
+    pub fn test_clone_packed() {
+        let _ = fs::remove_dir_all("./test_local_packed");
+        let origin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_local_packed/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let first = commit(&origin, None, "one");
+        let (name, _) = origin.repack(true, false).unwrap().unwrap();
+        let loose = commit(&origin, Some(&first), "two");
+
+        let repo = clone(&CloneRepo {
+            bare: true,
+            repository: "./test_local_packed/origin.git".to_string(),
+            directory: Some("./test_local_packed/clone.git".to_string()),
+        })
+        .unwrap();
+
+        // The pack is shared rather than unpacked, the loose object files too
+        let objects = repo.common_dir.join("objects");
+        let pack = objects.join("pack").join(&name).with_extension("pack");
+        assert!(pack.is_file());
+        assert!(pack.with_extension("idx").is_file());
+        assert!(objects.join(&loose[..2]).join(&loose[2..]).is_file());
+        assert!(!(objects.join(&first[..2]).exists()));
+        assert_eq!(Some(loose), repo.ref_resolve("refs/heads/master"));
+        assert!(repo.has_object(&first));
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(2, fs::metadata(&pack).unwrap().nlink());
+        }
+
+        fs::remove_dir_all("./test_local_packed").unwrap();
+    }
+}
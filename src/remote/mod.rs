@@ -0,0 +1,586 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+pub mod bundle;
+pub mod credential;
+pub mod daemon;
+pub mod fetch;
+pub mod http;
+pub mod local;
+pub mod pkt_line;
+pub mod protocol;
+pub mod push;
+pub mod refspec;
+pub mod server;
+
+use crate::config::file::ConfigFile;
+use crate::config::Config;
+use crate::error::{self, Error};
+use crate::object::format::ObjectFormat;
+use crate::repo::refs::check_ref_format;
+use crate::repo::Repo;
+use refspec::Refspec;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// A repository this one fetches from and pushes to, configured in `[remote "<name>"]`.
+pub struct Remote {
+    pub name: String,
+    pub url: String,
+
+    /// Where to push if it differs from url, `remote.<name>.pushurl`
+    pub push_url: Option<String>,
+
+    /// How remote refs map to local ones when fetching
+    pub fetch: Vec<Refspec>,
+
+    /// What to push when no refspec is given
+    pub push: Vec<Refspec>,
+}
+
+impl Remote {
+    pub fn load(repo: &Repo, name: &str) -> error::Result<Self> {
+        let not_found = || Error::Remote(error::remote::Remote::NotFound(name.to_owned()));
+        let conf = repo.conf.as_ref().ok_or_else(not_found)?;
+        let refspecs = |key: &str| -> error::Result<Vec<Refspec>> {
+            conf.get_all(&format!("remote.{}.{}", name, key))?
+                .iter()
+                .filter_map(|entry| entry.value.as_deref())
+                .map(Refspec::parse)
+                .collect()
+        };
+
+        Ok(Remote {
+            name: name.to_owned(),
+            url: conf
+                .get(&format!("remote.{}.url", name))
+                .ok_or_else(not_found)?,
+            push_url: conf.get(&format!("remote.{}.pushurl", name)),
+            fetch: refspecs("fetch")?,
+            push: refspecs("push")?,
+        })
+    }
+
+    pub fn push_url(&self) -> &str {
+        self.push_url.as_deref().unwrap_or(&self.url)
+    }
+
+    /// The names of the configured remotes, in the order they appear in the configuration.
+    pub fn names(repo: &Repo) -> Vec<String> {
+        repo.conf
+            .as_ref()
+            .map(|conf| conf.subsections("remote"))
+            .unwrap_or_default()
+    }
+
+    /// A remote name must make valid ref names under `refs/remotes/<name>/`.
+    fn check_name(name: &str) -> error::Result<()> {
+        let valid = !(name.is_empty()
+            || name.starts_with(['/', '.', '-'])
+            || name.ends_with(['/', '.'])
+            || name.contains(['\0', ' ', '~', '^', ':', '?', '*', '[', '\\'])
+            || name.contains("..")
+            || name.contains("//"));
+
+        if valid {
+            Ok(())
+        } else {
+            Err(Error::Remote(error::remote::Remote::InvalidName(
+                name.to_owned(),
+            )))
+        }
+    }
+
+    fn local_config(repo: &Repo) -> error::Result<ConfigFile> {
+        ConfigFile::open(&repo.common_dir.join("config"))
+    }
+
+    /// Configure a new remote fetching all its branches as remote-tracking branches.
+    pub fn add(repo: &Repo, name: &str, url: &str) -> error::Result<()> {
+        Self::check_name(name)?;
+        if Self::names(repo).iter().any(|remote| remote == name) {
+            return Err(Error::Remote(error::remote::Remote::AlreadyExists(
+                name.to_owned(),
+            )));
+        }
+
+        let mut conf = Self::local_config(repo)?;
+        conf.set(&format!("remote.{}.url", name), url)?;
+        conf.set(
+            &format!("remote.{}.fetch", name),
+            &format!("+refs/heads/*:refs/remotes/{}/*", name),
+        )?;
+        conf.save()
+    }
+
+    /// Set the url of a remote, or its push url.
+    pub fn set_url(repo: &Repo, name: &str, url: &str, push: bool) -> error::Result<()> {
+        Self::load(repo, name)?;
+
+        let key = if push { "pushurl" } else { "url" };
+        let mut conf = Self::local_config(repo)?;
+        conf.set(&format!("remote.{}.{}", name, key), url)?;
+        conf.save()
+    }
+
+    /// The branches whose upstream is on the remote name.
+    fn tracking_branches(repo: &Repo, name: &str) -> Vec<String> {
+        repo.conf
+            .as_ref()
+            .map(|conf| {
+                conf.subsections("branch")
+                    .into_iter()
+                    .filter(|branch| {
+                        conf.get(&format!("branch.{}.remote", branch)).as_deref() == Some(name)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Remove a remote with its remote-tracking refs and the upstream of the branches tracking it.
+    pub fn remove(repo: &Repo, name: &str) -> error::Result<()> {
+        let remote = Self::load(repo, name)?;
+
+        let mut conf = Self::local_config(repo)?;
+        conf.remove_section("remote", Some(name))?;
+        for branch in Self::tracking_branches(repo, name) {
+            conf.unset_all(&format!("branch.{}.remote", branch))?;
+            conf.unset_all(&format!("branch.{}.merge", branch))?;
+        }
+        conf.save()?;
+
+        repo.ref_list()
+            .keys()
+            .filter(|tracking| {
+                remote
+                    .fetch
+                    .iter()
+                    .any(|fetch| fetch.reverse_map(tracking).is_some())
+            })
+            .try_for_each(|tracking| repo.ref_delete(tracking))?;
+
+        let _ = fs::remove_dir(repo.common_dir.join("refs/remotes").join(name));
+        Ok(())
+    }
+
+    /// Rename a remote, moving its remote-tracking refs and the upstream of the branches
+    /// tracking it.
+    pub fn rename(repo: &Repo, old: &str, new: &str) -> error::Result<()> {
+        let remote = Self::load(repo, old)?;
+        Self::check_name(new)?;
+        if Self::names(repo).iter().any(|remote| remote == new) {
+            return Err(Error::Remote(error::remote::Remote::AlreadyExists(
+                new.to_owned(),
+            )));
+        }
+
+        let old_prefix = format!("refs/remotes/{}/", old);
+        let new_prefix = format!("refs/remotes/{}/", new);
+        let rename = |name: &str| match name.strip_prefix(&old_prefix) {
+            Some(rest) => format!("{}{}", new_prefix, rest),
+            None => name.to_owned(),
+        };
+
+        let mut conf = Self::local_config(repo)?;
+        conf.rename_section("remote", Some(old), Some(new))?;
+        conf.unset_all(&format!("remote.{}.fetch", new))?;
+        for fetch in &remote.fetch {
+            let fetch = Refspec {
+                dst: fetch.dst.as_deref().map(rename),
+                ..fetch.clone()
+            };
+            conf.add(&format!("remote.{}.fetch", new), &fetch.to_string())?;
+        }
+        for branch in Self::tracking_branches(repo, old) {
+            conf.set(&format!("branch.{}.remote", branch), new)?;
+        }
+        conf.save()?;
+
+        for (name, sha) in repo.ref_list().range(old_prefix.clone()..) {
+            if !(name.starts_with(&old_prefix)) {
+                break;
+            }
+
+            match repo.ref_symbolic_target(name) {
+                Some(target) => repo.ref_update_symbolic(&rename(name), &rename(&target))?,
+                None => repo.ref_update(&rename(name), sha)?,
+            }
+            repo.ref_delete(name)?;
+        }
+
+        let _ = fs::remove_dir(repo.common_dir.join("refs/remotes").join(old));
+        Ok(())
+    }
+
+    /// The remote of the current branch, `origin` if it has none.
+    pub fn default_name(repo: &Repo) -> String {
+        repo.ref_symbolic_target("HEAD")
+            .and_then(|head| {
+                let branch = head.strip_prefix("refs/heads/")?.to_string();
+                repo.conf
+                    .as_ref()?
+                    .get(&format!("branch.{}.remote", branch))
+            })
+            .unwrap_or("origin".to_string())
+    }
+}
+
+/// A ref as a remote repository advertises it.
+#[derive(Debug, Clone)]
+pub struct AdvertisedRef {
+    pub name: String,
+    pub sha: String,
+
+    /// The ref a symbolic ref points to, e.g. for `HEAD`
+    pub symref_target: Option<String>,
+
+    /// The object an annotated tag points to
+    pub peeled: Option<String>,
+}
+
+/// How refs and objects are obtained from a remote repository.
+pub trait Transport {
+    fn object_format(&self) -> ObjectFormat;
+
+    /// The `HEAD` of the remote repository and its refs under `refs/`.
+    fn refs(&mut self) -> error::Result<Vec<AdvertisedRef>>;
+
+    /// Receive the objects reachable from wants that repo lacks, returns how many came.
+    fn fetch_objects(&mut self, repo: &Repo, wants: &[String]) -> error::Result<usize>;
+}
+
+/// How refs and objects are sent to a remote repository.
+pub trait PushTransport {
+    fn object_format(&self) -> ObjectFormat;
+
+    /// The refs of the remote repository under `refs/`.
+    fn refs(&mut self) -> error::Result<BTreeMap<String, String>>;
+
+    /// Send the objects the updates need and apply those which are not rejected,
+    /// rejecting the ones the remote refuses. Returns how many objects were sent.
+    fn push_objects(&mut self, repo: &Repo, updates: &mut [RefUpdate]) -> error::Result<usize>;
+}
+
+/// Whether url is served by git's smart protocols rather than read directly.
+fn is_smart(url: &str) -> bool {
+    ["file://", "ext::", "git://", "http://", "https://"]
+        .iter()
+        .any(|prefix| url.starts_with(prefix))
+}
+
+/// Open the transport for url: git's protocol version 2 for `file://`, `ext::`, `git://`,
+/// `http://` and `https://` urls, the bundle for paths of bundle files, direct access to
+/// the repository for other paths. conf decides which protocols are allowed.
+pub fn connect(url: &str, conf: Option<&Config>) -> error::Result<Box<dyn Transport>> {
+    if is_smart(url) {
+        Ok(Box::new(protocol::Connection::open(url, conf)?))
+    } else if url.contains("://") {
+        Err(Error::Remote(error::remote::Remote::UnsupportedUrl(
+            url.to_owned(),
+        )))
+    } else if bundle::Bundle::is_bundle(Path::new(url)) {
+        Ok(Box::new(bundle::BundleTransport::open(url)?))
+    } else {
+        Ok(Box::new(local::LocalTransport::open(url)?))
+    }
+}
+
+/// Open the transport pushing to url: `git-receive-pack` for `ext::`, `git://`, `http://`
+/// and `https://` urls, direct access to the repository for paths and `file://` urls.
+pub fn connect_push(url: &str, conf: Option<&Config>) -> error::Result<Box<dyn PushTransport>> {
+    if is_smart(url) && !(url.starts_with("file://")) {
+        Ok(Box::new(protocol::ReceivePack::open(url, conf)?))
+    } else if url.contains("://") && !(url.starts_with("file://")) {
+        Err(Error::Remote(error::remote::Remote::UnsupportedUrl(
+            url.to_owned(),
+        )))
+    } else {
+        Ok(Box::new(local::LocalTransport::open(url)?))
+    }
+}
+
+fn check_object_format(repo: &Repo, remote: ObjectFormat) -> error::Result<()> {
+    if repo.object_format() == remote {
+        Ok(())
+    } else {
+        Err(Error::Remote(error::remote::Remote::ObjectFormatMismatch(
+            repo.object_format().to_string(),
+            remote.to_string(),
+        )))
+    }
+}
+
+/// Check the names of the refs advertised by the remote at url, which come from the wire
+/// and end up as paths once mapped to local refs.
+fn check_advertised<'a>(
+    url: &str,
+    mut names: impl Iterator<Item = &'a String>,
+) -> error::Result<()> {
+    match names.find(|name| !(check_ref_format(name))) {
+        Some(name) => Err(Error::Remote(error::remote::Remote::Protocol(
+            url.to_owned(),
+            format!("invalid ref name `{}`", name),
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Find the ref a short name given on the command line stands for, as git does.
+fn expand_ref<'a>(
+    refs: &'a BTreeMap<String, String>,
+    name: &str,
+) -> Option<(&'a String, &'a String)> {
+    [
+        name.to_string(),
+        format!("refs/{}", name),
+        format!("refs/tags/{}", name),
+        format!("refs/heads/{}", name),
+        format!("refs/remotes/{}", name),
+    ]
+    .iter()
+    .find_map(|candidate| refs.get_key_value(candidate))
+}
+
+/// The remote-tracking ref of the upstream of branch, from `branch.<name>.remote` and
+/// `branch.<name>.merge`. A `.` remote means a branch of this repository.
+pub fn upstream(repo: &Repo, branch: &str) -> Option<String> {
+    let conf = repo.conf.as_ref()?;
+    let remote = conf.get(&format!("branch.{}.remote", branch))?;
+    let merge = conf.get(&format!("branch.{}.merge", branch))?;
+
+    if remote == "." {
+        Some(merge)
+    } else {
+        Remote::load(repo, &remote)
+            .ok()?
+            .fetch
+            .iter()
+            .find_map(|fetch| fetch.map(&merge))
+    }
+}
+
+/// Make the ref merge of remote the upstream of branch.
+pub fn set_upstream(repo: &Repo, branch: &str, remote: &str, merge: &str) -> error::Result<()> {
+    let mut conf = Remote::local_config(repo)?;
+    conf.set(&format!("branch.{}.remote", branch), remote)?;
+    conf.set(&format!("branch.{}.merge", branch), merge)?;
+    conf.save()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Status {
+    Created,
+    FastForward,
+    Forced,
+    Deleted,
+    UpToDate,
+    Rejected(String),
+}
+
+/// The update of one ref by a fetch or a push.
+#[derive(Debug, Clone)]
+pub struct RefUpdate {
+    pub src: String,
+    pub dst: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+    pub status: Status,
+}
+
+impl RefUpdate {
+    /// Decide how dst can move from old to new, in the repository receiving the update,
+    /// which must already have the objects of new.
+    pub fn new(repo: &Repo, src: &str, dst: &str, new: Option<String>, force: bool) -> Self {
+        Self::with_old(repo, src, dst, repo.ref_resolve(dst), new, force)
+    }
+
+    /// Decide how dst can move from old to new, as far as repo, which must have the objects
+    /// of new, knows: an old it lacks cannot be an ancestor.
+    pub fn with_old(
+        repo: &Repo,
+        src: &str,
+        dst: &str,
+        old: Option<String>,
+        new: Option<String>,
+        force: bool,
+    ) -> Self {
+        let status = match (&old, &new) {
+            (None, None) => Status::UpToDate,
+            (Some(_), None) => Status::Deleted,
+            (None, Some(_)) => Status::Created,
+            (Some(old), Some(new)) if old == new => Status::UpToDate,
+            (Some(old), Some(new))
+                if !(dst.starts_with("refs/tags/")) && repo.is_ancestor(old, new) =>
+            {
+                Status::FastForward
+            }
+            (Some(_), Some(_)) if force => Status::Forced,
+            (Some(_), Some(_)) if dst.starts_with("refs/tags/") => {
+                Status::Rejected("already exists".to_string())
+            }
+            (Some(old), Some(_)) if !(repo.has_object(old)) => {
+                Status::Rejected("fetch first".to_string())
+            }
+            _ => Status::Rejected("non-fast-forward".to_string()),
+        };
+
+        RefUpdate {
+            src: src.to_owned(),
+            dst: dst.to_owned(),
+            old,
+            new,
+            status,
+        }
+    }
+
+    pub fn is_rejected(&self) -> bool {
+        matches!(self.status, Status::Rejected(_))
+    }
+
+    /// Write the update to the ref of repo, unless it is rejected.
+    pub fn apply(&self, repo: &Repo) -> error::Result<()> {
+        match (&self.status, &self.new) {
+            (Status::Rejected(_) | Status::UpToDate, _) => Ok(()),
+            (_, Some(new)) => repo.ref_update(&self.dst, new),
+            (_, None) => repo.ref_delete(&self.dst),
+        }
+    }
+}
+
+/// Ref names are shown without their well-known prefix.
+fn short_name(name: &str) -> &str {
+    ["refs/heads/", "refs/tags/", "refs/remotes/"]
+        .iter()
+        .find_map(|prefix| name.strip_prefix(prefix))
+        .unwrap_or(name)
+}
+
+impl fmt::Display for RefUpdate {
+    /// The same summary lines as git.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let abbrev = |sha: &Option<String>| sha.as_deref().unwrap_or_default()[..7].to_string();
+        let refs = format!("{} -> {}", short_name(&self.src), short_name(&self.dst));
+
+        match &self.status {
+            Status::Created => {
+                let kind = if self.dst.starts_with("refs/tags/") {
+                    "[new tag]"
+                } else if self.dst.starts_with("refs/heads/")
+                    || self.dst.starts_with("refs/remotes/")
+                {
+                    "[new branch]"
+                } else {
+                    "[new ref]"
+                };
+                write!(f, " * {:<17} {}", kind, refs)
+            }
+            Status::FastForward => write!(
+                f,
+                "   {:<17} {}",
+                format!("{}..{}", abbrev(&self.old), abbrev(&self.new)),
+                refs
+            ),
+            Status::Forced => write!(
+                f,
+                " + {:<17} {} (forced update)",
+                format!("{}...{}", abbrev(&self.old), abbrev(&self.new)),
+                refs
+            ),
+            Status::Deleted => write!(f, " - {:<17} {}", "[deleted]", short_name(&self.dst)),
+            Status::UpToDate => write!(f, " = {:<17} {}", "[up to date]", refs),
+            Status::Rejected(reason) => write!(f, " ! {:<17} {} ({})", "[rejected]", refs, reason),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{upstream, Remote};
+    use crate::commands::init::Init;
+    use crate::repo::Repo;
+    use std::fs;
+
+    fn reopen(repo: &Repo) -> Repo {
+        Repo::repo_open(&repo.worktree).unwrap()
+    }
+
+    #[test]
+    pub fn test_remote_add_rename_remove() {
+        let _ = fs::remove_dir_all("./test_remote");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: false,
+            path: String::from("./test_remote"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let sha = "0123456789012345678901234567890123456789";
+
+        Remote::add(&repo, "origin", "/srv/origin.git").unwrap();
+        assert!(Remote::add(&reopen(&repo), "origin", "/srv/other.git").is_err());
+        assert!(Remote::add(&repo, "bad name", "/srv/other.git").is_err());
+
+        let repo = reopen(&repo);
+        Remote::set_url(&repo, "origin", "/srv/push.git", true).unwrap();
+        super::set_upstream(&repo, "master", "origin", "refs/heads/master").unwrap();
+        repo.ref_update("refs/remotes/origin/master", sha).unwrap();
+        repo.ref_update_symbolic("refs/remotes/origin/HEAD", "refs/remotes/origin/master")
+            .unwrap();
+
+        let repo = reopen(&repo);
+        let remote = Remote::load(&repo, "origin").unwrap();
+        assert_eq!(vec!["origin".to_string()], Remote::names(&repo));
+        assert_eq!("/srv/push.git", remote.push_url());
+        assert_eq!(
+            "+refs/heads/*:refs/remotes/origin/*",
+            remote.fetch[0].to_string()
+        );
+        assert_eq!(
+            Some("refs/remotes/origin/master".to_string()),
+            upstream(&repo, "master")
+        );
+
+        Remote::rename(&repo, "origin", "upstream").unwrap();
+        let repo = reopen(&repo);
+        assert_eq!(vec!["upstream".to_string()], Remote::names(&repo));
+        assert_eq!(
+            "+refs/heads/*:refs/remotes/upstream/*",
+            Remote::load(&repo, "upstream").unwrap().fetch[0].to_string()
+        );
+        assert_eq!(
+            Some("refs/remotes/upstream/master".to_string()),
+            upstream(&repo, "master")
+        );
+        assert_eq!(None, repo.ref_resolve("refs/remotes/origin/master"));
+        assert_eq!(
+            Some("refs/remotes/upstream/master".to_string()),
+            repo.ref_symbolic_target("refs/remotes/upstream/HEAD")
+        );
+
+        Remote::remove(&repo, "upstream").unwrap();
+        let repo = reopen(&repo);
+        assert!(Remote::names(&repo).is_empty());
+        assert_eq!(None, upstream(&repo, "master"));
+        assert!(repo.ref_list().is_empty());
+
+        fs::remove_dir_all("./test_remote").unwrap();
+    }
+}
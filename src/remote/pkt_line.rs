@@ -0,0 +1,133 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use std::io::{self, Read, Write};
+
+/// The largest payload of a packet, its length prefix excluded.
+pub const MAX_DATA_LEN: usize = 65516;
+
+/// A unit of git's wire protocol: data prefixed by its length in 4 hex digits,
+/// or one of the special packets with no data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Packet {
+    /// `0000`, ends a message
+    Flush,
+
+    /// `0001`, separates the sections of a message in protocol v2
+    Delim,
+
+    /// `0002`, ends a response in stateless connections
+    ResponseEnd,
+    Data(Vec<u8>),
+}
+
+impl Packet {
+    /// The data of a text packet, without its trailing newline.
+    pub fn line(&self) -> Option<String> {
+        match self {
+            Packet::Data(data) => {
+                Some(String::from_utf8_lossy(data.strip_suffix(b"\n").unwrap_or(data)).to_string())
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct PktReader<R: Read> {
+    inner: R,
+}
+
+impl<R: Read> PktReader<R> {
+    pub fn new(inner: R) -> Self {
+        PktReader { inner }
+    }
+
+    pub fn read(&mut self) -> io::Result<Packet> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, reason);
+
+        let mut prefix = [0u8; 4];
+        self.inner.read_exact(&mut prefix)?;
+        let len = std::str::from_utf8(&prefix)
+            .ok()
+            .and_then(|prefix| usize::from_str_radix(prefix, 16).ok())
+            .ok_or_else(|| invalid("invalid packet length"))?;
+
+        match len {
+            0 => Ok(Packet::Flush),
+            1 => Ok(Packet::Delim),
+            2 => Ok(Packet::ResponseEnd),
+            3 => Err(invalid("invalid packet length")),
+            len => {
+                let mut data = vec![0u8; len - 4];
+                self.inner.read_exact(&mut data)?;
+                Ok(Packet::Data(data))
+            }
+        }
+    }
+}
+
+pub fn write_data<W: Write + ?Sized>(writer: &mut W, data: &[u8]) -> io::Result<()> {
+    if data.len() > MAX_DATA_LEN {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "packet too long",
+        ));
+    }
+
+    writer.write_all(format!("{:04x}", data.len() + 4).as_bytes())?;
+    writer.write_all(data)
+}
+
+/// A text packet, newline terminated.
+pub fn write_line<W: Write + ?Sized>(writer: &mut W, line: &str) -> io::Result<()> {
+    write_data(writer, format!("{}\n", line).as_bytes())
+}
+
+pub fn write_flush<W: Write + ?Sized>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"0000")
+}
+
+pub fn write_delim<W: Write + ?Sized>(writer: &mut W) -> io::Result<()> {
+    writer.write_all(b"0001")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_delim, write_flush, write_line, Packet, PktReader};
+
+    #[test]
+    pub fn test_pkt_line() {
+        let mut buffer = Vec::new();
+        write_line(&mut buffer, "command=ls-refs").unwrap();
+        write_delim(&mut buffer).unwrap();
+        write_flush(&mut buffer).unwrap();
+        assert_eq!(b"0014command=ls-refs\n00010000".to_vec(), buffer);
+
+        let mut reader = PktReader::new(buffer.as_slice());
+        assert_eq!(
+            Some("command=ls-refs".to_string()),
+            reader.read().unwrap().line()
+        );
+        assert_eq!(Packet::Delim, reader.read().unwrap());
+        assert_eq!(Packet::Flush, reader.read().unwrap());
+        assert!(reader.read().is_err());
+
+        let mut reader = PktReader::new(b"0003".as_slice());
+        assert!(reader.read().is_err());
+    }
+}
@@ -0,0 +1,868 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::Config;
+use crate::error::{self, Error};
+use crate::object::format::ObjectFormat;
+use crate::object::pack;
+use crate::r#const::{project_info, LIT_PROTOCOL_FROM_USER_ENV};
+use crate::remote::credential::ConfiguredHelpers;
+use crate::remote::daemon;
+use crate::remote::http::HttpChannel;
+use crate::remote::pkt_line::{self, Packet, PktReader};
+use crate::remote::{AdvertisedRef, PushTransport, RefUpdate, Status, Transport};
+use crate::repo::Repo;
+use std::collections::{BTreeMap, HashSet};
+use std::io::{IsTerminal, Read, Write};
+use std::net::TcpStream;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// How many haves are sent in each round of the negotiation
+const HAVES_PER_ROUND: usize = 32;
+
+/// How many haves may go unacknowledged after the last common commit before the
+/// negotiation is given up, as git's `MAX_IN_VAIN`
+const MAX_IN_VAIN: usize = 256;
+
+/// How the messages of a connection reach the server and its responses come back.
+pub trait Channel {
+    /// Send a message, after which the response to it can be read.
+    fn send(&mut self, message: &[u8]) -> error::Result<()>;
+
+    fn response(&mut self) -> &mut dyn Read;
+}
+
+/// A server running as a subprocess, talking on its standard input and output.
+struct Process {
+    url: String,
+    child: Child,
+    stdout: ChildStdout,
+
+    /// Closed when the process is dropped, which ends the server
+    stdin: Option<ChildStdin>,
+}
+
+impl Process {
+    fn spawn(
+        url: &str,
+        service: &str,
+        protocol_v2: bool,
+        conf: Option<&Config>,
+    ) -> error::Result<Self> {
+        let mut command = service_command(url, service, conf)?;
+        if protocol_v2 {
+            command.env("GIT_PROTOCOL", "version=2");
+        }
+
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .map_err(|e| {
+                Error::Remote(error::remote::Remote::Transport(
+                    url.to_owned(),
+                    e.to_string(),
+                ))
+            })?;
+
+        Ok(Process {
+            url: url.to_owned(),
+            stdout: child.stdout.take().unwrap(),
+            stdin: child.stdin.take(),
+            child,
+        })
+    }
+}
+
+impl Channel for Process {
+    fn send(&mut self, message: &[u8]) -> error::Result<()> {
+        let stdin = self.stdin.as_mut().unwrap();
+        stdin
+            .write_all(message)
+            .and_then(|_| stdin.flush())
+            .map_err(|e| {
+                Error::Remote(error::remote::Remote::Transport(
+                    self.url.clone(),
+                    e.to_string(),
+                ))
+            })
+    }
+
+    fn response(&mut self) -> &mut dyn Read {
+        &mut self.stdout
+    }
+}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        // Closing its input tells the server there are no more commands.
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+/// A `git://` daemon, which is told the service and repository it should run.
+struct Daemon {
+    url: String,
+    stream: TcpStream,
+}
+
+impl Daemon {
+    fn connect(url: &str, service: &str, protocol_v2: bool) -> error::Result<Self> {
+        let transport = |e: std::io::Error| {
+            Error::Remote(error::remote::Remote::Transport(
+                url.to_owned(),
+                e.to_string(),
+            ))
+        };
+
+        let address = url.strip_prefix("git://").unwrap_or(url);
+        let (host, path) = match address.find('/') {
+            Some(slash) => address.split_at(slash),
+            None => (address, "/"),
+        };
+        let target = if host.contains(':') {
+            host.to_owned()
+        } else {
+            format!("{}:{}", host, daemon::DEFAULT_PORT)
+        };
+
+        let mut stream = TcpStream::connect(target).map_err(transport)?;
+        let mut request = format!("{} {}\0host={}\0", service, path, host);
+        if protocol_v2 {
+            request.push_str("\0version=2\0");
+        }
+        pkt_line::write_data(&mut stream, request.as_bytes()).map_err(transport)?;
+
+        Ok(Daemon {
+            url: url.to_owned(),
+            stream,
+        })
+    }
+}
+
+impl Channel for Daemon {
+    fn send(&mut self, message: &[u8]) -> error::Result<()> {
+        self.stream
+            .write_all(message)
+            .and_then(|_| self.stream.flush())
+            .map_err(|e| {
+                Error::Remote(error::remote::Remote::Transport(
+                    self.url.clone(),
+                    e.to_string(),
+                ))
+            })
+    }
+
+    fn response(&mut self) -> &mut dyn Read {
+        &mut self.stream
+    }
+}
+
+/// Whether the transport scheme may be used: `protocol.<scheme>.allow`, else `protocol.allow`,
+/// is `always`, `never` or `user` (allowed unless `LIT_PROTOCOL_FROM_USER` is 0). As with git,
+/// `git`, `http` and `https` are always allowed by default, and `ext` never since its urls run
+/// arbitrary commands.
+fn check_allowed(scheme: &str, conf: Option<&Config>) -> error::Result<()> {
+    let policy = conf
+        .and_then(|conf| {
+            conf.get(&format!("protocol.{}.allow", scheme))
+                .or_else(|| conf.get("protocol.allow"))
+        })
+        .unwrap_or(
+            match scheme {
+                "git" | "http" | "https" => "always",
+                "ext" => "never",
+                _ => "user",
+            }
+            .to_string(),
+        );
+
+    let allowed = match policy.as_str() {
+        "always" => true,
+        "user" => {
+            std::env::var(LIT_PROTOCOL_FROM_USER_ENV).map_or(true, |from_user| from_user != "0")
+        }
+        _ => false,
+    };
+
+    if allowed {
+        Ok(())
+    } else {
+        Err(Error::Remote(error::remote::Remote::ProtocolNotAllowed(
+            scheme.to_owned(),
+        )))
+    }
+}
+
+/// The command running service for url. An `ext::` url is a command line split on spaces,
+/// where `% ` is a space inside an argument, `%%` a percent sign, `%S` the service
+/// and `%s` the service without its `git-` prefix.
+fn service_command(url: &str, service: &str, conf: Option<&Config>) -> error::Result<Command> {
+    let unsupported = || Error::Remote(error::remote::Remote::UnsupportedUrl(url.to_owned()));
+
+    if let Some(path) = url.strip_prefix("file://") {
+        check_allowed("file", conf)?;
+
+        let mut command = Command::new(service);
+        command.arg(path);
+        return Ok(command);
+    }
+
+    let line = url.strip_prefix("ext::").ok_or_else(unsupported)?;
+    check_allowed("ext", conf)?;
+
+    let mut args = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let arg = args.last_mut().unwrap();
+
+        match c {
+            ' ' if arg.is_empty() => (),
+            ' ' => args.push(String::new()),
+            '%' => match chars.next() {
+                Some(c @ ('%' | ' ')) => arg.push(c),
+                Some('S') => arg.push_str(service),
+                Some('s') => arg.push_str(service.strip_prefix("git-").unwrap_or(service)),
+                _ => return Err(unsupported()),
+            },
+            c => arg.push(c),
+        }
+    }
+
+    args.retain(|arg| !(arg.is_empty()));
+    let (program, args) = args.split_first().ok_or_else(unsupported)?;
+    let mut command = Command::new(program);
+    command.args(args);
+    Ok(command)
+}
+
+/// Reach service at url: a POST per message for `http://` and `https://` urls,
+/// a connection to the daemon for `git://` urls, a subprocess otherwise.
+fn open_channel(
+    url: &str,
+    service: &str,
+    protocol_v2: bool,
+    conf: Option<&Config>,
+) -> error::Result<Box<dyn Channel>> {
+    match url.split_once("://") {
+        Some((scheme @ ("http" | "https"), _)) => {
+            check_allowed(scheme, conf)?;
+            Ok(Box::new(HttpChannel::open(
+                url,
+                service,
+                protocol_v2,
+                Box::new(ConfiguredHelpers::new(conf)),
+            )?))
+        }
+        Some(("git", _)) => {
+            check_allowed("git", conf)?;
+            Ok(Box::new(Daemon::connect(url, service, protocol_v2)?))
+        }
+        _ => Ok(Box::new(Process::spawn(url, service, protocol_v2, conf)?)),
+    }
+}
+
+fn protocol_error(url: &str, reason: &str) -> Error {
+    Error::Remote(error::remote::Remote::Protocol(
+        url.to_owned(),
+        reason.to_owned(),
+    ))
+}
+
+/// Read a packet from channel, an `ERR` packet is the error of the server.
+fn read_packet(channel: &mut dyn Channel, url: &str) -> error::Result<Packet> {
+    match PktReader::new(channel.response()).read() {
+        Ok(packet) => match packet
+            .line()
+            .as_deref()
+            .and_then(|line| line.strip_prefix("ERR "))
+        {
+            Some(message) => Err(Error::Remote(error::remote::Remote::ServerError(
+                message.to_owned(),
+            ))),
+            None => Ok(packet),
+        },
+        Err(e) => Err(Error::Remote(error::remote::Remote::Transport(
+            url.to_owned(),
+            e.to_string(),
+        ))),
+    }
+}
+
+/// Read the first line of an advertisement, after the `# service=` announcement
+/// smart HTTP servers may start with.
+fn read_advertisement(channel: &mut dyn Channel, url: &str) -> error::Result<Packet> {
+    let packet = read_packet(channel, url)?;
+
+    match packet.line() {
+        Some(line) if line.starts_with("# service=") => {
+            if read_packet(channel, url)? != Packet::Flush {
+                return Err(protocol_error(url, "expected a flush after the service"));
+            }
+            read_packet(channel, url)
+        }
+        _ => Ok(packet),
+    }
+}
+
+fn agent() -> String {
+    format!("agent={}/{}", project_info::NAME, env!("CARGO_PKG_VERSION"))
+}
+
+/// A connection to `git-upload-pack` speaking git's protocol version 2. The server runs
+/// as a subprocess for `file://` and `ext::` urls, and behind a web server for `http://`
+/// and `https://` urls.
+pub struct Connection {
+    url: String,
+    channel: Box<dyn Channel>,
+    capabilities: Vec<String>,
+    object_format: ObjectFormat,
+}
+
+impl Connection {
+    /// Reach `git-upload-pack` at url and read the capabilities it advertises.
+    pub fn open(url: &str, conf: Option<&Config>) -> error::Result<Self> {
+        let mut connection = Connection {
+            url: url.to_owned(),
+            channel: open_channel(url, "git-upload-pack", true, conf)?,
+            capabilities: Vec::new(),
+            object_format: ObjectFormat::Sha1,
+        };
+
+        let version = read_advertisement(connection.channel.as_mut(), url)?;
+        if version.line().as_deref() != Some("version 2") {
+            return Err(connection.protocol_error("the server does not speak protocol version 2"));
+        }
+
+        while let Some(capability) = connection.read()?.line() {
+            connection.capabilities.push(capability);
+        }
+
+        if let Some(name) = connection.capability("object-format") {
+            connection.object_format = ObjectFormat::from_name(&name)
+                .ok_or_else(|| connection.protocol_error("unknown object format"))?;
+        }
+
+        debug!("{} capabilities: {:?}", url, connection.capabilities);
+        Ok(connection)
+    }
+
+    /// The value of an advertised capability, empty for a capability without value.
+    fn capability(&self, name: &str) -> Option<String> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability.split_once('=') {
+                Some((key, value)) if key == name => Some(value.to_string()),
+                None if capability == name => Some(String::new()),
+                _ => None,
+            })
+    }
+
+    fn protocol_error(&self, reason: &str) -> Error {
+        protocol_error(&self.url, reason)
+    }
+
+    fn read(&mut self) -> error::Result<Packet> {
+        read_packet(self.channel.as_mut(), &self.url)
+    }
+
+    /// Send command with its arguments, along with the capabilities every command takes.
+    fn request(&mut self, command: &str, args: &[String]) -> error::Result<()> {
+        let mut message = Vec::new();
+        let mut lines = vec![format!("command={}", command)];
+
+        if self.capability("agent").is_some() {
+            lines.push(agent());
+        }
+        if self.capability("object-format").is_some() {
+            lines.push(format!("object-format={}", self.object_format.name()));
+        }
+
+        let written: std::io::Result<()> = (|| {
+            for line in &lines {
+                pkt_line::write_line(&mut message, line)?;
+            }
+            pkt_line::write_delim(&mut message)?;
+            for arg in args {
+                pkt_line::write_line(&mut message, arg)?;
+            }
+            pkt_line::write_flush(&mut message)
+        })();
+
+        written.map_err(|e| self.protocol_error(&e.to_string()))?;
+        self.channel.send(&message)
+    }
+    /// Read the response to a fetch request: None when the negotiation goes on,
+    /// else the pack the server sent. The commits the server acknowledges are added to common.
+    fn read_fetch_response(&mut self, common: &mut Vec<String>) -> error::Result<Option<Vec<u8>>> {
+        loop {
+            let section = self
+                .read()?
+                .line()
+                .ok_or_else(|| self.protocol_error("expected a section"))?;
+
+            match section.as_str() {
+                "acknowledgments" => {
+                    let mut ready = false;
+
+                    loop {
+                        match self.read()? {
+                            Packet::Flush if !(ready) => return Ok(None),
+                            Packet::Delim if ready => break,
+                            packet => match packet.line().as_deref() {
+                                Some("ready") => ready = true,
+                                Some("NAK") => debug!("NAK"),
+                                Some(ack) if ack.starts_with("ACK ") => {
+                                    debug!("{}", ack);
+                                    common.push(ack["ACK ".len()..].to_string());
+                                }
+                                _ => return Err(self.protocol_error("bad acknowledgment")),
+                            },
+                        }
+                    }
+                }
+                "packfile" => return self.read_packfile().map(Some),
+
+                // shallow-info, wanted-refs and packfile-uris are not asked for
+                _ => loop {
+                    match self.read()? {
+                        Packet::Delim => break,
+                        Packet::Data(_) => (),
+                        _ => return Err(self.protocol_error("truncated response")),
+                    }
+                },
+            }
+        }
+    }
+
+    /// Read the packfile section: pack data multiplexed with progress and errors of the server.
+    fn read_packfile(&mut self) -> error::Result<Vec<u8>> {
+        let mut data = Vec::new();
+
+        loop {
+            match self.read()? {
+                Packet::Flush => return Ok(data),
+                Packet::Data(packet) => match packet.split_first() {
+                    Some((1, pack)) => data.extend_from_slice(pack),
+                    Some((2, progress)) => eprint!("remote: {}", String::from_utf8_lossy(progress)),
+                    Some((3, message)) => {
+                        return Err(Error::Remote(error::remote::Remote::ServerError(
+                            String::from_utf8_lossy(message).trim_end().to_string(),
+                        )))
+                    }
+                    _ => return Err(self.protocol_error("bad sideband packet")),
+                },
+                _ => return Err(self.protocol_error("truncated pack")),
+            }
+        }
+    }
+}
+
+impl Transport for Connection {
+    fn object_format(&self) -> ObjectFormat {
+        self.object_format
+    }
+
+    fn refs(&mut self) -> error::Result<Vec<AdvertisedRef>> {
+        let args = ["peel", "symrefs", "ref-prefix HEAD", "ref-prefix refs/"].map(String::from);
+        self.request("ls-refs", &args)?;
+
+        let mut refs = Vec::new();
+        while let Some(line) = self.read()?.line() {
+            let mut fields = line.split(' ');
+            let (sha, name) = fields
+                .next()
+                .zip(fields.next())
+                .ok_or_else(|| self.protocol_error("bad ref"))?;
+
+            let mut advertised = AdvertisedRef {
+                name: name.to_string(),
+                sha: sha.to_string(),
+                symref_target: None,
+                peeled: None,
+            };
+            for attribute in fields {
+                if let Some(target) = attribute.strip_prefix("symref-target:") {
+                    advertised.symref_target = Some(target.to_string());
+                } else if let Some(peeled) = attribute.strip_prefix("peeled:") {
+                    advertised.peeled = Some(peeled.to_string());
+                }
+            }
+            refs.push(advertised);
+        }
+
+        Ok(refs)
+    }
+
+    /// Negotiate with haves, the commits of repo nearest to its refs first, until the server
+    /// is ready or they run out, then receive the pack and store it with its index. The
+    /// protocol is stateless: the commits found in common are sent again in every round.
+    /// Once some are found, the negotiation is given up after `MAX_IN_VAIN` haves in vain.
+    fn fetch_objects(&mut self, repo: &Repo, wants: &[String]) -> error::Result<usize> {
+        let mut wants: Vec<&String> = wants.iter().filter(|sha| !(repo.has_object(sha))).collect();
+        wants.sort();
+        wants.dedup();
+        if wants.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tips: Vec<String> = repo.ref_list().into_values().collect();
+        tips.extend(repo.ref_resolve("HEAD"));
+        let haves = repo.walk_commits(&tips);
+
+        let mut base: Vec<String> = wants.iter().map(|sha| format!("want {}", sha)).collect();
+        base.push("ofs-delta".to_string());
+        if !(std::io::stderr().is_terminal()) {
+            base.push("no-progress".to_string());
+        }
+
+        let mut rounds = haves.chunks(HAVES_PER_ROUND).peekable();
+        let mut common: Vec<String> = Vec::new();
+        let mut in_vain = 0;
+        let pack = loop {
+            let round = rounds.next().unwrap_or_default();
+            let done = rounds.peek().is_none() || (!(common.is_empty()) && in_vain >= MAX_IN_VAIN);
+
+            let mut args = base.clone();
+            args.extend(common.iter().map(|sha| format!("have {}", sha)));
+            args.extend(
+                round
+                    .iter()
+                    .filter(|sha| !(common.contains(sha)))
+                    .map(|sha| format!("have {}", sha)),
+            );
+            if done {
+                args.push("done".to_string());
+            }
+
+            self.request("fetch", &args)?;
+            let known = common.len();
+            match self.read_fetch_response(&mut common)? {
+                Some(pack) => break pack,
+                None if done => return Err(self.protocol_error("no pack after done")),
+                None => (),
+            }
+
+            common.sort();
+            common.dedup();
+            if common.len() > known {
+                in_vain = 0;
+            } else {
+                in_vain += round.len();
+            }
+        };
+
+        pack::index_pack(repo, &pack)
+    }
+}
+
+/// A connection to `git-receive-pack`, which only speaks git's protocol version 0:
+/// the server advertises its refs, then receives the commands updating them and a pack.
+pub struct ReceivePack {
+    url: String,
+    channel: Box<dyn Channel>,
+    refs: BTreeMap<String, String>,
+    capabilities: Vec<String>,
+}
+
+impl ReceivePack {
+    /// Reach `git-receive-pack` at url and read the refs and capabilities it advertises.
+    pub fn open(url: &str, conf: Option<&Config>) -> error::Result<Self> {
+        let mut channel = open_channel(url, "git-receive-pack", false, conf)?;
+        let mut refs = BTreeMap::new();
+        let mut capabilities = Vec::new();
+
+        let mut packet = read_advertisement(channel.as_mut(), url)?;
+        while let Some(line) = packet.line() {
+            // The capabilities hide behind a NUL after the first ref.
+            let line = match line.split_once('\0') {
+                Some((line, advertised)) => {
+                    capabilities = advertised.split(' ').map(String::from).collect();
+                    line.to_string()
+                }
+                None => line,
+            };
+
+            match line.split_once(' ') {
+                // An empty repository advertises its capabilities on a fake ref.
+                Some((_, "capabilities^{}")) | Some((_, ".have")) => (),
+                Some(("shallow", _)) => (),
+                Some((sha, name)) => {
+                    refs.insert(name.to_string(), sha.to_string());
+                }
+                None => return Err(protocol_error(url, "bad ref")),
+            }
+            packet = read_packet(channel.as_mut(), url)?;
+        }
+
+        debug!("{} capabilities: {:?}", url, capabilities);
+        Ok(ReceivePack {
+            url: url.to_owned(),
+            channel,
+            refs,
+            capabilities,
+        })
+    }
+
+    fn capability(&self, name: &str) -> Option<String> {
+        self.capabilities
+            .iter()
+            .find_map(|capability| match capability.split_once('=') {
+                Some((key, value)) if key == name => Some(value.to_string()),
+                None if capability == name => Some(String::new()),
+                _ => None,
+            })
+    }
+
+    fn protocol_error(&self, reason: &str) -> Error {
+        protocol_error(&self.url, reason)
+    }
+
+    fn read(&mut self) -> error::Result<Packet> {
+        read_packet(self.channel.as_mut(), &self.url)
+    }
+
+    /// The objects reachable from tips that the remote does not have, as far as repo knows:
+    /// those reachable from the remote refs whose objects repo has are left out.
+    fn missing_objects(&self, repo: &Repo, tips: &[String]) -> error::Result<Vec<String>> {
+        let known: Vec<String> = self
+            .refs
+            .values()
+            .filter(|sha| repo.has_object(sha))
+            .cloned()
+            .collect();
+        let known: HashSet<String> = repo
+            .missing_objects(&known, &|_| false)?
+            .into_iter()
+            .collect();
+
+        repo.missing_objects(tips, &|sha| known.contains(sha))
+    }
+}
+
+impl PushTransport for ReceivePack {
+    fn object_format(&self) -> ObjectFormat {
+        self.capability("object-format")
+            .and_then(|name| ObjectFormat::from_name(&name))
+            .unwrap_or(ObjectFormat::Sha1)
+    }
+
+    fn refs(&mut self) -> error::Result<BTreeMap<String, String>> {
+        Ok(self.refs.clone())
+    }
+
+    /// Send a command per update with the pack of the objects they need, then read
+    /// the report of the server: the updates it refused are marked rejected.
+    fn push_objects(&mut self, repo: &Repo, updates: &mut [RefUpdate]) -> error::Result<usize> {
+        let mut pending: Vec<&mut RefUpdate> = updates
+            .iter_mut()
+            .filter(|update| !(update.is_rejected()) && update.status != Status::UpToDate)
+            .collect();
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        if self.capability("report-status").is_none() {
+            return Err(self.protocol_error("the server does not report the status of updates"));
+        }
+
+        let mut requested = vec!["report-status".to_string(), agent()];
+        if pending.iter().any(|update| update.new.is_none()) {
+            if self.capability("delete-refs").is_none() {
+                return Err(self.protocol_error("the server does not allow deleting refs"));
+            }
+            requested.push("delete-refs".to_string());
+        }
+        if self.capability("object-format").is_some() {
+            requested.push(format!("object-format={}", repo.object_format().name()));
+        }
+
+        let zero = "0".repeat(repo.object_format().hex_len());
+        let mut message = Vec::new();
+        for (i, update) in pending.iter().enumerate() {
+            let mut command = format!(
+                "{} {} {}",
+                update.old.as_ref().unwrap_or(&zero),
+                update.new.as_ref().unwrap_or(&zero),
+                update.dst
+            );
+            if i == 0 {
+                command = format!("{}\0{}", command, requested.join(" "));
+            }
+            pkt_line::write_line(&mut message, &command)
+                .map_err(|e| self.protocol_error(&e.to_string()))?;
+        }
+        pkt_line::write_flush(&mut message).unwrap();
+
+        // Deleting refs only needs no pack.
+        let tips: Vec<String> = pending
+            .iter()
+            .filter_map(|update| update.new.clone())
+            .collect();
+        let objects = self.missing_objects(repo, &tips)?;
+        if !(tips.is_empty()) {
+            message.extend(pack::write(repo, &objects)?);
+        }
+        self.channel.send(&message)?;
+
+        match self.read()?.line() {
+            Some(line) if line == "unpack ok" => (),
+            Some(line) => {
+                return Err(Error::Remote(error::remote::Remote::ServerError(
+                    line.strip_prefix("unpack ").unwrap_or(&line).to_string(),
+                )))
+            }
+            None => return Err(self.protocol_error("expected the status of the pack")),
+        }
+
+        while let Some(line) = self.read()?.line() {
+            let (refused, name, reason) = match line.split_once(' ') {
+                Some(("ok", name)) => (false, name, ""),
+                Some(("ng", rest)) => match rest.split_once(' ') {
+                    Some((name, reason)) => (true, name, reason),
+                    None => (true, rest, "rejected"),
+                },
+                _ => return Err(self.protocol_error("bad status")),
+            };
+
+            if let Some(update) = pending.iter_mut().find(|update| update.dst == name) {
+                if refused {
+                    update.status = Status::Rejected(reason.to_string());
+                }
+            }
+        }
+
+        Ok(objects.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{service_command, Connection, HAVES_PER_ROUND};
+    use crate::commands::clone::CloneRepo;
+    use crate::commands::init::Init;
+    use crate::config::{Config, Scope};
+    use crate::remote::{fetch, Remote, Transport};
+    use crate::repo::Repo;
+    use crate::test_util::commit;
+    use std::fs;
+
+    #[test]
+    pub fn test_ext_command() {
+        let conf = Config::default();
+        assert!(service_command("ext::%S% foo %s", "git-upload-pack", Some(&conf)).is_err());
+
+        fs::create_dir_all("./test_ext_command").unwrap();
+        fs::write(
+            "./test_ext_command/config",
+            "[protocol \"ext\"]\n\tallow = always\n",
+        )
+        .unwrap();
+        let conf = Config::load_file(
+            std::path::Path::new("./test_ext_command/config"),
+            Scope::Local,
+            None,
+        )
+        .unwrap();
+
+        let command =
+            service_command("ext::%S% foo  %s %%", "git-upload-pack", Some(&conf)).unwrap();
+        assert_eq!("git-upload-pack foo", command.get_program());
+        assert_eq!(
+            vec!["upload-pack", "%"],
+            command.get_args().collect::<Vec<_>>()
+        );
+        assert!(service_command("ext::%G", "git-upload-pack", Some(&conf)).is_err());
+
+        fs::remove_dir_all("./test_ext_command").unwrap();
+    }
+
+    /// Against the `git-upload-pack` of git, which must be installed.
+    #[test]
+    pub fn test_fetch_protocol_v2() {
+        let _ = fs::remove_dir_all("./test_protocol");
+        let origin = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_protocol/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let first = commit(&origin, None, "one");
+        let url = format!(
+            "file://{}",
+            fs::canonicalize("./test_protocol/origin.git")
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+
+        let mut connection = Connection::open(&url, None).unwrap();
+        let refs = connection.refs().unwrap();
+        let head = refs.iter().find(|advertised| advertised.name == "HEAD");
+        assert_eq!(
+            Some("refs/heads/master"),
+            head.and_then(|head| head.symref_target.as_deref())
+        );
+        assert_eq!(Some(&first), head.map(|head| &head.sha));
+        drop(connection);
+
+        let repo = fetch::clone(&CloneRepo {
+            bare: false,
+            repository: url.clone(),
+            directory: Some("./test_protocol/clone".to_string()),
+        })
+        .unwrap();
+        assert_eq!(
+            "one",
+            fs::read_to_string("./test_protocol/clone/file").unwrap()
+        );
+        assert_eq!(
+            Some(url.clone()),
+            repo.conf.as_ref().unwrap().get("remote.origin.url")
+        );
+
+        // Only the new commit, its tree and its blob come with the next fetch, though the
+        // commit in common is found after rounds of commits the clone has alone.
+        let mut local = first.clone();
+        for i in 0..2 * HAVES_PER_ROUND {
+            local = commit(&repo, Some(&local), &i.to_string());
+        }
+        let second = commit(&origin, Some(&first), "two");
+        let mut connection = Connection::open(&url, None).unwrap();
+        assert_eq!(
+            3,
+            connection
+                .fetch_objects(&repo, std::slice::from_ref(&second))
+                .unwrap()
+        );
+        assert_eq!(
+            0,
+            connection
+                .fetch_objects(&repo, std::slice::from_ref(&second))
+                .unwrap()
+        );
+        drop(connection);
+
+        let updates = fetch::fetch(&repo, &Remote::load(&repo, "origin").unwrap(), &[]).unwrap();
+        assert_eq!(Some(second), updates[0].new);
+
+        fs::remove_dir_all("./test_protocol").unwrap();
+    }
+}
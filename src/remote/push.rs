@@ -0,0 +1,143 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::remote::local::advertised_refs;
+use crate::remote::refspec::{is_excluded, Refspec};
+use crate::remote::{
+    check_advertised, check_object_format, connect_push, expand_ref, RefUpdate, Remote,
+};
+use crate::repo::refs::check_ref_format;
+use crate::repo::Repo;
+
+/// Push the refs matched by refspecs, else by `remote.<name>.push`, else the current branch,
+/// along with their objects. Updates which are not fast-forwards are rejected unless forced.
+pub fn push(
+    repo: &Repo,
+    remote: &Remote,
+    refspecs: &[Refspec],
+    force: bool,
+) -> error::Result<Vec<RefUpdate>> {
+    let mut transport = connect_push(remote.push_url(), repo.conf.as_ref())?;
+    check_object_format(repo, transport.object_format())?;
+
+    // Negative refspecs alone narrow down the default ones.
+    let refspecs: Vec<Refspec> = if !(refspecs.iter().all(|refspec| refspec.negative)) {
+        refspecs.to_vec()
+    } else if !(remote.push.is_empty()) {
+        remote.push.iter().chain(refspecs).cloned().collect()
+    } else {
+        let head = repo
+            .ref_symbolic_target("HEAD")
+            .filter(|head| head.starts_with("refs/heads/"))
+            .ok_or(Error::Remote(error::remote::Remote::DetachedHead))?;
+
+        let mut current = vec![Refspec {
+            force: false,
+            negative: false,
+            src: head.clone(),
+            dst: Some(head),
+        }];
+        current.extend_from_slice(refspecs);
+        current
+    };
+
+    let local_refs = advertised_refs(repo);
+    let remote_refs = transport.refs()?;
+    check_advertised(remote.push_url(), remote_refs.keys())?;
+    let no_match =
+        |name: &str| Error::Remote(error::remote::Remote::NoMatchingRef(name.to_owned()));
+
+    // (local ref, object or None to delete, remote ref, force)
+    let mut pushes: Vec<(String, Option<String>, String, bool)> = Vec::new();
+    for refspec in refspecs.iter().filter(|refspec| !(refspec.negative)) {
+        if refspec.src.is_empty() {
+            let dst = refspec.dst.as_deref().unwrap_or_default();
+            let (dst, _) = expand_ref(&remote_refs, dst).ok_or_else(|| no_match(dst))?;
+            pushes.push((String::new(), None, dst.clone(), true));
+        } else if refspec.is_wildcard() {
+            local_refs
+                .iter()
+                .filter_map(|(name, sha)| Some((name, sha, refspec.map(name)?)))
+                .for_each(|(name, sha, dst)| {
+                    pushes.push((name.clone(), Some(sha.clone()), dst, refspec.force))
+                });
+        } else {
+            let (name, sha) = expand_ref(&local_refs, &refspec.src)
+                .map(|(name, sha)| (name.clone(), sha.clone()))
+                .or_else(|| Some((refspec.src.clone(), repo.resolve_name(&refspec.src)?)))
+                .ok_or_else(|| no_match(&refspec.src))?;
+
+            // A short destination lives in the namespace of the source.
+            let namespace = if name.starts_with("refs/tags/") {
+                "refs/tags/"
+            } else {
+                "refs/heads/"
+            };
+            let dst = match &refspec.dst {
+                Some(dst) if dst.starts_with("refs/") => dst.clone(),
+                Some(dst) => format!("{}{}", namespace, dst),
+                None if name.starts_with("refs/") => name.clone(),
+                None => return Err(no_match(&refspec.src)),
+            };
+            pushes.push((name, Some(sha), dst, refspec.force));
+        }
+    }
+    pushes.retain(|(name, _, _, _)| name.is_empty() || !(is_excluded(&refspecs, name)));
+    if let Some((_, _, dst, _)) = pushes
+        .iter()
+        .find(|(_, _, dst, _)| !(check_ref_format(dst)))
+    {
+        return Err(Error::Repo(error::repo::Repo::InvalidReference(
+            dst.clone(),
+        )));
+    }
+
+    let mut updates: Vec<RefUpdate> = pushes
+        .iter()
+        .map(|(src, new, dst, refspec_force)| {
+            RefUpdate::with_old(
+                repo,
+                src,
+                dst,
+                remote_refs.get(dst).cloned(),
+                new.clone(),
+                force || *refspec_force,
+            )
+        })
+        .collect();
+
+    let count = transport.push_objects(repo, &mut updates)?;
+    debug!("{} objects sent to {}", count, remote.name);
+
+    // The remote-tracking refs follow what was pushed.
+    for update in updates.iter().filter(|update| !(update.is_rejected())) {
+        for tracking in remote
+            .fetch
+            .iter()
+            .filter_map(|fetch| fetch.map(&update.dst))
+        {
+            match &update.new {
+                Some(new) => repo.ref_update(&tracking, new)?,
+                None => repo.ref_delete(&tracking)?,
+            }
+        }
+    }
+
+    Ok(updates)
+}
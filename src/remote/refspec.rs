@@ -0,0 +1,187 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use std::fmt;
+
+/// A refspec maps refs of one repository to refs of another: `[+]<src>[:<dst>]`.
+/// `*` in both sides matches any part of a ref name, `+` allows non-fast-forward updates.
+/// A negative refspec, `^<src>`, excludes the refs it matches from the other refspecs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Refspec {
+    pub force: bool,
+    pub negative: bool,
+
+    /// Empty to delete dst when pushing
+    pub src: String,
+    pub dst: Option<String>,
+}
+
+impl Refspec {
+    pub fn parse(spec: &str) -> error::Result<Self> {
+        let invalid = || Error::Remote(error::remote::Remote::InvalidRefspec(spec.to_owned()));
+        let wildcards = |name: &str| name.matches('*').count();
+        let valid = |name: &str| {
+            !(name.contains(['\0', ' ', '~', '^', ':', '?', '[', '\\']) || name.contains(".."))
+        };
+
+        // Negative refspecs only name full source refs, they cannot be forced or mapped.
+        if let Some(src) = spec.strip_prefix('^') {
+            return if src.starts_with("refs/") && valid(src) && wildcards(src) <= 1 {
+                Ok(Refspec {
+                    force: false,
+                    negative: true,
+                    src: src.to_string(),
+                    dst: None,
+                })
+            } else {
+                Err(invalid())
+            };
+        }
+
+        let (force, rest) = match spec.strip_prefix('+') {
+            Some(rest) => (true, rest),
+            None => (false, spec),
+        };
+
+        let (src, dst) = match rest.split_once(':') {
+            Some((src, dst)) => (src.to_string(), Some(dst.to_string())),
+            None => (rest.to_string(), None),
+        };
+
+        match &dst {
+            _ if !(valid(&src)) || wildcards(&src) > 1 => Err(invalid()),
+            _ if src.is_empty() && (force || dst.as_deref().is_none_or(str::is_empty)) => {
+                Err(invalid())
+            }
+            Some(dst) if !(valid(dst)) || wildcards(&src) != wildcards(dst) => Err(invalid()),
+            None if wildcards(&src) > 0 => Err(invalid()),
+            _ => Ok(Refspec {
+                force,
+                negative: false,
+                src,
+                dst,
+            }),
+        }
+    }
+
+    pub fn is_wildcard(&self) -> bool {
+        self.src.contains('*')
+    }
+
+    /// Whether the ref name matches the source side of the refspec.
+    pub fn matches(&self, name: &str) -> bool {
+        expand(&self.src, &self.src, name).is_some()
+    }
+
+    /// The destination of the ref name if it matches the source side of the refspec.
+    pub fn map(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_deref().unwrap_or(&self.src);
+        (!(self.negative))
+            .then(|| expand(&self.src, dst, name))
+            .flatten()
+    }
+
+    /// The source of the ref name if it matches the destination side of the refspec.
+    pub fn reverse_map(&self, name: &str) -> Option<String> {
+        let dst = self.dst.as_deref().unwrap_or(&self.src);
+        (!(self.negative))
+            .then(|| expand(dst, &self.src, name))
+            .flatten()
+    }
+}
+
+/// Whether a negative refspec among refspecs excludes the ref name.
+pub fn is_excluded(refspecs: &[Refspec], name: &str) -> bool {
+    refspecs
+        .iter()
+        .any(|refspec| refspec.negative && refspec.matches(name))
+}
+
+/// Match name against pattern, substituting what `*` matched into replacement.
+fn expand(pattern: &str, replacement: &str, name: &str) -> Option<String> {
+    match pattern.split_once('*') {
+        Some((prefix, suffix)) => {
+            let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+            Some(replacement.replacen('*', matched, 1))
+        }
+        None if pattern == name => Some(replacement.to_string()),
+        None => None,
+    }
+}
+
+impl fmt::Display for Refspec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.force {
+            write!(f, "+")?;
+        }
+
+        if self.negative {
+            write!(f, "^")?;
+        }
+
+        match &self.dst {
+            Some(dst) => write!(f, "{}:{}", self.src, dst),
+            None => write!(f, "{}", self.src),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_excluded, Refspec};
+
+    #[test]
+    pub fn test_refspec() {
+        let refspec = Refspec::parse("+refs/heads/*:refs/remotes/origin/*").unwrap();
+
+        assert!(refspec.force);
+        assert_eq!(
+            Some("refs/remotes/origin/feature/x".to_string()),
+            refspec.map("refs/heads/feature/x")
+        );
+        assert_eq!(None, refspec.map("refs/tags/v1"));
+        assert_eq!("+refs/heads/*:refs/remotes/origin/*", refspec.to_string());
+
+        let delete = Refspec::parse(":refs/heads/old").unwrap();
+        assert!(delete.src.is_empty());
+
+        assert!(Refspec::parse("refs/heads/*:refs/remotes/origin/main").is_err());
+        assert!(Refspec::parse("refs/heads/*").is_err());
+        assert!(Refspec::parse("refs/heads/a..b").is_err());
+        assert!(Refspec::parse(":").is_err());
+
+        let refspecs = [
+            Refspec::parse("refs/heads/*:refs/remotes/origin/*").unwrap(),
+            Refspec::parse("^refs/heads/wip/*").unwrap(),
+        ];
+        assert!(refspecs[1].negative);
+        assert_eq!(None, refspecs[1].map("refs/heads/wip/x"));
+        assert!(is_excluded(&refspecs, "refs/heads/wip/x"));
+        assert!(!is_excluded(&refspecs, "refs/heads/main"));
+        assert_eq!("^refs/heads/wip/*", refspecs[1].to_string());
+        assert_eq!(
+            Some("refs/heads/main".to_string()),
+            refspecs[0].reverse_map("refs/remotes/origin/main")
+        );
+
+        assert!(Refspec::parse("^main").is_err());
+        assert!(Refspec::parse("^refs/heads/a:refs/heads/b").is_err());
+        assert!(Refspec::parse("+^refs/heads/a").is_err());
+    }
+}
@@ -0,0 +1,642 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error, Log};
+use crate::object::pack;
+use crate::r#const::project_info;
+use crate::remote::local::advertised_refs;
+use crate::remote::pkt_line::{self, Packet, PktReader};
+use crate::repo::refs::{check_ref_format, RefLock};
+use crate::repo::Repo;
+use std::collections::HashSet;
+use std::io::{self, BufRead, Write};
+
+fn bad_request(reason: &str) -> Error {
+    Error::Remote(error::remote::Remote::BadRequest(reason.to_owned()))
+}
+
+fn io_error(e: io::Error) -> Error {
+    Error::Remote(error::remote::Remote::Transport(
+        "client".to_string(),
+        e.to_string(),
+    ))
+}
+
+fn agent() -> String {
+    format!("agent={}/{}", project_info::NAME, env!("CARGO_PKG_VERSION"))
+}
+
+/// Read a packet, None when the client hung up.
+fn read(input: &mut dyn BufRead) -> error::Result<Option<Packet>> {
+    match PktReader::new(input).read() {
+        Ok(packet) => Ok(Some(packet)),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(io_error(e)),
+    }
+}
+
+/// Write text packets followed by a flush.
+fn write_lines(output: &mut dyn Write, lines: &[String]) -> error::Result<()> {
+    lines
+        .iter()
+        .try_for_each(|line| pkt_line::write_line(output, line))
+        .and_then(|_| pkt_line::write_flush(output))
+        .and_then(|_| output.flush())
+        .map_err(io_error)
+}
+
+/// The objects reachable from bases, which the client has when it has the bases.
+fn reachable(repo: &Repo, bases: &[String]) -> error::Result<HashSet<String>> {
+    Ok(repo
+        .missing_objects(bases, &|_| false)?
+        .into_iter()
+        .collect())
+}
+
+/// Serve `git-upload-pack` on repo with git's protocol version 2: advertise the capabilities,
+/// then answer `ls-refs` and `fetch` commands until the client is done.
+pub fn upload_pack(
+    repo: &Repo,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> error::Result<()> {
+    write_lines(
+        output,
+        &[
+            "version 2".to_string(),
+            agent(),
+            "ls-refs=unborn".to_string(),
+            "fetch".to_string(),
+            format!("object-format={}", repo.object_format().name()),
+        ],
+    )?;
+
+    loop {
+        let command = match read(input)? {
+            None | Some(Packet::Flush) => return Ok(()),
+            Some(packet) => packet.line().unwrap_or_default(),
+        };
+
+        // Capabilities come before the delimiter, arguments after it.
+        let mut args = Vec::new();
+        let mut in_args = false;
+        loop {
+            match read(input)?.ok_or_else(|| bad_request("truncated request"))? {
+                Packet::Flush => break,
+                Packet::Delim => in_args = true,
+                packet if in_args => args.push(packet.line().unwrap_or_default()),
+                _ => (),
+            }
+        }
+
+        match command.as_str() {
+            "command=ls-refs" => ls_refs(repo, &args, output)?,
+            "command=fetch" => fetch(repo, &args, output)?,
+            _ => return Err(bad_request(&format!("unknown {}", command))),
+        }
+    }
+}
+
+/// List `HEAD` and the refs under `refs/` starting with one of the requested prefixes.
+fn ls_refs(repo: &Repo, args: &[String], output: &mut dyn Write) -> error::Result<()> {
+    let prefixes: Vec<&str> = args
+        .iter()
+        .filter_map(|arg| arg.strip_prefix("ref-prefix "))
+        .collect();
+    let has = |arg: &str| args.iter().any(|candidate| candidate == arg);
+
+    let refs = advertised_refs(repo);
+
+    let mut lines = Vec::new();
+    if !(refs.contains_key("HEAD")) && has("unborn") {
+        if let Some(target) = repo.ref_symbolic_target("HEAD") {
+            lines.push(format!("unborn HEAD symref-target:{}", target));
+        }
+    }
+
+    for (name, sha) in refs.iter().filter(|(name, _)| {
+        prefixes.is_empty() || prefixes.iter().any(|prefix| name.starts_with(prefix))
+    }) {
+        let mut line = format!("{} {}", sha, name);
+        if has("symrefs") {
+            if let Some(target) = repo.ref_symbolic_target(name) {
+                line.push_str(&format!(" symref-target:{}", target));
+            }
+        }
+        if has("peel") {
+            if let Some(peeled) = repo.peel(sha).filter(|peeled| peeled != sha) {
+                line.push_str(&format!(" peeled:{}", peeled));
+            }
+        }
+        lines.push(line);
+    }
+
+    write_lines(output, &lines)
+}
+
+/// Answer a round of negotiation: acknowledge the haves repo has, then send the pack once
+/// the client is done or has something in common with repo.
+fn fetch(repo: &Repo, args: &[String], output: &mut dyn Write) -> error::Result<()> {
+    let values = |key: &str| -> Vec<String> {
+        args.iter()
+            .filter_map(|arg| arg.strip_prefix(key).map(String::from))
+            .collect()
+    };
+    let has = |arg: &str| args.iter().any(|candidate| candidate == arg);
+
+    // Only what the advertised refs reach can be asked for, not the unreachable objects
+    // left in the repository.
+    let wants = values("want ");
+    let tips: Vec<String> = advertised_refs(repo).into_values().collect();
+    if wants.iter().any(|want| !(tips.contains(want))) {
+        let ours = reachable(repo, &tips)?;
+        if let Some(unknown) = wants.iter().find(|want| !(ours.contains(*want))) {
+            pkt_line::write_line(output, &format!("ERR upload-pack: not our ref {}", unknown))
+                .and_then(|_| output.flush())
+                .map_err(io_error)?;
+            return Err(bad_request(&format!("not our ref {}", unknown)));
+        }
+    }
+    let common: Vec<String> = values("have ")
+        .into_iter()
+        .filter(|have| repo.has_object(have))
+        .collect();
+
+    let mut lines = Vec::new();
+    if !(has("done")) {
+        lines.push("acknowledgments".to_string());
+        lines.extend(common.iter().map(|sha| format!("ACK {}", sha)));
+        if common.is_empty() {
+            lines.push("NAK".to_string());
+            return write_lines(output, &lines);
+        }
+        lines.push("ready".to_string());
+    }
+
+    let known = reachable(repo, &common)?;
+    let mut objects = repo.missing_objects(&wants, &|sha| known.contains(sha))?;
+
+    // Annotated tags pointing into the pack come along when asked for.
+    if has("include-tag") {
+        let sent: HashSet<String> = objects.iter().cloned().collect();
+        let tags: Vec<String> = repo
+            .ref_list()
+            .into_iter()
+            .filter(|(name, sha)| {
+                name.starts_with("refs/tags/")
+                    && !(sent.contains(sha) || known.contains(sha))
+                    && repo
+                        .peel(sha)
+                        .is_some_and(|peeled| peeled != *sha && sent.contains(&peeled))
+            })
+            .map(|(_, sha)| sha)
+            .collect();
+        objects
+            .extend(repo.missing_objects(&tags, &|sha| sent.contains(sha) || known.contains(sha))?);
+    }
+
+    let pack = pack::write(repo, &objects)?;
+    let written: io::Result<()> = (|| {
+        for line in &lines {
+            pkt_line::write_line(output, line)?;
+        }
+        if !(lines.is_empty()) {
+            pkt_line::write_delim(output)?;
+        }
+        pkt_line::write_line(output, "packfile")?;
+        for chunk in pack.chunks(pkt_line::MAX_DATA_LEN - 1) {
+            pkt_line::write_data(output, &[&[1], chunk].concat())?;
+        }
+        pkt_line::write_flush(output)?;
+        output.flush()
+    })();
+
+    written.map_err(io_error)
+}
+
+/// One ref update requested by a push: `<old> <new> <ref>`.
+struct Command {
+    old: Option<String>,
+    new: Option<String>,
+    name: String,
+
+    /// Why the update is refused, None when it goes ahead
+    error: Option<String>,
+}
+
+impl Command {
+    fn line(&self, zero: &str) -> String {
+        format!(
+            "{} {} {}",
+            self.old.as_deref().unwrap_or(zero),
+            self.new.as_deref().unwrap_or(zero),
+            self.name
+        )
+    }
+}
+
+/// Serve `git-receive-pack` on repo with git's protocol version 0: advertise the refs, read
+/// the commands and the pack, then update the refs as the hooks and checks allow:
+/// `pre-receive` may refuse everything, `update` each ref, and `post-receive` learns
+/// what was updated. With `atomic`, a single refused update refuses them all.
+pub fn receive_pack(
+    repo: &Repo,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> error::Result<()> {
+    let format = repo.object_format();
+    let zero = "0".repeat(format.hex_len());
+    let capabilities = [
+        "report-status".to_string(),
+        "delete-refs".to_string(),
+        "atomic".to_string(),
+        "ofs-delta".to_string(),
+        format!("object-format={}", format.name()),
+        agent(),
+    ]
+    .join(" ");
+
+    let refs = repo.ref_list();
+    let mut lines: Vec<String> = refs
+        .iter()
+        .map(|(name, sha)| format!("{} {}", sha, name))
+        .collect();
+    if lines.is_empty() {
+        lines.push(format!("{} capabilities^{{}}", zero));
+    }
+    lines[0] = format!("{}\0{}", lines[0], capabilities);
+    write_lines(output, &lines)?;
+
+    let mut commands = Vec::new();
+    let mut requested = Vec::new();
+    while let Some(Packet::Data(data)) = read(input)? {
+        let line = String::from_utf8_lossy(data.strip_suffix(b"\n").unwrap_or(&data)).to_string();
+        let line = match line.split_once('\0') {
+            Some((line, capabilities)) => {
+                requested = capabilities.split(' ').map(String::from).collect();
+                line.to_string()
+            }
+            None => line,
+        };
+
+        let mut fields = line.splitn(3, ' ');
+        let mut sha = || {
+            fields
+                .next()
+                .filter(|sha| format.is_full_name(sha))
+                .map(|sha| (sha != zero).then(|| sha.to_string()))
+                .ok_or_else(|| bad_request("bad command"))
+        };
+        let (old, new) = (sha()?, sha()?);
+        let name = fields
+            .next()
+            .ok_or_else(|| bad_request("bad command"))?
+            .to_string();
+        let valid = name.starts_with("refs/") && check_ref_format(&name);
+        commands.push(Command {
+            old,
+            new,
+            name,
+            error: (!(valid)).then(|| "invalid ref name".to_string()),
+        });
+    }
+
+    // Nothing to do, the client had nothing to push.
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let unpacked = if commands.iter().any(|command| command.new.is_some()) {
+        pack::read(input, format)
+            .map_err(|e| e.to_string())
+            .and_then(|data| pack::index_pack(repo, &data).map_err(|e| e.fmt()))
+            .map(|count| debug!("{} objects received", count))
+    } else {
+        Ok(())
+    };
+
+    update_refs(repo, &mut commands, &zero, &requested, &unpacked);
+
+    if requested
+        .iter()
+        .any(|capability| capability == "report-status")
+    {
+        let mut report = vec![match &unpacked {
+            Ok(()) => "unpack ok".to_string(),
+            Err(reason) => format!("unpack {}", reason),
+        }];
+        report.extend(commands.iter().map(|command| match &command.error {
+            None => format!("ok {}", command.name),
+            Some(reason) => format!("ng {} {}", command.name, reason),
+        }));
+        write_lines(output, &report)?;
+    }
+
+    Ok(())
+}
+
+/// Check the commands and update the refs of those which pass, as the hooks allow.
+fn update_refs(
+    repo: &Repo,
+    commands: &mut [Command],
+    zero: &str,
+    requested: &[String],
+    unpacked: &Result<(), String>,
+) {
+    let refuse_all = |commands: &mut [Command], reason: &str| {
+        commands
+            .iter_mut()
+            .filter(|command| command.error.is_none())
+            .for_each(|command| command.error = Some(reason.to_string()))
+    };
+
+    if unpacked.is_err() {
+        refuse_all(commands, "unpacker error");
+        return;
+    }
+
+    let input: String = commands
+        .iter()
+        .filter(|command| command.error.is_none())
+        .map(|command| command.line(zero) + "\n")
+        .collect();
+    if !(repo.run_hook("pre-receive", &[], input.as_bytes())) {
+        refuse_all(commands, "pre-receive hook declined");
+        return;
+    }
+
+    let deny_non_fast_forwards = repo
+        .conf
+        .as_ref()
+        .and_then(|conf| conf.get_bool("receive.denynonfastforwards").ok().flatten())
+        .unwrap_or(false);
+
+    // Each ref stays locked from the check of its old value to its update, so that pushes
+    // running at the same time cannot both move it.
+    let mut locks: Vec<Option<RefLock>> = commands.iter().map(|_| None).collect();
+    for (command, lock) in commands
+        .iter_mut()
+        .zip(locks.iter_mut())
+        .filter(|(command, _)| command.error.is_none())
+    {
+        *lock = match repo.ref_lock(&command.name) {
+            Ok(lock) => Some(lock),
+            Err(_) => {
+                command.error = Some("failed to lock".to_string());
+                continue;
+            }
+        };
+
+        command.error = if repo.ref_resolve(&command.name) != command.old {
+            Some("stale info".to_string())
+        } else if command
+            .new
+            .as_ref()
+            .is_some_and(|new| !(repo.has_object(new)))
+        {
+            Some("missing necessary objects".to_string())
+        } else if !(repo.is_bare()) && repo.worktree_with_branch(&command.name).is_some() {
+            Some("branch is currently checked out".to_string())
+        } else if let (true, Some(old), Some(new)) =
+            (deny_non_fast_forwards, &command.old, &command.new)
+        {
+            (!(repo.is_ancestor(old, new))).then(|| "non-fast-forward".to_string())
+        } else {
+            None
+        };
+
+        let old = command.old.as_deref().unwrap_or(zero);
+        let new = command.new.as_deref().unwrap_or(zero);
+        if command.error.is_none() && !(repo.run_hook("update", &[&command.name, old, new], &[])) {
+            command.error = Some("hook declined".to_string());
+        }
+    }
+
+    let atomic = requested.iter().any(|capability| capability == "atomic");
+    if atomic && commands.iter().any(|command| command.error.is_some()) {
+        refuse_all(commands, "atomic push failure");
+    }
+
+    // The refs are only written once every command went through its checks.
+    for (command, lock) in commands.iter_mut().zip(locks) {
+        if let (None, Some(lock)) = (&command.error, lock) {
+            if let Err(e) = repo.ref_commit(lock, command.new.as_deref()) {
+                command.error = Some(e.fmt());
+            }
+        }
+    }
+
+    let updated: String = commands
+        .iter()
+        .filter(|command| command.error.is_none())
+        .map(|command| command.line(zero) + "\n")
+        .collect();
+    if !(updated.is_empty()) {
+        repo.run_hook("post-receive", &[], updated.as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{fetch, receive_pack};
+    use crate::commands::init::Init;
+    use crate::object::blob::Blob;
+    use crate::object::commit::{Commit, Kvlm};
+    use crate::object::operation::{write, write_raw};
+    use crate::object::pack;
+    use crate::object::tree::{Tree, TreeEntry};
+    use crate::remote::pkt_line::{self, Packet, PktReader};
+    use crate::repo::Repo;
+    use std::fs;
+    use std::os::unix::fs::PermissionsExt;
+
+    /// Send commands to the `git-receive-pack` of repo, returns its report.
+    fn push(repo: &Repo, commands: &[String], capabilities: &str) -> Vec<String> {
+        let mut input = Vec::new();
+        for (i, command) in commands.iter().enumerate() {
+            match i {
+                0 => pkt_line::write_line(&mut input, &format!("{}\0{}", command, capabilities)),
+                _ => pkt_line::write_line(&mut input, command),
+            }
+            .unwrap();
+        }
+        pkt_line::write_flush(&mut input).unwrap();
+        input.extend(pack::write(repo, &[]).unwrap());
+
+        let mut output = Vec::new();
+        receive_pack(repo, &mut input.as_slice(), &mut output).unwrap();
+
+        // Skip the advertisement.
+        let mut reader = PktReader::new(output.as_slice());
+        while reader.read().unwrap() != Packet::Flush {}
+
+        let mut report = Vec::new();
+        while let Some(line) = reader.read().unwrap().line() {
+            report.push(line);
+        }
+        report
+    }
+
+    #[test]
+    pub fn test_receive_pack_hooks() {
+        let _ = fs::remove_dir_all("./test_receive_pack");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_receive_pack/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        let blob = write(Box::new(Blob::new("one")), Some(&repo));
+        let tree = write(
+            Box::new(Tree::new(vec![TreeEntry {
+                mode: "100644".to_string(),
+                name: "file".to_string(),
+                sha: blob,
+            }])),
+            Some(&repo),
+        );
+        let commit = write(
+            Box::new(Commit::new(Kvlm {
+                headers: vec![("tree".to_string(), tree)],
+                message: "one\n".to_string(),
+            })),
+            Some(&repo),
+        );
+
+        let hook = |name: &str, script: &str| {
+            let path = repo.common_dir.join("hooks").join(name);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(&path, format!("#!/bin/sh\n{}\n", script)).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+        };
+        hook("update", "test \"$1\" != refs/heads/blocked");
+        hook("post-receive", "cat > received");
+
+        let zero = "0".repeat(40);
+        let commands = [
+            format!("{} {} refs/heads/blocked", zero, commit),
+            format!("{} {} refs/heads/master", zero, commit),
+        ];
+
+        // One refused update refuses an atomic push.
+        assert_eq!(
+            vec![
+                "unpack ok".to_string(),
+                "ng refs/heads/blocked hook declined".to_string(),
+                "ng refs/heads/master atomic push failure".to_string(),
+            ],
+            push(&repo, &commands, "report-status atomic")
+        );
+        assert_eq!(None, repo.ref_resolve("refs/heads/master"));
+        assert!(!(repo.lit_dir.join("received").exists()));
+
+        assert_eq!(
+            vec![
+                "unpack ok".to_string(),
+                "ng refs/heads/blocked hook declined".to_string(),
+                "ok refs/heads/master".to_string(),
+            ],
+            push(&repo, &commands, "report-status")
+        );
+        assert_eq!(Some(commit.clone()), repo.ref_resolve("refs/heads/master"));
+        assert_eq!(None, repo.ref_resolve("refs/heads/blocked"));
+        assert_eq!(
+            format!("{}\n", commands[1]),
+            fs::read_to_string(repo.lit_dir.join("received")).unwrap()
+        );
+
+        // An update from a stale old value is refused.
+        assert_eq!(
+            vec![
+                "unpack ok".to_string(),
+                "ng refs/heads/master stale info".to_string(),
+            ],
+            push(&repo, &commands[1..], "report-status")
+        );
+
+        // A ref someone else is updating is left alone.
+        let lock = repo.ref_lock("refs/heads/master").unwrap();
+        let update = format!("{} {} refs/heads/master", commit, zero);
+        assert_eq!(
+            vec![
+                "unpack ok".to_string(),
+                "ng refs/heads/master failed to lock".to_string(),
+            ],
+            push(&repo, std::slice::from_ref(&update), "report-status")
+        );
+        drop(lock);
+        assert_eq!(Some(commit.clone()), repo.ref_resolve("refs/heads/master"));
+
+        // Ref names leading out of the repository are refused.
+        assert_eq!(
+            vec![
+                "unpack ok".to_string(),
+                "ng refs/../../escaped-ref invalid ref name".to_string(),
+            ],
+            push(
+                &repo,
+                &[format!("{} {} refs/../../escaped-ref", zero, commit)],
+                "report-status"
+            )
+        );
+        assert!(!(fs::exists("./test_receive_pack/escaped-ref").unwrap()));
+
+        fs::remove_dir_all("./test_receive_pack").unwrap();
+    }
+
+    #[test]
+    pub fn test_upload_pack_wants() {
+        let _ = fs::remove_dir_all("./test_upload_pack_wants");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_upload_pack_wants/origin.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        let tree = write(Box::new(Tree::new(vec![])), Some(&repo));
+        let commit = write(
+            Box::new(Commit::new(Kvlm {
+                headers: vec![("tree".to_string(), tree.clone())],
+                message: "empty\n".to_string(),
+            })),
+            Some(&repo),
+        );
+        repo.ref_update("refs/heads/master", &commit).unwrap();
+        let secret = write_raw(&repo, "blob", b"deleted secret");
+
+        // What the refs reach can be fetched, tips or not.
+        for want in [&commit, &tree] {
+            let args = [format!("want {}", want), "done".to_string()];
+            assert!(fetch(&repo, &args, &mut Vec::new()).is_ok());
+        }
+
+        let mut output = Vec::new();
+        let args = [format!("want {}", secret), "done".to_string()];
+        assert!(fetch(&repo, &args, &mut output).is_err());
+        assert_eq!(
+            Packet::Data(format!("ERR upload-pack: not our ref {}\n", secret).into_bytes()),
+            PktReader::new(output.as_slice()).read().unwrap()
+        );
+
+        fs::remove_dir_all("./test_upload_pack_wants").unwrap();
+    }
+}
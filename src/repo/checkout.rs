@@ -0,0 +1,663 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::index::{Index, IndexEntry};
+use crate::object::blob::Blob;
+use crate::object::check;
+use crate::object::operation;
+use crate::object::tree::{Tree, TreeEntry};
+use crate::repo::Repo;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const MODE_EXECUTABLE: &str = "100755";
+const MODE_SYMLINK: &str = "120000";
+const MODE_GITLINK: &str = "160000";
+
+/// A change the checkout has to apply to the worktree
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    Create(String, TreeEntry),
+    Update(String, TreeEntry),
+    Delete(String),
+}
+
+impl Change {
+    pub fn path(&self) -> &str {
+        match self {
+            Change::Create(path, _) | Change::Update(path, _) | Change::Delete(path) => path,
+        }
+    }
+}
+
+/// Reported to the progress callback after each change is applied.
+pub struct Progress<'a> {
+    pub completed: usize,
+    pub total: usize,
+    pub change: &'a Change,
+}
+
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub created: usize,
+    pub updated: usize,
+    pub deleted: usize,
+}
+
+impl Repo {
+    pub fn index_path(&self) -> PathBuf {
+        Repo::repo_path(&self.lit_dir, &["index"]).unwrap()
+    }
+
+    /// Recursively read the tree sha, returning its blobs keyed by their full path. Every
+    /// name has to be one a worktree can hold, so that no path leaves the worktree or goes
+    /// through the repository directory.
+    pub fn flatten_tree(&self, sha: &str) -> error::Result<BTreeMap<String, TreeEntry>> {
+        let mut files = BTreeMap::new();
+        self.flatten_tree_into(sha, "", &mut files)?;
+        Ok(files)
+    }
+
+    fn flatten_tree_into(
+        &self,
+        sha: &str,
+        prefix: &str,
+        files: &mut BTreeMap<String, TreeEntry>,
+    ) -> error::Result<()> {
+        let data = self.read_typed(sha, "tree")?;
+        let entries = Tree::parse(&data, self.object_format()).ok_or(Error::Object(
+            error::object::Object::Malformed(sha.to_owned(), data.len()),
+        ))?;
+
+        for entry in entries {
+            check::check_worktree_name(&entry.name).map_err(|reason| {
+                Error::Object(error::object::Object::Invalid(
+                    sha.to_owned(),
+                    "tree".to_string(),
+                    reason,
+                ))
+            })?;
+            let path = format!("{}{}", prefix, entry.name);
+
+            if entry.is_tree() {
+                self.flatten_tree_into(&entry.sha, &format!("{}/", path), files)?;
+            } else {
+                files.insert(path, entry);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn read_typed(&self, sha: &str, expected: &str) -> error::Result<Vec<u8>> {
+        match operation::read_raw(self, sha) {
+            None => Err(Error::Object(error::object::Object::NotFound(
+                sha.to_owned(),
+            ))),
+            Some((fmt, _)) if fmt != expected => Err(Error::Object(
+                error::object::Object::UnexpectedType(sha.to_owned(), expected.to_owned(), fmt),
+            )),
+            Some((_, data)) => Ok(data),
+        }
+    }
+
+    /// Compute the minimal set of changes turning the files recorded in index into target.
+    pub fn checkout_changes(index: &Index, target: &BTreeMap<String, TreeEntry>) -> Vec<Change> {
+        let mut changes: Vec<Change> = index
+            .entries
+            .iter()
+            .filter(|entry| !target.contains_key(&entry.path))
+            .map(|entry| Change::Delete(entry.path.clone()))
+            .collect();
+
+        changes.dedup();
+
+        target
+            .iter()
+            .for_each(|(path, entry)| match index.get(path) {
+                None => changes.push(Change::Create(path.clone(), entry.clone())),
+                Some(current) if current.sha != entry.sha || current.tree_mode() != entry.mode => {
+                    changes.push(Change::Update(path.clone(), entry.clone()))
+                }
+                Some(_) => (),
+            });
+
+        changes
+    }
+
+    /// Materialize the tree sha into the worktree and make the index match it.
+    ///
+    /// Only the files that differ from the index are touched. Unless force is set,
+    /// nothing is written if a change would lose local modifications or untracked files.
+    pub fn checkout_tree(
+        &self,
+        sha: &str,
+        force: bool,
+        progress: &mut dyn FnMut(Progress),
+    ) -> error::Result<Summary> {
+        self.require_worktree()?;
+
+        let target = self.flatten_tree(sha)?;
+        let index = Index::read(&self.index_path(), self.object_format())?;
+        index.check_writable()?;
+        let changes = Self::checkout_changes(&index, &target);
+
+        if !force {
+            self.check_conflicts(&index, &changes)?;
+        }
+
+        let mut summary = Summary::default();
+        let mut ordered: Vec<&Change> = changes
+            .iter()
+            .filter(|change| matches!(change, Change::Delete(_)))
+            .collect();
+        ordered.extend(
+            changes
+                .iter()
+                .filter(|change| !matches!(change, Change::Delete(_))),
+        );
+
+        for (completed, change) in ordered.iter().enumerate() {
+            match change {
+                Change::Delete(path) => {
+                    self.remove_file(path)?;
+                    summary.deleted += 1;
+                }
+                Change::Create(path, entry) => {
+                    self.materialize(path, entry)?;
+                    summary.created += 1;
+                }
+                Change::Update(path, entry) => {
+                    self.materialize(path, entry)?;
+                    summary.updated += 1;
+                }
+            }
+
+            progress(Progress {
+                completed: completed + 1,
+                total: ordered.len(),
+                change,
+            });
+        }
+
+        self.write_checkout_index(&index, &target)?;
+        Ok(summary)
+    }
+
+    fn check_conflicts(&self, index: &Index, changes: &[Change]) -> error::Result<()> {
+        let mut modified = Vec::new();
+        let mut untracked = Vec::new();
+
+        for change in changes {
+            let path = change.path().to_string();
+            let file = self.worktree.join(&path);
+
+            if let Change::Update(_, _) | Change::Delete(_) = change {
+                if let Some(entry) = index.get(&path) {
+                    if self.is_modified(entry) {
+                        modified.push(path.clone());
+                    }
+                }
+            }
+
+            // A directory standing at path is removed with everything in it,
+            // unless a submodule is checked out there.
+            let gitlink = matches!(change, Change::Create(_, entry) if entry.mode == MODE_GITLINK);
+            if let Ok(meta) = fs::symlink_metadata(&file) {
+                let lost = if meta.is_dir() {
+                    !gitlink && self.has_untracked(index, &file)
+                } else {
+                    matches!(change, Change::Create(_, entry) if self.hash_worktree_file(&file) != entry.sha)
+                };
+                if lost {
+                    untracked.push(path.clone());
+                }
+            }
+
+            // A file standing where a parent directory has to be created is removed.
+            if let Change::Create(_, _) | Change::Update(_, _) = change {
+                let mut parent = Path::new(&path).parent();
+                while let Some(dir) = parent.filter(|dir| dir != &Path::new("")) {
+                    let name = dir.to_str().unwrap();
+                    if fs::symlink_metadata(self.worktree.join(dir))
+                        .is_ok_and(|meta| !(meta.is_dir()))
+                        && index.get(name).is_none()
+                    {
+                        untracked.push(name.to_string());
+                    }
+                    parent = dir.parent();
+                }
+            }
+        }
+
+        untracked.sort();
+        untracked.dedup();
+
+        if !(modified.is_empty()) {
+            Err(Error::Worktree(error::worktree::Worktree::LocalChanges(
+                modified,
+            )))
+        } else if !(untracked.is_empty()) {
+            Err(Error::Worktree(
+                error::worktree::Worktree::UntrackedOverwritten(untracked),
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Whether dir holds a file that is not in the index.
+    fn has_untracked(&self, index: &Index, dir: &Path) -> bool {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return false,
+        };
+
+        entries.flatten().any(|entry| {
+            let file = entry.path();
+            if entry.file_type().is_ok_and(|typ| typ.is_dir()) {
+                self.has_untracked(index, &file)
+            } else {
+                let path = file.strip_prefix(&self.worktree).unwrap();
+                index.get(path.to_str().unwrap()).is_none()
+            }
+        })
+    }
+
+    /// Whether the worktree copy of an index entry differs from what the index recorded.
+    /// A missing file is not a modification worth protecting.
+    pub fn is_modified(&self, entry: &IndexEntry) -> bool {
+        let file = self.worktree.join(&entry.path);
+
+        match fs::symlink_metadata(&file) {
+            Err(_) => false,
+            Ok(meta) if entry.is_fresh(&meta) => false,
+            Ok(_) => self.hash_worktree_file(&file) != entry.sha,
+        }
+    }
+
+    /// Compute the blob sha of a worktree file, the link target is hashed for symlinks.
+    pub fn hash_worktree_file(&self, file: &Path) -> String {
+        let data = match fs::read_link(file) {
+            Ok(target) => target.to_str().unwrap().as_bytes().to_vec(),
+            Err(_) => fs::read(file).unwrap_or_default(),
+        };
+
+        operation::hash_object(&Blob::new(data), self.object_format())
+    }
+
+    fn remove_file(&self, path: &str) -> error::Result<()> {
+        let file = self.worktree.join(path);
+
+        match fs::symlink_metadata(&file) {
+            Ok(meta) if meta.is_dir() => {
+                fs::remove_dir_all(&file).map_err(|e| Self::io_error(&file, e))?
+            }
+            Ok(_) => fs::remove_file(&file).map_err(|e| Self::io_error(&file, e))?,
+            Err(_) => (),
+        }
+
+        // Prune the directories left empty, up to the worktree root.
+        let mut dir = file.parent();
+        while let Some(parent) = dir {
+            if parent == self.worktree || fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+
+        Ok(())
+    }
+
+    /// Write the blob of entry to path through a temporary file renamed into place,
+    /// so that the worktree never contains a partially written file.
+    fn materialize(&self, path: &str, entry: &TreeEntry) -> error::Result<()> {
+        let file = self.worktree.join(path);
+        let parent = file.parent().unwrap();
+
+        if entry.mode == MODE_GITLINK {
+            return fs::create_dir_all(&file).map_err(|e| Self::io_error(&file, e));
+        }
+
+        // A file may stand where a directory has to be created, or the opposite.
+        Self::clear_path(parent, &self.worktree)?;
+        if fs::symlink_metadata(&file).is_ok_and(|meta| meta.is_dir()) {
+            fs::remove_dir_all(&file).map_err(|e| Self::io_error(&file, e))?;
+        }
+        fs::create_dir_all(parent).map_err(|e| Self::io_error(&file, e))?;
+
+        let data = self.read_typed(&entry.sha, "blob")?;
+        let temp = parent.join(format!(
+            ".{}.lit-tmp",
+            file.file_name().unwrap().to_str().unwrap()
+        ));
+        let _ = fs::remove_file(&temp);
+
+        if entry.mode == MODE_SYMLINK {
+            Self::write_symlink(&temp, &data).map_err(|e| Self::io_error(&file, e))?;
+        } else {
+            fs::write(&temp, &data).map_err(|e| Self::io_error(&file, e))?;
+            Self::set_executable(&temp, entry.mode == MODE_EXECUTABLE)
+                .map_err(|e| Self::io_error(&file, e))?;
+        }
+
+        fs::rename(&temp, &file).map_err(|e| {
+            let _ = fs::remove_file(&temp);
+            Self::io_error(&file, e)
+        })
+    }
+
+    /// Remove the files standing where the directories leading to dir should be.
+    fn clear_path(dir: &Path, worktree: &Path) -> error::Result<()> {
+        let mut current = dir;
+
+        while current != worktree {
+            if let Ok(meta) = fs::symlink_metadata(current) {
+                if !(meta.is_dir()) {
+                    fs::remove_file(current).map_err(|e| Self::io_error(current, e))?;
+                }
+            }
+
+            match current.parent() {
+                Some(parent) => current = parent,
+                None => break,
+            }
+        }
+
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn write_symlink(link: &Path, target: &[u8]) -> std::io::Result<()> {
+        use std::os::unix::ffi::OsStrExt;
+        std::os::unix::fs::symlink(std::ffi::OsStr::from_bytes(target), link)
+    }
+
+    /// Platforms without symlinks get a plain file containing the link target, like git does.
+    #[cfg(not(unix))]
+    fn write_symlink(link: &Path, target: &[u8]) -> std::io::Result<()> {
+        fs::write(link, target)
+    }
+
+    #[cfg(unix)]
+    fn set_executable(file: &Path, executable: bool) -> std::io::Result<()> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let mut permissions = fs::metadata(file)?.permissions();
+        let mode = permissions.mode();
+        permissions.set_mode(if executable {
+            mode | ((mode & 0o444) >> 2)
+        } else {
+            mode & !0o111
+        });
+        fs::set_permissions(file, permissions)
+    }
+
+    #[cfg(not(unix))]
+    fn set_executable(_file: &Path, _executable: bool) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    fn write_checkout_index(
+        &self,
+        index: &Index,
+        target: &BTreeMap<String, TreeEntry>,
+    ) -> error::Result<()> {
+        let mut entries = Vec::with_capacity(target.len());
+
+        for (path, entry) in target {
+            let mode = u32::from_str_radix(&entry.mode, 8).unwrap();
+
+            match index.get(path) {
+                Some(current) if current.sha == entry.sha && current.mode == mode => {
+                    entries.push(current.clone())
+                }
+                _ => {
+                    let file = self.worktree.join(path);
+                    let meta = fs::symlink_metadata(&file).map_err(|e| Self::io_error(&file, e))?;
+                    entries.push(IndexEntry::from_metadata(path, &entry.sha, mode, &meta));
+                }
+            }
+        }
+
+        Index {
+            entries,
+            version: index.version,
+            extensions: index.extensions.clone(),
+        }
+        .write(&self.index_path(), self.object_format())
+    }
+
+    fn io_error(path: &Path, error: std::io::Error) -> Error {
+        Error::Worktree(error::worktree::Worktree::Io(
+            path.to_owned(),
+            error.to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Change;
+    use crate::object::blob::Blob;
+    use crate::object::operation::write;
+    use crate::object::tree::{Tree, TreeEntry};
+    use crate::repo::Repo;
+    use crate::test_util::create;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn entry(mode: &str, name: &str, sha: String) -> TreeEntry {
+        TreeEntry {
+            mode: mode.to_string(),
+            name: name.to_string(),
+            sha,
+        }
+    }
+
+    fn blob(repo: &Repo, data: &str) -> String {
+        write(Box::new(Blob::new(data)), Some(repo))
+    }
+
+    fn tree(repo: &Repo, entries: Vec<TreeEntry>) -> String {
+        write(Box::new(Tree::new(entries)), Some(repo))
+    }
+
+    #[test]
+    pub fn test_checkout_tree() {
+        let repo = create("./test_checkout_tree", false);
+
+        let readme = blob(&repo, "readme\n");
+        let script = blob(&repo, "#!/bin/sh\n");
+        let src = tree(&repo, vec![entry("100755", "build.sh", script.clone())]);
+        let first = tree(
+            &repo,
+            vec![
+                entry("100644", "README.md", readme.clone()),
+                entry("40000", "src", src),
+            ],
+        );
+
+        let mut reported = 0;
+        let summary = repo
+            .checkout_tree(&first, false, &mut |progress| {
+                reported = progress.completed;
+                assert_eq!(2, progress.total);
+            })
+            .unwrap();
+
+        assert_eq!(2, summary.created);
+        assert_eq!(2, reported);
+        assert_eq!(
+            "readme\n",
+            fs::read_to_string("./test_checkout_tree/README.md").unwrap()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata("./test_checkout_tree/src/build.sh")
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(0o111, mode & 0o111);
+        }
+
+        // Replace the `src` directory by a symlink and drop README.md
+        let second = tree(&repo, vec![entry("120000", "src", readme.clone())]);
+        let summary = repo.checkout_tree(&second, false, &mut |_| ()).unwrap();
+
+        assert_eq!(2, summary.deleted);
+        assert_eq!(1, summary.created);
+        assert!(!PathBuf::from("./test_checkout_tree/README.md").exists());
+
+        #[cfg(unix)]
+        assert_eq!(
+            PathBuf::from("readme\n"),
+            fs::read_link("./test_checkout_tree/src").unwrap()
+        );
+
+        fs::remove_dir_all("./test_checkout_tree").unwrap();
+    }
+
+    #[test]
+    pub fn test_checkout_refuses_to_lose_changes() {
+        let repo = create("./test_checkout_conflict", false);
+
+        let old = blob(&repo, "old\n");
+        let new = blob(&repo, "new\n");
+        let first = tree(&repo, vec![entry("100644", "file", old)]);
+        let second = tree(&repo, vec![entry("100644", "file", new)]);
+
+        repo.checkout_tree(&first, false, &mut |_| ()).unwrap();
+        fs::write("./test_checkout_conflict/file", "local edit\n").unwrap();
+
+        assert!(repo.checkout_tree(&second, false, &mut |_| ()).is_err());
+        assert_eq!(
+            "local edit\n",
+            fs::read_to_string("./test_checkout_conflict/file").unwrap()
+        );
+
+        repo.checkout_tree(&second, true, &mut |_| ()).unwrap();
+        assert_eq!(
+            "new\n",
+            fs::read_to_string("./test_checkout_conflict/file").unwrap()
+        );
+
+        fs::remove_dir_all("./test_checkout_conflict").unwrap();
+    }
+
+    #[test]
+    pub fn test_checkout_keeps_untracked_files() {
+        let repo = create("./test_checkout_untracked", false);
+
+        let data = blob(&repo, "data\n");
+        let inner = tree(&repo, vec![entry("100644", "file", data.clone())]);
+        let as_dir = tree(&repo, vec![entry("40000", "dir", inner.clone())]);
+        let as_file = tree(&repo, vec![entry("100644", "dir", data.clone())]);
+        let nested = tree(
+            &repo,
+            vec![
+                entry("40000", "dir", inner),
+                entry(
+                    "40000",
+                    "new",
+                    tree(&repo, vec![entry("100644", "file", data)]),
+                ),
+            ],
+        );
+
+        // A tracked directory holding an untracked file is not replaced by a file
+        repo.checkout_tree(&as_dir, false, &mut |_| ()).unwrap();
+        fs::write("./test_checkout_untracked/dir/untracked", "mine\n").unwrap();
+        assert!(repo.checkout_tree(&as_file, false, &mut |_| ()).is_err());
+        assert_eq!(
+            "mine\n",
+            fs::read_to_string("./test_checkout_untracked/dir/untracked").unwrap()
+        );
+
+        // An untracked file is not replaced by a directory created above a file
+        fs::write("./test_checkout_untracked/new", "mine\n").unwrap();
+        assert!(repo.checkout_tree(&nested, false, &mut |_| ()).is_err());
+        assert_eq!(
+            "mine\n",
+            fs::read_to_string("./test_checkout_untracked/new").unwrap()
+        );
+
+        repo.checkout_tree(&nested, true, &mut |_| ()).unwrap();
+        assert!(PathBuf::from("./test_checkout_untracked/new/file").is_file());
+        repo.checkout_tree(&as_file, true, &mut |_| ()).unwrap();
+        assert!(PathBuf::from("./test_checkout_untracked/dir").is_file());
+
+        fs::remove_dir_all("./test_checkout_untracked").unwrap();
+    }
+
+    #[test]
+    pub fn test_checkout_refuses_bad_names() {
+        let repo = create("./test_checkout_names/repo", false);
+
+        let data = blob(&repo, "pwned\n");
+        let inner = tree(&repo, vec![entry("100644", "pwned", data.clone())]);
+        for name in ["..", ".", ".git", ".GIT", ".Lit"] {
+            let bad = tree(
+                &repo,
+                vec![
+                    entry("100644", "a", data.clone()),
+                    entry("40000", name, inner.clone()),
+                ],
+            );
+            assert!(repo.checkout_tree(&bad, false, &mut |_| ()).is_err());
+        }
+
+        // Nothing is written when a single name is bad.
+        assert!(!PathBuf::from("./test_checkout_names/repo/a").exists());
+        assert!(!PathBuf::from("./test_checkout_names/pwned").exists());
+        assert!(!PathBuf::from("./test_checkout_names/repo/.git/pwned").exists());
+
+        fs::remove_dir_all("./test_checkout_names").unwrap();
+    }
+
+    #[test]
+    pub fn test_checkout_changes_is_minimal() {
+        let repo = create("./test_checkout_minimal", false);
+
+        let a = blob(&repo, "a\n");
+        let b = blob(&repo, "b\n");
+        let first = tree(
+            &repo,
+            vec![entry("100644", "a", a.clone()), entry("100644", "b", b)],
+        );
+        repo.checkout_tree(&first, false, &mut |_| ()).unwrap();
+
+        let c = blob(&repo, "c\n");
+        let second = tree(
+            &repo,
+            vec![entry("100644", "a", a), entry("100644", "c", c)],
+        );
+        let index = crate::index::Index::read(&repo.index_path(), repo.object_format()).unwrap();
+        let changes = Repo::checkout_changes(&index, &repo.flatten_tree(&second).unwrap());
+
+        assert_eq!(2, changes.len());
+        assert_eq!(Change::Delete("b".to_string()), changes[0]);
+        assert_eq!("c", changes[1].path());
+
+        fs::remove_dir_all("./test_checkout_minimal").unwrap();
+    }
+}
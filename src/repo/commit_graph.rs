@@ -0,0 +1,426 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::object::commit::Kvlm;
+use crate::object::commit_graph::{self, CommitGraph, GraphCommit, MAX_GENERATION};
+use crate::object::operation;
+use crate::object::tree::{Tree, TreeEntry};
+use crate::repo::Repo;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::PathBuf;
+
+/// The generation of commits the commit-graph does not have: anything may be below them
+pub const GENERATION_INFINITY: u32 = u32::MAX;
+
+impl Repo {
+    pub fn commit_graph_path(&self) -> PathBuf {
+        self.common_dir
+            .join("objects")
+            .join("info")
+            .join("commit-graph")
+    }
+
+    /// The commit-graph, read the first time it is needed, unless `core.commitGraph` is false.
+    pub fn commit_graph(&self) -> Option<&CommitGraph> {
+        self.commit_graph
+            .get_or_init(|| {
+                let enabled = self
+                    .conf
+                    .as_ref()
+                    .and_then(|conf| conf.get_bool("core.commitGraph").ok().flatten())
+                    .unwrap_or(true);
+                let data = fs::read(self.commit_graph_path())
+                    .ok()
+                    .filter(|_| enabled)?;
+
+                let graph = CommitGraph::parse(data, self.object_format());
+                if graph.is_none() {
+                    warn!(
+                        "Ignoring the malformed {}",
+                        self.commit_graph_path().display()
+                    );
+                }
+                graph
+            })
+            .as_ref()
+    }
+
+    /// Commit sha as the commit-graph stores it, read from the object when the commit-graph
+    /// does not have it, its generation being unknown then.
+    pub fn graph_commit(&self, sha: &str) -> Option<GraphCommit> {
+        if let Some(commit) = self.commit_graph().and_then(|graph| graph.commit(sha)) {
+            return Some(commit);
+        }
+
+        match operation::read_raw(self, sha)? {
+            (fmt, data) if fmt == "commit" => {
+                let mut commit = Self::parse_graph_commit(&data)?;
+                commit.generation = GENERATION_INFINITY;
+                Some(commit)
+            }
+            _ => None,
+        }
+    }
+
+    /// The tree, the parents and the committer date of a commit, its generation left to 0.
+    fn parse_graph_commit(data: &[u8]) -> Option<GraphCommit> {
+        let kvlm = Kvlm::parse(data)?;
+        // `<name> <email> <timestamp> <timezone>`
+        let time = kvlm
+            .get("committer")
+            .and_then(|committer| committer.rsplit(' ').nth(1))
+            .and_then(|time| time.parse().ok())
+            .unwrap_or(0);
+
+        Some(GraphCommit {
+            tree: kvlm.get("tree")?.to_string(),
+            parents: kvlm
+                .get_all("parent")
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            generation: 0,
+            time,
+        })
+    }
+
+    pub(super) fn tree_entries(
+        &self,
+        sha: Option<&str>,
+    ) -> error::Result<BTreeMap<String, TreeEntry>> {
+        let sha = match sha {
+            Some(sha) => sha,
+            None => return Ok(BTreeMap::new()),
+        };
+
+        let data = self.read_typed(sha, "tree")?;
+        let entries = Tree::parse(&data, self.object_format()).ok_or(Error::Object(
+            error::object::Object::Malformed(sha.to_owned(), data.len()),
+        ))?;
+        Ok(entries
+            .into_iter()
+            .map(|entry| (entry.name.clone(), entry))
+            .collect())
+    }
+
+    /// The files that differ between the trees old and new, identical subtrees being skipped.
+    pub(super) fn changed_paths(
+        &self,
+        old: Option<&str>,
+        new: Option<&str>,
+        prefix: &str,
+        paths: &mut Vec<String>,
+    ) -> error::Result<()> {
+        let old = self.tree_entries(old)?;
+        let new = self.tree_entries(new)?;
+        let names: BTreeSet<&String> = old.keys().chain(new.keys()).collect();
+
+        for name in names {
+            let (old, new) = (old.get(name), new.get(name));
+            if old.is_some_and(|old| Some(old) == new) {
+                continue;
+            }
+
+            let path = format!("{}{}", prefix, name);
+            if old.is_some_and(|old| !old.is_tree()) || new.is_some_and(|new| !new.is_tree()) {
+                paths.push(path.clone());
+            }
+            let old = old.filter(|old| old.is_tree()).map(|old| old.sha.as_str());
+            let new = new.filter(|new| new.is_tree()).map(|new| new.sha.as_str());
+            if old.is_some() || new.is_some() {
+                self.changed_paths(old, new, &format!("{}/", path), paths)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the commit-graph of the commits reachable from the refs and the HEADs, with the
+    /// filters of the paths each commit changes compared to its first parent when
+    /// changed_paths, or when the current commit-graph has them. Returns the number of commits.
+    pub fn write_commit_graph(&self, changed_paths: bool) -> error::Result<usize> {
+        let previous = self.commit_graph();
+        let changed_paths = changed_paths
+            || previous
+                .is_some_and(|graph| graph.count() > 0 && graph.bloom_filter_at(0).is_some());
+
+        let mut tips: Vec<String> = self.ref_list().into_values().collect();
+        tips.extend(
+            self.worktree_list_entries()
+                .into_iter()
+                .filter_map(|worktree| worktree.head),
+        );
+
+        // Read the commits, then give each one a generation once its parents have one.
+        let mut commits: BTreeMap<String, GraphCommit> = BTreeMap::new();
+        let mut stack: Vec<String> = tips
+            .iter()
+            .filter_map(|tip| self.peel(tip))
+            .filter(|sha| {
+                self.objects
+                    .read_header(sha)
+                    .is_some_and(|(fmt, _)| fmt == "commit")
+            })
+            .collect();
+        while let Some(sha) = stack.pop() {
+            if commits.contains_key(&sha) {
+                continue;
+            }
+
+            let data = self.read_typed(&sha, "commit")?;
+            let commit = Self::parse_graph_commit(&data).ok_or(Error::Object(
+                error::object::Object::Malformed(sha.clone(), data.len()),
+            ))?;
+            stack.extend(commit.parents.iter().cloned());
+            commits.insert(sha, commit);
+        }
+
+        let mut pending: Vec<String> = commits.keys().cloned().collect();
+        while let Some(sha) = pending.pop() {
+            if commits[&sha].generation > 0 {
+                continue;
+            }
+
+            let parents = commits[&sha].parents.clone();
+            let missing: Vec<String> = parents
+                .iter()
+                .filter(|parent| commits[*parent].generation == 0)
+                .cloned()
+                .collect();
+            if missing.is_empty() {
+                let highest = parents
+                    .iter()
+                    .map(|parent| commits[parent].generation)
+                    .max();
+                commits.get_mut(&sha).unwrap().generation =
+                    highest.map_or(1, |highest| (highest + 1).min(MAX_GENERATION));
+            } else {
+                pending.push(sha);
+                pending.extend(missing);
+            }
+        }
+
+        let mut filters = BTreeMap::new();
+        if changed_paths {
+            for (sha, commit) in &commits {
+                // Filters are kept as they are, the commits never change.
+                let kept = previous
+                    .and_then(|graph| graph.bloom_filter_at(graph.find(sha)?))
+                    .filter(|(version, _)| *version == 1)
+                    .map(|(_, filter)| filter.to_vec());
+
+                let filter = match kept {
+                    Some(filter) => filter,
+                    None => {
+                        let mut paths = Vec::new();
+                        let parent = commit
+                            .parents
+                            .first()
+                            .map(|parent| commits[parent].tree.as_str());
+                        self.changed_paths(parent, Some(&commit.tree), "", &mut paths)?;
+                        commit_graph::bloom_filter(Some(&paths))
+                    }
+                };
+                filters.insert(sha.clone(), filter);
+            }
+        }
+
+        let data = CommitGraph::serialize(
+            &commits,
+            changed_paths.then_some(&filters),
+            self.object_format(),
+        );
+        let path = self.commit_graph_path();
+        let temp = path.with_extension("lock");
+        let io_error = |e: std::io::Error| {
+            Error::Object(error::object::Object::Io(path.clone(), e.to_string()))
+        };
+        fs::create_dir_all(path.parent().unwrap()).map_err(io_error)?;
+        fs::write(&temp, data)
+            .and_then(|_| fs::rename(&temp, &path))
+            .map_err(io_error)?;
+
+        Ok(commits.len())
+    }
+
+    /// Check the commit-graph against the commits it describes: its checksum, the order of
+    /// the commits, and the tree, parents, date and generation of each one.
+    pub fn verify_commit_graph(&self) -> Vec<(String, String)> {
+        let path = self.commit_graph_path();
+        let file = path.display().to_string();
+        let graph = match fs::read(&path) {
+            Ok(data) => match CommitGraph::parse(data, self.object_format()) {
+                Some(graph) => graph,
+                None => return vec![(file, "malformed commit-graph".to_string())],
+            },
+            Err(_) => return Vec::new(),
+        };
+
+        let mut problems = Vec::new();
+        if !(graph.checksum_matches()) {
+            problems.push((file, "checksum mismatch".to_string()));
+        }
+
+        for i in 0..graph.count() {
+            let sha = graph.name(i);
+            if i > 0 && graph.name(i - 1) >= sha {
+                problems.push((sha.clone(), "commits out of order".to_string()));
+            }
+            if graph.find(&sha) != Some(i) {
+                problems.push((sha.clone(), "wrong fanout".to_string()));
+            }
+
+            let stored = match graph.commit_at(i) {
+                Some(commit) => commit,
+                None => {
+                    problems.push((sha, "invalid parent position".to_string()));
+                    continue;
+                }
+            };
+            let actual = match operation::read_raw(self, &sha) {
+                Some((fmt, data)) if fmt == "commit" => Self::parse_graph_commit(&data),
+                _ => None,
+            };
+            let actual = match actual {
+                Some(actual) => actual,
+                None => {
+                    problems.push((sha, "not a readable commit".to_string()));
+                    continue;
+                }
+            };
+
+            if stored.tree != actual.tree {
+                problems.push((sha.clone(), format!("wrong tree {}", stored.tree)));
+            }
+            if stored.parents != actual.parents {
+                problems.push((sha.clone(), "wrong parents".to_string()));
+            }
+            if stored.time != actual.time {
+                problems.push((sha.clone(), format!("wrong date {}", stored.time)));
+            }
+
+            let expected = stored
+                .parents
+                .iter()
+                .map(|parent| graph.commit(parent).map_or(0, |parent| parent.generation))
+                .max()
+                .map_or(1, |highest| (highest + 1).min(MAX_GENERATION));
+            if stored.generation != expected {
+                problems.push((sha, format!("wrong generation {}", stored.generation)));
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::GENERATION_INFINITY;
+    use crate::object::operation::write_raw;
+    use crate::repo::Repo;
+    use crate::test_util::{commit_tree, create, tree};
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_commit_graph_write() {
+        let repo = create("./test_commit_graph/repo.git", true);
+        let commit = |tree: &str, parents: &[&String]| {
+            let parents: Vec<&str> = parents.iter().map(|parent| parent.as_str()).collect();
+            commit_tree(&repo, tree, &parents, "message")
+        };
+        let tree = |entries: &[(&str, &str, &String)]| {
+            let entries: Vec<(&str, &str, &str)> = entries
+                .iter()
+                .map(|(mode, name, sha)| (*mode, *name, sha.as_str()))
+                .collect();
+            tree(&repo, &entries)
+        };
+
+        let one = write_raw(&repo, "blob", b"one");
+        let two = write_raw(&repo, "blob", b"two");
+        let first_dir = tree(&[("100644", "file", &one)]);
+        let second_dir = tree(&[("100644", "file", &two)]);
+        let first_tree = tree(&[("40000", "dir", &first_dir), ("100644", "top", &one)]);
+        let second_tree = tree(&[("40000", "dir", &second_dir), ("100644", "top", &one)]);
+
+        let root = commit(&first_tree, &[]);
+        let left = commit(&second_tree, &[&root]);
+        let right = commit(&first_tree, &[&root]);
+        let other = commit(&tree(&[("100644", "top", &two)]), &[&root]);
+        let merge = commit(&second_tree, &[&left, &right, &other]);
+        repo.ref_update("refs/heads/master", &merge).unwrap();
+
+        let unrelated = commit(&second_tree, &[]);
+        assert!(!(repo.is_ancestor(&unrelated, &merge)));
+        assert_eq!(
+            GENERATION_INFINITY,
+            repo.graph_commit(&merge).unwrap().generation
+        );
+
+        assert_eq!(5, repo.write_commit_graph(true).unwrap());
+        assert!(repo.verify_commit_graph().is_empty());
+
+        // The commit-graph is read once, when first needed.
+        let repo = Repo::repo_open(Path::new("./test_commit_graph/repo.git")).unwrap();
+        let graph = repo.commit_graph().unwrap();
+        assert_eq!(3, graph.commit(&merge).unwrap().generation);
+        assert_eq!(
+            vec![left.clone(), right, other],
+            graph.commit(&merge).unwrap().parents
+        );
+        assert_eq!(1_700_000_000, graph.commit(&root).unwrap().time);
+        assert_eq!(Some(true), graph.maybe_changed(&left, "dir/file"));
+        assert_eq!(Some(true), graph.maybe_changed(&left, "dir"));
+        assert_eq!(Some(false), graph.maybe_changed(&left, "top"));
+        assert_eq!(Some(true), graph.maybe_changed(&root, "top"));
+
+        assert!(repo.is_ancestor(&root, &merge));
+        assert!(!(repo.is_ancestor(&merge, &root)));
+        assert!(!(repo.is_ancestor(&unrelated, &merge)));
+        assert_eq!(5, repo.walk_commits(std::slice::from_ref(&merge)).len());
+        assert_eq!((3, 0), repo.ahead_behind(&merge, &left));
+
+        // Walks limited to paths follow the parent having them as the merge does, the filters
+        // telling it for left without reading its trees.
+        let tips = std::slice::from_ref(&merge);
+        assert_eq!(
+            vec![root.clone()],
+            repo.rev_list(tips, &[], false, &["top".to_string()])
+                .unwrap()
+        );
+        assert_eq!(
+            vec![left.clone(), root.clone()],
+            repo.rev_list(tips, &[], false, &["dir/".to_string()])
+                .unwrap()
+        );
+
+        // A commit-graph which no longer matches its commits.
+        let path = repo.commit_graph_path();
+        let mut data = fs::read(&path).unwrap();
+        let len = data.len();
+        data[len - 1] ^= 1;
+        fs::write(&path, data).unwrap();
+        assert_eq!(1, repo.verify_commit_graph().len());
+
+        fs::remove_dir_all("./test_commit_graph").unwrap();
+    }
+}
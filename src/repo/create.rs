@@ -17,6 +17,7 @@
  */
 
 use crate::commands::init::Init;
+use crate::config::file::ConfigFile;
 use crate::error;
 use crate::error::Log;
 use crate::repo::Repo;
@@ -24,7 +25,27 @@ use std::fs;
 
 impl Repo {
     pub fn create(args: &Init) -> Repo {
-        let repo = Repo::new(&args.path, !args.force);
+        // Refuse unknown extensions before touching the disk.
+        // SHA-1 is the format of version 0 repositories, other formats need the extension.
+        let object_format = args
+            .object_format
+            .iter()
+            .filter(|format| !(format.eq_ignore_ascii_case("sha1")))
+            .map(|format| format!("objectformat={}", format));
+
+        let extensions: Vec<(String, String)> = args
+            .extensions
+            .iter()
+            .cloned()
+            .chain(object_format)
+            .map(|extension| Repo::parse_extension(&extension).unwrap_or_else(|e| e.panic()))
+            .collect();
+
+        let repo = if args.bare {
+            Repo::new_bare(&args.path, !args.force)
+        } else {
+            Repo::new(&args.path, !args.force)
+        };
 
         info!(
             "create repository worktree on {}",
@@ -39,12 +60,18 @@ impl Repo {
         Self::create_file(&repo);
 
         info!("create repository configuration file...");
-        Self::create_config(&repo);
+        Self::create_config(&repo, args.bare, &extensions);
 
-        repo
+        // Reopen the repository so that it carries the configuration just written.
+        if args.bare {
+            Repo::new_bare(&args.path, false)
+        } else {
+            Repo::new(&args.path, false)
+        }
     }
 
     // Make sure the path either doesn't exist or is an empty dir.
+    // For a bare repository the lit_dir is the worktree path itself.
     fn create_worktree(repo: &Repo, force: bool) {
         let worktree = &repo.worktree;
         let lit_dir = &repo.lit_dir;
@@ -64,7 +91,8 @@ impl Repo {
 
     fn create_dirs(repo: &Repo) {
         Repo::repo_dir(&repo.lit_dir, &["branchs"], true).unwrap();
-        Repo::repo_dir(&repo.lit_dir, &["objects"], true).unwrap();
+        Repo::repo_dir(&repo.lit_dir, &["objects", "info"], true).unwrap();
+        Repo::repo_dir(&repo.lit_dir, &["objects", "pack"], true).unwrap();
         Repo::repo_dir(&repo.lit_dir, &["refs", "tags"], true).unwrap();
         Repo::repo_dir(&repo.lit_dir, &["refs", "heads"], true).unwrap();
     }
@@ -83,20 +111,64 @@ impl Repo {
         .unwrap();
     }
 
-    fn create_config(repo: &Repo) {
-        let mut conf = ini::Ini::new();
-        conf.with_section(Some("core"))
-            // The version of the lit_dir format.
-            // 0 means the initial format,
-            // 1 the same with extensions.
-            // If > 1, lit will panic; lit will only accept 0.
-            .set("repositoryformatversion", "0")
+    fn create_config(repo: &Repo, bare: bool, extensions: &[(String, String)]) {
+        let mut conf =
+            ConfigFile::open(&Repo::repo_file(&repo.lit_dir, &["config"], false).unwrap())
+                .unwrap_or_else(|e| e.panic());
+
+        // The version of the lit_dir format.
+        // 0 means the initial format,
+        // 1 the same with extensions.
+        // If > 1, lit will panic; lit will only accept 0 and 1.
+        let version = if extensions.is_empty() { "0" } else { "1" };
+
+        conf.set("core.repositoryformatversion", version)
             // Disable tracking of file mode (permissions) changes in the work tree.
-            .set("filemode", "false")
-            // Dedicates that this repository has a worktree.
-            .set("bare", "false");
+            .and_then(|_| conf.set("core.filemode", "false"))
+            // Dedicates whether this repository has a worktree.
+            .and_then(|_| conf.set("core.bare", &bare.to_string()))
+            .and_then(|_| {
+                extensions
+                    .iter()
+                    .try_for_each(|(name, value)| conf.set(&format!("extensions.{}", name), value))
+            })
+            .and_then(|_| conf.save())
+            .unwrap_or_else(|e| e.panic())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::init::Init;
+    use crate::error;
+    use crate::repo::Repo;
+    use std::fs;
+    use std::path::PathBuf;
+
+    #[test]
+    pub fn test_create_bare() {
+        let _ = fs::remove_dir_all("./test_create_bare");
+        fs::create_dir_all("./test_create_bare").unwrap();
+
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_create_bare"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        assert!(repo.is_bare());
+        assert!(PathBuf::from("./test_create_bare/HEAD").is_file());
+        assert!(PathBuf::from("./test_create_bare/config").is_file());
+        assert!(PathBuf::from("./test_create_bare/objects").is_dir());
+        assert!(PathBuf::from("./test_create_bare/refs/heads").is_dir());
+        assert!(!PathBuf::from("./test_create_bare/.lit").exists());
+        assert!(matches!(
+            repo.require_worktree(),
+            Err(error::Error::Repo(error::repo::Repo::BareRepository(_)))
+        ));
 
-        conf.write_to_file(Repo::repo_file(&repo.lit_dir, &["config"], false).unwrap())
-            .unwrap()
+        fs::remove_dir_all("./test_create_bare").unwrap();
     }
 }
@@ -0,0 +1,293 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::{parse_bool, Config, Scope};
+use crate::error::{self, Error};
+use crate::object::format::ObjectFormat;
+use crate::repo::Repo;
+use std::path::{Path, PathBuf};
+
+/// The extensions this version of lit understands, lowercased like config keys.
+/// A repository declaring any other extension in format version 1 is refused,
+/// since lit could corrupt it without knowing what the extension changes.
+///
+/// - `objectformat`: the hash function of object names, `sha1` or `sha256`
+/// - `worktreeconfig`: read `config.worktree` from the lit directory of each worktree
+/// - `preciousobjects`: objects must never be deleted from the repository
+/// - `noop`: no effect, used to test extension handling
+pub const KNOWN_EXTENSIONS: [&str; 4] =
+    ["noop", "objectformat", "preciousobjects", "worktreeconfig"];
+
+impl Repo {
+    /// Version 0 is the original format, version 1 the same with extensions.
+    /// Version 0 repositories ignore the `[extensions]` section, as in git.
+    pub(super) fn check_repositoryformatversion(conf: &Config) -> error::Result<()> {
+        let unsupported = |version: String| {
+            Err(Error::Repo(
+                error::repo::Repo::UnsupportedRepositoryFormatVersion(version),
+            ))
+        };
+
+        let version = match conf.get_int("core.repositoryformatversion") {
+            Ok(version) => version.unwrap_or(0),
+            Err(_) => return unsupported(conf.get("core.repositoryformatversion").unwrap()),
+        };
+
+        match version {
+            0 => Ok(()),
+            1 => Self::extensions(conf)
+                .iter()
+                .try_for_each(|(name, value)| Self::check_extension(name, value)),
+            _ => unsupported(version.to_string()),
+        }
+    }
+
+    fn check_extension(name: &str, value: &str) -> error::Result<()> {
+        let invalid = || {
+            Err(Error::Repo(error::repo::Repo::InvalidExtension(
+                name.to_string(),
+                value.to_string(),
+            )))
+        };
+
+        if !(KNOWN_EXTENSIONS.contains(&name)) {
+            return Err(Error::Repo(error::repo::Repo::UnknownExtension(
+                name.to_string(),
+            )));
+        }
+
+        match name {
+            "objectformat" if ObjectFormat::from_name(value).is_some() => Ok(()),
+            "objectformat" => invalid(),
+            _ => match parse_bool(Some(value)) {
+                Some(_) => Ok(()),
+                None => invalid(),
+            },
+        }
+    }
+
+    /// The extensions declared by the repository. Only its own config file can declare
+    /// them, a global or included file must not change how the repository is read.
+    fn extensions(conf: &Config) -> Vec<(String, String)> {
+        conf.entries
+            .iter()
+            .filter(|entry| entry.scope == Scope::Local && entry.section == "extensions")
+            .filter(|entry| entry.subsection.is_none())
+            .map(|entry| {
+                (
+                    entry.key.clone(),
+                    entry.value.clone().unwrap_or("true".to_string()),
+                )
+            })
+            .collect()
+    }
+
+    /// Whether the repository has format version 1 and enables the boolean extension name.
+    pub fn has_extension(conf: &Config, name: &str) -> bool {
+        conf.get_int("core.repositoryformatversion")
+            .is_ok_and(|version| version == Some(1))
+            && Self::extensions(conf)
+                .iter()
+                .rev()
+                .find(|(extension, _)| extension == name)
+                .is_some_and(|(_, value)| parse_bool(Some(value)) == Some(true))
+    }
+
+    /// The hash function of the repository, SHA-1 unless `extensions.objectformat` says otherwise.
+    pub fn object_format(&self) -> ObjectFormat {
+        Self::conf_object_format(self.conf.as_ref())
+    }
+
+    pub(super) fn conf_object_format(conf: Option<&Config>) -> ObjectFormat {
+        conf.filter(|conf| {
+            conf.get_int("core.repositoryformatversion")
+                .is_ok_and(|version| version == Some(1))
+        })
+        .and_then(|conf| {
+            Self::extensions(conf)
+                .into_iter()
+                .rev()
+                .find(|(extension, _)| extension == "objectformat")
+        })
+        .and_then(|(_, value)| ObjectFormat::from_name(&value))
+        .unwrap_or_default()
+    }
+
+    /// With the `worktreeconfig` extension, each worktree has its own `config.worktree`
+    /// overriding the shared configuration.
+    pub(super) fn read_worktree_conf_file(conf: &mut Config, lit_dir: &Path) -> error::Result<()> {
+        if Self::has_extension(conf, "worktreeconfig") {
+            conf.add_file(
+                &lit_dir.join("config.worktree"),
+                Scope::Worktree,
+                Some(lit_dir),
+            )
+        } else {
+            Ok(())
+        }
+    }
+
+    /// The file written by `lit config --worktree`: `config.worktree` with the
+    /// `worktreeconfig` extension, the shared config file otherwise.
+    pub fn worktree_config_path(&self) -> PathBuf {
+        match &self.conf {
+            Some(conf) if Self::has_extension(conf, "worktreeconfig") => {
+                self.lit_dir.join("config.worktree")
+            }
+            _ => self.common_dir.join("config"),
+        }
+    }
+
+    /// Parse an extension given to `lit init --extension`, as `name` or `name=value`.
+    pub fn parse_extension(extension: &str) -> error::Result<(String, String)> {
+        let (name, value) = match extension.split_once('=') {
+            Some((name, value)) => (name.to_lowercase(), value.to_string()),
+            None => (extension.to_lowercase(), "true".to_string()),
+        };
+
+        Self::check_extension(&name, &value)?;
+        Ok((name, value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::init::Init;
+    use crate::config::file::ConfigFile;
+    use crate::config::{Config, Scope};
+    use crate::error;
+    use crate::object::blob::Blob;
+    use crate::object::commit::{Commit, Kvlm};
+    use crate::object::format::ObjectFormat;
+    use crate::object::operation::write;
+    use crate::object::tree::{Tree, TreeEntry};
+    use crate::repo::Repo;
+    use std::fs;
+    use std::path::Path;
+
+    #[test]
+    pub fn test_extensions() {
+        let _ = fs::remove_dir_all("./test_extensions");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: false,
+            path: String::from("./test_extensions"),
+            extensions: vec!["worktreeConfig".to_string(), "noop".to_string()],
+            object_format: None,
+        });
+
+        let conf = repo.conf.as_ref().unwrap();
+        assert_eq!(
+            Some(1),
+            conf.get_int("core.repositoryformatversion").unwrap()
+        );
+        assert!(Repo::has_extension(conf, "worktreeconfig"));
+        assert_eq!(
+            Path::new("./test_extensions/.lit/config.worktree"),
+            repo.worktree_config_path()
+        );
+
+        fs::write(
+            "./test_extensions/.lit/config.worktree",
+            "[core]\n\tsparseCheckout = true\n",
+        )
+        .unwrap();
+        let repo = Repo::new(&"./test_extensions".to_string(), false);
+        assert_eq!(
+            Some(true),
+            repo.conf.unwrap().get_bool("core.sparsecheckout").unwrap()
+        );
+
+        let mut file = ConfigFile::open(Path::new("./test_extensions/.lit/config")).unwrap();
+        file.set("extensions.future", "true").unwrap();
+        file.save().unwrap();
+
+        let conf = Config::load_file(
+            Path::new("./test_extensions/.lit/config"),
+            Scope::Local,
+            None,
+        )
+        .unwrap();
+        assert!(matches!(
+            Repo::check_repositoryformatversion(&conf),
+            Err(error::Error::Repo(error::repo::Repo::UnknownExtension(name))) if name == "future"
+        ));
+
+        assert!(matches!(
+            Repo::parse_extension("objectFormat=md5"),
+            Err(error::Error::Repo(error::repo::Repo::InvalidExtension(
+                _,
+                _
+            )))
+        ));
+
+        fs::remove_dir_all("./test_extensions").unwrap();
+    }
+
+    #[test]
+    pub fn test_sha256_repository() {
+        let _ = fs::remove_dir_all("./test_sha256");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: false,
+            path: String::from("./test_sha256"),
+            extensions: vec![],
+            object_format: Some("sha256".to_string()),
+        });
+        assert_eq!(ObjectFormat::Sha256, repo.object_format());
+
+        let blob = write(Box::new(Blob::new("hello\n")), Some(&repo));
+        assert_eq!(
+            "2cf8d83d9ee29543b34a87727421fdecb7e3f3a183d337639025de576db9ebb4",
+            blob
+        );
+
+        let tree = write(
+            Box::new(Tree::new(vec![TreeEntry {
+                mode: "100644".to_string(),
+                name: "hello.txt".to_string(),
+                sha: blob.clone(),
+            }])),
+            Some(&repo),
+        );
+        let commit = write(
+            Box::new(Commit::new(Kvlm {
+                headers: vec![("tree".to_string(), tree.clone())],
+                message: "init\n".to_string(),
+            })),
+            Some(&repo),
+        );
+        repo.ref_update("refs/heads/master", &commit).unwrap();
+
+        assert_eq!(Some(commit.clone()), repo.resolve_name("master"));
+        assert_eq!(Some(commit.clone()), repo.resolve_name(&commit));
+        assert_eq!(Some(tree.clone()), repo.resolve_tree("HEAD"));
+
+        repo.checkout_tree(&tree, false, &mut |_| ()).unwrap();
+        assert_eq!(
+            "hello\n",
+            fs::read_to_string("./test_sha256/hello.txt").unwrap()
+        );
+
+        let index = crate::index::Index::read(&repo.index_path(), ObjectFormat::Sha256).unwrap();
+        assert_eq!(blob, index.entries[0].sha);
+        assert!(!(repo.is_modified(&index.entries[0])));
+
+        fs::remove_dir_all("./test_sha256").unwrap();
+    }
+}
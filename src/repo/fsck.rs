@@ -0,0 +1,264 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::object::check;
+use crate::repo::walk::links;
+use crate::repo::Repo;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+/// What `fsck` finds wrong with an object, or worth knowing about it
+#[derive(Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// An object, or a file holding objects, cannot be read, or is not named after its content
+    Corrupt(String, String),
+
+    /// The content of an object is malformed: its type, its name and what is wrong
+    Invalid(String, String, String),
+
+    /// A ref names a missing object
+    BrokenRef(String, String),
+
+    /// An object links to a missing one: the type and name of both
+    BrokenLink(String, String, String, String),
+
+    /// An object reachable from the refs is missing
+    Missing(String, String),
+
+    /// An unreachable object no other object links to
+    Dangling(String, String),
+
+    /// An object the refs, the HEADs, the reflogs and the indexes do not reach
+    Unreachable(String, String),
+}
+
+impl Problem {
+    /// Whether the repository is damaged, rather than holding objects nothing needs.
+    pub fn is_error(&self) -> bool {
+        !matches!(self, Problem::Dangling(..) | Problem::Unreachable(..))
+    }
+
+    /// The problem as one line of tab separated fields: the kind of problem, the type and
+    /// the name of the object, then the details, if any.
+    pub fn porcelain(&self) -> String {
+        let fields: Vec<&str> = match self {
+            Problem::Corrupt(name, reason) => vec!["corrupt", "-", name, reason],
+            Problem::Invalid(fmt, sha, reason) => vec!["invalid", fmt, sha, reason],
+            Problem::BrokenRef(name, sha) => vec!["broken-ref", "-", sha, name],
+            Problem::BrokenLink(fmt, sha, to_fmt, to) => {
+                vec!["broken-link", fmt, sha, to_fmt, to]
+            }
+            Problem::Missing(fmt, sha) => vec!["missing", fmt, sha],
+            Problem::Dangling(fmt, sha) => vec!["dangling", fmt, sha],
+            Problem::Unreachable(fmt, sha) => vec!["unreachable", fmt, sha],
+        };
+        fields.join("\t")
+    }
+}
+
+/// The problem as git reports it.
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Problem::Corrupt(name, reason) => write!(f, "error: {}: {}", name, reason),
+            Problem::Invalid(fmt, sha, reason) => write!(f, "error in {} {}: {}", fmt, sha, reason),
+            Problem::BrokenRef(name, sha) => write!(f, "error: {}: invalid pointer {}", name, sha),
+            Problem::BrokenLink(fmt, sha, to_fmt, to) => write!(
+                f,
+                "broken link from {:>7} {}\n              to {:>7} {}",
+                fmt, sha, to_fmt, to
+            ),
+            Problem::Missing(fmt, sha) => write!(f, "missing {} {}", fmt, sha),
+            Problem::Dangling(fmt, sha) => write!(f, "dangling {} {}", fmt, sha),
+            Problem::Unreachable(fmt, sha) => write!(f, "unreachable {} {}", fmt, sha),
+        }
+    }
+}
+
+impl Repo {
+    /// Check the integrity of the objects, unless connectivity_only, and that the objects the
+    /// refs, the HEADs of the worktrees, the reflogs and the indexes reach are there. Objects in packs and
+    /// alternates are only checked when full. Unreachable objects are reported as dangling
+    /// when no other object links to them, or all of them with unreachable.
+    pub fn fsck(&self, full: bool, connectivity_only: bool, unreachable: bool) -> Vec<Problem> {
+        let format = self.object_format();
+        let mut problems = Vec::new();
+        let mut corrupt = HashSet::new();
+
+        if !connectivity_only {
+            for (name, reason) in self.objects.verify(full) {
+                corrupt.insert(name.clone());
+                problems.push(Problem::Corrupt(name, reason));
+            }
+        }
+
+        // The type of each object, and the objects it links to.
+        let mut types = BTreeMap::new();
+        let mut links_of = BTreeMap::new();
+        for sha in self.objects.iter() {
+            if corrupt.contains(&sha) {
+                continue;
+            }
+
+            let fmt = match self.objects.read_header(&sha) {
+                Some((fmt, _)) if fmt != "blob" => match self.objects.read(&sha) {
+                    Some((fmt, data)) => {
+                        if !connectivity_only {
+                            if let Err(reason) = check::check(&fmt, &data, format) {
+                                problems.push(Problem::Invalid(fmt.clone(), sha.clone(), reason));
+                            }
+                        }
+                        links_of.insert(sha.clone(), links(&fmt, &data, format));
+                        fmt
+                    }
+                    None => continue,
+                },
+                Some((fmt, _)) => fmt,
+                None => continue,
+            };
+            types.insert(sha, fmt);
+        }
+
+        let mut linked = HashSet::new();
+        for (sha, links) in &links_of {
+            for (to_fmt, to) in links {
+                linked.insert(to.clone());
+                match types.get(to) {
+                    Some(fmt) if fmt != to_fmt => problems.push(Problem::Invalid(
+                        types[sha].clone(),
+                        sha.clone(),
+                        format!("links to {} as a {}, it is a {}", to, to_fmt, fmt),
+                    )),
+                    None if !corrupt.contains(to) => problems.push(Problem::BrokenLink(
+                        types[sha].clone(),
+                        sha.clone(),
+                        to_fmt.clone(),
+                        to.clone(),
+                    )),
+                    _ => (),
+                }
+            }
+        }
+
+        // Walk from the refs, the HEADs, the reflogs and the indexes.
+        let mut stack: Vec<(String, String)> = Vec::new();
+        for (name, sha) in self.ref_list() {
+            match types.get(&sha) {
+                Some(fmt) => stack.push((fmt.clone(), sha)),
+                None if !corrupt.contains(&sha) => problems.push(Problem::BrokenRef(name, sha)),
+                None => (),
+            }
+        }
+        stack.extend(self.reachability_roots());
+
+        let mut reached = HashSet::new();
+        while let Some((fmt, sha)) = stack.pop() {
+            if !(reached.insert(sha.clone())) {
+                continue;
+            }
+
+            if !(types.contains_key(&sha) || corrupt.contains(&sha)) {
+                problems.push(Problem::Missing(fmt, sha));
+            } else if let Some(links) = links_of.get(&sha) {
+                stack.extend(links.iter().cloned());
+            }
+        }
+
+        for (sha, fmt) in types {
+            if reached.contains(&sha) {
+                continue;
+            }
+
+            if unreachable {
+                problems.push(Problem::Unreachable(fmt, sha));
+            } else if !(linked.contains(&sha)) {
+                problems.push(Problem::Dangling(fmt, sha));
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Problem;
+    use crate::object::database::encode;
+    use crate::object::operation::write_raw;
+    use crate::test_util::{commit_tree, create, tree};
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    pub fn test_fsck() {
+        let repo = create("./test_fsck/repo.git", true);
+
+        let blob = write_raw(&repo, "blob", b"content");
+        let tree = tree(&repo, &[("100644", "file", &blob)]);
+        let commit = commit_tree(&repo, &tree, &[], "message");
+        repo.ref_update("refs/heads/master", &commit).unwrap();
+        assert_eq!(Vec::<Problem>::new(), repo.fsck(true, false, false));
+
+        let dangling = write_raw(&repo, "blob", b"dangling");
+        assert_eq!(
+            vec![Problem::Dangling("blob".to_string(), dangling.clone())],
+            repo.fsck(true, false, false)
+        );
+        assert_eq!(
+            vec![Problem::Unreachable("blob".to_string(), dangling.clone())],
+            repo.fsck(true, false, true)
+        );
+
+        // An object holding the content of another.
+        let path = |sha: &str| format!("./test_fsck/repo.git/objects/{}/{}", &sha[..2], &sha[2..]);
+        let mut corrupt = ZlibEncoder::new(Vec::new(), Compression::default());
+        corrupt.write_all(&encode("blob", b"other")).unwrap();
+        fs::remove_file(path(&dangling)).unwrap();
+        fs::write(path(&dangling), corrupt.finish().unwrap()).unwrap();
+        let problems = repo.fsck(true, false, false);
+        assert!(matches!(&problems[..], [Problem::Corrupt(sha, _)] if *sha == dangling));
+        assert!(problems[0].is_error());
+        assert_eq!(
+            vec![Problem::Dangling("blob".to_string(), dangling.clone())],
+            repo.fsck(true, true, false)
+        );
+        fs::remove_file(path(&dangling)).unwrap();
+
+        fs::remove_file(path(&blob)).unwrap();
+        assert_eq!(
+            vec![
+                Problem::BrokenLink(
+                    "tree".to_string(),
+                    tree.clone(),
+                    "blob".to_string(),
+                    blob.clone()
+                ),
+                Problem::Missing("blob".to_string(), blob.clone()),
+            ],
+            repo.fsck(true, false, false)
+        );
+        assert_eq!(
+            format!("missing\tblob\t{}", blob),
+            Problem::Missing("blob".to_string(), blob).porcelain()
+        );
+
+        fs::remove_dir_all("./test_fsck").unwrap();
+    }
+}
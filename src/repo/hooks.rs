@@ -0,0 +1,85 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::r#const::LIT_DIR_ENV;
+use crate::repo::Repo;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+impl Repo {
+    /// The directory of the hooks, `core.hookspath` or `hooks` in the common directory.
+    fn hooks_dir(&self) -> PathBuf {
+        self.conf
+            .as_ref()
+            .and_then(|conf| conf.get_path("core.hookspath").ok().flatten())
+            .unwrap_or(self.common_dir.join("hooks"))
+    }
+
+    #[cfg(unix)]
+    fn is_executable(path: &Path) -> bool {
+        use std::os::unix::fs::PermissionsExt;
+        fs::metadata(path)
+            .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+    }
+
+    #[cfg(not(unix))]
+    fn is_executable(path: &Path) -> bool {
+        path.is_file()
+    }
+
+    /// Run the hook name with args, writing input to it. Whether it succeeded, a missing
+    /// or non-executable hook always does. Its output goes to standard error, standard
+    /// output may carry a protocol.
+    pub fn run_hook(&self, name: &str, args: &[&str], input: &[u8]) -> bool {
+        // The hook runs from another directory, where a relative path would not lead to it.
+        let hook = match fs::canonicalize(self.hooks_dir().join(name)) {
+            Ok(hook) if Self::is_executable(&hook) => hook,
+            _ => return true,
+        };
+
+        let directory = if self.is_bare() {
+            &self.lit_dir
+        } else {
+            &self.worktree
+        };
+        let child = Command::new(&hook)
+            .args(args)
+            .current_dir(directory)
+            .env(
+                LIT_DIR_ENV,
+                fs::canonicalize(&self.lit_dir).unwrap_or_default(),
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::from(std::io::stderr()))
+            .spawn();
+
+        match child {
+            Ok(mut child) => {
+                // A hook may not read its input, and exit before it is written.
+                let _ = child.stdin.take().unwrap().write_all(input);
+                child.wait().is_ok_and(|status| status.success())
+            }
+            Err(e) => {
+                warn!("Cannot run the {} hook: {}", name, e);
+                false
+            }
+        }
+    }
+}
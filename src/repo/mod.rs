@@ -16,78 +16,138 @@
  * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
  */
 
+pub mod checkout;
+pub mod commit_graph;
 pub mod create;
+pub mod format;
+pub mod fsck;
+pub mod hooks;
 mod path;
+pub mod prune;
+pub mod refs;
+pub mod repack;
+pub mod stats;
+pub mod status;
+pub mod tree;
+pub mod walk;
+pub mod worktree;
 
+use crate::config::Config;
 use crate::error::{self, Log};
+use crate::object::commit_graph::CommitGraph;
+use crate::object::database::bitmap::PackBitmap;
+use crate::object::database::{ObjectDatabase, ObjectDirectory};
 use crate::r#const::LIT_DIR;
-use ini::Ini;
+use std::cell::OnceCell;
 use std::path::{Path, PathBuf};
 
 /// The lit repository
 pub struct Repo {
     pub worktree: PathBuf,
+
+    /// The per-worktree directory, holding `HEAD` and `index`
     pub lit_dir: PathBuf,
-    pub conf: Option<Ini>,
+
+    /// The directory shared by all the worktrees, holding objects, refs and config.
+    /// Same as `lit_dir`, except for linked worktrees.
+    pub common_dir: PathBuf,
+
+    /// The system, global and repository configuration merged
+    pub conf: Option<Config>,
+
+    /// Where objects are read and written: the `objects` directory of `common_dir`,
+    /// unless another storage replaces it
+    pub objects: Box<dyn ObjectDatabase>,
+
+    /// The commit-graph of `objects/info`, read when a history walk first needs it
+    commit_graph: OnceCell<Option<CommitGraph>>,
+
+    /// The bitmaps of the packs, read when a reachability walk first needs them
+    pack_bitmap: OnceCell<Option<PackBitmap>>,
 }
 
 impl Repo {
     pub fn new(path: &String, force: bool) -> Self {
-        let worktree = PathBuf::from(path);
-        let lit_dir = Path::new(path).join(LIT_DIR);
+        Self::open(PathBuf::from(path), Path::new(path).join(LIT_DIR), force)
+    }
 
+    /// A bare repository has no worktree: the lit directory is the path itself.
+    pub fn new_bare(path: &String, force: bool) -> Self {
+        Self::open(PathBuf::from(path), PathBuf::from(path), force)
+    }
+
+    fn open(worktree: PathBuf, lit_dir: PathBuf, force: bool) -> Self {
         if !(force || Path::new(&lit_dir).is_dir()) {
             error::repo::Repo::NotLitRepo(lit_dir.clone()).panic();
         }
 
-        match Self::read_conf_file(&lit_dir, force) {
-            Some(mut conf) => {
-                Self::check_repositoryformatversion(&mut conf).unwrap();
-                Repo {
-                    worktree,
-                    lit_dir,
-                    conf: Some(conf),
-                }
-            }
-            None => Repo {
-                worktree,
-                lit_dir,
-                conf: None,
-            },
+        let common_dir = Self::read_common_dir(&lit_dir).unwrap_or(lit_dir.clone());
+        let conf = Self::read_conf_file(&lit_dir, &common_dir, force);
+        if let Some(conf) = &conf {
+            Self::check_repositoryformatversion(conf).unwrap_or_else(|e| e.panic());
+        }
+
+        Repo {
+            worktree,
+            objects: Box::new(ObjectDirectory::open(
+                &common_dir.join("objects"),
+                Self::conf_object_format(conf.as_ref()),
+            )),
+            lit_dir,
+            common_dir,
+            conf,
+            commit_graph: OnceCell::new(),
+            pack_bitmap: OnceCell::new(),
         }
     }
 
-    pub fn new_with_pathbuf(path: &Path, force: bool) -> Self {
-        Self::new(&path.to_str().unwrap().to_string(), force)
+    /// The admin directory of a linked worktree names the shared directory in its `commondir` file.
+    fn read_common_dir(lit_dir: &Path) -> Option<PathBuf> {
+        let common_dir = std::fs::read_to_string(lit_dir.join("commondir")).ok()?;
+        std::fs::canonicalize(lit_dir.join(common_dir.trim())).ok()
     }
 
-    pub(self) fn check_repositoryformatversion(conf: &mut Ini) -> error::Result<()> {
-        let repositoryformatversion = conf
-            .with_section(Some("core"))
-            .get("repositoryformatversion")
-            .unwrap()
-            .to_string();
+    /// Whether `core.bare` is set, i.e. the repository has no worktree.
+    /// It only applies to the main worktree, linked worktrees of a bare repository are not bare.
+    pub fn is_bare(&self) -> bool {
+        self.lit_dir == self.common_dir
+            && self
+                .conf
+                .as_ref()
+                .and_then(|conf| conf.get_bool("core.bare").ok().flatten())
+                .unwrap_or(false)
+    }
 
-        if repositoryformatversion != "0" {
-            error::repo::Repo::UnsupportedRepositoryFormatVersion(repositoryformatversion).panic()
+    /// Fail for operations which need a worktree when the repository is bare.
+    pub fn require_worktree(&self) -> error::Result<()> {
+        if self.is_bare() {
+            Err(error::Error::Repo(error::repo::Repo::BareRepository(
+                self.lit_dir.clone(),
+            )))
         } else {
             Ok(())
         }
     }
 
-    pub(self) fn read_conf_file(lit_dir: &PathBuf, force: bool) -> Option<Ini> {
-        let conf = Self::repo_file(lit_dir, &["config"], false);
+    pub(self) fn read_conf_file(
+        lit_dir: &Path,
+        common_dir: &PathBuf,
+        force: bool,
+    ) -> Option<Config> {
+        let conf = Self::repo_file(common_dir, &["config"], false);
 
         match conf {
             Some(path) => {
                 if path.exists() {
-                    Some(Ini::load_from_file(path).unwrap())
+                    let mut conf =
+                        Config::load(Some(lit_dir), Some(&path)).unwrap_or_else(|e| e.panic());
+                    Self::read_worktree_conf_file(&mut conf, lit_dir).unwrap_or_else(|e| e.panic());
+                    Some(conf)
                 } else if !force {
                     error::repo::Repo::MissingConfigFile(path).panic();
-                } else if force {
-                    Some(Ini::load_from_file(path).unwrap())
                 } else {
-                    panic!("read_conf_file({:?}, {:?})", lit_dir, force)
+                    // The repository is being created, its config is not written yet.
+                    None
                 }
             }
             _ => None,
@@ -18,8 +18,9 @@
 
 use crate::error;
 use crate::error::Log;
-use crate::r#const::LIT_DIR;
-use std::{fs, path::PathBuf};
+use crate::r#const::{LIT_DIR_NAMES, LIT_DIR_NAMES_ENV};
+use std::fs;
+use std::path::{Path, PathBuf};
 
 impl crate::repo::Repo {
     /// Compute path under repo's lit_dir
@@ -52,19 +53,32 @@ impl crate::repo::Repo {
             }
         } else if mkdir {
             fs::create_dir_all(&path).unwrap();
-            return Some(path);
+            Some(path)
         } else {
             None
         }
     }
 
     /// Look for that root, starting at the current directory and recursing back to /.
-    /// To identify a path as a repository, it will check for the presence of a `LIT_DIR` directory.
-    pub fn repo_find(path: &String, required: bool) -> Option<Self> {
+    /// To identify a path as a repository, it will check for the presence of one of the
+    /// `LIT_DIR_NAMES` (a directory or a `gitdir:` file), or for the layout of a bare repository.
+    ///
+    /// lit_dir, from `--lit-dir` or the `LIT_DIR` environment variable, bypasses the discovery.
+    pub fn repo_find(path: &String, required: bool, lit_dir: Option<&Path>) -> Option<Self> {
+        match lit_dir {
+            Some(lit_dir) if !(lit_dir.as_os_str().is_empty()) => Some(Self::repo_open_lit_dir(
+                lit_dir,
+                &std::fs::canonicalize(PathBuf::from(path)).unwrap(),
+            )),
+            _ => Self::repo_discover(path, required),
+        }
+    }
+
+    fn repo_discover(path: &String, required: bool) -> Option<Self> {
         let path = std::fs::canonicalize(PathBuf::from(path)).unwrap();
 
-        if path.join(LIT_DIR).is_dir() {
-            Some(Self::new_with_pathbuf(&path, false))
+        if let Some(repo) = Self::repo_open(&path) {
+            Some(repo)
         } else {
             // Recurse in parent
             let parent = std::fs::canonicalize(PathBuf::from(&path).join("..")).unwrap();
@@ -77,19 +91,113 @@ impl crate::repo::Repo {
                     None
                 }
             } else {
-                Self::repo_find(&parent.to_str().unwrap().to_string(), required)
+                Self::repo_discover(&parent.to_str().unwrap().to_string(), required)
             }
         }
     }
+
+    /// Open the repository at path itself, without looking at its parents.
+    pub fn repo_open(path: &Path) -> Option<Self> {
+        let path = fs::canonicalize(path).ok()?;
+        let lit_dir = Self::lit_dir_names()
+            .iter()
+            .find_map(|name| Self::resolve_lit_dir(&path.join(name)));
+
+        if let Some(lit_dir) = lit_dir {
+            Some(Self::open(path, lit_dir, false))
+        } else if Self::is_bare_dir(&path) {
+            Some(Self::new_bare(&path.to_str().unwrap().to_string(), false))
+        } else {
+            None
+        }
+    }
+
+    /// Open the lit directory named explicitly by the user, the worktree is the current path.
+    fn repo_open_lit_dir(lit_dir: &Path, worktree: &Path) -> Self {
+        let lit_dir = match Self::resolve_lit_dir(lit_dir) {
+            Some(lit_dir) => lit_dir,
+            None => error::repo::Repo::NotLitRepo(lit_dir.to_owned()).panic(),
+        };
+
+        let repo = Self::open(worktree.to_owned(), lit_dir, false);
+        if repo.is_bare() {
+            Self::open(repo.lit_dir.clone(), repo.lit_dir, false)
+        } else {
+            repo
+        }
+    }
+
+    /// The names of the directories identifying a repository, in order of precedence.
+    /// Defaults to `.lit` then `.git`, and can be changed with a colon separated
+    /// list in the `LIT_DIR_NAMES` environment variable.
+    pub fn lit_dir_names() -> Vec<String> {
+        match std::env::var(LIT_DIR_NAMES_ENV) {
+            Ok(names) if !(names.trim().is_empty()) => names
+                .split(':')
+                .filter(|name| !(name.is_empty()))
+                .map(|name| name.to_string())
+                .collect(),
+            _ => LIT_DIR_NAMES.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    /// Resolve path to a lit directory: either path is a directory, or it is a file
+    /// containing `gitdir: <path>` as used by submodules and linked worktrees.
+    pub fn resolve_lit_dir(path: &Path) -> Option<PathBuf> {
+        if path.is_dir() {
+            fs::canonicalize(path).ok()
+        } else if path.is_file() {
+            let content = fs::read_to_string(path).ok()?;
+            let target = PathBuf::from(content.trim().strip_prefix("gitdir:")?.trim());
+            let target = if target.is_relative() {
+                path.parent()?.join(target)
+            } else {
+                target
+            };
+
+            fs::canonicalize(target).ok().filter(|dir| dir.is_dir())
+        } else {
+            None
+        }
+    }
+
+    /// A bare repository keeps `HEAD`, `objects` and `refs` directly in its directory.
+    pub fn is_bare_dir(path: &Path) -> bool {
+        path.join("HEAD").is_file() && path.join("objects").is_dir() && path.join("refs").is_dir()
+    }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::r#const::LIT_DIR;
+    use crate::cli::CommandLineParser;
+    use crate::r#const::{LIT_DIR, LIT_DIR_ENV, LIT_DIR_NAMES_ENV};
     use crate::repo::Repo;
+    use crate::test_util::create;
     use std::fs;
     use std::panic::{catch_unwind, AssertUnwindSafe};
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::process::Command;
+    use structopt::StructOpt;
+
+    /// Set in the child processes of `passes_in_child`
+    const CHILD_ENV: &str = "LIT_TEST_CHILD";
+
+    /// Whether test passes when run alone in a child process with the environment variable
+    /// name set to value, which the tests running alongside in this one must not see.
+    fn passes_in_child(test: &str, name: &str, value: &str) -> bool {
+        Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", test])
+            .env(name, value)
+            .env(CHILD_ENV, "1")
+            .output()
+            .unwrap()
+            .status
+            .success()
+    }
+
+    fn in_child() -> bool {
+        std::env::var_os(CHILD_ENV).is_some()
+    }
 
     #[test]
     pub fn test_repo_path() {
@@ -175,9 +283,122 @@ mod test {
 
     #[test]
     pub fn test_repo_find_not_found() {
-        fs::create_dir_all("./test/a/b/c").unwrap();
-        assert!(Repo::repo_find(&String::from("./test/a/b/c"), false).is_none());
-        fs::remove_dir_all("./test").unwrap();
+        // Outside of the project, whose own `.git` would be found otherwise
+        let test = std::env::temp_dir().join("lit_test_not_found");
+        let path = test.join("a").join("b").join("c");
+
+        fs::create_dir_all(&path).unwrap();
+        assert!(Repo::repo_find(&path.to_str().unwrap().to_string(), false, None).is_none());
+        fs::remove_dir_all(test).unwrap();
+    }
+
+    #[test]
+    pub fn test_repo_find_git_dir() {
+        create("./test_find_git", false);
+        fs::rename("./test_find_git/.lit", "./test_find_git/.git").unwrap();
+        fs::create_dir_all("./test_find_git/src").unwrap();
+
+        let repo = Repo::repo_find(&String::from("./test_find_git/src"), true, None).unwrap();
+
+        assert_eq!(
+            fs::canonicalize("./test_find_git/.git").unwrap(),
+            repo.lit_dir
+        );
+        assert_eq!(fs::canonicalize("./test_find_git").unwrap(), repo.worktree);
+
+        fs::remove_dir_all("./test_find_git").unwrap();
+    }
+
+    #[test]
+    pub fn test_repo_find_precedence() {
+        if in_child() {
+            // The empty `.git` directory wins, and has no configuration file
+            assert!(catch_unwind(AssertUnwindSafe(|| {
+                Repo::repo_find(&String::from("./test_find_precedence"), true, None)
+            }))
+            .is_err());
+            return;
+        }
+
+        create("./test_find_precedence", false);
+        fs::create_dir_all("./test_find_precedence/.git").unwrap();
+
+        let repo = Repo::repo_find(&String::from("./test_find_precedence"), true, None).unwrap();
+        assert!(repo.lit_dir.ends_with(LIT_DIR));
+        assert!(passes_in_child(
+            "repo::path::test::test_repo_find_precedence",
+            LIT_DIR_NAMES_ENV,
+            ".git:.lit"
+        ));
+
+        fs::remove_dir_all("./test_find_precedence").unwrap();
+    }
+
+    #[test]
+    pub fn test_repo_find_gitdir_file() {
+        let _ = fs::remove_dir_all("./test_gitdir_file");
+        create("./test_gitdir_file/main", false);
+        fs::create_dir_all("./test_gitdir_file/sub").unwrap();
+        fs::write("./test_gitdir_file/sub/.git", "gitdir: ../main/.lit\n").unwrap();
+
+        let repo = Repo::repo_find(&String::from("./test_gitdir_file/sub"), true, None).unwrap();
+
+        assert_eq!(
+            fs::canonicalize("./test_gitdir_file/main/.lit").unwrap(),
+            repo.lit_dir
+        );
+        assert_eq!(
+            fs::canonicalize("./test_gitdir_file/sub").unwrap(),
+            repo.worktree
+        );
+
+        fs::remove_dir_all("./test_gitdir_file").unwrap();
+    }
+
+    #[test]
+    pub fn test_repo_find_lit_dir_env() {
+        let lit_dir = fs::canonicalize("./test_lit_dir_env/.lit");
+        if in_child() {
+            // The environment variable is read with the command line.
+            let args = CommandLineParser::from_iter(["lit", "status"]);
+            let lit_dir_arg = args.lit_dir.as_deref().map(Path::new);
+            let repo = Repo::repo_find(&String::from("."), true, lit_dir_arg).unwrap();
+            assert_eq!(lit_dir.unwrap(), repo.lit_dir);
+            return;
+        }
+
+        create("./test_lit_dir_env", false);
+        let repo = Repo::repo_find(
+            &String::from("."),
+            true,
+            Some(Path::new("./test_lit_dir_env/.lit")),
+        )
+        .unwrap();
+        assert_eq!(
+            fs::canonicalize("./test_lit_dir_env/.lit").unwrap(),
+            repo.lit_dir
+        );
+        assert!(passes_in_child(
+            "repo::path::test::test_repo_find_lit_dir_env",
+            LIT_DIR_ENV,
+            "./test_lit_dir_env/.lit"
+        ));
+
+        fs::remove_dir_all("./test_lit_dir_env").unwrap();
+    }
+
+    #[test]
+    pub fn test_repo_find_bare() {
+        create("./test_find_bare", true);
+
+        let repo =
+            Repo::repo_find(&String::from("./test_find_bare/refs/heads"), true, None).unwrap();
+
+        assert!(repo.is_bare());
+        assert_eq!(repo.lit_dir, repo.worktree);
+        assert_eq!(fs::canonicalize("./test_find_bare").unwrap(), repo.lit_dir);
+
+        fs::remove_dir_all("./test_find_bare").unwrap();
     }
 
     #[test]
@@ -186,7 +407,7 @@ mod test {
         fs::create_dir_all(LIT_DIR).unwrap();
 
         assert!(catch_unwind(AssertUnwindSafe(|| {
-            Repo::repo_find(&String::from("./test/a/b/c"), false);
+            Repo::repo_find(&String::from("./test/a/b/c"), false, None);
         }))
         .is_err());
 
@@ -0,0 +1,169 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::config::parse_expiry;
+use crate::error::{self, Error};
+use crate::object::database::loose::LooseDatabase;
+use crate::object::database::ObjectDatabase;
+use crate::repo::Repo;
+use std::fs;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How long unreachable objects are kept when neither `--expire` nor `gc.pruneExpire` is set
+const DEFAULT_PRUNE_EXPIRE: &str = "2.weeks.ago";
+
+/// The last modification of path, in seconds since the epoch.
+fn modified(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_secs())
+}
+
+impl Repo {
+    /// The time before which unreachable objects expire: value, else `gc.pruneExpire`,
+    /// else two weeks ago.
+    pub fn prune_expire(&self, value: Option<&str>) -> error::Result<u64> {
+        let value = value
+            .map(|value| value.to_string())
+            .or_else(|| self.conf.as_ref()?.get("gc.pruneExpire"))
+            .unwrap_or(DEFAULT_PRUNE_EXPIRE.to_string());
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+
+        parse_expiry(&value, now)
+            .ok_or_else(|| Error::Repo(error::repo::Repo::InvalidDate(value.clone())))
+    }
+
+    /// Delete the loose objects the refs, the HEADs, the reflogs and the indexes do not reach,
+    /// and which were last modified before expire, along with stale temporary files. Objects
+    /// modified since then are kept with everything they reach, they may be about to be
+    /// referenced. Nothing is deleted when dry_run. Returns the name and type of the objects
+    /// pruned.
+    pub fn prune(&self, expire: u64, dry_run: bool) -> error::Result<Vec<(String, String)>> {
+        self.check_precious_objects()?;
+
+        let loose = LooseDatabase::new(&self.common_dir.join("objects"), self.object_format());
+        let (old, recent): (Vec<_>, Vec<_>) = loose
+            .iter()
+            .filter_map(|sha| Some((sha.clone(), modified(&loose.object_path(&sha)?)?)))
+            .partition(|(_, modified)| *modified < expire);
+
+        let recent: Vec<(String, String)> = recent
+            .into_iter()
+            .filter_map(|(sha, _)| Some((loose.read_header(&sha)?.0, sha)))
+            .collect();
+        let reachable = self.reachable_objects(&recent);
+
+        let mut pruned = Vec::new();
+        for (sha, _) in old {
+            if reachable.contains(&sha) {
+                continue;
+            }
+
+            // Unreadable objects are left for fsck to report.
+            let fmt = match loose.read_header(&sha) {
+                Some((fmt, _)) => fmt,
+                None => continue,
+            };
+
+            if !dry_run {
+                let path = loose.object_path(&sha).unwrap();
+                fs::remove_file(&path).map_err(|e| {
+                    Error::Object(error::object::Object::Io(path.clone(), e.to_string()))
+                })?;
+                // Only succeeds once the fan-out directory is empty.
+                let _ = fs::remove_dir(path.parent().unwrap());
+            }
+            pruned.push((sha, fmt));
+        }
+
+        if !dry_run {
+            for temp in loose.temp_files() {
+                if modified(&temp).is_some_and(|modified| modified < expire) {
+                    let _ = fs::remove_file(temp);
+                }
+            }
+        }
+
+        Ok(pruned)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::index::{Index, IndexEntry};
+    use crate::object::operation::write_raw;
+    use crate::test_util::{commit_tree, create, IDENT};
+    use std::fs;
+
+    #[test]
+    pub fn test_prune() {
+        let repo = create("./test_prune/repo", false);
+        let commit = |tree: &str, message: &str| commit_tree(&repo, tree, &[], message);
+
+        let tree = write_raw(&repo, "tree", b"");
+        let master = commit(&tree, "master");
+        repo.ref_update("refs/heads/master", &master).unwrap();
+
+        // Only a reflog entry, as a dropped stash leaves, keeps this one.
+        let logged = commit(&tree, "stash");
+        fs::create_dir_all("./test_prune/repo/.lit/logs/refs").unwrap();
+        fs::write(
+            "./test_prune/repo/.lit/logs/refs/stash",
+            format!("{} {} {}\tWIP\n", "0".repeat(40), logged, IDENT),
+        )
+        .unwrap();
+
+        // Only the index keeps this one.
+        let staged = write_raw(&repo, "blob", b"staged");
+        fs::write("./test_prune/repo/file", "staged").unwrap();
+        let meta = fs::metadata("./test_prune/repo/file").unwrap();
+        Index {
+            entries: vec![IndexEntry::from_metadata("file", &staged, 0o100644, &meta)],
+            ..Default::default()
+        }
+        .write(&repo.index_path(), repo.object_format())
+        .unwrap();
+
+        let unreachable = write_raw(&repo, "blob", b"unreachable");
+        let path = format!(
+            "./test_prune/repo/.lit/objects/{}/{}",
+            &unreachable[..2],
+            &unreachable[2..]
+        );
+
+        // Within the grace period, and in a dry run, nothing is deleted.
+        let expire = repo.prune_expire(Some("1.hour.ago")).unwrap();
+        assert!(repo.prune(expire, false).unwrap().is_empty());
+        let now = repo.prune_expire(Some("now")).unwrap();
+        let expected = vec![(unreachable.clone(), "blob".to_string())];
+        assert_eq!(expected, repo.prune(now, true).unwrap());
+        assert!(fs::metadata(&path).is_ok());
+
+        assert_eq!(expected, repo.prune(now, false).unwrap());
+        assert!(fs::metadata(&path).is_err());
+        for sha in [&tree, &master, &logged, &staged] {
+            assert!(repo.has_object(sha));
+        }
+        assert!(repo.prune(now, false).unwrap().is_empty());
+        assert!(repo.prune_expire(Some("2.fortnights.ago")).is_err());
+
+        fs::remove_dir_all("./test_prune").unwrap();
+    }
+}
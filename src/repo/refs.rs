@@ -0,0 +1,432 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::lock::{self, Lock};
+use crate::object::commit::Commit;
+use crate::object::{operation, Object};
+use crate::repo::Repo;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The longest chain of symbolic references followed before giving up.
+const MAX_SYMREF_DEPTH: usize = 5;
+
+/// A ref locked by `Repo::ref_lock`, updated by `Repo::ref_commit`
+pub struct RefLock {
+    name: String,
+    lock: Lock,
+}
+
+/// The error of locking or writing the file at path.
+fn lock_error(path: &Path, e: io::Error) -> Error {
+    Error::Repo(match e.kind() {
+        io::ErrorKind::AlreadyExists => error::repo::Repo::Locked(path.to_owned()),
+        _ => error::repo::Repo::Io(path.to_owned(), e.to_string()),
+    })
+}
+
+/// The characters ref names cannot hold, besides the control characters
+const REF_FORBIDDEN: &str = " ~^:?*[\\";
+
+/// Whether name is a valid ref name, as `git check-ref-format` has it: its components are
+/// not empty, do not start with a dot nor end with `.lock`, and it holds no `..`, no `@{`,
+/// no control character and none of `~^:?*[\` or a space. Outside of `refs/`, only the
+/// pseudo refs such as `HEAD` or `ORIG_HEAD` are valid.
+pub fn check_ref_format(name: &str) -> bool {
+    if !(name.starts_with("refs/")) {
+        return !(name.is_empty()) && name.chars().all(|c| c.is_ascii_uppercase() || c == '_');
+    }
+
+    let forbidden = name.contains("..")
+        || name.contains("@{")
+        || name.ends_with('.')
+        || name
+            .chars()
+            .any(|c| c.is_ascii_control() || REF_FORBIDDEN.contains(c));
+    !(forbidden)
+        && name.split('/').all(|component| {
+            !(component.is_empty() || component.starts_with('.') || component.ends_with(".lock"))
+        })
+}
+
+impl Repo {
+    /// Refs outside of `refs/` (`HEAD`, `ORIG_HEAD`...) belong to the worktree,
+    /// the others are shared by all the worktrees. Invalid names have no path, so that
+    /// none leads out of the repository.
+    pub fn ref_path(&self, name: &str) -> error::Result<PathBuf> {
+        if !(check_ref_format(name)) {
+            return Err(Error::Repo(error::repo::Repo::InvalidReference(
+                name.to_owned(),
+            )));
+        }
+
+        Ok(if name.starts_with("refs/") {
+            self.common_dir.join(name)
+        } else {
+            self.lit_dir.join(name)
+        })
+    }
+
+    /// Resolve the ref name to an object name, following symbolic refs.
+    pub fn ref_resolve(&self, name: &str) -> Option<String> {
+        let mut name = name.to_owned();
+
+        for _ in 0..MAX_SYMREF_DEPTH {
+            match self.ref_read(&name)? {
+                Ok(sha) => return Some(sha),
+                Err(target) => name = target,
+            }
+        }
+
+        None
+    }
+
+    /// Read a single ref: Ok(sha) for a direct ref, Err(target) for a symbolic ref.
+    fn ref_read(&self, name: &str) -> Option<Result<String, String>> {
+        match fs::read_to_string(self.ref_path(name).ok()?) {
+            Ok(content) => {
+                let content = content.trim();
+
+                Some(match content.strip_prefix("ref:") {
+                    Some(target) => Err(target.trim().to_string()),
+                    None => Ok(content.to_string()),
+                })
+            }
+            Err(_) => self.packed_refs().remove(name).map(Ok),
+        }
+    }
+
+    /// The ref a symbolic ref points to, e.g. `refs/heads/master` for `HEAD`.
+    pub fn ref_symbolic_target(&self, name: &str) -> Option<String> {
+        self.ref_read(name)?.err()
+    }
+
+    /// All the refs under `refs/`, loose and packed, resolved to object names.
+    pub fn ref_list(&self) -> BTreeMap<String, String> {
+        let mut refs = self.packed_refs();
+        let mut dirs = vec![self.common_dir.join("refs")];
+
+        while let Some(dir) = dirs.pop() {
+            for entry in fs::read_dir(&dir).into_iter().flatten().flatten() {
+                let path = entry.path();
+
+                if path.is_dir() {
+                    dirs.push(path);
+                } else if path.extension().is_none_or(|ext| ext != "lock") {
+                    let name = path
+                        .strip_prefix(&self.common_dir)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .replace('\\', "/");
+
+                    match self.ref_resolve(&name) {
+                        Some(sha) => refs.insert(name, sha),
+                        None => refs.remove(&name),
+                    };
+                }
+            }
+        }
+
+        refs
+    }
+
+    /// Lock the ref name until the lock is given to `ref_commit` or dropped: other updates
+    /// of the ref fail meanwhile, so its value read while holding the lock is the one the
+    /// update replaces.
+    pub fn ref_lock(&self, name: &str) -> error::Result<RefLock> {
+        let path = self.ref_path(name)?;
+        let lock = Lock::acquire(&path).map_err(|e| lock_error(&path, e))?;
+
+        Ok(RefLock {
+            name: name.to_owned(),
+            lock,
+        })
+    }
+
+    /// Point the ref locked by lock at sha, or delete it, loose and packed, without sha.
+    pub fn ref_commit(&self, lock: RefLock, sha: Option<&str>) -> error::Result<()> {
+        let path = self.ref_path(&lock.name)?;
+        let io = |e| lock_error(&path, e);
+        match sha {
+            Some(sha) => {
+                let mut lock = lock.lock;
+                lock.write(format!("{}\n", sha).as_bytes())
+                    .and_then(|_| lock.commit())
+                    .map_err(io)
+            }
+            None => {
+                self.packed_ref_delete(&lock.name)?;
+                lock.lock.delete().map_err(io)
+            }
+        }
+    }
+
+    /// Delete the ref name, loose or packed.
+    pub fn ref_delete(&self, name: &str) -> error::Result<()> {
+        self.ref_commit(self.ref_lock(name)?, None)
+    }
+
+    /// Remove the ref name from `packed-refs`.
+    fn packed_ref_delete(&self, name: &str) -> error::Result<()> {
+        let packed = self.common_dir.join("packed-refs");
+        if !(packed.exists()) {
+            return Ok(());
+        }
+
+        let mut lock = Lock::acquire(&packed).map_err(|e| lock_error(&packed, e))?;
+        let content = fs::read_to_string(&packed).unwrap_or_default();
+        let mut removing = false;
+        let kept: String = content
+            .lines()
+            .filter(|line| {
+                // A peeled line belongs to the ref above it.
+                if !(line.starts_with('^')) {
+                    removing = line
+                        .split_once(' ')
+                        .is_some_and(|(_, ref_name)| ref_name == name);
+                }
+                !(removing)
+            })
+            .map(|line| format!("{}\n", line))
+            .collect();
+
+        if kept != content {
+            lock.write(kept.as_bytes())
+                .and_then(|_| lock.commit())
+                .map_err(|e| lock_error(&packed, e))?;
+        }
+
+        Ok(())
+    }
+
+    /// Point the ref name at sha, through a lock file.
+    pub fn ref_update(&self, name: &str, sha: &str) -> error::Result<()> {
+        self.ref_commit(self.ref_lock(name)?, Some(sha))
+    }
+
+    /// Make name a symbolic ref to target.
+    pub fn ref_update_symbolic(&self, name: &str, target: &str) -> error::Result<()> {
+        if !(check_ref_format(target)) {
+            return Err(Error::Repo(error::repo::Repo::InvalidReference(
+                target.to_owned(),
+            )));
+        }
+
+        let path = self.ref_path(name)?;
+        lock::write(&path, format!("ref: {}\n", target).as_bytes())
+            .map_err(|e| lock_error(&path, e))
+    }
+
+    /// The refs stored in `packed-refs`, as written by git when it packs refs.
+    pub fn packed_refs(&self) -> BTreeMap<String, String> {
+        fs::read_to_string(self.common_dir.join("packed-refs"))
+            .unwrap_or_default()
+            .lines()
+            .filter(|line| !(line.starts_with('#') || line.starts_with('^')))
+            .filter_map(|line| line.split_once(' '))
+            .map(|(sha, name)| (name.trim().to_string(), sha.to_string()))
+            .collect()
+    }
+
+    /// Resolve a name given by the user to an object name: `HEAD`, a full or abbreviated
+    /// object name, a tag, a branch, a remote branch or a full ref name, followed by any
+    /// number of `~<n>` (the n-th first-parent ancestor) and `^<n>` (the n-th parent).
+    pub fn resolve_name(&self, name: &str) -> Option<String> {
+        if let Some(at) = name.rfind(['~', '^']) {
+            let (base, suffix) = name.split_at(at);
+            let n = match &suffix[1..] {
+                "" => 1,
+                n => n.parse::<usize>().ok()?,
+            };
+            let commit = self.peel(&self.resolve_name(base)?)?;
+
+            return if suffix.starts_with('~') {
+                (0..n).try_fold(commit, |sha, _| self.parent(&sha, 1))
+            } else if n == 0 {
+                Some(commit)
+            } else {
+                self.parent(&commit, n)
+            };
+        }
+
+        if name == "HEAD" {
+            return self.ref_resolve("HEAD");
+        }
+
+        if self.object_format().is_full_name(name) {
+            return Some(name.to_lowercase());
+        }
+
+        self.ref_full_name(name)
+            .and_then(|name| self.ref_resolve(&name))
+            .or_else(|| self.resolve_prefix(name))
+    }
+
+    /// The full name of the ref a name given by the user designates, trying it as is, then
+    /// as a tag, a branch and a remote branch.
+    pub fn ref_full_name(&self, name: &str) -> Option<String> {
+        [
+            name.to_string(),
+            format!("refs/{}", name),
+            format!("refs/tags/{}", name),
+            format!("refs/heads/{}", name),
+            format!("refs/remotes/{}", name),
+        ]
+        .into_iter()
+        .find(|candidate| self.ref_resolve(candidate).is_some())
+    }
+
+    /// The n-th parent of the commit sha, counting from 1.
+    fn parent(&self, sha: &str, n: usize) -> Option<String> {
+        match operation::read_raw(self, sha)? {
+            (fmt, data) if fmt == "commit" => Commit::deserialize(&data)
+                .parents()
+                .get(n.checked_sub(1)?)
+                .map(|parent| parent.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Resolve an abbreviated object name, if it is unambiguous.
+    fn resolve_prefix(&self, prefix: &str) -> Option<String> {
+        if prefix.len() < 4 || !(prefix.chars().all(|c| c.is_ascii_hexdigit())) {
+            return None;
+        }
+
+        let matches = self.objects.lookup_prefix(&prefix.to_lowercase());
+
+        match matches.as_slice() {
+            [sha] => Some(sha.clone()),
+            _ => None,
+        }
+    }
+
+    /// Resolve name to the tree it designates, peeling tags and commits.
+    pub fn resolve_tree(&self, name: &str) -> Option<String> {
+        let sha = self.peel(&self.resolve_name(name)?)?;
+        let (fmt, data) = operation::read_raw(self, &sha)?;
+
+        match fmt.as_str() {
+            "tree" => Some(sha),
+            "commit" => Commit::deserialize(&data)
+                .tree()
+                .map(|tree| tree.to_string()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_ref_format;
+    use crate::commands::init::Init;
+    use crate::repo::Repo;
+    use std::fs;
+
+    #[test]
+    pub fn test_check_ref_format() {
+        for name in [
+            "HEAD",
+            "ORIG_HEAD",
+            "refs/heads/master",
+            "refs/tags/v1.0",
+            "refs/a-b/c_d",
+        ] {
+            assert!(check_ref_format(name), "{}", name);
+        }
+        for name in [
+            "",
+            "master",
+            "../HEAD",
+            "refs/",
+            "refs/heads//a",
+            "refs/heads/a/",
+            "refs/../../escaped",
+            "refs/heads/.hidden",
+            "refs/heads/a.lock",
+            "refs/heads/a.",
+            "refs/heads/a@{1}",
+            "refs/heads/a b",
+            "refs/heads/a~1",
+            "refs/heads/a^",
+            "refs/heads/a:b",
+            "refs/heads/a?",
+            "refs/heads/a*",
+            "refs/heads/a[",
+            "refs/heads/a\\b",
+            "refs/heads/a\tb",
+            "refs/heads/a\x7f",
+        ] {
+            assert!(!(check_ref_format(name)), "{}", name);
+        }
+    }
+
+    #[test]
+    pub fn test_refs() {
+        let _ = fs::remove_dir_all("./test_refs");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: false,
+            path: String::from("./test_refs"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        let sha = "9ca6e1d93dfc2343e4e404a6b742220b148649a0";
+        assert_eq!(None, repo.ref_resolve("HEAD"));
+
+        repo.ref_update("refs/heads/master", sha).unwrap();
+        assert!(repo.ref_update("refs/../../escaped", sha).is_err());
+        assert!(!(std::path::Path::new("./escaped").exists()));
+        fs::write(
+            "./test_refs/.lit/packed-refs",
+            "# pack-refs with: peeled fully-peeled sorted\n\
+             4b825dc642cb6eb9a060e54bf8d69288fbee4904 refs/tags/v1\n\
+             ^9ca6e1d93dfc2343e4e404a6b742220b148649a0\n",
+        )
+        .unwrap();
+
+        assert_eq!(Some(sha.to_string()), repo.ref_resolve("HEAD"));
+        assert_eq!(Some(sha.to_string()), repo.resolve_name("master"));
+        assert_eq!(
+            Some("4b825dc642cb6eb9a060e54bf8d69288fbee4904".to_string()),
+            repo.resolve_name("v1")
+        );
+        assert_eq!(
+            Some("refs/heads/master".to_string()),
+            repo.ref_symbolic_target("HEAD")
+        );
+        assert_eq!(
+            vec!["refs/heads/master", "refs/tags/v1"],
+            repo.ref_list().keys().collect::<Vec<_>>()
+        );
+
+        repo.ref_delete("refs/tags/v1").unwrap();
+        assert_eq!(None, repo.resolve_name("v1"));
+        assert_eq!(
+            "# pack-refs with: peeled fully-peeled sorted\n",
+            fs::read_to_string("./test_refs/.lit/packed-refs").unwrap()
+        );
+
+        fs::remove_dir_all("./test_refs").unwrap();
+    }
+}
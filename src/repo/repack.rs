@@ -0,0 +1,301 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::object::database::bitmap::{Bitmap, PackBitmap};
+use crate::object::database::loose::LooseDatabase;
+use crate::object::database::packed::PackDatabase;
+use crate::object::database::ObjectDatabase;
+use crate::repo::walk::links;
+use crate::repo::Repo;
+use std::collections::HashSet;
+use std::fs;
+
+/// Besides the commits the refs point to, one commit in this many gets a bitmap
+const BITMAP_INTERVAL: usize = 100;
+
+/// Objects are packed by type, in this order
+const PACK_ORDER: [&str; 4] = ["commit", "tag", "tree", "blob"];
+
+impl Repo {
+    pub fn pack_database(&self) -> PackDatabase {
+        PackDatabase::new(
+            &self.common_dir.join("objects").join("pack"),
+            self.object_format(),
+        )
+    }
+
+    /// Refuse to delete objects when `extensions.preciousObjects` is set.
+    pub(super) fn check_precious_objects(&self) -> error::Result<()> {
+        if self
+            .conf
+            .as_ref()
+            .is_some_and(|conf| Self::has_extension(conf, "preciousobjects"))
+        {
+            return Err(Error::Repo(error::repo::Repo::PreciousObjects(
+                self.common_dir.clone(),
+            )));
+        }
+        Ok(())
+    }
+
+    /// Delete the packs the multi-pack-index takes no object from, which precious objects
+    /// forbid. Returns the names of the deleted packs.
+    pub fn expire_multi_pack_index(&self) -> error::Result<Vec<String>> {
+        self.check_precious_objects()?;
+        self.pack_database().expire_multi_pack_index()
+    }
+
+    /// The bitmaps of the packs, read the first time they are needed, unless
+    /// `pack.useBitmaps` is false.
+    pub fn pack_bitmap(&self) -> Option<&PackBitmap> {
+        self.pack_bitmap
+            .get_or_init(|| {
+                let enabled = self
+                    .conf
+                    .as_ref()
+                    .and_then(|conf| conf.get_bool("pack.useBitmaps").ok().flatten())
+                    .unwrap_or(true);
+                enabled
+                    .then(|| self.pack_database().pack_bitmap())
+                    .flatten()
+            })
+            .as_ref()
+    }
+
+    /// The objects reachable from tips, given with their type: those of the pack of bitmap
+    /// as positions there, the others by name. A commit having a bitmap is not walked, its
+    /// bitmap has everything it reaches. Missing objects are left out, blobs are never read.
+    pub(super) fn bitmap_walk(
+        &self,
+        bitmap: &PackBitmap,
+        tips: Vec<(String, String)>,
+    ) -> (Bitmap, HashSet<String>) {
+        let format = self.object_format();
+        let mut reached = Bitmap::default();
+        let mut outside = HashSet::new();
+        let mut stack = tips;
+
+        while let Some((fmt, sha)) = stack.pop() {
+            let position = bitmap.position(&sha);
+            match position {
+                Some(position) if reached.get(position) => continue,
+                None if outside.contains(&sha) => continue,
+                _ => (),
+            }
+
+            if let Some(commit) = bitmap.commit(&sha) {
+                reached.or(commit);
+                continue;
+            }
+
+            if fmt == "blob" {
+                if position.is_none() && !(self.objects.exists(&sha)) {
+                    continue;
+                }
+            } else if let Some((fmt, data)) = self.objects.read(&sha) {
+                stack.extend(links(&fmt, &data, format));
+            } else {
+                continue;
+            }
+
+            match position {
+                Some(position) => reached.set(position),
+                None => {
+                    outside.insert(sha);
+                }
+            }
+        }
+
+        (reached, outside)
+    }
+
+    /// Pack the objects reachable from the refs, the HEADs, the reflogs and the indexes into
+    /// a single pack. With write_bitmap, the commits the refs point to and one in
+    /// `BITMAP_INTERVAL` of those they reach get a bitmap. With delete, the other packs go,
+    /// along with the unreachable objects they had, and so do the loose objects the new pack
+    /// has, which precious objects forbid. Returns the name of the pack and how many objects
+    /// it has, None when there is nothing to pack.
+    pub fn repack(
+        &self,
+        delete: bool,
+        write_bitmap: bool,
+    ) -> error::Result<Option<(String, usize)>> {
+        if delete {
+            self.check_precious_objects()?;
+        }
+
+        let mut objects: Vec<(String, String)> = self
+            .reachable_objects(&[])
+            .into_iter()
+            .filter_map(|sha| Some((sha.clone(), self.objects.read_header(&sha)?.0)))
+            .collect();
+        if objects.is_empty() {
+            return Ok(None);
+        }
+        objects
+            .sort_by_key(|(sha, fmt)| (PACK_ORDER.iter().position(|typ| typ == fmt), sha.clone()));
+
+        let bitmap = write_bitmap.then(|| {
+            let mut bitmap = PackBitmap::new(&objects);
+            let tips: Vec<String> = self.ref_list().into_values().collect();
+            let tips: HashSet<String> = tips.iter().filter_map(|tip| self.peel(tip)).collect();
+
+            // Older commits first, so that newer ones stop at their bitmaps.
+            let commits = self.walk_commits(&tips.iter().cloned().collect::<Vec<_>>());
+            for (i, commit) in commits.iter().enumerate().rev() {
+                if i % BITMAP_INTERVAL == 0 || tips.contains(commit) {
+                    let (reached, _) =
+                        self.bitmap_walk(&bitmap, vec![("commit".to_string(), commit.clone())]);
+                    bitmap.insert(commit, reached);
+                }
+            }
+            bitmap
+        });
+
+        let packs = self.pack_database();
+        let names: Vec<String> = objects.into_iter().map(|(sha, _)| sha).collect();
+        let name = packs.write_pack(&names, self.objects.as_ref(), bitmap.as_ref())?;
+
+        if delete {
+            packs.remove_packs_except(&name)?;
+
+            let loose = LooseDatabase::new(&self.common_dir.join("objects"), self.object_format());
+            let packed: HashSet<&String> = names.iter().collect();
+            let redundant: Vec<String> = loose.iter().filter(|sha| packed.contains(sha)).collect();
+            for sha in redundant {
+                let path = loose.object_path(&sha).unwrap();
+                fs::remove_file(&path).map_err(|e| {
+                    Error::Object(error::object::Object::Io(path.clone(), e.to_string()))
+                })?;
+                // Only succeeds once the fan-out directory is empty.
+                let _ = fs::remove_dir(path.parent().unwrap());
+            }
+        }
+
+        Ok(Some((name, names.len())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::init::Init;
+    use crate::object::operation::write_raw;
+    use crate::repo::Repo;
+    use crate::test_util::{commit_tree, create, tree};
+    use std::collections::HashSet;
+    use std::fs;
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    pub fn test_repack() {
+        let repo = create("./test_repack/repo.git", true);
+        let mut parent = None;
+        let mut commits = Vec::new();
+        for i in 0..5 {
+            let blob = write_raw(&repo, "blob", format!("{}", i).as_bytes());
+            let tree = tree(&repo, &[("100644", "file", &blob)]);
+            let parents: Vec<&str> = parent.iter().map(String::as_str).collect();
+            let commit = commit_tree(&repo, &tree, &parents, &i.to_string());
+            commits.push(commit.clone());
+            parent = Some(commit);
+        }
+        repo.ref_update("refs/heads/master", &commits[4]).unwrap();
+        repo.ref_update("refs/heads/side", &commits[2]).unwrap();
+        let unreachable = write_raw(&repo, "blob", b"unreachable");
+
+        let reachable = repo.reachable_objects(&[]);
+        assert_eq!(15, reachable.len());
+        let (name, count) = repo.repack(true, true).unwrap().unwrap();
+        assert_eq!(15, count);
+
+        // Only the unreachable object is left loose.
+        let repo = Repo::repo_open(Path::new("./test_repack/repo.git")).unwrap();
+        let loose: Vec<String> = fs::read_dir("./test_repack/repo.git/objects")
+            .unwrap()
+            .flatten()
+            .filter(|entry| entry.file_name().len() == 2)
+            .flat_map(|entry| fs::read_dir(entry.path()).unwrap().flatten())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect();
+        assert_eq!(vec![unreachable[2..].to_string()], loose);
+
+        let bitmap = repo.pack_bitmap().unwrap();
+        assert_eq!(Some(0), bitmap.position(&commits[0]));
+        for tip in [&commits[4], &commits[2]] {
+            assert!(bitmap.commit(tip).is_some());
+        }
+        assert!(bitmap.commit(&commits[3]).is_none());
+        assert_eq!(reachable, repo.reachable_objects(&[]));
+
+        let missing: HashSet<String> = repo
+            .missing_objects(&[commits[4].clone()], &|_| false)
+            .unwrap()
+            .into_iter()
+            .collect();
+        assert_eq!(reachable, missing);
+        let listed = repo
+            .rev_list(&[commits[4].clone()], &[commits[2].clone()], true, &[])
+            .unwrap();
+        assert_eq!(6, listed.len());
+        assert!(listed[..2].contains(&commits[3]) && listed[..2].contains(&commits[4]));
+        assert_eq!(
+            vec![commits[4].clone(), commits[3].clone()],
+            repo.rev_list(&[commits[4].clone()], &[commits[2].clone()], false, &[])
+                .unwrap()
+        );
+
+        // git reads the bitmaps, and finds them right.
+        assert!(Command::new("git")
+            .args([
+                "--git-dir",
+                "./test_repack/repo.git",
+                "rev-list",
+                "--test-bitmap"
+            ])
+            .arg(&commits[4])
+            .stderr(Stdio::null())
+            .status()
+            .unwrap()
+            .success());
+
+        // Repacking again replaces the pack.
+        let (again, _) = repo.repack(true, false).unwrap().unwrap();
+        assert_eq!(name, again);
+        assert!(Path::new(&format!(
+            "./test_repack/repo.git/objects/pack/{}.pack",
+            name
+        ))
+        .exists());
+
+        // Precious objects are never deleted, not by repacking nor by expiring packs.
+        let precious = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_repack/precious.git"),
+            extensions: vec!["preciousObjects".to_string()],
+            object_format: None,
+        });
+        assert!(precious.repack(true, false).is_err());
+        assert!(precious.expire_multi_pack_index().is_err());
+        assert!(repo.expire_multi_pack_index().unwrap().is_empty());
+
+        fs::remove_dir_all("./test_repack").unwrap();
+    }
+}
@@ -0,0 +1,308 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error;
+use crate::object::database::loose::LooseDatabase;
+use crate::object::database::ObjectDatabase;
+use crate::repo::Repo;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// What `count-objects` reports, sizes in bytes
+#[derive(Debug, Default, PartialEq)]
+pub struct ObjectCounts {
+    /// The loose objects, and the disk space they use
+    pub count: usize,
+    pub size: u64,
+
+    /// The objects of the packs, the packs, and the size of the packs and of their indexes
+    pub in_pack: usize,
+    pub packs: usize,
+    pub size_pack: u64,
+
+    /// The loose objects a pack has too
+    pub prune_packable: usize,
+
+    /// The files of the object directories that are neither objects nor packs, and their
+    /// length
+    pub garbage: Vec<PathBuf>,
+    pub size_garbage: u64,
+}
+
+/// What `stats` reports, each list largest first and cut to its top entries
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// How many objects of each type there are, and their total size
+    pub types: BTreeMap<String, (usize, u64)>,
+
+    /// The size, the name and a path of the biggest blobs of the history
+    pub biggest_blobs: Vec<(u64, String, String)>,
+
+    /// The depth and the path of the deepest trees of the history
+    pub deepest_trees: Vec<(usize, String)>,
+
+    /// The length of the delta chain and the name of packed objects
+    pub delta_chains: Vec<(usize, String)>,
+
+    /// How many commits changed something under each directory, compared to their first parent
+    pub churn: Vec<(usize, String)>,
+}
+
+/// The space a file takes on disk, in blocks where it has some.
+fn disk_usage(path: &Path) -> u64 {
+    let meta = match fs::symlink_metadata(path) {
+        Ok(meta) => meta,
+        Err(_) => return 0,
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        meta.blocks() * 512
+    }
+
+    #[cfg(not(unix))]
+    meta.len()
+}
+
+/// The top entries of list, largest first, ties by name.
+fn top<N: Ord + Copy, T: Ord>(mut list: Vec<(N, T)>, top: usize) -> Vec<(N, T)> {
+    list.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    list.truncate(top);
+    list
+}
+
+impl Repo {
+    /// Count the loose objects and the packed ones, and find the garbage lying among them.
+    pub fn count_objects(&self) -> ObjectCounts {
+        let loose = LooseDatabase::new(&self.common_dir.join("objects"), self.object_format());
+        let packs = self.pack_database();
+        let names: Vec<String> = loose.iter().collect();
+        let (pack_count, in_pack, size_pack) = packs.counts();
+        let garbage: Vec<PathBuf> = loose.garbage().into_iter().chain(packs.garbage()).collect();
+
+        ObjectCounts {
+            count: names.len(),
+            size: names
+                .iter()
+                .filter_map(|sha| loose.object_path(sha))
+                .map(|path| disk_usage(&path))
+                .sum(),
+            in_pack,
+            packs: pack_count,
+            size_pack,
+            prune_packable: names.iter().filter(|sha| packs.exists(sha)).count(),
+            size_garbage: garbage
+                .iter()
+                .filter_map(|path| fs::metadata(path).ok())
+                .map(|meta| meta.len())
+                .sum(),
+            garbage,
+        }
+    }
+
+    /// Gather what makes the repository big: the objects by type, then the top of the biggest
+    /// blobs and deepest trees of the commits reachable from the refs and HEAD, of the longest
+    /// delta chains of the packs, and of the directories the most commits changed.
+    pub fn stats(&self, count: usize) -> error::Result<Stats> {
+        let mut types = BTreeMap::new();
+        for sha in self.objects.iter() {
+            if let Some((fmt, size)) = self.objects.read_header(&sha) {
+                let (objects, total) = types.entry(fmt).or_insert((0, 0));
+                *objects += 1;
+                *total += size as u64;
+            }
+        }
+
+        let tips: Vec<String> = self
+            .ref_list()
+            .into_values()
+            .chain(self.ref_resolve("HEAD"))
+            .collect();
+        let mut seen = HashSet::new();
+        let mut blobs: HashMap<String, String> = HashMap::new();
+        let mut trees: HashMap<String, usize> = HashMap::new();
+        let mut churn: HashMap<String, usize> = HashMap::new();
+
+        for sha in self.walk_commits(&tips) {
+            let commit = match self.graph_commit(&sha) {
+                Some(commit) => commit,
+                None => continue,
+            };
+
+            // A tree seen once has been walked whole, the first path of a blob is kept.
+            let mut stack = vec![(commit.tree.clone(), String::new(), 0)];
+            while let Some((tree, path, depth)) = stack.pop() {
+                if !(seen.insert(tree.clone())) {
+                    continue;
+                }
+                if depth > 0 {
+                    trees.entry(path.clone()).or_insert(depth);
+                }
+
+                for entry in self.tree_entries(Some(&tree))?.into_values() {
+                    let path = match path.as_str() {
+                        "" => entry.name.clone(),
+                        path => format!("{}/{}", path, entry.name),
+                    };
+                    match entry.object_type() {
+                        "tree" => stack.push((entry.sha, path, depth + 1)),
+                        "blob" => {
+                            blobs.entry(entry.sha).or_insert(path);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+
+            let parent = commit
+                .parents
+                .first()
+                .and_then(|parent| self.graph_commit(parent))
+                .map(|parent| parent.tree);
+            let mut paths = Vec::new();
+            self.changed_paths(parent.as_deref(), Some(&commit.tree), "", &mut paths)?;
+            let dirs: BTreeSet<&str> = paths
+                .iter()
+                .flat_map(|path| path.match_indices('/').map(move |(end, _)| &path[..end]))
+                .collect();
+            for dir in dirs {
+                *churn.entry(dir.to_string()).or_default() += 1;
+            }
+        }
+
+        let biggest_blobs = top(
+            blobs
+                .into_iter()
+                .filter_map(|(sha, path)| {
+                    let (_, size) = self.objects.read_header(&sha)?;
+                    Some((size as u64, (sha, path)))
+                })
+                .collect(),
+            count,
+        );
+
+        Ok(Stats {
+            types,
+            biggest_blobs: biggest_blobs
+                .into_iter()
+                .map(|(size, (sha, path))| (size, sha, path))
+                .collect(),
+            deepest_trees: top(
+                trees
+                    .into_iter()
+                    .map(|(path, depth)| (depth, path))
+                    .collect(),
+                count,
+            ),
+            delta_chains: top(
+                self.pack_database()
+                    .delta_depths()
+                    .into_iter()
+                    .map(|(sha, depth)| (depth, sha))
+                    .collect(),
+                count,
+            ),
+            churn: top(
+                churn
+                    .into_iter()
+                    .map(|(dir, commits)| (commits, dir))
+                    .collect(),
+                count,
+            ),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::object::operation::write_raw;
+    use crate::repo::Repo;
+    use crate::test_util::{commit_tree, create, tree};
+    use std::fs;
+    use std::path::Path;
+
+    /// A tree holding a single file at path, directories included.
+    fn tree_at(repo: &Repo, path: &str, content: &str) -> String {
+        let mut sha = write_raw(repo, "blob", content.as_bytes());
+        let mut mode = "100644";
+        for name in path.rsplit('/') {
+            sha = tree(repo, &[(mode, name, &sha)]);
+            mode = "40000";
+        }
+        sha
+    }
+
+    #[test]
+    pub fn test_stats() {
+        let repo = create("./test_stats/repo.git", true);
+        let mut parent = String::new();
+        for (path, content) in [
+            ("a/file", "1"),
+            ("a/file", "22"),
+            ("a/b/c/file", "333"),
+            ("d/file", "4444"),
+        ] {
+            let tree = tree_at(&repo, path, content);
+            let parents: Vec<&str> = Some(parent.as_str())
+                .filter(|parent| !(parent.is_empty()))
+                .into_iter()
+                .collect();
+            parent = commit_tree(&repo, &tree, &parents, content);
+        }
+        repo.ref_update("refs/heads/master", &parent).unwrap();
+
+        let counts = repo.count_objects();
+        // 4 commits, 4 blobs and 2 + 2 + 4 + 2 trees
+        assert_eq!((18, 0, 0), (counts.count, counts.packs, counts.in_pack));
+        assert!(counts.size > 0 && counts.garbage.is_empty());
+
+        let stats = repo.stats(2).unwrap();
+        assert_eq!(Some(&(4, 10)), stats.types.get("blob"));
+        assert_eq!(
+            vec![(4, "d/file".to_string()), (3, "a/b/c/file".to_string())],
+            stats
+                .biggest_blobs
+                .iter()
+                .map(|(size, _, path)| (*size, path.clone()))
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            vec![(3, "a/b/c".to_string()), (2, "a/b".to_string())],
+            stats.deepest_trees
+        );
+        assert!(stats.delta_chains.is_empty());
+        assert_eq!(
+            vec![(4, "a".to_string()), (2, "a/b".to_string())],
+            stats.churn
+        );
+
+        repo.repack(true, false).unwrap();
+        fs::write("./test_stats/repo.git/objects/pack/junk", "junk").unwrap();
+        let counts = Repo::repo_open(Path::new("./test_stats/repo.git"))
+            .unwrap()
+            .count_objects();
+        assert_eq!((0, 1, 18), (counts.count, counts.packs, counts.in_pack));
+        assert!(counts.size_pack > 0);
+        assert_eq!(1, counts.garbage.len());
+        assert_eq!(4, counts.size_garbage);
+
+        fs::remove_dir_all("./test_stats").unwrap();
+    }
+}
@@ -0,0 +1,332 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::index::Index;
+use crate::r#const::LIT_DIR_NAMES;
+use crate::remote;
+use crate::repo::Repo;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// How a file differs between two of HEAD, the index and the worktree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileStatus {
+    Added,
+    Modified,
+    Deleted,
+}
+
+impl fmt::Display for FileStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileStatus::Added => write!(f, "new file"),
+            FileStatus::Modified => write!(f, "modified"),
+            FileStatus::Deleted => write!(f, "deleted"),
+        }
+    }
+}
+
+/// Where the current branch stands compared to its upstream.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Tracking {
+    /// The remote-tracking ref, e.g. `refs/remotes/origin/master`
+    pub upstream: String,
+
+    /// Whether the remote-tracking ref is missing, e.g. the branch was deleted on the remote
+    pub gone: bool,
+    pub ahead: usize,
+    pub behind: usize,
+}
+
+/// The state of the worktree, as shown by `lit status`
+#[derive(Debug)]
+pub struct Status {
+    /// The current branch, None when HEAD is detached
+    pub branch: Option<String>,
+    pub head: Option<String>,
+    pub tracking: Option<Tracking>,
+
+    /// Differences between HEAD and the index
+    pub staged: Vec<(FileStatus, String)>,
+
+    /// Differences between the index and the worktree
+    pub unstaged: Vec<(FileStatus, String)>,
+
+    /// Files the index does not know, a directory without tracked files is listed as `dir/`
+    pub untracked: Vec<String>,
+}
+
+impl Repo {
+    pub fn status(&self) -> error::Result<Status> {
+        self.require_worktree()?;
+
+        let branch = self
+            .ref_symbolic_target("HEAD")
+            .and_then(|head| head.strip_prefix("refs/heads/").map(|b| b.to_string()));
+        let head = self.ref_resolve("HEAD");
+        let index = Index::read(&self.index_path(), self.object_format())?;
+
+        let tracking = branch.as_ref().and_then(|branch| {
+            let upstream = remote::upstream(self, branch)?;
+            Some(match (&head, self.ref_resolve(&upstream)) {
+                (Some(head), Some(sha)) => {
+                    let (ahead, behind) = self.ahead_behind(head, &sha);
+                    Tracking {
+                        upstream,
+                        gone: false,
+                        ahead,
+                        behind,
+                    }
+                }
+                (_, sha) => Tracking {
+                    upstream,
+                    gone: sha.is_none(),
+                    ahead: 0,
+                    behind: 0,
+                },
+            })
+        });
+
+        let committed = match head.as_ref().and_then(|head| self.resolve_tree(head)) {
+            Some(tree) => self.flatten_tree(&tree)?,
+            None => BTreeMap::new(),
+        };
+
+        // Files only intended to be added are new to the worktree, not to the index.
+        let mut staged: Vec<(FileStatus, String)> = index
+            .entries
+            .iter()
+            .filter(|entry| !(entry.intent_to_add))
+            .filter_map(|entry| match committed.get(&entry.path) {
+                None => Some((FileStatus::Added, entry.path.clone())),
+                Some(file) if file.sha != entry.sha || file.mode != entry.tree_mode() => {
+                    Some((FileStatus::Modified, entry.path.clone()))
+                }
+                Some(_) => None,
+            })
+            .collect();
+        staged.extend(
+            committed
+                .keys()
+                .filter(|path| index.get(path).is_none())
+                .map(|path| (FileStatus::Deleted, path.clone())),
+        );
+        staged.sort_by(|a, b| a.1.cmp(&b.1));
+        staged.dedup();
+
+        // Files left out of the worktree by a sparse checkout are not missing from it.
+        let mut unstaged: Vec<(FileStatus, String)> = index
+            .entries
+            .iter()
+            .filter(|entry| !(entry.skip_worktree))
+            .filter_map(|entry| {
+                if fs::symlink_metadata(self.worktree.join(&entry.path)).is_err() {
+                    Some((FileStatus::Deleted, entry.path.clone()))
+                } else if entry.intent_to_add {
+                    Some((FileStatus::Added, entry.path.clone()))
+                } else if self.is_modified(entry) {
+                    Some((FileStatus::Modified, entry.path.clone()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        unstaged.dedup();
+
+        Ok(Status {
+            branch,
+            head,
+            tracking,
+            staged,
+            unstaged,
+            untracked: self.untracked_files(&index)?,
+        })
+    }
+
+    /// The files of the worktree the index does not track. Like git, a directory
+    /// containing no tracked file is reported as a whole instead of file by file.
+    fn untracked_files(&self, index: &Index) -> error::Result<Vec<String>> {
+        let tracked_dirs: HashSet<&str> = index
+            .entries
+            .iter()
+            .flat_map(|entry| {
+                entry
+                    .path
+                    .match_indices('/')
+                    .map(|(pos, _)| &entry.path[..pos])
+            })
+            .collect();
+
+        let mut untracked = Vec::new();
+        let mut stack = vec![self.worktree.clone()];
+        while let Some(dir) = stack.pop() {
+            for path in Self::status_read_dir(&dir)? {
+                let relative = path
+                    .strip_prefix(&self.worktree)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .replace('\\', "/");
+
+                if dir == self.worktree && LIT_DIR_NAMES.contains(&relative.as_str()) {
+                    continue;
+                }
+
+                if !(path.is_dir()) || path.is_symlink() {
+                    if index.get(&relative).is_none() {
+                        untracked.push(relative);
+                    }
+                } else if tracked_dirs.contains(relative.as_str()) {
+                    stack.push(path);
+                } else if Self::has_files(&path)? {
+                    untracked.push(format!("{}/", relative));
+                }
+            }
+        }
+
+        untracked.sort();
+        Ok(untracked)
+    }
+
+    /// Whether dir contains a file, empty directories are not worth reporting.
+    fn has_files(dir: &Path) -> error::Result<bool> {
+        for path in Self::status_read_dir(dir)? {
+            if !(path.is_dir()) || path.is_symlink() || Self::has_files(&path)? {
+                return Ok(true);
+            }
+        }
+
+        Ok(false)
+    }
+
+    fn status_read_dir(dir: &Path) -> error::Result<Vec<PathBuf>> {
+        let io = |e: std::io::Error| {
+            Error::Worktree(error::worktree::Worktree::Io(dir.to_owned(), e.to_string()))
+        };
+
+        fs::read_dir(dir)
+            .map_err(io)?
+            .map(|entry| entry.map(|entry| entry.path()).map_err(io))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::FileStatus;
+    use crate::commands::init::Init;
+    use crate::config::file::ConfigFile;
+    use crate::object::blob::Blob;
+    use crate::object::commit::{Commit, Kvlm};
+    use crate::object::operation::write;
+    use crate::object::tree::{Tree, TreeEntry};
+    use crate::repo::Repo;
+    use std::fs;
+
+    /// Commit the files on top of parent, moving master.
+    fn commit(repo: &Repo, parent: Option<&str>, files: &[(&str, &str)]) -> String {
+        let entries = files
+            .iter()
+            .map(|(name, content)| TreeEntry {
+                mode: "100644".to_string(),
+                name: name.to_string(),
+                sha: write(Box::new(Blob::new(*content)), Some(repo)),
+            })
+            .collect();
+        let tree = write(Box::new(Tree::new(entries)), Some(repo));
+
+        let mut headers = vec![("tree".to_string(), tree)];
+        headers.extend(parent.map(|parent| ("parent".to_string(), parent.to_string())));
+        let commit = write(
+            Box::new(Commit::new(Kvlm {
+                headers,
+                message: "commit\n".to_string(),
+            })),
+            Some(repo),
+        );
+
+        repo.ref_update("refs/heads/master", &commit).unwrap();
+        commit
+    }
+
+    #[test]
+    pub fn test_status() {
+        let _ = fs::remove_dir_all("./test_status");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: false,
+            path: String::from("./test_status"),
+            extensions: vec![],
+            object_format: None,
+        });
+
+        let first = commit(&repo, None, &[("a", "a\n"), ("b", "b\n")]);
+        repo.checkout_tree(&repo.resolve_tree(&first).unwrap(), false, &mut |_| ())
+            .unwrap();
+        let status = repo.status().unwrap();
+        assert_eq!(Some("master".to_string()), status.branch);
+        assert!(status.staged.is_empty() && status.unstaged.is_empty());
+        assert!(status.tracking.is_none());
+
+        // The index holds the tree of HEAD, moving HEAD makes it look staged.
+        commit(&repo, Some(&first), &[("a", "a\n"), ("c", "c\n")]);
+        fs::write("./test_status/a", "changed a\n").unwrap();
+        fs::remove_file("./test_status/b").unwrap();
+        fs::create_dir_all("./test_status/new/dir").unwrap();
+        fs::write("./test_status/new/dir/file", "").unwrap();
+        fs::create_dir_all("./test_status/empty").unwrap();
+        fs::write("./test_status/d", "").unwrap();
+
+        let status = repo.status().unwrap();
+        assert_eq!(
+            vec![
+                (FileStatus::Added, "b".to_string()),
+                (FileStatus::Deleted, "c".to_string())
+            ],
+            status.staged
+        );
+        assert_eq!(
+            vec![
+                (FileStatus::Modified, "a".to_string()),
+                (FileStatus::Deleted, "b".to_string())
+            ],
+            status.unstaged
+        );
+        assert_eq!(vec!["d".to_string(), "new/".to_string()], status.untracked);
+
+        // A branch of this repository as upstream.
+        repo.ref_update("refs/heads/base", &first).unwrap();
+        let mut conf = ConfigFile::open(&repo.common_dir.join("config")).unwrap();
+        conf.set("branch.master.remote", ".").unwrap();
+        conf.set("branch.master.merge", "refs/heads/base").unwrap();
+        conf.save().unwrap();
+
+        let repo = Repo::repo_open(&repo.worktree).unwrap();
+        let tracking = repo.status().unwrap().tracking.unwrap();
+        assert_eq!("refs/heads/base", tracking.upstream);
+        assert_eq!((1, 0), (tracking.ahead, tracking.behind));
+
+        repo.ref_delete("refs/heads/base").unwrap();
+        assert!(repo.status().unwrap().tracking.unwrap().gone);
+
+        fs::remove_dir_all("./test_status").unwrap();
+    }
+}
@@ -0,0 +1,495 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::index::{Index, IndexEntry};
+use crate::object::tree::{Tree, TreeEntry};
+use crate::object::Object;
+use crate::repo::Repo;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The mode of the directories in trees
+const MODE_TREE: &str = "40000";
+
+/// Whether two versions of a path are the same object with the same mode, absent ones
+/// being the same.
+pub(super) fn same(a: Option<&TreeEntry>, b: Option<&TreeEntry>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.sha == b.sha && a.mode == b.mode,
+        (a, b) => a.is_none() && b.is_none(),
+    }
+}
+
+/// Whether an index entry holds the version of a tree, absent ones matching.
+fn matches(index: Option<&IndexEntry>, tree: Option<&TreeEntry>) -> bool {
+    match (index, tree) {
+        (Some(index), Some(tree)) => index.sha == tree.sha && index.tree_mode() == tree.mode,
+        (index, tree) => index.is_none() && tree.is_none(),
+    }
+}
+
+/// The relation of the path of an entry to a path given to `ls-tree`: whether it is at that
+/// path or under it, and whether it is a directory leading to it. A path ending with a slash
+/// names what the directory holds rather than the directory.
+fn path_match(path: &str, spec: &str) -> (bool, bool) {
+    let directory = spec.ends_with('/');
+    let spec = spec.trim_end_matches('/');
+    let inside = (path == spec && !directory) || path.starts_with(&format!("{}/", spec));
+    let leading = (path == spec && directory) || spec.starts_with(&format!("{}/", path));
+    (inside, leading)
+}
+
+impl Repo {
+    /// The entries of tree sha as `ls-tree` lists them, with their path. Subtrees are walked
+    /// when recursive, and listed then only with show_trees. Given paths, only the entries at
+    /// or under them are listed, the directories leading to them being walked.
+    pub fn ls_tree(
+        &self,
+        sha: &str,
+        recursive: bool,
+        show_trees: bool,
+        paths: &[String],
+    ) -> error::Result<Vec<(String, TreeEntry)>> {
+        let mut listed = Vec::new();
+        self.ls_tree_into(sha, "", recursive, show_trees, paths, &mut listed)?;
+        Ok(listed)
+    }
+
+    fn ls_tree_into(
+        &self,
+        sha: &str,
+        prefix: &str,
+        recursive: bool,
+        show_trees: bool,
+        paths: &[String],
+        listed: &mut Vec<(String, TreeEntry)>,
+    ) -> error::Result<()> {
+        for entry in self.tree_entries(Some(sha))?.into_values() {
+            let path = format!("{}{}", prefix, entry.name);
+            let (inside, leading) = paths
+                .iter()
+                .map(|spec| path_match(&path, spec))
+                .fold((paths.is_empty(), false), |(inside, leading), (i, l)| {
+                    (inside || i, leading || l)
+                });
+
+            let (show, walk) = match entry.is_tree() {
+                true if inside => (!recursive || show_trees, recursive),
+                true if leading => (show_trees, true),
+                _ => (inside, false),
+            };
+            if show {
+                listed.push((path.clone(), entry.clone()));
+            }
+            if walk {
+                let prefix = format!("{}/", path);
+                self.ls_tree_into(&entry.sha, &prefix, recursive, show_trees, paths, listed)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write the trees of the index, returning the name of the top one. Every object the
+    /// index names must exist, and no path may be unmerged.
+    pub fn write_tree(&self) -> error::Result<String> {
+        let index = Index::read(&self.index_path(), self.object_format())?;
+        if let Some(entry) = index.entries.iter().find(|entry| entry.stage != 0) {
+            return Err(Error::Index(error::index::Index::Unmerged(
+                entry.path.clone(),
+            )));
+        }
+
+        // Files only intended to be added have no content staged yet.
+        let mut entries: Vec<&IndexEntry> = index
+            .entries
+            .iter()
+            .filter(|entry| !(entry.intent_to_add))
+            .collect();
+        entries.sort_by(|a, b| a.path.as_bytes().cmp(b.path.as_bytes()));
+        self.write_index_tree(&entries, "")
+    }
+
+    /// Write the tree of the entries under prefix, all of them sorted.
+    fn write_index_tree(&self, entries: &[&IndexEntry], prefix: &str) -> error::Result<String> {
+        let mut tree = Vec::new();
+        let mut i = 0;
+
+        while i < entries.len() {
+            let name = &entries[i].path[prefix.len()..];
+            match name.split_once('/') {
+                None => {
+                    let entry = entries[i];
+                    // Gitlinks name commits of another repository.
+                    if entry.tree_mode() != "160000" && !(self.has_object(&entry.sha)) {
+                        return Err(Error::Object(error::object::Object::NotFound(
+                            entry.sha.clone(),
+                        )));
+                    }
+                    tree.push(TreeEntry {
+                        mode: entry.tree_mode(),
+                        name: name.to_string(),
+                        sha: entry.sha.clone(),
+                    });
+                    i += 1;
+                }
+                Some((dir, _)) => {
+                    let sub = format!("{}{}/", prefix, dir);
+                    let end = i + entries[i..]
+                        .iter()
+                        .take_while(|entry| entry.path.starts_with(&sub))
+                        .count();
+                    tree.push(TreeEntry {
+                        mode: MODE_TREE.to_string(),
+                        name: dir.to_string(),
+                        sha: self.write_index_tree(&entries[i..end], &sub)?,
+                    });
+                    i = end;
+                }
+            }
+        }
+
+        self.objects.write("tree", &Tree::new(tree).serialize())
+    }
+
+    /// Read trees into the index, each a tree name. Without merge, the files of the trees
+    /// replace the index, later trees overriding earlier ones, or are added under prefix,
+    /// which must not be in the index yet. With merge, a single tree is read the same way,
+    /// two trees move the index from the first to the second, keeping its own changes, and
+    /// three trees merge the last two, the first being their base, conflicts being left in
+    /// stages 1 to 3. A merge refuses to lose a change of the index or of the worktree.
+    /// Entries unchanged keep their stat data.
+    pub fn read_tree(
+        &self,
+        trees: &[String],
+        merge: bool,
+        prefix: Option<&str>,
+    ) -> error::Result<()> {
+        let index = Index::read(&self.index_path(), self.object_format())?;
+        if merge {
+            if let Some(entry) = index.entries.iter().find(|entry| entry.stage != 0) {
+                return Err(Error::Index(error::index::Index::Unmerged(
+                    entry.path.clone(),
+                )));
+            }
+        }
+        let trees = trees
+            .iter()
+            .map(|tree| self.flatten_tree(tree))
+            .collect::<error::Result<Vec<_>>>()?;
+
+        let current: BTreeMap<&str, &IndexEntry> = index
+            .entries
+            .iter()
+            .filter(|entry| entry.stage == 0)
+            .map(|entry| (entry.path.as_str(), entry))
+            .collect();
+        let entry = |path: &str, tree: &TreeEntry, stage: u16| match current.get(path) {
+            Some(index) if stage == 0 && matches(Some(index), Some(tree)) => (*index).clone(),
+            _ => IndexEntry {
+                mode: u32::from_str_radix(&tree.mode, 8).unwrap(),
+                sha: tree.sha.clone(),
+                stage,
+                path: path.to_string(),
+                ..Default::default()
+            },
+        };
+
+        let entries = match (trees.as_slice(), prefix) {
+            ([tree], Some(prefix)) => {
+                let prefix = format!("{}/", prefix.trim_end_matches('/'));
+                let dir = prefix.trim_end_matches('/');
+                if index
+                    .entries
+                    .iter()
+                    .any(|entry| entry.path == dir || entry.path.starts_with(&prefix))
+                {
+                    return Err(Error::Index(error::index::Index::PrefixExists(
+                        dir.to_string(),
+                    )));
+                }
+
+                let mut entries = index.entries.clone();
+                entries.extend(
+                    tree.iter()
+                        .map(|(path, tree)| entry(&format!("{}{}", prefix, path), tree, 0)),
+                );
+                entries
+            }
+            ([head, target], None) if merge => {
+                self.two_way_merge(&current, head, target, &entry)?
+            }
+            ([base, ours, theirs], None) if merge => {
+                self.three_way_merge(&current, base, ours, theirs, &entry)?
+            }
+            (trees, _) => {
+                let mut files = BTreeMap::new();
+                trees.iter().for_each(|tree| files.extend(tree.iter()));
+                files
+                    .into_iter()
+                    .map(|(path, tree)| entry(path, tree, 0))
+                    .collect()
+            }
+        };
+
+        Index { entries, ..index }.write(&self.index_path(), self.object_format())
+    }
+
+    /// Whether the worktree still has what the index recorded for an entry, as a merge
+    /// needs to touch it. Repositories without a worktree always do.
+    fn is_uptodate(&self, entry: &IndexEntry) -> bool {
+        self.is_bare() || !(self.is_modified(entry))
+    }
+
+    /// Move the index from the tree head to the tree target, as git's two-way merge does:
+    /// a path the index changed is kept as it is, unless target changed it too.
+    fn two_way_merge(
+        &self,
+        current: &BTreeMap<&str, &IndexEntry>,
+        head: &BTreeMap<String, TreeEntry>,
+        target: &BTreeMap<String, TreeEntry>,
+        entry: &dyn Fn(&str, &TreeEntry, u16) -> IndexEntry,
+    ) -> error::Result<Vec<IndexEntry>> {
+        let initial = current.is_empty();
+        let paths: BTreeSet<&str> = current
+            .keys()
+            .copied()
+            .chain(head.keys().map(String::as_str))
+            .chain(target.keys().map(String::as_str))
+            .collect();
+        let overwritten =
+            |path: &str| Error::Index(error::index::Index::WouldOverwrite(path.to_string()));
+        let mut entries = Vec::new();
+
+        for path in paths {
+            let (index, head, target) =
+                (current.get(path).copied(), head.get(path), target.get(path));
+
+            match (index, head, target) {
+                (None, _, Some(target)) if initial || head.is_none() => {
+                    entries.push(entry(path, target, 0))
+                }
+                (None, _, _) if same(head, target) || target.is_none() => (),
+                (None, _, _) => return Err(overwritten(path)),
+                (Some(index), _, _) if same(head, target) || matches(Some(index), target) => {
+                    entries.push(index.clone())
+                }
+                (Some(index), Some(_), _) if matches(Some(index), head) => {
+                    if !(self.is_uptodate(index)) {
+                        return Err(Error::Index(error::index::Index::NotUptodate(
+                            path.to_string(),
+                        )));
+                    }
+                    entries.extend(target.map(|target| entry(path, target, 0)));
+                }
+                (Some(_), _, _) => return Err(overwritten(path)),
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// Merge the trees ours and theirs, base being their common ancestor, as git's three-way
+    /// merge does: a path only one side changed takes that side, one both sides changed the
+    /// same way takes it, the others are left in stages, 1 for base, 2 for ours and 3 for
+    /// theirs. The index must match ours.
+    fn three_way_merge(
+        &self,
+        current: &BTreeMap<&str, &IndexEntry>,
+        base: &BTreeMap<String, TreeEntry>,
+        ours: &BTreeMap<String, TreeEntry>,
+        theirs: &BTreeMap<String, TreeEntry>,
+        entry: &dyn Fn(&str, &TreeEntry, u16) -> IndexEntry,
+    ) -> error::Result<Vec<IndexEntry>> {
+        let paths: BTreeSet<&str> = current
+            .keys()
+            .copied()
+            .chain(
+                [base, ours, theirs]
+                    .iter()
+                    .flat_map(|tree| tree.keys().map(String::as_str)),
+            )
+            .collect();
+        let overwritten =
+            |path: &str| Error::Index(error::index::Index::WouldOverwrite(path.to_string()));
+        let mut entries = Vec::new();
+
+        for path in paths {
+            let index = current.get(path).copied();
+            let (base, ours, theirs) = (base.get(path), ours.get(path), theirs.get(path));
+            let ours_unchanged = !(same(ours, theirs)) && same(base, ours);
+            let theirs_unchanged = !(same(ours, theirs)) && same(base, theirs);
+
+            // Only theirs changed: the index may already have their version.
+            if let (Some(theirs), true, false) = (theirs, ours_unchanged, theirs_unchanged) {
+                if index.is_some() && !(matches(index, Some(theirs)) || matches(index, ours)) {
+                    return Err(overwritten(path));
+                }
+                entries.push(entry(path, theirs, 0));
+                continue;
+            }
+            if !(matches(index, ours)) {
+                return Err(overwritten(path));
+            }
+
+            if let Some(ours) = ours {
+                if same(Some(ours), theirs) || (theirs_unchanged && !ours_unchanged) {
+                    entries.push(entry(path, ours, 0));
+                    continue;
+                }
+            } else if theirs.is_none() && base.is_none() {
+                continue;
+            }
+
+            if let Some(index) = index {
+                if !(self.is_uptodate(index)) {
+                    return Err(Error::Index(error::index::Index::NotUptodate(
+                        path.to_string(),
+                    )));
+                }
+            }
+            for (stage, tree) in [(1, base), (2, ours), (3, theirs)] {
+                entries.extend(tree.map(|tree| entry(path, tree, stage)));
+            }
+        }
+
+        Ok(entries)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::init::Init;
+    use crate::index::{Index, IndexEntry};
+    use crate::object::operation::write_raw;
+    use crate::repo::Repo;
+    use std::fs;
+    use std::slice;
+
+    /// Replace the index with files, given by path and blob.
+    fn stage(repo: &Repo, files: &[(&str, &str)]) {
+        let entries = files
+            .iter()
+            .map(|(path, sha)| IndexEntry {
+                mode: 0o100644,
+                sha: sha.to_string(),
+                path: path.to_string(),
+                ..Default::default()
+            })
+            .collect();
+        Index {
+            entries,
+            ..Default::default()
+        }
+        .write(&repo.index_path(), repo.object_format())
+        .unwrap();
+    }
+
+    /// The path, blob and stage of the entries of the index.
+    fn staged(repo: &Repo) -> Vec<(String, String, u16)> {
+        Index::read(&repo.index_path(), repo.object_format())
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| (entry.path, entry.sha, entry.stage))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_tree() {
+        let _ = fs::remove_dir_all("./test_tree");
+        let repo = Repo::create(&Init {
+            force: false,
+            bare: true,
+            path: String::from("./test_tree/repo.git"),
+            extensions: vec![],
+            object_format: None,
+        });
+        let [one, two, three] = ["1", "2", "3"].map(|c| write_raw(&repo, "blob", c.as_bytes()));
+        let paths = |listed: Vec<(String, _)>| -> Vec<String> {
+            listed.into_iter().map(|(path, _)| path).collect()
+        };
+
+        stage(&repo, &[("a", &one), ("d/x", &one), ("d/y", &one)]);
+        let base = repo.write_tree().unwrap();
+        assert_eq!(
+            vec!["a", "d/x", "d/y"],
+            repo.flatten_tree(&base)
+                .unwrap()
+                .into_keys()
+                .collect::<Vec<_>>()
+        );
+        stage(&repo, &[("a", &two), ("d/x", &one), ("d/y", &one)]);
+        let ours = repo.write_tree().unwrap();
+        stage(&repo, &[("a", &three), ("d/x", &two), ("d/y", &one)]);
+        let theirs = repo.write_tree().unwrap();
+
+        let ls = |recursive, show_trees, specs: &[&str]| {
+            let specs: Vec<String> = specs.iter().map(|spec| spec.to_string()).collect();
+            paths(repo.ls_tree(&base, recursive, show_trees, &specs).unwrap())
+        };
+        assert_eq!(vec!["a", "d"], ls(false, false, &[]));
+        assert_eq!(vec!["a", "d/x", "d/y"], ls(true, false, &[]));
+        assert_eq!(vec!["a", "d", "d/x", "d/y"], ls(true, true, &[]));
+        assert_eq!(vec!["d/x"], ls(false, false, &["d/x"]));
+        assert_eq!(vec!["d/x", "d/y"], ls(false, false, &["d/"]));
+
+        // Moving from base to ours keeps a change of the index only ours does not touch.
+        repo.read_tree(slice::from_ref(&base), false, None).unwrap();
+        stage(&repo, &[("a", &one), ("d/x", &three), ("d/y", &one)]);
+        repo.read_tree(&[base.clone(), ours.clone()], true, None)
+            .unwrap();
+        assert_eq!(
+            (two.clone(), 0),
+            (staged(&repo)[0].1.clone(), staged(&repo)[0].2)
+        );
+        assert_eq!(three, staged(&repo)[1].1);
+        stage(&repo, &[("a", &three), ("d/x", &one), ("d/y", &one)]);
+        assert!(repo
+            .read_tree(&[base.clone(), ours.clone()], true, None)
+            .is_err());
+
+        // Both sides changed a, only theirs changed d/x.
+        repo.read_tree(slice::from_ref(&ours), false, None).unwrap();
+        repo.read_tree(&[base.clone(), ours.clone(), theirs.clone()], true, None)
+            .unwrap();
+        assert_eq!(
+            vec![
+                ("a".to_string(), one.clone(), 1),
+                ("a".to_string(), two.clone(), 2),
+                ("a".to_string(), three.clone(), 3),
+                ("d/x".to_string(), two.clone(), 0),
+                ("d/y".to_string(), one.clone(), 0),
+            ],
+            staged(&repo)
+        );
+        assert!(repo.write_tree().is_err());
+
+        repo.read_tree(slice::from_ref(&base), false, None).unwrap();
+        repo.read_tree(slice::from_ref(&ours), false, Some("sub"))
+            .unwrap();
+        assert_eq!(6, staged(&repo).len());
+        assert_eq!(
+            ("sub/a".to_string(), two),
+            (staged(&repo)[3].0.clone(), staged(&repo)[3].1.clone())
+        );
+        assert!(repo.read_tree(&[ours], false, Some("sub/")).is_err());
+
+        fs::remove_dir_all("./test_tree").unwrap();
+    }
+}
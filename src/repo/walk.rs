@@ -0,0 +1,442 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::error::{self, Error};
+use crate::index::Index;
+use crate::object::commit::{Commit, Kvlm};
+use crate::object::commit_graph::GraphCommit;
+use crate::object::database::bitmap::PackBitmap;
+use crate::object::format::ObjectFormat;
+use crate::object::operation;
+use crate::object::tree::{Tree, TreeEntry};
+use crate::object::Object;
+use crate::repo::tree::same;
+use crate::repo::Repo;
+use std::collections::{HashSet, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Gitlinks name commits of another repository, they are never walked.
+const GITLINK_MODE: &str = "160000";
+
+/// The objects an object of type fmt links to, with the type they must have.
+/// Gitlinks name commits of another repository, they are not followed.
+pub fn links(fmt: &str, data: &[u8], format: ObjectFormat) -> Vec<(String, String)> {
+    match fmt {
+        "commit" => Kvlm::parse(data)
+            .map(|commit| {
+                let tree = commit.get_all("tree").into_iter().map(|sha| ("tree", sha));
+                let parents = commit
+                    .get_all("parent")
+                    .into_iter()
+                    .map(|sha| ("commit", sha));
+                tree.chain(parents)
+                    .map(|(fmt, sha)| (fmt.to_string(), sha.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        "tree" => Tree::parse(data, format)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|entry| entry.mode != GITLINK_MODE)
+            .map(|entry| (entry.object_type().to_string(), entry.sha))
+            .collect(),
+        "tag" => Kvlm::parse(data)
+            .and_then(|tag| Some((tag.get("type")?.to_string(), tag.get("object")?.to_string())))
+            .into_iter()
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// The files under dir, recursively.
+fn files_under(dir: &Path) -> Vec<PathBuf> {
+    fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .flat_map(|entry| match entry.path() {
+            path if path.is_dir() => files_under(&path),
+            path => vec![path],
+        })
+        .collect()
+}
+
+impl Repo {
+    pub fn has_object(&self, sha: &str) -> bool {
+        self.objects.exists(sha)
+    }
+
+    fn read_object(&self, sha: &str) -> error::Result<(String, Vec<u8>)> {
+        operation::read_raw(self, sha).ok_or(Error::Object(error::object::Object::NotFound(
+            sha.to_owned(),
+        )))
+    }
+
+    /// Follow annotated tags down to the object they name.
+    pub fn peel(&self, sha: &str) -> Option<String> {
+        let mut sha = sha.to_owned();
+
+        loop {
+            match operation::read_raw(self, &sha)? {
+                (fmt, data) if fmt == "tag" => sha = Kvlm::parse(&data)?.get("object")?.to_string(),
+                _ => return Some(sha),
+            }
+        }
+    }
+
+    /// Whether ancestor is descendant or one of its ancestors. With the commit-graph, commits
+    /// whose generation is not above the one of ancestor are not walked past: ancestor cannot
+    /// be below them.
+    pub fn is_ancestor(&self, ancestor: &str, descendant: &str) -> bool {
+        let lowest = self
+            .commit_graph()
+            .and_then(|graph| graph.commit(ancestor))
+            .map_or(0, |commit| commit.generation);
+        let mut seen = HashSet::new();
+        let mut queue = VecDeque::from([descendant.to_owned()]);
+
+        while let Some(sha) = queue.pop_front() {
+            if sha == ancestor {
+                return true;
+            }
+
+            if seen.insert(sha.clone()) {
+                if let Some(commit) = self.graph_commit(&sha) {
+                    if commit.generation > lowest {
+                        queue.extend(commit.parents);
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    /// The commits reachable from tips, nearest to them first, their parents read from the
+    /// commit-graph when it has them.
+    pub fn walk_commits(&self, tips: &[String]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut commits = Vec::new();
+        let mut queue: VecDeque<String> = tips.iter().filter_map(|tip| self.peel(tip)).collect();
+
+        while let Some(sha) = queue.pop_front() {
+            if !(seen.insert(sha.clone())) {
+                continue;
+            }
+
+            if let Some(commit) = self.graph_commit(&sha) {
+                queue.extend(commit.parents);
+                commits.push(sha);
+            }
+        }
+
+        commits
+    }
+
+    /// The commit sha and all its ancestors.
+    fn ancestors(&self, sha: &str) -> HashSet<String> {
+        let mut seen = HashSet::new();
+        let mut stack = vec![sha.to_owned()];
+
+        while let Some(sha) = stack.pop() {
+            if seen.insert(sha.clone()) {
+                if let Some(commit) = self.graph_commit(&sha) {
+                    stack.extend(commit.parents);
+                }
+            }
+        }
+
+        seen
+    }
+
+    /// How many commits local has that upstream does not have, and the other way around.
+    pub fn ahead_behind(&self, local: &str, upstream: &str) -> (usize, usize) {
+        let local = self.ancestors(local);
+        let upstream = self.ancestors(upstream);
+
+        (
+            local.difference(&upstream).count(),
+            upstream.difference(&local).count(),
+        )
+    }
+
+    /// The objects reachable from tips that the other side lacks, according to has. A commit
+    /// or tree it already has is not walked: having an object means having everything it
+    /// refers to. Nor is a commit with a pack bitmap, which has everything it reaches.
+    pub fn missing_objects(
+        &self,
+        tips: &[String],
+        has: &dyn Fn(&str) -> bool,
+    ) -> error::Result<Vec<String>> {
+        let bitmap = self.pack_bitmap();
+        let mut seen = HashSet::new();
+        let mut missing = Vec::new();
+        let mut stack: Vec<String> = tips.to_vec();
+
+        while let Some(sha) = stack.pop() {
+            if !(seen.insert(sha.clone())) || has(&sha) {
+                continue;
+            }
+
+            if let Some((bitmap, reached)) =
+                bitmap.and_then(|bitmap| Some((bitmap, bitmap.commit(&sha)?)))
+            {
+                for object in reached.ones().map(|position| bitmap.object(position)) {
+                    if seen.insert(object.to_string()) && !(has(object)) {
+                        missing.push(object.to_string());
+                    }
+                }
+                missing.push(sha);
+                continue;
+            }
+
+            let (fmt, data) = self.read_object(&sha)?;
+            let malformed =
+                || Error::Object(error::object::Object::Malformed(sha.clone(), data.len()));
+
+            match fmt.as_str() {
+                "commit" => {
+                    let commit = Commit::deserialize(&data);
+                    stack.extend(commit.tree().map(|tree| tree.to_string()));
+                    stack.extend(commit.parents().iter().map(|parent| parent.to_string()));
+                }
+                "tree" => stack.extend(
+                    Tree::parse(&data, self.object_format())
+                        .ok_or_else(malformed)?
+                        .into_iter()
+                        .filter(|entry| entry.mode != GITLINK_MODE)
+                        .map(|entry| entry.sha),
+                ),
+                "tag" => stack.push(
+                    Kvlm::parse(&data)
+                        .and_then(|tag| tag.get("object").map(|object| object.to_string()))
+                        .ok_or_else(malformed)?,
+                ),
+                _ => (),
+            }
+
+            missing.push(sha);
+        }
+
+        Ok(missing)
+    }
+
+    /// The directories holding the HEAD, the index and the reflogs of each worktree.
+    fn admin_dirs(&self) -> Vec<PathBuf> {
+        let linked = fs::read_dir(self.worktrees_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.is_dir());
+
+        std::iter::once(self.common_dir.clone())
+            .chain(linked)
+            .collect()
+    }
+
+    /// The objects to keep besides the refs, with their type: the HEADs of the worktrees,
+    /// the old and new values of every reflog entry, which covers the stashes, and the blobs
+    /// staged in the indexes.
+    pub fn reachability_roots(&self) -> Vec<(String, String)> {
+        let format = self.object_format();
+        let zero = "0".repeat(format.hex_len());
+        let mut roots: Vec<(String, String)> = self
+            .worktree_list_entries()
+            .into_iter()
+            .filter_map(|worktree| worktree.head)
+            .map(|head| ("commit".to_string(), head))
+            .collect();
+
+        for dir in self.admin_dirs() {
+            // Each line is `<old> <new> <ident>\t<message>`.
+            for log in files_under(&dir.join("logs")) {
+                let content = fs::read_to_string(&log).unwrap_or_default();
+                for line in content.lines() {
+                    roots.extend(
+                        line.split(' ')
+                            .take(2)
+                            .filter(|sha| sha.len() == zero.len() && *sha != zero)
+                            .map(|sha| ("commit".to_string(), sha.to_string())),
+                    );
+                }
+            }
+
+            roots.extend(
+                Index::read(&dir.join("index"), format)
+                    .map(|index| index.entries)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|entry| entry.tree_mode() != GITLINK_MODE)
+                    .map(|entry| ("blob".to_string(), entry.sha)),
+            );
+        }
+
+        roots
+    }
+
+    /// The objects reachable from the refs, the reachability roots and extra, missing ones left
+    /// out. Blobs are never read, nor the commits the pack bitmaps cover.
+    pub fn reachable_objects(&self, extra: &[(String, String)]) -> HashSet<String> {
+        let mut tips: Vec<(String, String)> = self
+            .ref_list()
+            .into_values()
+            .filter_map(|sha| Some((self.objects.read_header(&sha)?.0, sha)))
+            .collect();
+        tips.extend(self.reachability_roots());
+        tips.extend(extra.iter().cloned());
+
+        let empty = PackBitmap::default();
+        let bitmap = self.pack_bitmap().unwrap_or(&empty);
+        let (reached, outside) = self.bitmap_walk(bitmap, tips);
+
+        reached
+            .ones()
+            .map(|position| bitmap.object(position).to_string())
+            .chain(outside)
+            .collect()
+    }
+
+    /// The entry of tree sha at path, None when there is none.
+    fn tree_entry_at(&self, sha: &str, path: &str) -> Option<TreeEntry> {
+        let mut tree = sha.to_string();
+        let mut entry = None;
+
+        for name in path.trim_end_matches('/').split('/') {
+            let found = self.tree_entries(Some(&tree)).ok()?.remove(name)?;
+            tree = found.sha.clone();
+            entry = Some(found);
+        }
+
+        entry
+    }
+
+    /// How a walk limited to paths goes through commit sha, as `rev-list -- <path>...` does
+    /// by default: a commit having them as one of its parents does is left out, and only that
+    /// parent is walked. A root commit is listed when it has one of them. The changed-path
+    /// filters of the commit-graph tell when the first parent has them, without reading any
+    /// tree. Returns whether the commit is listed, and the parents to walk.
+    fn simplify(&self, sha: &str, commit: GraphCommit, paths: &[String]) -> (bool, Vec<String>) {
+        let entries = |tree: &str| -> Vec<Option<TreeEntry>> {
+            paths
+                .iter()
+                .map(|path| self.tree_entry_at(tree, path))
+                .collect()
+        };
+
+        if commit.parents.is_empty() {
+            return (entries(&commit.tree).iter().any(Option::is_some), vec![]);
+        }
+
+        let unchanged = self.commit_graph().is_some_and(|graph| {
+            paths
+                .iter()
+                .all(|path| graph.maybe_changed(sha, path) == Some(false))
+        });
+        if unchanged {
+            return (false, vec![commit.parents[0].clone()]);
+        }
+
+        let own = entries(&commit.tree);
+        let same_parent = commit.parents.iter().find(|parent| {
+            self.graph_commit(parent).is_some_and(|parent| {
+                own.iter()
+                    .zip(entries(&parent.tree))
+                    .all(|(own, theirs)| same(own.as_ref(), theirs.as_ref()))
+            })
+        });
+        match same_parent {
+            Some(parent) => (false, vec![parent.clone()]),
+            None => (true, commit.parents),
+        }
+    }
+
+    /// The commits reachable from tips, nearest to them first, changing one of paths, the
+    /// history being simplified as it is walked.
+    fn walk_commits_limited(&self, tips: &[String], paths: &[String]) -> Vec<String> {
+        let mut seen = HashSet::new();
+        let mut commits = Vec::new();
+        let mut queue: VecDeque<String> = tips.iter().filter_map(|tip| self.peel(tip)).collect();
+
+        while let Some(sha) = queue.pop_front() {
+            if !(seen.insert(sha.clone())) {
+                continue;
+            }
+
+            if let Some(commit) = self.graph_commit(&sha) {
+                let (listed, parents) = self.simplify(&sha, commit, paths);
+                queue.extend(parents);
+                if listed {
+                    commits.push(sha);
+                }
+            }
+        }
+
+        commits
+    }
+
+    /// The objects reachable from include but not from exclude, or only the commits unless
+    /// objects, for `rev-list`: commits nearest to include first, then the other objects.
+    /// With paths, only the commits changing one of them are listed.
+    pub fn rev_list(
+        &self,
+        include: &[String],
+        exclude: &[String],
+        objects: bool,
+        paths: &[String],
+    ) -> error::Result<Vec<String>> {
+        let listed = if objects {
+            let excluded: HashSet<String> = self
+                .missing_objects(exclude, &|_| false)?
+                .into_iter()
+                .collect();
+            let mut listed = self.missing_objects(include, &|sha| excluded.contains(sha))?;
+            listed.sort_by_key(|sha| {
+                self.objects
+                    .read_header(sha)
+                    .map(|(fmt, _)| fmt != "commit")
+            });
+            listed
+        } else {
+            let excluded: HashSet<String> = self.walk_commits(exclude).into_iter().collect();
+            self.walk_commits(include)
+                .into_iter()
+                .filter(|sha| !(excluded.contains(sha)))
+                .collect()
+        };
+
+        if paths.is_empty() {
+            return Ok(listed);
+        }
+        let limited: HashSet<String> = self
+            .walk_commits_limited(include, paths)
+            .into_iter()
+            .collect();
+        Ok(listed
+            .into_iter()
+            .filter(|sha| {
+                limited.contains(sha)
+                    || self
+                        .objects
+                        .read_header(sha)
+                        .is_some_and(|(fmt, _)| fmt != "commit")
+            })
+            .collect())
+    }
+}
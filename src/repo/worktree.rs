@@ -0,0 +1,693 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::commands::worktree::WorktreeAdd;
+use crate::error::{self, Error};
+use crate::index::Index;
+use crate::r#const::{LIT_DIR, LIT_DIR_NAMES};
+use crate::repo::Repo;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A working tree of the repository, as shown by `lit worktree list`
+pub struct WorktreeEntry {
+    pub path: PathBuf,
+
+    /// The name of the admin directory `worktrees/<name>`, None for the main worktree
+    pub name: Option<String>,
+    pub head: Option<String>,
+    pub branch: Option<String>,
+    pub bare: bool,
+    pub locked: Option<String>,
+    pub prunable: Option<String>,
+}
+
+impl WorktreeEntry {
+    pub fn porcelain(&self) -> String {
+        let mut lines = vec![format!("worktree {}", self.path.to_str().unwrap())];
+
+        if self.bare {
+            lines.push("bare".to_string());
+        } else {
+            if let Some(head) = &self.head {
+                lines.push(format!("HEAD {}", head));
+            }
+
+            match &self.branch {
+                Some(branch) => lines.push(format!("branch {}", branch)),
+                None => lines.push("detached".to_string()),
+            }
+        }
+
+        if let Some(reason) = &self.locked {
+            lines.push(format!("locked {}", reason).trim_end().to_string());
+        }
+
+        if let Some(reason) = &self.prunable {
+            lines.push(format!("prunable {}", reason));
+        }
+
+        lines.join("\n")
+    }
+}
+
+impl fmt::Display for WorktreeEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.path.to_str().unwrap())?;
+
+        if self.bare {
+            return write!(f, " (bare)");
+        }
+
+        match &self.head {
+            Some(head) => write!(f, " {}", &head[0..7.min(head.len())])?,
+            None => write!(f, " 0000000")?,
+        }
+
+        match &self.branch {
+            Some(branch) => write!(f, " [{}]", branch.trim_start_matches("refs/heads/"))?,
+            None => write!(f, " (detached HEAD)")?,
+        }
+
+        if self.locked.is_some() {
+            write!(f, " locked")?;
+        }
+
+        if self.prunable.is_some() {
+            write!(f, " prunable")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Repo {
+    /// The directory holding the admin directories of the linked worktrees
+    pub fn worktrees_dir(&self) -> PathBuf {
+        self.common_dir.join("worktrees")
+    }
+
+    /// The main worktree, followed by the linked worktrees sorted by name.
+    pub fn worktree_list_entries(&self) -> Vec<WorktreeEntry> {
+        let bare = self
+            .conf
+            .as_ref()
+            .and_then(|conf| conf.get_bool("core.bare").ok().flatten())
+            .unwrap_or(false);
+
+        let main = if self.lit_dir == self.common_dir {
+            self.worktree.clone()
+        } else if bare {
+            self.common_dir.clone()
+        } else {
+            self.common_dir.parent().unwrap().to_owned()
+        };
+
+        let (head, branch) = self.worktree_head(&self.common_dir);
+        let mut entries = vec![WorktreeEntry {
+            path: main,
+            name: None,
+            head,
+            branch,
+            bare,
+            locked: None,
+            prunable: None,
+        }];
+
+        let mut names: Vec<String> = fs::read_dir(self.worktrees_dir())
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter(|entry| entry.path().is_dir())
+            .map(|entry| entry.file_name().to_str().unwrap().to_string())
+            .collect();
+        names.sort();
+
+        names.into_iter().for_each(|name| {
+            let admin = self.worktrees_dir().join(&name);
+            let (head, branch) = self.worktree_head(&admin);
+            let gitdir = Self::worktree_gitdir(&admin);
+
+            entries.push(WorktreeEntry {
+                path: gitdir
+                    .as_ref()
+                    .and_then(|gitdir| gitdir.parent())
+                    .map_or(admin.clone(), |path| path.to_owned()),
+                name: Some(name),
+                head,
+                branch,
+                bare: false,
+                locked: fs::read_to_string(admin.join("locked"))
+                    .ok()
+                    .map(|reason| reason.trim().to_string()),
+                prunable: Self::worktree_prune_reason(&admin),
+            });
+        });
+
+        entries
+    }
+
+    /// Read the HEAD of the worktree whose admin directory is admin.
+    fn worktree_head(&self, admin: &Path) -> (Option<String>, Option<String>) {
+        match fs::read_to_string(admin.join("HEAD")) {
+            Ok(content) => match content.trim().strip_prefix("ref:") {
+                Some(target) => {
+                    let target = target.trim();
+                    (self.ref_resolve(target), Some(target.to_string()))
+                }
+                None => (Some(content.trim().to_string()), None),
+            },
+            Err(_) => (None, None),
+        }
+    }
+
+    /// The `gitdir` file of an admin directory records where the linking file of the worktree is.
+    fn worktree_gitdir(admin: &Path) -> Option<PathBuf> {
+        fs::read_to_string(admin.join("gitdir"))
+            .ok()
+            .map(|gitdir| PathBuf::from(gitdir.trim()))
+    }
+
+    /// Why the admin directory can be pruned, None if it is still in use or locked.
+    fn worktree_prune_reason(admin: &Path) -> Option<String> {
+        if admin.join("locked").exists() {
+            None
+        } else {
+            match Self::worktree_gitdir(admin) {
+                None => Some("gitdir file does not exist".to_string()),
+                Some(gitdir) if !(gitdir.exists()) => {
+                    Some("gitdir file points to non-existent location".to_string())
+                }
+                Some(_) => None,
+            }
+        }
+    }
+
+    /// Find a linked worktree by its name or its path.
+    fn worktree_lookup(&self, worktree: &str) -> error::Result<WorktreeEntry> {
+        let path = fs::canonicalize(worktree).ok();
+
+        self.worktree_list_entries()
+            .into_iter()
+            .filter(|entry| entry.name.is_some())
+            .find(|entry| {
+                entry.name.as_deref() == Some(worktree)
+                    || path.as_ref().is_some_and(|path| *path == entry.path)
+            })
+            .ok_or(Error::Worktree(error::worktree::Worktree::NotFound(
+                worktree.to_owned(),
+            )))
+    }
+
+    /// The worktree which has the branch checked out, if any.
+    pub fn worktree_with_branch(&self, branch: &str) -> Option<PathBuf> {
+        self.worktree_list_entries()
+            .into_iter()
+            .find(|entry| !(entry.bare) && entry.branch.as_deref() == Some(branch))
+            .map(|entry| entry.path)
+    }
+
+    /// Create a linked worktree and check out the requested commit into it.
+    pub fn worktree_add(&self, args: &WorktreeAdd) -> error::Result<Repo> {
+        let path = PathBuf::from(&args.path);
+
+        if path.exists() && path.read_dir().map_or(true, |mut dir| dir.next().is_some()) {
+            return Err(Error::Worktree(error::worktree::Worktree::AlreadyExists(
+                path,
+            )));
+        }
+
+        let commit_ish = args.commit_ish.clone().unwrap_or("HEAD".to_string());
+        let invalid = || Error::Repo(error::repo::Repo::InvalidReference(commit_ish.clone()));
+        let sha = self.resolve_name(&commit_ish).ok_or_else(invalid)?;
+        let tree = self.resolve_tree(&sha).ok_or_else(invalid)?;
+        let name = self.worktree_name(&path);
+
+        // What the HEAD of the new worktree points to: a branch, or the commit itself.
+        let existing_branch = format!("refs/heads/{}", commit_ish);
+        let branch = if args.detach {
+            None
+        } else if let Some(branch) = &args.branch {
+            Some((format!("refs/heads/{}", branch), true))
+        } else if args.commit_ish.is_none() {
+            let branch = format!("refs/heads/{}", name);
+            let exists = self.ref_resolve(&branch).is_some();
+            Some((branch, !exists))
+        } else if self.ref_resolve(&existing_branch).is_some() {
+            Some((existing_branch, false))
+        } else {
+            None
+        };
+
+        match &branch {
+            Some((branch, true)) if self.ref_resolve(branch).is_some() && !(args.force) => {
+                return Err(Error::Worktree(error::worktree::Worktree::BranchExists(
+                    branch.trim_start_matches("refs/heads/").to_string(),
+                )))
+            }
+            Some((branch, false)) if !(args.force) => {
+                if let Some(other) = self.worktree_with_branch(branch) {
+                    return Err(Error::Worktree(
+                        error::worktree::Worktree::BranchCheckedOut(branch.clone(), other),
+                    ));
+                }
+            }
+            _ => (),
+        }
+
+        // Whatever is created from here on is removed again if adding fails,
+        // a branch reset with force points to its previous commit again.
+        let admin = self.worktrees_dir().join(&name);
+        let reset_branch = match &branch {
+            Some((branch, true)) => Some((branch.clone(), self.ref_resolve(branch))),
+            _ => None,
+        };
+        let path_existed = path.exists();
+
+        self.worktree_create(args, &path, &admin, &branch, &sha, &tree)
+            .inspect_err(|_| {
+                let _ = fs::remove_dir_all(&admin);
+                if self
+                    .worktrees_dir()
+                    .read_dir()
+                    .is_ok_and(|mut dir| dir.next().is_none())
+                {
+                    let _ = fs::remove_dir(self.worktrees_dir());
+                }
+                match &reset_branch {
+                    Some((branch, Some(previous))) => {
+                        let _ = self.ref_update(branch, previous);
+                    }
+                    Some((branch, None)) => {
+                        let _ = self.ref_delete(branch);
+                    }
+                    None => (),
+                }
+                if path_existed {
+                    let _ = fs::remove_dir_all(&path).and_then(|_| fs::create_dir(&path));
+                } else {
+                    let _ = fs::remove_dir_all(&path);
+                }
+            })
+    }
+
+    /// Create the admin directory and the worktree of worktree_add, and check
+    /// out the tree into it.
+    fn worktree_create(
+        &self,
+        args: &WorktreeAdd,
+        path: &Path,
+        admin: &Path,
+        branch: &Option<(String, bool)>,
+        sha: &str,
+        tree: &str,
+    ) -> error::Result<Repo> {
+        // The admin directory is locked until the checkout is done, so that
+        // a concurrent prune does not remove it.
+        fs::create_dir_all(admin).map_err(|e| Self::worktree_io(admin, e))?;
+        fs::write(admin.join("locked"), "initializing\n")
+            .map_err(|e| Self::worktree_io(admin, e))?;
+
+        fs::create_dir_all(path).map_err(|e| Self::worktree_io(path, e))?;
+        let path = fs::canonicalize(path).unwrap();
+        let admin = fs::canonicalize(admin).unwrap();
+        let link = path.join(self.worktree_link_name());
+
+        fs::write(admin.join("commondir"), "../..\n").map_err(|e| Self::worktree_io(&admin, e))?;
+        fs::write(
+            admin.join("gitdir"),
+            format!("{}\n", link.to_str().unwrap()),
+        )
+        .map_err(|e| Self::worktree_io(&admin, e))?;
+        fs::write(&link, format!("gitdir: {}\n", admin.to_str().unwrap()))
+            .map_err(|e| Self::worktree_io(&link, e))?;
+
+        let linked = Repo::open(path, admin.clone(), false);
+        match branch {
+            Some((branch, create)) => {
+                if *create {
+                    linked.ref_update(branch, sha)?;
+                }
+                linked.ref_update_symbolic("HEAD", branch)?;
+            }
+            None => linked.ref_update("HEAD", sha)?,
+        }
+
+        linked.checkout_tree(tree, false, &mut |progress| {
+            debug!(
+                "checkout {}/{} {}",
+                progress.completed,
+                progress.total,
+                progress.change.path()
+            )
+        })?;
+
+        if args.lock {
+            fs::write(admin.join("locked"), "added with --lock\n")
+                .map_err(|e| Self::worktree_io(&admin, e))?;
+        } else {
+            fs::remove_file(admin.join("locked")).map_err(|e| Self::worktree_io(&admin, e))?;
+        }
+
+        Ok(linked)
+    }
+
+    /// The name of the admin directory for a worktree at path: its base name,
+    /// with a number appended if it is already taken.
+    fn worktree_name(&self, path: &Path) -> String {
+        let base: String = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("worktree")
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '-'
+                }
+            })
+            .collect();
+
+        let base = base.trim_start_matches('.').to_string();
+        let base = if base.is_empty() {
+            "worktree".to_string()
+        } else {
+            base
+        };
+
+        (0..)
+            .map(|i| {
+                if i == 0 {
+                    base.clone()
+                } else {
+                    format!("{}{}", base, i)
+                }
+            })
+            .find(|name| !(self.worktrees_dir().join(name).exists()))
+            .unwrap()
+    }
+
+    /// Linked worktrees use the same name as the main repository for their linking file,
+    /// so that a `.git` repository stays usable by git.
+    fn worktree_link_name(&self) -> &str {
+        let name = self.common_dir.file_name().and_then(|name| name.to_str());
+
+        LIT_DIR_NAMES
+            .iter()
+            .find(|candidate| Some(**candidate) == name)
+            .copied()
+            .unwrap_or(LIT_DIR)
+    }
+
+    /// Delete a linked worktree and its admin directory.
+    /// Unless force is set, locked worktrees and worktrees with local changes are kept.
+    pub fn worktree_remove(&self, worktree: &str, force: bool) -> error::Result<()> {
+        let entry = self.worktree_lookup(worktree)?;
+        let admin = self.worktrees_dir().join(entry.name.as_ref().unwrap());
+
+        if !(force) {
+            if let Some(reason) = entry.locked {
+                return Err(Error::Worktree(error::worktree::Worktree::Locked(
+                    worktree.to_owned(),
+                    reason,
+                )));
+            }
+
+            if entry.path.is_dir() {
+                let linked = Repo::open(entry.path.clone(), admin.clone(), false);
+                if linked.worktree_is_dirty()? {
+                    return Err(Error::Worktree(error::worktree::Worktree::Dirty(
+                        entry.path,
+                    )));
+                }
+            }
+        }
+
+        if entry.path.is_dir() {
+            fs::remove_dir_all(&entry.path).map_err(|e| Self::worktree_io(&entry.path, e))?;
+        }
+
+        fs::remove_dir_all(&admin).map_err(|e| Self::worktree_io(&admin, e))?;
+        self.worktree_remove_empty_dir();
+        Ok(())
+    }
+
+    /// Whether the worktree has modified tracked files, or untracked files.
+    fn worktree_is_dirty(&self) -> error::Result<bool> {
+        let index = Index::read(&self.index_path(), self.object_format())?;
+
+        if index
+            .entries
+            .iter()
+            .any(|entry| self.is_modified(entry) || !(self.worktree.join(&entry.path).exists()))
+        {
+            return Ok(true);
+        }
+
+        let mut stack = vec![self.worktree.clone()];
+        while let Some(dir) = stack.pop() {
+            for entry in fs::read_dir(&dir).map_err(|e| Self::worktree_io(&dir, e))? {
+                let path = entry.map_err(|e| Self::worktree_io(&dir, e))?.path();
+                let relative = path
+                    .strip_prefix(&self.worktree)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .replace('\\', "/");
+
+                if dir == self.worktree && LIT_DIR_NAMES.contains(&relative.as_str()) {
+                    continue;
+                }
+
+                if path.is_dir() && !(path.is_symlink()) {
+                    stack.push(path);
+                } else if index.get(&relative).is_none() {
+                    return Ok(true);
+                }
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Remove the admin directories of the worktrees whose directory vanished,
+    /// returning their names with the reason.
+    pub fn worktree_prune(&self, dry_run: bool) -> Vec<(String, String)> {
+        let pruned: Vec<(String, String)> = self
+            .worktree_list_entries()
+            .into_iter()
+            .filter_map(|entry| Some((entry.name?, entry.prunable?)))
+            .collect();
+
+        if !(dry_run) {
+            pruned.iter().for_each(|(name, _)| {
+                let _ = fs::remove_dir_all(self.worktrees_dir().join(name));
+            });
+
+            self.worktree_remove_empty_dir();
+        }
+
+        pruned
+    }
+
+    fn worktree_remove_empty_dir(&self) {
+        let _ = fs::remove_dir(self.worktrees_dir());
+    }
+
+    pub fn worktree_lock(&self, worktree: &str, reason: Option<&str>) -> error::Result<()> {
+        let entry = self.worktree_lookup(worktree)?;
+
+        if let Some(current) = entry.locked {
+            return Err(Error::Worktree(error::worktree::Worktree::Locked(
+                worktree.to_owned(),
+                current,
+            )));
+        }
+
+        let admin = self.worktrees_dir().join(entry.name.unwrap());
+        fs::write(admin.join("locked"), reason.unwrap_or_default())
+            .map_err(|e| Self::worktree_io(&admin, e))
+    }
+
+    pub fn worktree_unlock(&self, worktree: &str) -> error::Result<()> {
+        let entry = self.worktree_lookup(worktree)?;
+        let admin = self.worktrees_dir().join(entry.name.unwrap());
+
+        match fs::remove_file(admin.join("locked")) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Self::worktree_io(&admin, e)),
+            _ => Ok(()),
+        }
+    }
+
+    fn worktree_io(path: &Path, error: std::io::Error) -> Error {
+        Error::Worktree(error::worktree::Worktree::Io(
+            path.to_owned(),
+            error.to_string(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::commands::worktree::WorktreeAdd;
+    use crate::object::operation::write_raw;
+    use crate::repo::Repo;
+    use crate::test_util::{commit_tree, create, tree};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn add(path: &str, branch: Option<&str>) -> WorktreeAdd {
+        WorktreeAdd {
+            force: false,
+            detach: false,
+            lock: false,
+            branch: branch.map(|branch| branch.to_string()),
+            path: path.to_string(),
+            commit_ish: None,
+        }
+    }
+
+    /// A repository whose master branch has one commit with a single file
+    fn create_with_commit(path: &str) -> Repo {
+        let repo = create(path, false);
+        let blob = write_raw(&repo, "blob", b"hello\n");
+        let tree = tree(&repo, &[("100644", "hello.txt", &blob)]);
+        let commit = commit_tree(&repo, &tree, &[], "init");
+
+        repo.ref_update("refs/heads/master", &commit).unwrap();
+        repo
+    }
+
+    #[test]
+    pub fn test_worktree_add_and_find() {
+        let repo = create_with_commit("./test_worktree_add");
+        let linked = repo
+            .worktree_add(&add("./test_worktree_add/linked", None))
+            .unwrap();
+
+        assert_eq!(
+            "hello\n",
+            fs::read_to_string("./test_worktree_add/linked/hello.txt").unwrap()
+        );
+        assert!(fs::read_to_string("./test_worktree_add/linked/.lit")
+            .unwrap()
+            .starts_with("gitdir: "));
+
+        let found =
+            Repo::repo_find(&String::from("./test_worktree_add/linked"), true, None).unwrap();
+        assert_eq!(linked.lit_dir, found.lit_dir);
+        assert_eq!(
+            fs::canonicalize(&repo.lit_dir).unwrap(),
+            found.lit_dir.parent().unwrap().parent().unwrap()
+        );
+        assert_eq!(
+            fs::canonicalize("./test_worktree_add/.lit").unwrap(),
+            found.common_dir
+        );
+        assert!(found.lit_dir.join("index").is_file());
+        assert_eq!(
+            Some("refs/heads/linked".to_string()),
+            repo.worktree_list_entries()[1].branch
+        );
+        assert_eq!(repo.ref_resolve("HEAD"), found.ref_resolve("HEAD"));
+
+        // The branch is checked out in `linked`, it cannot be checked out twice
+        let mut again = add("./test_worktree_add/again", None);
+        again.commit_ish = Some("linked".to_string());
+        assert!(repo.worktree_add(&again).is_err());
+
+        let entries = repo.worktree_list_entries();
+        assert_eq!(2, entries.len());
+        assert_eq!(Some("linked".to_string()), entries[1].name);
+
+        fs::remove_dir_all("./test_worktree_add").unwrap();
+    }
+
+    #[test]
+    pub fn test_worktree_add_rolls_back() {
+        let repo = create_with_commit("./test_worktree_rollback");
+
+        // A commit whose tree cannot be checked out
+        let blob = write_raw(&repo, "blob", b"pwned\n");
+        let inner = tree(&repo, &[("100644", "pwned", &blob)]);
+        let bad = tree(&repo, &[("40000", ".lit", &inner)]);
+        let commit = commit_tree(&repo, &bad, &[], "bad");
+        repo.ref_update("refs/heads/bad", &commit).unwrap();
+
+        let mut bad = add("./test_worktree_rollback/new", Some("created"));
+        bad.commit_ish = Some("bad".to_string());
+        assert!(repo.worktree_add(&bad).is_err());
+        assert!(!(repo.worktrees_dir().exists()));
+        assert!(repo.ref_resolve("refs/heads/created").is_none());
+        assert!(!(PathBuf::from("./test_worktree_rollback/new").exists()));
+
+        // An empty target directory is kept, a branch reset with force restored
+        fs::create_dir("./test_worktree_rollback/empty").unwrap();
+        let mut forced = add("./test_worktree_rollback/empty", Some("master"));
+        forced.commit_ish = Some("bad".to_string());
+        forced.force = true;
+        let master = repo.ref_resolve("refs/heads/master");
+        assert!(repo.worktree_add(&forced).is_err());
+        assert_eq!(master, repo.ref_resolve("refs/heads/master"));
+        assert!(fs::read_dir("./test_worktree_rollback/empty")
+            .unwrap()
+            .next()
+            .is_none());
+
+        fs::remove_dir_all("./test_worktree_rollback").unwrap();
+    }
+
+    #[test]
+    pub fn test_worktree_remove_and_prune() {
+        let repo = create_with_commit("./test_worktree_prune");
+        repo.worktree_add(&add("./test_worktree_prune/a", Some("a")))
+            .unwrap();
+        repo.worktree_add(&add("./test_worktree_prune/b", Some("b")))
+            .unwrap();
+        repo.worktree_add(&add("./test_worktree_prune/c", Some("c")))
+            .unwrap();
+
+        // A dirty worktree is only removed with force
+        fs::write("./test_worktree_prune/a/untracked", "").unwrap();
+        assert!(repo.worktree_remove("a", false).is_err());
+        repo.worktree_remove("a", true).unwrap();
+        assert!(!(repo.worktrees_dir().join("a").exists()));
+
+        // b vanished, c vanished but is locked
+        repo.worktree_lock("c", Some("on a removable disk"))
+            .unwrap();
+        fs::remove_dir_all("./test_worktree_prune/b").unwrap();
+        fs::remove_dir_all("./test_worktree_prune/c").unwrap();
+
+        assert_eq!(1, repo.worktree_prune(true).len());
+        assert!(repo.worktrees_dir().join("b").exists());
+
+        let pruned = repo.worktree_prune(false);
+        assert_eq!("b", pruned[0].0);
+        assert!(!(repo.worktrees_dir().join("b").exists()));
+        assert!(repo.worktrees_dir().join("c").exists());
+
+        repo.worktree_unlock("c").unwrap();
+        assert_eq!(1, repo.worktree_prune(false).len());
+        assert!(!(repo.worktrees_dir().exists()));
+
+        fs::remove_dir_all("./test_worktree_prune").unwrap();
+    }
+}
@@ -0,0 +1,83 @@
+/*
+ * Copyright (C) 2023 Muqiu Han
+ *
+ * This library is free software; you can redistribute it and/or
+ * modify it under the terms of the GNU Library General Public
+ * License as published by the Free Software Foundation; either
+ * version 2 of the License, or (at your option) any later version.
+ *
+ * This library is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the GNU
+ * Library General Public License for more details.
+ *
+ * You should have received a copy of the GNU Library General Public
+ * License along with this library; if not, write to the Free Software
+ * Foundation, Inc., 51 Franklin Street, Fifth Floor, Boston, MA  02110-1301  USA
+ */
+
+use crate::commands::init::Init;
+use crate::object::format::from_hex;
+use crate::object::operation::write_raw;
+use crate::repo::Repo;
+use std::fs;
+
+/// The author and committer of the commits of the tests
+pub const IDENT: &str = "A U Thor <author@example.com> 1700000000 +0000";
+
+/// A new repository at path, replacing whatever was there.
+pub fn create(path: &str, bare: bool) -> Repo {
+    let _ = fs::remove_dir_all(path);
+    Repo::create(&Init {
+        force: false,
+        bare,
+        path: String::from(path),
+        extensions: vec![],
+        object_format: None,
+    })
+}
+
+/// The content of a tree of entries given by mode, name and sha.
+pub fn tree_data(entries: &[(&str, &str, &str)]) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (mode, name, sha) in entries {
+        data.extend_from_slice(format!("{} {}\0", mode, name).as_bytes());
+        data.extend(from_hex(sha));
+    }
+    data
+}
+
+pub fn tree(repo: &Repo, entries: &[(&str, &str, &str)]) -> String {
+    write_raw(repo, "tree", &tree_data(entries))
+}
+
+/// The content of a commit of tree on top of parents, by `IDENT`.
+pub fn commit_data(tree: &str, parents: &[&str], message: &str) -> String {
+    let parents: String = parents
+        .iter()
+        .map(|parent| format!("parent {}\n", parent))
+        .collect();
+    format!(
+        "tree {}\n{}author {}\ncommitter {}\n\n{}\n",
+        tree, parents, IDENT, IDENT, message
+    )
+}
+
+pub fn commit_tree(repo: &Repo, tree: &str, parents: &[&str], message: &str) -> String {
+    write_raw(
+        repo,
+        "commit",
+        commit_data(tree, parents, message).as_bytes(),
+    )
+}
+
+/// Commit a single file with content on top of parent, moving master.
+pub fn commit(repo: &Repo, parent: Option<&str>, content: &str) -> String {
+    let blob = write_raw(repo, "blob", content.as_bytes());
+    let tree = tree(repo, &[("100644", "file", &blob)]);
+    let parents: Vec<&str> = parent.into_iter().collect();
+    let commit = commit_tree(repo, &tree, &parents, content);
+
+    repo.ref_update("refs/heads/master", &commit).unwrap();
+    commit
+}